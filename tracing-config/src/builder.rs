@@ -0,0 +1,534 @@
+//! [`TracingConfig`], choosing where logs go and how they look.
+
+use std::{fmt, io, path::PathBuf, time::Duration};
+
+use tracing::{info, Subscriber};
+use tracing_appender::{
+    non_blocking,
+    non_blocking::{NonBlocking, WorkerGuard},
+};
+use tracing_subscriber::{
+    fmt as format, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Layer,
+    Registry,
+};
+
+use crate::{
+    filters::{FilterHandle, LogFilters},
+    otlp::{self, OtlpSettings},
+    redaction::{Redacting, Redaction},
+    rolling::{FilePolicy, RollingFile},
+    TracingGuard, TracingSettings,
+};
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TracingError {
+    #[error("invalid {sink} filter `{directive}`: {source}")]
+    InvalidFilter {
+        sink: String,
+        directive: String,
+        source: tracing_subscriber::filter::ParseError,
+    },
+    #[error("failed to open the log files in {directory}: {source}")]
+    FileAppender { directory: PathBuf, source: io::Error },
+    #[error("{0}")]
+    Otlp(String),
+    #[error("invalid redaction pattern `{pattern}`: {source}")]
+    InvalidPattern { pattern: String, source: regex::Error },
+    #[error("two sinks are named {0}")]
+    DuplicateSink(String),
+    #[error("no sink is named {0}")]
+    UnknownSink(String),
+    #[error("failed to change a filter: {0}")]
+    Reload(String),
+    #[error("a global subscriber is already set: {0}")]
+    AlreadyInitialized(#[from] tracing_subscriber::util::TryInitError),
+}
+
+/// How often a file sink starts a new file, named after its prefix and the period.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Hourly,
+    Daily,
+    /// A single file named by the prefix alone.
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One json object per line, with the fields of the current spans.
+    Json,
+    /// Multi-line output meant for reading in a terminal.
+    Pretty,
+    /// A single line, span fields after the event's.
+    Compact,
+    /// `key=value` pairs on a single line.
+    Logfmt,
+    /// The default `tracing-subscriber` line with the span context in front.
+    Full,
+}
+
+#[derive(Debug, Clone)]
+enum Target {
+    Stdout,
+    Stderr,
+    File {
+        directory: PathBuf,
+        prefix: String,
+        policy: FilePolicy,
+    },
+}
+
+/// One place logs are written to, with its own format and `EnvFilter` directives.
+#[derive(Debug, Clone)]
+pub struct Sink {
+    name: String,
+    target: Target,
+    format: Format,
+    filter: String,
+    ansi: bool,
+}
+
+impl Sink {
+    /// Full lines from `info`, with colors.
+    pub fn stdout() -> Self {
+        Self {
+            name: "stdout".to_string(),
+            target: Target::Stdout,
+            format: Format::Full,
+            filter: "info".to_string(),
+            ansi: true,
+        }
+    }
+
+    /// Full lines from `warn`, with colors.
+    pub fn stderr() -> Self {
+        Self {
+            name: "stderr".to_string(),
+            target: Target::Stderr,
+            filter: "warn".to_string(),
+            ..Self::stdout()
+        }
+    }
+
+    /// Json lines from `info` into `directory`, rotated daily and kept forever. The directory is
+    /// created when missing.
+    pub fn file(directory: impl Into<PathBuf>, prefix: impl Into<String>) -> Self {
+        Self {
+            name: "file".to_string(),
+            target: Target::File {
+                directory: directory.into(),
+                prefix: prefix.into(),
+                policy: FilePolicy::default(),
+            },
+            format: Format::Json,
+            filter: "info".to_string(),
+            ansi: false,
+        }
+    }
+
+    /// What the sink's filter is changed by in [`LogFilters`], `stdout`, `stderr` or `file` by
+    /// default. Sinks of one config need distinct names.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// `EnvFilter` directives, like `info` or `warn,twitter_clone=debug`.
+    pub fn filter(mut self, directives: impl Into<String>) -> Self {
+        self.filter = directives.into();
+        self
+    }
+
+    pub fn ansi(mut self, ansi: bool) -> Self {
+        self.ansi = ansi;
+        self
+    }
+
+    /// Only used by file sinks, like the ones below.
+    pub fn rotation(self, rotation: Rotation) -> Self {
+        self.file_policy(|policy| policy.rotation = rotation)
+    }
+
+    /// Starts the next file of the period once this size would be exceeded, the full one is
+    /// renamed with a part number, see [`crate::rolling`].
+    pub fn max_bytes(self, max_bytes: u64) -> Self {
+        self.file_policy(|policy| policy.max_bytes = Some(max_bytes))
+    }
+
+    /// Deletes the oldest rotated files beyond this count.
+    pub fn max_files(self, max_files: usize) -> Self {
+        self.file_policy(|policy| policy.max_files = Some(max_files))
+    }
+
+    /// Deletes rotated files last written longer ago than this.
+    pub fn max_age(self, max_age: Duration) -> Self {
+        self.file_policy(|policy| policy.max_age = Some(max_age))
+    }
+
+    /// Gzips rotated files into `.gz` files.
+    pub fn compress(self, compress: bool) -> Self {
+        self.file_policy(|policy| policy.compress = compress)
+    }
+
+    fn file_policy(mut self, change: impl FnOnce(&mut FilePolicy)) -> Self {
+        if let Target::File { policy, .. } = &mut self.target {
+            change(policy);
+        }
+        self
+    }
+
+    fn env_filter(&self) -> Result<EnvFilter, TracingError> {
+        EnvFilter::try_new(&self.filter).map_err(|source| TracingError::InvalidFilter {
+            sink: self.name.clone(),
+            directive: self.filter.clone(),
+            source,
+        })
+    }
+
+    fn writer(&self) -> Result<(NonBlocking, WorkerGuard), TracingError> {
+        Ok(match &self.target {
+            Target::Stdout => non_blocking(io::stdout()),
+            Target::Stderr => non_blocking(io::stderr()),
+            Target::File { directory, prefix, policy } => non_blocking(
+                RollingFile::new(directory.clone(), prefix.clone(), policy.clone()).map_err(
+                    |source| TracingError::FileAppender { directory: directory.clone(), source },
+                )?,
+            ),
+        })
+    }
+
+    fn layer(&self, writer: NonBlocking, filter: reload::Layer<EnvFilter, Registry>) -> BoxedLayer {
+        let layer = match self.format {
+            Format::Json => format::layer()
+                .json()
+                .with_writer(writer)
+                .with_ansi(self.ansi)
+                .boxed(),
+            Format::Pretty => format::layer()
+                .pretty()
+                .with_writer(writer)
+                .with_ansi(self.ansi)
+                .boxed(),
+            Format::Compact => format::layer()
+                .compact()
+                .with_writer(writer)
+                .with_ansi(self.ansi)
+                .boxed(),
+            Format::Logfmt => tracing_logfmt::builder()
+                .layer()
+                .with_writer(writer)
+                .boxed(),
+            Format::Full => format::layer()
+                .with_writer(writer)
+                .with_ansi(self.ansi)
+                .boxed(),
+        };
+        layer.with_filter(filter).boxed()
+    }
+}
+
+/// Builds the global subscriber out of sinks, an optional OTLP exporter and extra layers. The
+/// sinks and the exporter only get fields after [`Redaction`], the default one unless set.
+///
+/// ```no_run
+/// use tracing_config::{Format, Rotation, Sink, TracingConfig};
+///
+/// let _guard = TracingConfig::new()
+///     .sink(Sink::file("logs", "app.jsonl").rotation(Rotation::Hourly))
+///     .sink(Sink::stdout().format(Format::Compact).filter("debug"))
+///     .try_init()
+///     .expect("tracing is set up once");
+/// ```
+#[derive(Default)]
+pub struct TracingConfig {
+    sinks: Vec<Sink>,
+    otlp: Option<(OtlpSettings, String)>,
+    redaction: Redaction,
+    layers: Vec<BoxedLayer>,
+}
+
+impl fmt::Debug for TracingConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TracingConfig")
+            .field("sinks", &self.sinks)
+            .field("otlp", &self.otlp)
+            .field("redaction", &self.redaction)
+            .field("layers", &self.layers.len())
+            .finish()
+    }
+}
+
+impl TracingConfig {
+    /// No sinks at all, add them with [`TracingConfig::sink`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What [`crate::init_tracing_with`] sets up: json lines at `file_level` in
+    /// `app.jsonl.<period>` files and errors alone in `errors.jsonl.<period>` files, both kept as
+    /// `files` says, full lines at `stdout_level` on stdout, warnings and errors on stderr, and
+    /// spans exported down to `file_level` when `otlp` is set.
+    pub fn from_settings(settings: &TracingSettings) -> Self {
+        let files = &settings.files;
+        let file = |prefix: &str| {
+            let mut sink = Sink::file(&files.directory, prefix)
+                .rotation(files.rotation)
+                .compress(files.compress);
+            if let Some(max_bytes) = files.max_bytes {
+                sink = sink.max_bytes(max_bytes);
+            }
+            if let Some(max_files) = files.max_files {
+                sink = sink.max_files(max_files);
+            }
+            if let Some(max_age) = files.max_age {
+                sink = sink.max_age(max_age);
+            }
+            sink
+        };
+        let mut config = Self::new().sink(file("app.jsonl").filter(&settings.file_level));
+        if files.error_file {
+            config = config.sink(file("errors.jsonl").name("errors").filter("error"));
+        }
+        let config = config
+            .sink(Sink::stdout().filter(&settings.stdout_level))
+            .sink(Sink::stderr())
+            .redaction(settings.redaction.clone());
+        match &settings.otlp {
+            Some(otlp) => config.otlp(otlp.clone(), &settings.file_level),
+            None => config,
+        }
+    }
+
+    pub fn sink(mut self, sink: Sink) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    /// Exports the spans passing `filter` to an OpenTelemetry collector.
+    pub fn otlp(mut self, settings: OtlpSettings, filter: impl Into<String>) -> Self {
+        self.otlp = Some((settings, filter.into()));
+        self
+    }
+
+    /// Replaces what is redacted from the fields the sinks and the exporter get.
+    pub fn redaction(mut self, redaction: Redaction) -> Self {
+        self.redaction = redaction;
+        self
+    }
+
+    /// Adds a layer next to the sinks. It sees every span and event unless it brings its own
+    /// filter, for example to turn spans into metrics.
+    pub fn layer(mut self, layer: impl Layer<Registry> + Send + Sync + 'static) -> Self {
+        self.layers.push(layer.boxed());
+        self
+    }
+
+    /// Sets the global subscriber, failing when a filter is invalid, two sinks share a name, a
+    /// log directory can't be created, the exporter can't be built or a subscriber is already set.
+    pub fn try_init(self) -> Result<TracingGuard, TracingError> {
+        let sinks = self.sinks.clone();
+        let otlp = self.otlp.clone();
+        let (subscriber, guard) = self.build()?;
+        subscriber.try_init()?;
+
+        for sink in &sinks {
+            info!(sink = %sink.name, filter = %sink.filter, format = ?sink.format, "Logging");
+        }
+        if let Some((settings, filter)) = &otlp {
+            info!(endpoint = %settings.endpoint, filter = %filter, "Exporting spans");
+        }
+        Ok(guard)
+    }
+
+    /// The subscriber [`TracingConfig::try_init`] sets, for callers setting it themselves, for
+    /// example only for a test with `tracing::subscriber::with_default`. The filters of the
+    /// guard change it as long as it is alive.
+    pub fn build(
+        self,
+    ) -> Result<(impl Subscriber + Send + Sync + 'static, TracingGuard), TracingError> {
+        let mut layers = self.layers;
+        let mut sinks = Vec::new();
+        let mut workers = Vec::new();
+        let mut handles: Vec<(String, FilterHandle)> = Vec::new();
+        // Checked up front so nothing is left half running when one is invalid.
+        let filters = self
+            .sinks
+            .iter()
+            .map(Sink::env_filter)
+            .collect::<Result<Vec<_>, _>>()?;
+        let redactor = self.redaction.compile()?;
+        for (sink, filter) in self.sinks.iter().zip(filters) {
+            if handles.iter().any(|(name, _)| *name == sink.name) {
+                return Err(TracingError::DuplicateSink(sink.name.clone()));
+            }
+            let (writer, worker) = sink.writer()?;
+            let (filter, handle) = reload::Layer::new(filter);
+            workers.push(worker);
+            handles.push((sink.name.clone(), handle));
+            sinks.push(sink.layer(writer, filter));
+        }
+
+        let tracer_provider = match &self.otlp {
+            Some((settings, directives)) => {
+                let filter = EnvFilter::try_new(directives).map_err(|source| {
+                    TracingError::InvalidFilter {
+                        sink: "otlp".to_string(),
+                        directive: directives.clone(),
+                        source,
+                    }
+                })?;
+                settings.validate().map_err(TracingError::Otlp)?;
+                let provider = otlp::tracer_provider(settings).map_err(TracingError::Otlp)?;
+                let (filter, handle) = reload::Layer::new(filter);
+                handles.push(("otlp".to_string(), handle));
+                sinks.push(
+                    tracing_opentelemetry::layer()
+                        .with_tracer(otlp::tracer(&provider, settings))
+                        .with_filter(filter)
+                        .boxed(),
+                );
+                Some(provider)
+            },
+            None => None,
+        };
+        layers.push(Redacting::new(sinks, redactor).boxed());
+
+        let guard = TracingGuard {
+            _workers: workers,
+            tracer_provider,
+            filters: LogFilters::new(handles),
+        };
+        Ok((tracing_subscriber::registry().with(layers), guard))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::LogFileSettings;
+
+    #[test]
+    fn test_invalid_filters_are_reported_by_sink() {
+        let err = TracingConfig::new()
+            .sink(Sink::stdout())
+            .sink(Sink::stderr().filter("warn,twitter_clone=loud"))
+            .try_init()
+            .unwrap_err();
+        assert!(matches!(
+            &err,
+            TracingError::InvalidFilter { sink, directive, .. }
+                if sink == "stderr" && directive == "warn,twitter_clone=loud"
+        ));
+        assert!(matches!(
+            TracingConfig::new()
+                .sink(Sink::stdout())
+                .sink(Sink::stderr().name("stdout"))
+                .build(),
+            Err(TracingError::DuplicateSink(sink)) if sink == "stdout"
+        ));
+    }
+
+    #[test]
+    fn test_sinks_write_their_format_and_second_init_fails() {
+        let directory = std::env::temp_dir().join(format!("tracing-config-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+
+        let guard = TracingConfig::new()
+            .sink(
+                Sink::file(&directory, "app.jsonl")
+                    .name("json")
+                    .rotation(Rotation::Never)
+                    .filter("debug"),
+            )
+            .sink(
+                Sink::file(&directory, "app.log")
+                    .name("logfmt")
+                    .rotation(Rotation::Never)
+                    .format(Format::Logfmt)
+                    .filter("warn"),
+            )
+            .try_init()
+            .unwrap();
+        tracing::debug!(user_id = 7, "Profile read");
+        tracing::warn!(user_id = 8, "Profile missing");
+        guard.filters().set("logfmt", "info").unwrap();
+        tracing::info!(user_id = 9, "Profile updated");
+        let sinks: Vec<_> = guard
+            .filters()
+            .list()
+            .into_iter()
+            .map(|filter| filter.sink)
+            .collect();
+        assert_eq!(sinks, ["json", "logfmt"]);
+        assert!(matches!(
+            TracingConfig::new().sink(Sink::stdout()).try_init(),
+            Err(TracingError::AlreadyInitialized(_))
+        ));
+        drop(guard);
+
+        let json = fs::read_to_string(directory.join("app.jsonl")).unwrap();
+        assert!(
+            json.contains(r#""fields":{"message":"Profile read","user_id":7}"#),
+            "{json}"
+        );
+        assert!(json.contains(r#""message":"Profile missing""#));
+        let logfmt = fs::read_to_string(directory.join("app.log")).unwrap();
+        assert!(!logfmt.contains("Profile read"));
+        assert!(
+            logfmt.contains(r#"level=warn"#)
+                && logfmt.contains(r#"message="Profile missing" user_id=8"#),
+            "{logfmt}"
+        );
+        assert!(logfmt.contains(r#"message="Profile updated" user_id=9"#));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_errors_get_their_own_file() {
+        let directory =
+            std::env::temp_dir().join(format!("tracing-config-errors-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        let settings = TracingSettings {
+            development: false,
+            stdout_level: "off".to_string(),
+            file_level: "info".to_string(),
+            otlp: None,
+            redaction: Redaction::default(),
+            files: LogFileSettings {
+                directory: directory.clone(),
+                rotation: Rotation::Never,
+                ..LogFileSettings::default()
+            },
+        };
+
+        let (subscriber, guard) = TracingConfig::from_settings(&settings).build().unwrap();
+        let sinks: Vec<_> = guard
+            .filters()
+            .list()
+            .into_iter()
+            .map(|filter| filter.sink)
+            .collect();
+        assert_eq!(sinks, ["file", "errors", "stdout", "stderr"]);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("Profile read");
+            tracing::error!(error_id = "e-1", "Database unreachable");
+        });
+        drop(guard);
+
+        let all = fs::read_to_string(directory.join("app.jsonl")).unwrap();
+        assert!(all.contains("Profile read") && all.contains("Database unreachable"));
+        let errors = fs::read_to_string(directory.join("errors.jsonl")).unwrap();
+        assert_eq!(errors.lines().count(), 1, "{errors}");
+        assert!(errors.contains(r#""error_id":"e-1""#), "{errors}");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
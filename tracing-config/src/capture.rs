@@ -0,0 +1,388 @@
+//! [`LogCapture`], keeping what is logged in memory so tests can assert on it. Built for the crate's
+//! own tests and with the `testing` feature, which other crates enable in their dev-dependencies.
+//!
+//! ```
+//! use tracing::Level;
+//! use tracing_config::capture::{ExpectedEvent, LogCapture};
+//!
+//! let capture = LogCapture::new();
+//! let _guard = capture.set_default();
+//! tracing::info_span!("handler", user_id = 7).in_scope(|| tracing::warn!(attempt = 2, "Retrying"));
+//!
+//! capture.assert_event(
+//!     &ExpectedEvent::new(Level::WARN)
+//!         .message("Retrying")
+//!         .field_eq("attempt", "2")
+//!         .span_field_eq("handler", "user_id", "7"),
+//! );
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use tracing::{
+    field::{Field, Visit},
+    span,
+    subscriber::DefaultGuard,
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{
+    layer::{Context, SubscriberExt},
+    registry::LookupSpan,
+    Layer,
+};
+
+/// A span an event was logged in, with the fields it has recorded so far.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedSpan {
+    pub name: &'static str,
+    pub fields: BTreeMap<&'static str, String>,
+}
+
+/// An event as it was logged. Values are kept as text: `%value` and `str` fields as displayed,
+/// `?value` fields as debugged and numbers and booleans as usual.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedEvent {
+    pub level: Level,
+    pub target: &'static str,
+    pub message: Option<String>,
+    pub fields: BTreeMap<&'static str, String>,
+    /// The spans the event was logged in, the root first.
+    pub spans: Vec<CapturedSpan>,
+}
+
+impl CapturedEvent {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+
+    /// The innermost span named `name`.
+    pub fn span(&self, name: &str) -> Option<&CapturedSpan> {
+        self.spans.iter().rev().find(|span| span.name == name)
+    }
+}
+
+impl fmt::Display for CapturedEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}:", self.level, self.target)?;
+        for span in &self.spans {
+            write!(f, " {}{{", span.name)?;
+            write_fields(f, &span.fields)?;
+            write!(f, "}}:")?;
+        }
+        if let Some(message) = &self.message {
+            write!(f, " {message}")?;
+        }
+        if !self.fields.is_empty() {
+            write!(f, " ")?;
+            write_fields(f, &self.fields)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_fields(
+    f: &mut fmt::Formatter<'_>,
+    fields: &BTreeMap<&'static str, String>,
+) -> fmt::Result {
+    for (index, (name, value)) in fields.iter().enumerate() {
+        let separator = if index == 0 { "" } else { " " };
+        write!(f, "{separator}{name}={value}")?;
+    }
+    Ok(())
+}
+
+/// What [`LogCapture::assert_event`] looks for. Only the level has to match unless more is set.
+#[derive(Debug, Clone)]
+pub struct ExpectedEvent {
+    level: Level,
+    message: Option<String>,
+    fields: Vec<(String, Option<String>)>,
+    spans: Vec<(String, Option<(String, String)>)>,
+}
+
+impl ExpectedEvent {
+    pub fn new(level: Level) -> Self {
+        Self {
+            level,
+            message: None,
+            fields: Vec::new(),
+            spans: Vec::new(),
+        }
+    }
+
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// The event has the field, whatever its value.
+    pub fn field(mut self, name: impl Into<String>) -> Self {
+        self.fields.push((name.into(), None));
+        self
+    }
+
+    pub fn field_eq(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.fields.push((name.into(), Some(value.into())));
+        self
+    }
+
+    /// The event was logged inside a span named `name`.
+    pub fn in_span(mut self, name: impl Into<String>) -> Self {
+        self.spans.push((name.into(), None));
+        self
+    }
+
+    /// The event was logged inside a span named `span` with the field set to `value`.
+    pub fn span_field_eq(
+        mut self,
+        span: impl Into<String>,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.spans
+            .push((span.into(), Some((name.into(), value.into()))));
+        self
+    }
+
+    pub fn matches(&self, event: &CapturedEvent) -> bool {
+        event.level == self.level
+            && self
+                .message
+                .as_ref()
+                .is_none_or(|message| event.message.as_ref() == Some(message))
+            && self.fields.iter().all(|(name, value)| match value {
+                Some(value) => event.field(name) == Some(value.as_str()),
+                None => event.field(name).is_some(),
+            })
+            && self.spans.iter().all(|(name, field)| {
+                event.spans.iter().any(|span| {
+                    span.name == name
+                        && field.as_ref().is_none_or(|(field, value)| {
+                            span.fields.get(field.as_str()) == Some(value)
+                        })
+                })
+            })
+    }
+}
+
+/// Records every event and span field of the threads it is the default subscriber of. Clones
+/// share the recorded events.
+#[derive(Debug, Clone, Default)]
+pub struct LogCapture {
+    events: Arc<Mutex<Vec<CapturedEvent>>>,
+}
+
+impl LogCapture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Captures what the current thread logs until the guard is dropped, at every level and
+    /// whatever the global subscriber is. Tests running in parallel on other threads are not
+    /// captured, neither is work moved to other threads like `spawn_blocking`.
+    pub fn set_default(&self) -> DefaultGuard {
+        tracing::subscriber::set_default(self.subscriber())
+    }
+
+    /// A subscriber recording into this capture, for [`tracing::subscriber::with_default`].
+    pub fn subscriber(&self) -> impl Subscriber + Send + Sync + 'static {
+        tracing_subscriber::registry().with(CaptureLayer { events: self.events.clone() })
+    }
+
+    /// The events recorded so far, oldest first.
+    pub fn events(&self) -> Vec<CapturedEvent> {
+        self.lock().clone()
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    pub fn find(&self, expected: &ExpectedEvent) -> Option<CapturedEvent> {
+        self.lock()
+            .iter()
+            .find(|event| expected.matches(event))
+            .cloned()
+    }
+
+    /// Returns the first event matching `expected`, panicking with every recorded event when none
+    /// does.
+    #[track_caller]
+    pub fn assert_event(&self, expected: &ExpectedEvent) -> CapturedEvent {
+        match self.find(expected) {
+            Some(event) => event,
+            None => panic!(
+                "no event matches {expected:?}, captured:\n{}",
+                self.describe()
+            ),
+        }
+    }
+
+    #[track_caller]
+    pub fn assert_no_event(&self, expected: &ExpectedEvent) {
+        if let Some(event) = self.find(expected) {
+            panic!("unexpected event matching {expected:?}: {event}");
+        }
+    }
+
+    fn describe(&self) -> String {
+        self.lock()
+            .iter()
+            .map(|event| format!("  {event}\n"))
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<CapturedEvent>> {
+        // A test panicking while holding the lock leaves the events intact.
+        self.events.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+struct CaptureLayer {
+    events: Arc<Mutex<Vec<CapturedEvent>>>,
+}
+
+impl<S> Layer<S> for CaptureLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        span.extensions_mut().insert(CapturedSpan {
+            name: attrs.metadata().name(),
+            fields: visitor.fields,
+        });
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(captured) = extensions.get_mut::<CapturedSpan>() {
+            let mut visitor = FieldVisitor {
+                fields: std::mem::take(&mut captured.fields),
+                message: None,
+            };
+            values.record(&mut visitor);
+            captured.fields = visitor.fields;
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let spans = ctx
+            .event_scope(event)
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+            .filter_map(|span| span.extensions().get::<CapturedSpan>().cloned())
+            .collect();
+        let metadata = event.metadata();
+        self.events
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(CapturedEvent {
+                level: *metadata.level(),
+                target: metadata.target(),
+                message: visitor.message,
+                fields: visitor.fields,
+                spans,
+            });
+    }
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    fields: BTreeMap<&'static str, String>,
+    message: Option<String>,
+}
+
+impl FieldVisitor {
+    fn insert(&mut self, field: &Field, value: String) {
+        if field.name() == "message" {
+            self.message = Some(value);
+        } else {
+            self.fields.insert(field.name(), value);
+        }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{value:?}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_and_span_fields_are_captured() {
+        let capture = LogCapture::new();
+        tracing::subscriber::with_default(capture.subscriber(), || {
+            let span = tracing::info_span!("request", id = 1, user = tracing::field::Empty);
+            let _entered = span.enter();
+            span.record("user", "dave");
+            tracing::debug!(
+                bytes = 12_u64,
+                ok = true,
+                name = "dave",
+                "Loaded {}",
+                "profile"
+            );
+            tracing::error!(error_id = %"e-1", error = ?"boom");
+        });
+
+        let event = capture.assert_event(
+            &ExpectedEvent::new(Level::DEBUG)
+                .message("Loaded profile")
+                .field_eq("bytes", "12")
+                .field_eq("ok", "true")
+                .field_eq("name", "dave")
+                .span_field_eq("request", "user", "dave"),
+        );
+        assert_eq!(event.target, module_path!());
+        assert_eq!(event.span("request").unwrap().fields["id"], "1");
+        assert_eq!(
+            event.to_string(),
+            format!(
+                "DEBUG {}: request{{id=1 user=dave}}: Loaded profile bytes=12 name=dave ok=true",
+                module_path!()
+            )
+        );
+
+        let error = capture.assert_event(&ExpectedEvent::new(Level::ERROR).field("error_id"));
+        assert_eq!(error.field("error_id"), Some("e-1"));
+        assert_eq!(error.field("error"), Some("\"boom\""));
+        assert_eq!(error.message, None);
+        capture.assert_no_event(&ExpectedEvent::new(Level::INFO));
+        capture.assert_no_event(&ExpectedEvent::new(Level::DEBUG).in_span("response"));
+
+        capture.clear();
+        assert!(capture.events().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "no event matches")]
+    fn test_missing_events_fail_the_assertion() {
+        let capture = LogCapture::new();
+        let _guard = capture.set_default();
+        tracing::info!(user_id = 1, "Profile created");
+
+        capture.assert_event(&ExpectedEvent::new(Level::INFO).field_eq("user_id", "2"));
+    }
+}
@@ -0,0 +1,123 @@
+//! [`LogFilters`], changing what the sinks log while the program runs.
+
+use std::sync::Arc;
+
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::TracingError;
+
+pub(crate) type FilterHandle = reload::Handle<EnvFilter, Registry>;
+
+/// The `EnvFilter` of every sink and of the OTLP exporter, by name. Clones share the filters, so
+/// one kept in the app state changes what the global subscriber logs.
+#[derive(Debug, Clone, Default)]
+pub struct LogFilters {
+    filters: Arc<Vec<(String, FilterHandle)>>,
+}
+
+/// The directives a sink currently logs with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SinkFilter {
+    pub sink: String,
+    pub directives: String,
+}
+
+impl LogFilters {
+    pub(crate) fn new(filters: Vec<(String, FilterHandle)>) -> Self {
+        Self { filters: Arc::new(filters) }
+    }
+
+    /// Every sink in the order they were added, the exporter last.
+    pub fn list(&self) -> Vec<SinkFilter> {
+        self.filters
+            .iter()
+            .filter_map(|(sink, handle)| {
+                Some(SinkFilter {
+                    sink: sink.clone(),
+                    directives: handle.with_current(ToString::to_string).ok()?,
+                })
+            })
+            .collect()
+    }
+
+    pub fn get(&self, sink: &str) -> Option<SinkFilter> {
+        self.list().into_iter().find(|filter| filter.sink == sink)
+    }
+
+    /// Replaces the directives of `sink`, returning the ones it had. Spans already open keep the
+    /// decision made when they were created.
+    pub fn set(&self, sink: &str, directives: &str) -> Result<SinkFilter, TracingError> {
+        let (_, handle) = self
+            .filters
+            .iter()
+            .find(|(name, _)| name == sink)
+            .ok_or_else(|| TracingError::UnknownSink(sink.to_string()))?;
+        let filter =
+            EnvFilter::try_new(directives).map_err(|source| TracingError::InvalidFilter {
+                sink: sink.to_string(),
+                directive: directives.to_string(),
+                source,
+            })?;
+        let previous = handle
+            .with_current(ToString::to_string)
+            .map_err(|err| TracingError::Reload(err.to_string()))?;
+        handle
+            .reload(filter)
+            .map_err(|err| TracingError::Reload(err.to_string()))?;
+        Ok(SinkFilter {
+            sink: sink.to_string(),
+            directives: previous,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tracing::{Event, Subscriber};
+    use tracing_subscriber::{
+        layer::{Context, SubscriberExt},
+        Layer,
+    };
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct CountingLayer(Arc<AtomicUsize>);
+
+    impl<S: Subscriber> Layer<S> for CountingLayer {
+        fn on_event(&self, _event: &Event<'_>, _ctx: Context<'_, S>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_directives_are_changed_at_runtime() {
+        let (filter, handle) = reload::Layer::new(EnvFilter::new("info"));
+        let counter = CountingLayer::default();
+        let subscriber = tracing_subscriber::registry().with(counter.clone().with_filter(filter));
+        let filters = LogFilters::new(vec![("stdout".to_string(), handle)]);
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!("Hidden");
+            let previous = filters.set("stdout", "info,tracing_config=debug").unwrap();
+            assert_eq!(previous.directives, "info");
+            tracing::debug!("Shown");
+            assert_eq!(
+                filters.get("stdout").unwrap().directives,
+                "tracing_config=debug,info"
+            );
+        });
+        assert_eq!(counter.0.load(Ordering::Relaxed), 1);
+
+        assert!(matches!(
+            filters.set("stdout", "info,tracing_config=loud"),
+            Err(TracingError::InvalidFilter { .. })
+        ));
+        assert!(matches!(
+            filters.set("file", "info"),
+            Err(TracingError::UnknownSink(sink)) if sink == "file"
+        ));
+    }
+}
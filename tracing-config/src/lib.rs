@@ -1,75 +1,135 @@
-use std::io;
-use tracing::info;
-use tracing_appender::{
-    non_blocking,
-    non_blocking::WorkerGuard,
-    rolling::{RollingFileAppender, Rotation},
-};
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+pub mod builder;
+#[cfg(any(test, feature = "testing"))]
+pub mod capture;
+pub mod filters;
+pub mod otlp;
+pub mod redaction;
+pub mod rolling;
 
-pub fn init_tracing() -> Vec<WorkerGuard> {
-    let mut guards = Vec::new();
+pub use builder::{Format, Rotation, Sink, TracingConfig, TracingError};
+pub use filters::{LogFilters, SinkFilter};
+use std::{path::PathBuf, time::Duration};
 
-    // Determine if we are in development or production
-    let is_development =
-        std::env::var("RUST_ENV").unwrap_or_else(|_| "development".to_string()) == "development";
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use otlp::OtlpSettings;
+pub use redaction::Redaction;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::EnvFilter;
 
-    // Create daily rolling file appender
-    let file_appender = RollingFileAppender::new(Rotation::DAILY, "logs", "app.jsonl");
-    let (file_writer, file_guard) = non_blocking(file_appender);
-    guards.push(file_guard);
-
-    // Create stdout writer for info and below
-    let (stdout_writer, stdout_guard) = non_blocking(io::stdout());
-    guards.push(stdout_guard);
+/// Log levels used by the tracing sinks, resolved by the caller before initialization.
+#[derive(Debug, Clone)]
+pub struct TracingSettings {
+    pub development: bool,
+    pub stdout_level: String,
+    pub file_level: String,
+    /// Spans are also exported to an OpenTelemetry collector when set.
+    pub otlp: Option<OtlpSettings>,
+    pub redaction: Redaction,
+    pub files: LogFileSettings,
+}
 
-    // Create stderr writer for warn and error
-    let (stderr_writer, stderr_guard) = non_blocking(io::stderr());
-    guards.push(stderr_guard);
+/// Where the json log files go and how long they are kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFileSettings {
+    pub directory: PathBuf,
+    pub rotation: Rotation,
+    /// A file reaching this size is continued in a new one before its period ends.
+    pub max_bytes: Option<u64>,
+    /// Rotated files kept per sink, the oldest are deleted first.
+    pub max_files: Option<usize>,
+    pub max_age: Option<Duration>,
+    /// Rotated files are gzipped.
+    pub compress: bool,
+    /// `error` events are also written alone to `errors.jsonl` files.
+    pub error_file: bool,
+}
 
-    // Get stdout log level from environment, default to "info"
-    let stdout_level = std::env::var("RUST_LOG_STDOUT").unwrap_or_else(|_| {
-        if is_development {
-            "trace".to_string()
-        } else {
-            "info".to_string()
+impl Default for LogFileSettings {
+    /// Daily files in `logs`, rolled over at 100 MiB, gzipped and kept for 14 rotations, with the
+    /// error file.
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("logs"),
+            rotation: Rotation::Daily,
+            max_bytes: Some(100 * 1024 * 1024),
+            max_files: Some(14),
+            max_age: None,
+            compress: true,
+            error_file: true,
         }
-    });
+    }
+}
 
-    // Get file log level from environment, default to 'trace' in 'dev' and 'info' in production
-    let file_level = std::env::var("RUST_LOG_FILE").unwrap_or_else(|_| {
-        if is_development {
-            "trace".to_string()
-        } else {
-            "info".to_string()
+impl TracingSettings {
+    /// Reads `RUST_ENV`, `RUST_LOG_STDOUT` and `RUST_LOG_FILE`, defaulting to `trace` in
+    /// development and `info` otherwise, and the exporter from [`OtlpSettings::from_env`].
+    pub fn from_env() -> Self {
+        let development = std::env::var("RUST_ENV").unwrap_or_else(|_| "development".to_string())
+            == "development";
+        let default_level = if development { "trace" } else { "info" };
+
+        Self {
+            development,
+            stdout_level: std::env::var("RUST_LOG_STDOUT")
+                .unwrap_or_else(|_| default_level.to_string()),
+            file_level: std::env::var("RUST_LOG_FILE")
+                .unwrap_or_else(|_| default_level.to_string()),
+            otlp: OtlpSettings::from_env(),
+            redaction: Redaction::default(),
+            files: LogFileSettings::default(),
         }
-    });
+    }
+
+    /// Checks that both levels are valid `EnvFilter` directives, the redaction patterns and the
+    /// exporter settings.
+    pub fn validate(&self) -> Result<(), String> {
+        EnvFilter::try_new(&self.stdout_level)
+            .map_err(|err| format!("invalid stdout log level `{}`: {err}", self.stdout_level))?;
+        EnvFilter::try_new(&self.file_level)
+            .map_err(|err| format!("invalid file log level `{}`: {err}", self.file_level))?;
+        self.redaction.validate().map_err(|err| err.to_string())?;
+        self.otlp.as_ref().map_or(Ok(()), OtlpSettings::validate)
+    }
+}
 
-    // Configure the subscriber with multiple layers
-    tracing_subscriber::registry()
-        .with(
-            // File layer - ALL levels including trace and debug
-            fmt::layer()
-                .json()
-                .with_writer(file_writer)
-                .with_ansi(false) // No ANSI colors in files
-                .with_filter(EnvFilter::new(&file_level)),
-        )
-        .with(
-            // Stdout layer - configurable via environment
-            fmt::layer()
-                .with_writer(stdout_writer)
-                .with_filter(EnvFilter::new(&stdout_level)),
-        )
-        .with(
-            // Stderr layer - warn and error only
-            fmt::layer()
-                .with_writer(stderr_writer)
-                .with_filter(EnvFilter::new("warn")),
-        )
-        .init();
+/// Keeps the sinks running, to be held until the program exits. Dropping it flushes the buffered
+/// log lines and exports the spans not sent yet.
+#[derive(Debug)]
+pub struct TracingGuard {
+    _workers: Vec<WorkerGuard>,
+    tracer_provider: Option<SdkTracerProvider>,
+    filters: LogFilters,
+}
+
+impl TracingGuard {
+    /// The filters of the sinks, to change their directives while running.
+    pub fn filters(&self) -> &LogFilters {
+        &self.filters
+    }
+}
 
-    info!(file_log_level = %file_level, stdout_log_level = %stdout_level, development = %is_development);
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.tracer_provider.take() {
+            if let Err(err) = provider.shutdown() {
+                eprintln!("failed to export the remaining spans: {err}");
+            }
+        }
+    }
+}
+
+/// Sets up the sinks of [`TracingConfig::from_settings`] for the levels in the environment, see
+/// [`TracingSettings::from_env`].
+///
+/// Panics when a global subscriber is already set, use [`TracingConfig::try_init`] instead where
+/// that can happen.
+pub fn init_tracing() -> TracingGuard {
+    init_tracing_with(&TracingSettings::from_env())
+}
 
-    guards
+/// Sets up the sinks of [`TracingConfig::from_settings`], panicking like [`init_tracing`].
+pub fn init_tracing_with(settings: &TracingSettings) -> TracingGuard {
+    TracingConfig::from_settings(settings)
+        .try_init()
+        .unwrap_or_else(|err| panic!("failed to set up tracing: {err}"))
 }
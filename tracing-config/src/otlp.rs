@@ -0,0 +1,201 @@
+//! Export of spans to an OpenTelemetry collector over OTLP/HTTP.
+//!
+//! Trace context travels in W3C `traceparent` headers: the propagator set up here is the one
+//! `tracing-actix-web` reads incoming requests' parents from, and [`trace_context_headers`] gives
+//! the headers to send along with outgoing requests.
+
+use std::collections::HashMap;
+
+use opentelemetry::{global, trace::TracerProvider};
+use opentelemetry_otlp::{Protocol, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    trace::{Sampler, SdkTracerProvider, Tracer},
+    Resource,
+};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Where and how much to export.
+#[derive(Debug, Clone)]
+pub struct OtlpSettings {
+    /// Base url of the collector's OTLP/HTTP receiver, like `http://localhost:4318`. Spans are
+    /// posted to `{endpoint}/v1/traces`.
+    pub endpoint: String,
+    pub service_name: String,
+    /// Share of new traces exported, from 0 to 1. Traces continued from a `traceparent` follow
+    /// the caller's sampling decision instead.
+    pub sampling_ratio: f64,
+}
+
+impl OtlpSettings {
+    /// Reads the standard `OTEL_EXPORTER_OTLP_ENDPOINT`, `OTEL_SERVICE_NAME` and
+    /// `OTEL_TRACES_SAMPLER_ARG`, exporting nothing without an endpoint.
+    pub fn from_env() -> Option<Self> {
+        let endpoint = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok()?;
+        Some(Self {
+            endpoint,
+            service_name: std::env::var("OTEL_SERVICE_NAME")
+                .unwrap_or_else(|_| env!("CARGO_PKG_NAME").to_string()),
+            sampling_ratio: std::env::var("OTEL_TRACES_SAMPLER_ARG")
+                .ok()
+                .and_then(|ratio| ratio.parse().ok())
+                .unwrap_or(1.0),
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.endpoint.starts_with("http://") && !self.endpoint.starts_with("https://") {
+            return Err(format!(
+                "otlp endpoint `{}` must be an http(s) url",
+                self.endpoint
+            ));
+        }
+        if self.service_name.trim().is_empty() {
+            return Err("otlp service name must not be empty".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.sampling_ratio) {
+            return Err(format!(
+                "otlp sampling ratio {} must be between 0 and 1",
+                self.sampling_ratio
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Builds the provider exporting in batches from its own thread, and makes W3C trace context
+/// the global propagator.
+pub(crate) fn tracer_provider(settings: &OtlpSettings) -> Result<SdkTracerProvider, String> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_protocol(Protocol::HttpBinary)
+        .with_endpoint(format!(
+            "{}/v1/traces",
+            settings.endpoint.trim_end_matches('/')
+        ))
+        .build()
+        .map_err(|err| format!("failed to build the otlp exporter: {err}"))?;
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    Ok(SdkTracerProvider::builder()
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            settings.sampling_ratio,
+        ))))
+        .with_resource(
+            Resource::builder()
+                .with_service_name(settings.service_name.clone())
+                .build(),
+        )
+        .with_batch_exporter(exporter)
+        .build())
+}
+
+pub(crate) fn tracer(provider: &SdkTracerProvider, settings: &OtlpSettings) -> Tracer {
+    provider.tracer(settings.service_name.clone())
+}
+
+/// The `traceparent` (and `tracestate`) headers continuing the trace of the current span. Empty
+/// while nothing is exported, since there is no trace to continue then.
+pub fn trace_context_headers() -> Vec<(String, String)> {
+    let context = tracing::Span::current().context();
+    let mut headers = HashMap::new();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut headers));
+    headers
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    /// Answers one OTLP request like a collector would and hands back its head and body.
+    fn collector_stand_in() -> (String, thread::JoinHandle<(String, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = String::new();
+            while !head.ends_with("\r\n\r\n") {
+                reader.read_line(&mut head).unwrap();
+            }
+            let length = head
+                .lines()
+                .find_map(|line| {
+                    line.to_lowercase()
+                        .strip_prefix("content-length: ")
+                        .map(str::to_string)
+                })
+                .and_then(|length| length.parse().ok())
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+            (head, body)
+        });
+        (endpoint, handle)
+    }
+
+    #[test]
+    fn test_spans_are_exported_and_propagated() {
+        let (endpoint, collector) = collector_stand_in();
+        let settings = OtlpSettings {
+            endpoint,
+            service_name: "otlp-test-service".to_string(),
+            sampling_ratio: 1.0,
+        };
+        let provider = tracer_provider(&settings).unwrap();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer(&provider, &settings)));
+
+        let headers = tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("exported_span").in_scope(trace_context_headers)
+        });
+        provider.shutdown().unwrap();
+
+        let [(name, traceparent)] = headers.as_slice() else {
+            panic!("expected only a traceparent: {headers:?}");
+        };
+        assert_eq!(name, "traceparent");
+        // Version, trace id, span id and the sampled flag.
+        assert!(traceparent.starts_with("00-") && traceparent.ends_with("-01"));
+
+        let (head, body) = collector.join().unwrap();
+        assert!(head.starts_with("POST /v1/traces HTTP/1.1"), "{head}");
+        assert!(head
+            .to_lowercase()
+            .contains("content-type: application/x-protobuf"));
+        let contains = |needle: &[u8]| body.windows(needle.len()).any(|window| window == needle);
+        assert!(contains(b"exported_span"));
+        assert!(contains(b"otlp-test-service"));
+    }
+
+    #[test]
+    fn test_settings_are_validated() {
+        let settings = OtlpSettings {
+            endpoint: "localhost:4318".to_string(),
+            service_name: "twitter-clone".to_string(),
+            sampling_ratio: 1.0,
+        };
+        assert!(settings.validate().unwrap_err().contains("http(s) url"));
+        let settings = OtlpSettings {
+            endpoint: "http://localhost:4318".to_string(),
+            sampling_ratio: -0.5,
+            ..settings
+        };
+        assert!(settings.validate().unwrap_err().contains("sampling ratio"));
+    }
+}
@@ -0,0 +1,511 @@
+//! [`Redaction`], keeping secrets and bulky values out of every sink.
+//!
+//! The sinks are wrapped in one layer that records the fields of each span and event before they
+//! see them. When something has to go, they get a copy with the same metadata and the cleaned up
+//! values instead, so filters, formats and the OTLP exporter all work as before.
+
+use std::{any::TypeId, borrow::Cow, fmt};
+
+use regex::{Captures, Regex};
+use sha2::{Digest, Sha256};
+use tracing::{
+    field::{display, DisplayValue, Field, Value, ValueSet, Visit},
+    span, Dispatch, Event, Metadata, Subscriber,
+};
+use tracing_subscriber::{filter::LevelFilter, layer::Context, registry::LookupSpan, Layer};
+
+use crate::TracingError;
+
+/// Fields whose name contains one of these are redacted by default.
+pub const DEFAULT_REDACTED_FIELDS: [&str; 7] = [
+    "password",
+    "token",
+    "secret",
+    "authorization",
+    "cookie",
+    "api_key",
+    "private_key",
+];
+/// Bearer credentials and PEM private keys, wherever they show up in a value.
+const DEFAULT_PATTERNS: [&str; 2] = [
+    r"(?i)\bbearer\s+[a-z0-9._~+/=-]+",
+    r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----",
+];
+const REDACTED: &str = "[redacted]";
+/// `Debug` output of a byte buffer, like `[137, 80, 78, 71, ...]`, from 16 bytes on.
+const BYTE_LIST: &str = r"\[(?:\d{1,3}, ){15,}\d{1,3}\]";
+/// Most fields a callsite can have, the `tracing` macros don't take more.
+const MAX_FIELDS: usize = 32;
+
+/// What is redacted from span and event fields before any sink sees them.
+///
+/// Byte buffers, recorded as bytes or printed as a list in `Debug` output, are always replaced by
+/// their length and the start of their SHA-256 hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redaction {
+    fields: Vec<String>,
+    patterns: Vec<String>,
+}
+
+impl Default for Redaction {
+    /// The [`DEFAULT_REDACTED_FIELDS`], bearer credentials and private keys.
+    fn default() -> Self {
+        Self {
+            fields: DEFAULT_REDACTED_FIELDS.map(str::to_string).to_vec(),
+            patterns: DEFAULT_PATTERNS.map(str::to_string).to_vec(),
+        }
+    }
+}
+
+impl Redaction {
+    /// Nothing redacted but byte buffers.
+    pub fn none() -> Self {
+        Self { fields: Vec::new(), patterns: Vec::new() }
+    }
+
+    /// Redacts fields whose name contains `name`, ignoring case, and the struct fields named so
+    /// in `Debug` output.
+    pub fn field(mut self, name: impl Into<String>) -> Self {
+        self.fields.push(name.into().to_lowercase());
+        self
+    }
+
+    /// Redacts the matches of a regular expression in any field value.
+    pub fn pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.patterns.push(pattern.into());
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), TracingError> {
+        self.compile().map(|_| ())
+    }
+
+    pub(crate) fn compile(&self) -> Result<Redactor, TracingError> {
+        let compile = |pattern: &str| {
+            Regex::new(pattern).map_err(|source| TracingError::InvalidPattern {
+                pattern: pattern.to_string(),
+                source,
+            })
+        };
+        let struct_fields = match self.fields.is_empty() {
+            true => None,
+            false => Some(compile(&format!(
+                r#"(?i)\b(\w*(?:{})\w*: (?:Some\()?)"(?:[^"\\]|\\.)*""#,
+                self.fields
+                    .iter()
+                    .map(|field| regex::escape(field))
+                    .collect::<Vec<_>>()
+                    .join("|")
+            ))?),
+        };
+        Ok(Redactor {
+            fields: self.fields.clone(),
+            struct_fields,
+            patterns: self
+                .patterns
+                .iter()
+                .map(|pattern| compile(pattern))
+                .collect::<Result<_, _>>()?,
+            byte_list: compile(BYTE_LIST)?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Redactor {
+    fields: Vec<String>,
+    struct_fields: Option<Regex>,
+    patterns: Vec<Regex>,
+    byte_list: Regex,
+}
+
+impl Redactor {
+    fn redacts(&self, field: &Field) -> bool {
+        let name = field.name().to_lowercase();
+        self.fields.iter().any(|redacted| name.contains(redacted))
+    }
+
+    fn redact_text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for pattern in &self.patterns {
+            if let Cow::Owned(redacted) = pattern.replace_all(&text, REDACTED) {
+                text = Cow::Owned(redacted);
+            }
+        }
+        if let Some(struct_fields) = &self.struct_fields {
+            if let Cow::Owned(redacted) =
+                struct_fields.replace_all(&text, format!("$1\"{REDACTED}\""))
+            {
+                text = Cow::Owned(redacted);
+            }
+        }
+        if let Cow::Owned(summarized) =
+            self.byte_list
+                .replace_all(&text, |captures: &Captures<'_>| {
+                    let list = &captures[0];
+                    list[1..list.len() - 1]
+                        .split(", ")
+                        .map(str::parse::<u8>)
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_or_else(|_| list.to_string(), |bytes| summarize(&bytes))
+                })
+        {
+            text = Cow::Owned(summarized);
+        }
+        text
+    }
+}
+
+/// Length and the first 8 bytes of the SHA-256 hash, enough to tell two buffers apart.
+fn summarize(bytes: &[u8]) -> String {
+    let hash = Sha256::digest(bytes);
+    let hash: String = hash[..8].iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("<{} bytes, sha256 {hash}>", bytes.len())
+}
+
+/// A field value kept to be recorded again.
+enum Recorded {
+    I64(i64),
+    U64(u64),
+    I128(i128),
+    U128(u128),
+    F64(f64),
+    Bool(bool),
+    Str(String),
+    /// `Debug` output and replacements, written as they are.
+    Text(DisplayValue<String>),
+}
+
+impl Recorded {
+    fn as_value(&self) -> &dyn Value {
+        match self {
+            Recorded::I64(value) => value,
+            Recorded::U64(value) => value,
+            Recorded::I128(value) => value,
+            Recorded::U128(value) => value,
+            Recorded::F64(value) => value,
+            Recorded::Bool(value) => value,
+            Recorded::Str(value) => value,
+            Recorded::Text(value) => value,
+        }
+    }
+}
+
+struct RedactingVisitor<'r> {
+    redactor: &'r Redactor,
+    fields: Vec<(Field, Recorded)>,
+    changed: bool,
+}
+
+impl<'r> RedactingVisitor<'r> {
+    fn new(redactor: &'r Redactor) -> Self {
+        Self {
+            redactor,
+            fields: Vec::new(),
+            changed: false,
+        }
+    }
+
+    fn push(&mut self, field: &Field, value: Recorded) {
+        if self.redactor.redacts(field) {
+            self.changed = true;
+            self.fields
+                .push((field.clone(), Recorded::Text(display(REDACTED.to_string()))));
+        } else {
+            self.fields.push((field.clone(), value));
+        }
+    }
+
+    fn push_text(&mut self, field: &Field, text: &str, as_str: bool) {
+        let text = match self.redactor.redact_text(text) {
+            Cow::Borrowed(text) => text.to_string(),
+            Cow::Owned(text) => {
+                self.changed = true;
+                text
+            },
+        };
+        match as_str {
+            true => self.push(field, Recorded::Str(text)),
+            false => self.push(field, Recorded::Text(display(text))),
+        }
+    }
+
+    /// Gives `record` the values as they should be logged, when they differ from the originals.
+    fn with_values<R>(
+        &self,
+        metadata: &'static Metadata<'static>,
+        record: impl FnOnce(&ValueSet<'_>) -> R,
+    ) -> Option<R> {
+        let (first, _) = self.fields.first()?;
+        if !self.changed || self.fields.len() > MAX_FIELDS {
+            return None;
+        }
+        // Value sets are built from arrays, the unused entries repeat a field without a value.
+        let values: [(&Field, Option<&dyn Value>); MAX_FIELDS] =
+            std::array::from_fn(|index| match self.fields.get(index) {
+                Some((field, value)) => (field, Some(value.as_value())),
+                None => (first, None),
+            });
+        Some(record(&metadata.fields().value_set(&values)))
+    }
+}
+
+impl Visit for RedactingVisitor<'_> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, Recorded::I64(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, Recorded::U64(value));
+    }
+
+    fn record_i128(&mut self, field: &Field, value: i128) {
+        self.push(field, Recorded::I128(value));
+    }
+
+    fn record_u128(&mut self, field: &Field, value: u128) {
+        self.push(field, Recorded::U128(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, Recorded::F64(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, Recorded::Bool(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push_text(field, value, true);
+    }
+
+    fn record_bytes(&mut self, field: &Field, value: &[u8]) {
+        self.changed = true;
+        self.push(field, Recorded::Text(display(summarize(value))));
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.push_text(field, &value.to_string(), false);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.push_text(field, &format!("{value:?}"), false);
+    }
+}
+
+/// Hands `inner` spans and events with their fields redacted.
+pub(crate) struct Redacting<L> {
+    inner: L,
+    redactor: Redactor,
+}
+
+impl<L> Redacting<L> {
+    pub(crate) fn new(inner: L, redactor: Redactor) -> Self {
+        Self { inner, redactor }
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>, L: Layer<S>> Layer<S> for Redacting<L> {
+    fn on_register_dispatch(&self, subscriber: &Dispatch) {
+        self.inner.on_register_dispatch(subscriber);
+    }
+
+    fn on_layer(&mut self, subscriber: &mut S) {
+        self.inner.on_layer(subscriber);
+    }
+
+    fn register_callsite(
+        &self,
+        metadata: &'static Metadata<'static>,
+    ) -> tracing::subscriber::Interest {
+        self.inner.register_callsite(metadata)
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, S>) -> bool {
+        self.inner.enabled(metadata, ctx)
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        self.inner.max_level_hint()
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let mut visitor = RedactingVisitor::new(&self.redactor);
+        attrs.record(&mut visitor);
+        let metadata = attrs.metadata();
+        let redacted = visitor.with_values(metadata, |values| {
+            let attrs = if attrs.is_contextual() {
+                span::Attributes::new(metadata, values)
+            } else if let Some(parent) = attrs.parent() {
+                span::Attributes::child_of(parent.clone(), metadata, values)
+            } else {
+                span::Attributes::new_root(metadata, values)
+            };
+            self.inner.on_new_span(&attrs, id, ctx.clone());
+        });
+        if redacted.is_none() {
+            self.inner.on_new_span(attrs, id, ctx);
+        }
+    }
+
+    fn on_record(&self, span: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let mut visitor = RedactingVisitor::new(&self.redactor);
+        values.record(&mut visitor);
+        let Some(metadata) = ctx.metadata(span) else {
+            return self.inner.on_record(span, values, ctx);
+        };
+        let redacted = visitor.with_values(metadata, |values| {
+            self.inner
+                .on_record(span, &span::Record::new(values), ctx.clone());
+        });
+        if redacted.is_none() {
+            self.inner.on_record(span, values, ctx);
+        }
+    }
+
+    fn on_follows_from(&self, span: &span::Id, follows: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_follows_from(span, follows, ctx);
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, ctx: Context<'_, S>) -> bool {
+        self.inner.event_enabled(event, ctx)
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = RedactingVisitor::new(&self.redactor);
+        event.record(&mut visitor);
+        let metadata = event.metadata();
+        let redacted = visitor.with_values(metadata, |values| {
+            let event = match event.is_contextual() {
+                true => Event::new(metadata, values),
+                false => Event::new_child_of(event.parent().cloned(), metadata, values),
+            };
+            self.inner.on_event(&event, ctx.clone());
+        });
+        if redacted.is_none() {
+            self.inner.on_event(event, ctx);
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_enter(id, ctx);
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_exit(id, ctx);
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        self.inner.on_close(id, ctx);
+    }
+
+    fn on_id_change(&self, old: &span::Id, new: &span::Id, ctx: Context<'_, S>) {
+        self.inner.on_id_change(old, new, ctx);
+    }
+
+    // Lets the subscriber find the sinks' per-layer filters and the OTLP layer's context through
+    // it. Only forwards to `inner`, which is what makes it unsafe.
+    #[allow(unsafe_code)]
+    unsafe fn downcast_raw(&self, id: TypeId) -> Option<*const ()> {
+        if id == TypeId::of::<Self>() {
+            Some(std::ptr::from_ref(self).cast())
+        } else {
+            unsafe { self.inner.downcast_raw(id) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    /// Keeps the `Debug` output of every event's and new span's fields.
+    #[derive(Clone, Default)]
+    struct Lines(Arc<Mutex<Vec<String>>>);
+
+    struct LineVisitor(String);
+
+    impl Visit for LineVisitor {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.push_str(&format!("{}={value:?} ", field.name()));
+        }
+    }
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Lines {
+        fn on_new_span(&self, attrs: &span::Attributes<'_>, _id: &span::Id, _ctx: Context<'_, S>) {
+            let mut visitor = LineVisitor(String::new());
+            attrs.record(&mut visitor);
+            self.0.lock().unwrap().push(visitor.0);
+        }
+
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            let mut visitor = LineVisitor(String::new());
+            event.record(&mut visitor);
+            self.0.lock().unwrap().push(visitor.0);
+        }
+    }
+
+    #[derive(Debug)]
+    #[allow(dead_code)]
+    struct ProfileCreate {
+        user_name: String,
+        password: Option<String>,
+        avatar: Option<Vec<u8>>,
+    }
+
+    #[test]
+    fn test_fields_patterns_and_bytes_are_redacted() {
+        let lines = Lines::default();
+        let redactor = Redaction::default().field("email").compile().unwrap();
+        let subscriber =
+            tracing_subscriber::registry().with(Redacting::new(lines.clone(), redactor));
+        let avatar: Vec<u8> = (0..=255).collect();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let profile = ProfileCreate {
+                user_name: "dave".to_string(),
+                password: Some("hunter2".to_string()),
+                avatar: Some(avatar.clone()),
+            };
+            tracing::info_span!("insert_profile_inner", ?profile).in_scope(|| {
+                tracing::info!(
+                    user_id = 7,
+                    api_token = "abc",
+                    contact_email = "dave@example.com",
+                    header = "Bearer eyJhbGciOi.payload",
+                    "Profile created"
+                );
+            });
+            tracing::info!(avatar = avatar.as_slice(), "Avatar stored");
+            tracing::info!(user_id = 8, "Nothing to redact");
+        });
+
+        let lines = lines.0.lock().unwrap();
+        let summary = summarize(&avatar);
+        assert_eq!(
+            lines[0],
+            format!(
+                r#"profile=ProfileCreate {{ user_name: "dave", password: Some("[redacted]"), avatar: Some({summary}) }} "#
+            )
+        );
+        assert_eq!(
+            lines[1],
+            r#"message=Profile created user_id=7 api_token=[redacted] contact_email=[redacted] header="[redacted]" "#
+        );
+        assert_eq!(summary, "<256 bytes, sha256 40aff2e9d2d8922e>");
+        assert_eq!(lines[2], format!("message=Avatar stored avatar={summary} "));
+        assert_eq!(lines[3], "message=Nothing to redact user_id=8 ");
+    }
+
+    #[test]
+    fn test_invalid_patterns_are_reported() {
+        assert!(matches!(
+            Redaction::none().pattern("card=[0-9").validate(),
+            Err(TracingError::InvalidPattern { pattern, .. }) if pattern == "card=[0-9"
+        ));
+    }
+}
@@ -0,0 +1,417 @@
+//! Log files rotated by time and size, compressed and pruned once rotated.
+//!
+//! The file being written is `{prefix}.{period}`, or `{prefix}` without time rotation, named like
+//! `tracing-appender` names them. A file reaching its size limit is renamed to
+//! `{prefix}.{period}.{n}` and a new one started, so numbered files hold the earlier parts of a
+//! period. Rotated files, including those left uncompressed by an earlier run, are compressed to
+//! `.gz` and old ones deleted on a background thread, keeping the thread writing the logs free.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+
+use crate::Rotation;
+
+/// When a file sink's files are rotated and how long they are kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FilePolicy {
+    pub(crate) rotation: Rotation,
+    pub(crate) max_bytes: Option<u64>,
+    pub(crate) max_files: Option<usize>,
+    pub(crate) max_age: Option<Duration>,
+    pub(crate) compress: bool,
+}
+
+impl Default for FilePolicy {
+    /// Daily files kept forever, like `tracing-appender` does.
+    fn default() -> Self {
+        Self {
+            rotation: Rotation::Daily,
+            max_bytes: None,
+            max_files: None,
+            max_age: None,
+            compress: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct RollingFile {
+    directory: PathBuf,
+    prefix: String,
+    policy: FilePolicy,
+    file: Option<BufWriter<File>>,
+    /// Of the file being written, `None` without time rotation.
+    period: Option<String>,
+    written: u64,
+    /// Compressing and pruning after the last rotation.
+    housekeeping: Option<JoinHandle<()>>,
+}
+
+impl RollingFile {
+    /// Creates `directory` when missing, and compresses and prunes the files left by earlier runs.
+    /// The file is opened by the first write.
+    pub(crate) fn new(directory: PathBuf, prefix: String, policy: FilePolicy) -> io::Result<Self> {
+        fs::create_dir_all(&directory)?;
+        let mut rolling = Self {
+            directory,
+            prefix,
+            policy,
+            file: None,
+            period: None,
+            written: 0,
+            housekeeping: None,
+        };
+        rolling.period = rolling.period(Utc::now());
+        rolling.spawn_housekeeping();
+        Ok(rolling)
+    }
+
+    fn period(&self, now: DateTime<Utc>) -> Option<String> {
+        match self.policy.rotation {
+            Rotation::Hourly => Some(now.format("%Y-%m-%d-%H").to_string()),
+            Rotation::Daily => Some(now.format("%Y-%m-%d").to_string()),
+            Rotation::Never => None,
+        }
+    }
+
+    fn path(&self, period: Option<&str>) -> PathBuf {
+        match period {
+            Some(period) => self.directory.join(format!("{}.{period}", self.prefix)),
+            None => self.directory.join(&self.prefix),
+        }
+    }
+
+    /// Appends to the file of the period of `now`, like after a restart.
+    fn open(&mut self, now: DateTime<Utc>) -> io::Result<()> {
+        self.period = self.period(now);
+        let path = self.path(self.period.as_deref());
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        self.written = file.metadata()?.len();
+        self.file = Some(BufWriter::new(file));
+        Ok(())
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        match self.file.take() {
+            Some(mut file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn write_at(&mut self, buf: &[u8], now: DateTime<Utc>) -> io::Result<()> {
+        if self.file.is_none() {
+            self.open(now)?;
+        } else if self.period(now) != self.period {
+            self.close()?;
+            self.open(now)?;
+            self.spawn_housekeeping();
+        } else if self.policy.max_bytes.is_some_and(|max_bytes| {
+            self.written > 0 && self.written + buf.len() as u64 > max_bytes
+        }) {
+            self.close()?;
+            let active = self.path(self.period.as_deref());
+            fs::rename(&active, next_part(&active))?;
+            self.open(now)?;
+            self.spawn_housekeeping();
+        }
+
+        if let Some(file) = &mut self.file {
+            file.write_all(buf)?;
+            self.written += buf.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Compresses the rotated files not compressed yet, then applies the retention limits to every
+    /// rotated file. Runs after the previous housekeeping, so two never work on the same files.
+    /// What to compress is listed right away, before a later rotation changes the active file.
+    fn spawn_housekeeping(&mut self) {
+        let policy = self.policy.clone();
+        if !policy.compress && policy.max_files.is_none() && policy.max_age.is_none() {
+            return;
+        }
+        let active = self.path(self.period.as_deref());
+        let prefix = self.prefix.clone();
+        let uncompressed = if policy.compress {
+            uncompressed(&active, &prefix).unwrap_or_else(|err| {
+                eprintln!(
+                    "failed to list the log files next to {}: {err}",
+                    active.display()
+                );
+                Vec::new()
+            })
+        } else {
+            Vec::new()
+        };
+        let previous = self.housekeeping.take();
+        self.housekeeping = Some(thread::spawn(move || {
+            if let Some(previous) = previous {
+                let _ = previous.join();
+            }
+            for (modified, path) in uncompressed {
+                if let Err(err) = gzip(&path, modified) {
+                    eprintln!("failed to compress {}: {err}", path.display());
+                }
+            }
+            if let Err(err) = prune(&active, &prefix, &policy) {
+                eprintln!(
+                    "failed to delete old log files next to {}: {err}",
+                    active.display()
+                );
+            }
+        }));
+    }
+}
+
+impl Write for RollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_at(buf, Utc::now())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for RollingFile {
+    /// Waits for the compression of the last rotated files.
+    fn drop(&mut self) {
+        let _ = self.close();
+        if let Some(handle) = self.housekeeping.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// `{active}.{n}`, numbered after the parts already rotated.
+fn next_part(active: &Path) -> PathBuf {
+    let name = active
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let taken = active
+        .parent()
+        .and_then(|directory| fs::read_dir(directory).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let entry_name = entry.ok()?.file_name().to_string_lossy().into_owned();
+            let part = entry_name.strip_prefix(&format!("{name}."))?;
+            part.trim_end_matches(".gz").parse::<u32>().ok()
+        })
+        .max()
+        .unwrap_or(0);
+    active.with_file_name(format!("{name}.{}", taken + 1))
+}
+
+/// The files named `{prefix}.` and a date or part number next to `active`, other than `active`,
+/// with when they were last written.
+fn rotated_files(active: &Path, prefix: &str) -> io::Result<Vec<(SystemTime, PathBuf)>> {
+    let Some(directory) = active.parent() else {
+        return Ok(Vec::new());
+    };
+    let mut rotated = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let is_rotated = name
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_prefix('.'))
+            .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()));
+        if is_rotated && entry.path() != active {
+            rotated.push((entry.metadata()?.modified()?, entry.path()));
+        }
+    }
+    Ok(rotated)
+}
+
+/// The rotated files which aren't gzipped yet.
+fn uncompressed(active: &Path, prefix: &str) -> io::Result<Vec<(SystemTime, PathBuf)>> {
+    let mut rotated = rotated_files(active, prefix)?;
+    rotated.retain(|(_, path)| path.extension().is_none_or(|extension| extension != "gz"));
+    Ok(rotated)
+}
+
+/// Keeps `modified` on the compressed file, for `max_age` to count from the last write. A file
+/// already gone was compressed by the housekeeping before, which listed it too.
+fn gzip(path: &Path, modified: SystemTime) -> io::Result<()> {
+    let source = match File::open(path) {
+        Ok(source) => source,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    let mut compressed = path.as_os_str().to_owned();
+    compressed.push(".gz");
+    let mut encoder = GzEncoder::new(
+        BufWriter::new(File::create(&compressed)?),
+        Compression::default(),
+    );
+    io::copy(&mut BufReader::new(source), &mut encoder)?;
+    let file = encoder
+        .finish()?
+        .into_inner()
+        .map_err(|err| err.into_error())?;
+    file.set_modified(modified)?;
+    fs::remove_file(path)
+}
+
+/// Deletes the rotated files beyond `max_files`, newest kept, and those older than `max_age`.
+fn prune(active: &Path, prefix: &str, policy: &FilePolicy) -> io::Result<()> {
+    let mut rotated = rotated_files(active, prefix)?;
+    // Newest first, the later part first when written in the same instant.
+    rotated.sort_by(|a, b| b.cmp(a));
+
+    let now = SystemTime::now();
+    for (index, (modified, path)) in rotated.into_iter().enumerate() {
+        let too_many = policy.max_files.is_some_and(|max_files| index >= max_files);
+        let too_old = policy
+            .max_age
+            .is_some_and(|max_age| now.duration_since(modified).is_ok_and(|age| age > max_age));
+        if too_many || too_old {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use chrono::TimeZone;
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    fn file_names(directory: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// An empty directory of its own for each test.
+    fn temp_directory(test: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "tracing-config-rolling-{test}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    /// Writes `name` as if it was last written `age` ago.
+    fn write_aged(directory: &Path, name: &str, age: Duration) {
+        let file = File::create(directory.join(name)).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    #[test]
+    fn test_files_roll_over_by_size_and_day_and_are_pruned() {
+        let directory = temp_directory("size");
+        let policy = FilePolicy {
+            max_bytes: Some(20),
+            max_files: Some(1),
+            compress: true,
+            ..FilePolicy::default()
+        };
+        let day = |day| Utc.with_ymd_and_hms(2026, 10, day, 12, 0, 0).unwrap();
+
+        let mut file =
+            RollingFile::new(directory.clone(), "app.jsonl".to_string(), policy).unwrap();
+        file.write_at(b"first line 1234\n", day(18)).unwrap();
+        // Over 20 bytes with the first one, so it starts a second part.
+        file.write_at(b"second line 123\n", day(18)).unwrap();
+        file.write_at(b"third line\n", day(19)).unwrap();
+        drop(file);
+
+        // The first part of the 18th, `app.jsonl.2026-10-18.1.gz`, is past `max_files`.
+        assert_eq!(
+            file_names(&directory),
+            ["app.jsonl.2026-10-18.gz", "app.jsonl.2026-10-19"]
+        );
+        let mut second = String::new();
+        GzDecoder::new(File::open(directory.join("app.jsonl.2026-10-18.gz")).unwrap())
+            .read_to_string(&mut second)
+            .unwrap();
+        assert_eq!(second, "second line 123\n");
+        let today = fs::read_to_string(directory.join("app.jsonl.2026-10-19")).unwrap();
+        assert_eq!(today, "third line\n");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_files_roll_over_by_hour() {
+        let directory = temp_directory("hourly");
+        let policy = FilePolicy {
+            rotation: Rotation::Hourly,
+            ..FilePolicy::default()
+        };
+        let hour = |hour, minute| Utc.with_ymd_and_hms(2026, 10, 18, hour, minute, 0).unwrap();
+
+        let mut file =
+            RollingFile::new(directory.clone(), "app.jsonl".to_string(), policy).unwrap();
+        file.write_at(b"noon\n", hour(12, 0)).unwrap();
+        file.write_at(b"still noon\n", hour(12, 59)).unwrap();
+        file.write_at(b"one o'clock\n", hour(13, 0)).unwrap();
+        drop(file);
+
+        assert_eq!(
+            file_names(&directory),
+            ["app.jsonl.2026-10-18-12", "app.jsonl.2026-10-18-13"]
+        );
+        let noon = fs::read_to_string(directory.join("app.jsonl.2026-10-18-12")).unwrap();
+        assert_eq!(noon, "noon\nstill noon\n");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_leftover_files_are_compressed_and_old_ones_deleted() {
+        let directory = temp_directory("leftovers");
+        let day = Duration::from_secs(24 * 60 * 60);
+        // Left by a run which stopped before compressing them.
+        write_aged(&directory, "app.jsonl.2026-10-17", day);
+        write_aged(&directory, "app.jsonl.2026-10-17.1", day);
+        write_aged(&directory, "app.jsonl.2026-10-01.gz", 10 * day);
+        write_aged(&directory, "app.jsonl.2026-09-30", 11 * day);
+        write_aged(&directory, "other.jsonl.2026-09-30", 11 * day);
+        let policy = FilePolicy {
+            max_age: Some(7 * day),
+            compress: true,
+            ..FilePolicy::default()
+        };
+
+        drop(RollingFile::new(directory.clone(), "app.jsonl".to_string(), policy).unwrap());
+
+        assert_eq!(
+            file_names(&directory),
+            [
+                "app.jsonl.2026-10-17.1.gz",
+                "app.jsonl.2026-10-17.gz",
+                "other.jsonl.2026-09-30"
+            ]
+        );
+        // Compressing didn't make them any younger.
+        let modified = fs::metadata(directory.join("app.jsonl.2026-10-17.gz"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert!(SystemTime::now().duration_since(modified).unwrap() >= day);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,6 @@
+-- Add migration script here
+drop table if exists message_broadcast;
+drop table if exists message_response;
+drop table if exists message;
+drop table if exists follow;
+drop table if exists profile;
@@ -0,0 +1,3 @@
+-- Add migration script here
+drop table if exists circle_group_member;
+drop table if exists circle_group;
@@ -0,0 +1,2 @@
+-- Add migration script here
+alter table message drop column if exists "hidden_at";
@@ -0,0 +1,2 @@
+-- Add migration script here
+alter table message add column "hidden_at" timestamptz(3);
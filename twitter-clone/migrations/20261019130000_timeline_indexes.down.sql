@@ -0,0 +1,2 @@
+drop index if exists message_user_id_updated_at_idx;
+drop index if exists follow_follower_id_idx;
@@ -0,0 +1,4 @@
+drop index if exists follow_following_id_idx;
+drop index if exists message_not_fanned_out_idx;
+drop table if exists home_timeline;
+alter table message drop column if exists "fanned_out";
@@ -0,0 +1,21 @@
+-- Materialized home timelines, filled by the fan-out on write when `timeline.mode = "fanout"`.
+alter table message add column "fanned_out" boolean NOT NULL DEFAULT false;
+
+create table home_timeline (
+    "follower_id" bigint NOT NULL,
+    "message_id" bigint NOT NULL,
+    "author_id" bigint NOT NULL,
+    "updated_at" timestamptz(3) NOT NULL,
+
+    primary key (follower_id, message_id),
+    constraint fk_profile_follower foreign key(follower_id) references profile(id),
+    constraint fk_message foreign key(message_id) references message(id),
+    constraint fk_profile_author foreign key(author_id) references profile(id)
+);
+
+create index home_timeline_follower_id_updated_at_idx
+    on home_timeline (follower_id, updated_at desc, message_id desc);
+-- Messages merged at read time: not fanned out yet, or from accounts above the follower limit.
+create index message_not_fanned_out_idx on message (user_id, updated_at desc) where not fanned_out;
+-- Follower counts when deciding whether to fan out.
+create index follow_following_id_idx on follow (following_id);
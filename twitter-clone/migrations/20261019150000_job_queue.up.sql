@@ -0,0 +1,22 @@
+-- Background jobs, claimed by the workers with `for update skip locked`. Finished jobs are
+-- deleted, jobs out of attempts stay behind as `dead` until retried or purged.
+create table job (
+    "id" bigserial primary key,
+    "created_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "kind" varchar(100) NOT NULL,
+    "payload" jsonb NOT NULL DEFAULT '{}',
+    "status" varchar(20) NOT NULL DEFAULT 'queued',
+    "attempts" int NOT NULL DEFAULT 0,
+    "run_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "locked_until" timestamptz(3),
+    "last_error" text,
+    -- `request_id` of the http request that enqueued the job, copied onto the job's span.
+    "request_id" varchar(36),
+
+    constraint job_status_check check (status in ('queued', 'running', 'dead'))
+);
+
+create index job_queued_run_at_idx on job (run_at) where status = 'queued';
+-- Jobs whose worker died before finishing, reclaimed once their lease expires.
+create index job_running_locked_until_idx on job (locked_until) where status = 'running';
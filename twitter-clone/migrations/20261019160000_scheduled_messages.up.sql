@@ -0,0 +1,5 @@
+-- Messages posted with a `publish_at` in the future stay out of every query until then. Their
+-- `updated_at` is set to `publish_at`, so once published they sort as if posted at that time.
+alter table message add column "publish_at" timestamptz(3);
+
+create index message_scheduled_idx on message (user_id, publish_at) where publish_at is not null;
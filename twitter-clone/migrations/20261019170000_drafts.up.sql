@@ -0,0 +1,22 @@
+-- Unpublished messages kept per profile. A draft either broadcasts or answers another message,
+-- never both; the target is dropped from the draft if that message goes away.
+create table draft (
+    "id" bigserial primary key,
+    "created_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "user_id" bigint NOT NULL,
+    "body" varchar(140) NOT NULL,
+    "msg_group_type" int NOT NULL,
+    "broadcasting_msg_id" bigint,
+    "original_msg_id" bigint,
+
+    constraint fk_profile foreign key(user_id) references profile(id),
+    constraint fk_broadcasting_message foreign key(broadcasting_msg_id)
+        references message(id) on delete set null,
+    constraint fk_original_message foreign key(original_msg_id)
+        references message(id) on delete set null,
+    constraint draft_single_target_check
+        check (broadcasting_msg_id is null or original_msg_id is null)
+);
+
+create index draft_user_id_updated_at_idx on draft (user_id, updated_at desc);
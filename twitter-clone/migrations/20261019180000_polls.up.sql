@@ -0,0 +1,36 @@
+-- A poll attached to a message, with its options and one vote per profile. Polls go away with
+-- their message.
+create table poll (
+    "id" bigserial primary key,
+    "created_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "message_id" bigint NOT NULL,
+    "closes_at" timestamptz(3) NOT NULL,
+
+    constraint fk_message foreign key(message_id) references message(id) on delete cascade,
+    constraint poll_message_id_key unique (message_id)
+);
+
+create table poll_option (
+    "id" bigserial primary key,
+    "poll_id" bigint NOT NULL,
+    "position" int NOT NULL,
+    "label" varchar(25) NOT NULL,
+
+    constraint fk_poll foreign key(poll_id) references poll(id) on delete cascade,
+    constraint poll_option_poll_id_position_key unique (poll_id, position)
+);
+
+create table poll_vote (
+    "id" bigserial primary key,
+    "created_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "poll_id" bigint NOT NULL,
+    "option_id" bigint NOT NULL,
+    "user_id" bigint NOT NULL,
+
+    constraint fk_poll foreign key(poll_id) references poll(id) on delete cascade,
+    constraint fk_poll_option foreign key(option_id) references poll_option(id) on delete cascade,
+    constraint fk_profile foreign key(user_id) references profile(id),
+    constraint poll_vote_poll_id_user_id_key unique (poll_id, user_id)
+);
+
+create index poll_vote_option_id_idx on poll_vote (option_id);
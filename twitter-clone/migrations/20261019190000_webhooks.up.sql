@@ -0,0 +1,45 @@
+-- Outgoing webhook subscriptions. `secret` signs every delivery, `events` lists the event names
+-- the subscription receives, e.g. `message.created`.
+create table webhook (
+    "id" bigserial primary key,
+    "created_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "url" varchar(2048) NOT NULL,
+    "secret" varchar(200) NOT NULL,
+    "events" varchar(50)[] NOT NULL
+);
+
+-- One event for one subscription, sent by a `deliver_webhook` job. `status` is the outcome of the
+-- last attempt, every attempt is kept in `webhook_attempt`.
+create table webhook_delivery (
+    "id" bigserial primary key,
+    "created_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "webhook_id" bigint NOT NULL,
+    "event" varchar(50) NOT NULL,
+    "payload" jsonb NOT NULL,
+    "status" varchar(20) NOT NULL DEFAULT 'pending',
+    "attempts" int NOT NULL DEFAULT 0,
+
+    constraint fk_webhook foreign key(webhook_id) references webhook(id) on delete cascade,
+    constraint webhook_delivery_status_check check (status in ('pending', 'delivered', 'failed'))
+);
+
+create index webhook_delivery_webhook_id_idx on webhook_delivery (webhook_id, id desc);
+
+create table webhook_attempt (
+    "id" bigserial primary key,
+    "created_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "delivery_id" bigint NOT NULL,
+    -- Unset when no response came back, `error` says why.
+    "status_code" int,
+    "error" text,
+    -- The start of the response body, for debugging the receiving end.
+    "response_body" text,
+    "duration_ms" bigint NOT NULL,
+
+    constraint fk_webhook_delivery foreign key(delivery_id)
+        references webhook_delivery(id) on delete cascade
+);
+
+create index webhook_attempt_delivery_id_idx on webhook_attempt (delivery_id);
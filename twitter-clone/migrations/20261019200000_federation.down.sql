@@ -0,0 +1,5 @@
+drop table if exists remote_reaction;
+drop table if exists remote_note;
+drop table if exists remote_following;
+drop table if exists remote_follower;
+drop table if exists actor_key;
@@ -0,0 +1,67 @@
+-- RSA key pair of a local profile, signing the activities it sends. Created on first use.
+create table actor_key (
+    "profile_id" bigint primary key,
+    "created_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "public_key_pem" text NOT NULL,
+    "private_key_pem" text NOT NULL,
+
+    constraint fk_profile foreign key(profile_id) references profile(id) on delete cascade
+);
+
+-- Remote actors following a local profile. New public messages of the profile are delivered to
+-- their `inbox`.
+create table remote_follower (
+    "id" bigserial primary key,
+    "created_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "profile_id" bigint NOT NULL,
+    "actor_id" varchar(2048) NOT NULL,
+    "inbox" varchar(2048) NOT NULL,
+    "follow_activity_id" varchar(2048) NOT NULL,
+
+    constraint fk_profile foreign key(profile_id) references profile(id) on delete cascade,
+    constraint remote_follower_unique unique (profile_id, actor_id)
+);
+
+-- Remote actors a local profile follows, `accepted` once their `Accept` came back.
+create table remote_following (
+    "id" bigserial primary key,
+    "created_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "profile_id" bigint NOT NULL,
+    "actor_id" varchar(2048) NOT NULL,
+    "inbox" varchar(2048) NOT NULL,
+    "follow_activity_id" varchar(2048) NOT NULL,
+    "accepted" boolean NOT NULL DEFAULT false,
+
+    constraint fk_profile foreign key(profile_id) references profile(id) on delete cascade,
+    constraint remote_following_unique unique (profile_id, actor_id)
+);
+
+create index remote_following_actor_id_idx on remote_following (actor_id);
+
+-- Notes created by remote actors, as delivered to a local inbox. `content` is the sender's html.
+create table remote_note (
+    "id" bigserial primary key,
+    "created_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "ap_id" varchar(2048) NOT NULL unique,
+    "actor_id" varchar(2048) NOT NULL,
+    "content" text NOT NULL,
+    "in_reply_to" varchar(2048),
+    "published" timestamptz(3) NOT NULL
+);
+
+create index remote_note_actor_id_idx on remote_note (actor_id, id desc);
+
+-- `Like` and `Announce` activities of remote actors on local messages, removed by their `Undo`.
+create table remote_reaction (
+    "id" bigserial primary key,
+    "created_at" timestamptz(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "activity_id" varchar(2048) NOT NULL unique,
+    "kind" varchar(10) NOT NULL,
+    "message_id" bigint NOT NULL,
+    "actor_id" varchar(2048) NOT NULL,
+
+    constraint fk_message foreign key(message_id) references message(id) on delete cascade,
+    constraint remote_reaction_kind_check check (kind in ('like', 'announce')),
+    constraint remote_reaction_unique unique (kind, message_id, actor_id)
+);
@@ -0,0 +1,7 @@
+drop table if exists circle_group_member;
+drop table if exists circle_group;
+drop table if exists message_broadcast;
+drop table if exists message_response;
+drop table if exists message;
+drop table if exists follow;
+drop table if exists profile;
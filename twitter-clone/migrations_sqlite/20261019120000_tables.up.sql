@@ -0,0 +1,65 @@
+-- SQLite version of the postgres migrations up to `message_hidden`.
+-- Timestamps are stored as RFC 3339 text in UTC with millisecond precision, like
+-- `2026-10-19T12:00:00.000Z`, so they compare and sort as plain strings.
+create table profile (
+    "id" integer primary key autoincrement,
+    "created_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "updated_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "user_name" text NOT NULL check (length("user_name") <= 50),
+    "full_name" text NOT NULL check (length("full_name") <= 100),
+    "description" text NOT NULL check (length("description") <= 250),
+    "region" text check (length("region") <= 50),
+    "main_url" text check (length("main_url") <= 250),
+    "avatar" blob
+);
+
+create table follow (
+    "id" integer primary key autoincrement,
+    "created_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "updated_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "follower_id" integer NOT NULL references profile(id),
+    "following_id" integer NOT NULL references profile(id)
+);
+
+create table message (
+    "id" integer primary key autoincrement,
+    "created_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "updated_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "user_id" integer NOT NULL references profile(id),
+    "body" text check (length("body") <= 140),
+    "likes" integer NOT NULL DEFAULT 0,
+    "image" blob,
+    "msg_group_type" integer,
+    "hidden_at" text
+);
+
+create table message_response (
+    "id" integer primary key autoincrement,
+    "created_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "updated_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "original_msg_id" integer NOT NULL references message(id),
+    "responding_msg_id" integer NOT NULL references message(id)
+);
+
+create table message_broadcast (
+    "id" integer primary key autoincrement,
+    "created_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "updated_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "main_msg_id" integer NOT NULL references message(id),
+    "broadcasting_msg_id" integer NOT NULL references message(id)
+);
+
+create table circle_group (
+    "id" integer primary key autoincrement,
+    "created_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "updated_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "owner_id" integer NOT NULL references profile(id)
+);
+
+create table circle_group_member (
+    "id" integer primary key autoincrement,
+    "created_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "updated_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "circle_group_id" integer NOT NULL references circle_group(id),
+    "member_id" integer NOT NULL references profile(id)
+);
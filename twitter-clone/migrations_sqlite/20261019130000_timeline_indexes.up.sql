@@ -0,0 +1,3 @@
+-- Timeline lookups go from the follower's follows to each followed profile's newest messages.
+create index if not exists follow_follower_id_idx on follow (follower_id);
+create index if not exists message_user_id_updated_at_idx on message (user_id, updated_at desc);
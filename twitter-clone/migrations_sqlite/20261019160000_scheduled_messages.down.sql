@@ -0,0 +1,2 @@
+drop index if exists message_scheduled_idx;
+alter table message drop column "publish_at";
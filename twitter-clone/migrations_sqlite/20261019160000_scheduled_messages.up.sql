@@ -0,0 +1,5 @@
+-- Same as the postgres `scheduled_messages` migration, `publish_at` uses the text timestamp layout.
+alter table message add column "publish_at" text;
+
+create index if not exists message_scheduled_idx on message (user_id, publish_at)
+    where publish_at is not null;
@@ -0,0 +1,15 @@
+-- Same as the postgres `drafts` migration.
+create table draft (
+    "id" integer primary key autoincrement,
+    "created_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "updated_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "user_id" integer NOT NULL references profile(id),
+    "body" text NOT NULL check (length("body") <= 140),
+    "msg_group_type" integer NOT NULL,
+    "broadcasting_msg_id" integer references message(id) on delete set null,
+    "original_msg_id" integer references message(id) on delete set null,
+
+    check (broadcasting_msg_id is null or original_msg_id is null)
+);
+
+create index if not exists draft_user_id_updated_at_idx on draft (user_id, updated_at desc);
@@ -0,0 +1,28 @@
+-- Same as the postgres `polls` migration.
+create table poll (
+    "id" integer primary key autoincrement,
+    "created_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "message_id" integer NOT NULL unique references message(id) on delete cascade,
+    "closes_at" text NOT NULL
+);
+
+create table poll_option (
+    "id" integer primary key autoincrement,
+    "poll_id" integer NOT NULL references poll(id) on delete cascade,
+    "position" integer NOT NULL,
+    "label" text NOT NULL check (length("label") <= 25),
+
+    unique (poll_id, position)
+);
+
+create table poll_vote (
+    "id" integer primary key autoincrement,
+    "created_at" text NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    "poll_id" integer NOT NULL references poll(id) on delete cascade,
+    "option_id" integer NOT NULL references poll_option(id) on delete cascade,
+    "user_id" integer NOT NULL references profile(id),
+
+    unique (poll_id, user_id)
+);
+
+create index if not exists poll_vote_option_id_idx on poll_vote (option_id);
@@ -0,0 +1,445 @@
+use std::{fs, path::PathBuf};
+
+use clap::{Args, Parser, Subcommand};
+use sqlx::migrate::{AppliedMigration, Migrate};
+
+use crate::{
+    common::entities::{
+        base::{run_migrations, DbConnGetter, DbRepo, MIGRATOR},
+        jobs::repo::{QueryDeadJobsFn, RetryDeadJobFn},
+        messages::repo::HideMessageFn,
+        profile::{
+            model::ProfileCreate,
+            repo::{DeleteProfileFn, InsertProfileFn, UpdateProfileAvatarFn},
+        },
+        timeline::repo::RebuildHomeTimelinesFn,
+    },
+    error::{IntoClientResult, Result, ServerSideError},
+    seed::{self, SeedOptions, SeedPlan},
+    settings::Settings,
+};
+
+/// Maintenance tasks run against the database outside of the server process.
+#[derive(Debug, Parser)]
+#[command(name = "twitter-admin", about, version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Apply, revert or inspect database migrations
+    #[command(subcommand)]
+    Migrate(MigrateCommand),
+    /// Create, delete or fix up profiles
+    #[command(subcommand)]
+    Profile(ProfileCommand),
+    /// Moderate messages
+    #[command(subcommand)]
+    Message(MessageCommand),
+    /// Fill the database with fake profiles, follows, messages and circles
+    Seed(SeedArgs),
+    /// Maintain the materialized home timelines
+    #[command(subcommand)]
+    Timeline(TimelineCommand),
+    /// Inspect and retry dead-lettered background jobs
+    #[command(subcommand)]
+    Job(JobCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MigrateCommand {
+    /// Apply every pending migration
+    Run,
+    /// Revert applied migrations newer than `--target`, only the latest one by default
+    Revert {
+        #[arg(long)]
+        target: Option<i64>,
+    },
+    /// List migrations and whether they are applied
+    Status,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ProfileCommand {
+    /// Create a profile
+    Create(ProfileCreateArgs),
+    /// Delete a profile with its follows, circles, drafts, votes and messages
+    Delete { id: i64 },
+    /// Remove a profile's avatar
+    ResetAvatar { id: i64 },
+}
+
+#[derive(Debug, Args)]
+pub struct ProfileCreateArgs {
+    #[arg(long)]
+    pub user_name: String,
+    #[arg(long)]
+    pub full_name: String,
+    #[arg(long, default_value = "")]
+    pub description: String,
+    #[arg(long)]
+    pub region: Option<String>,
+    #[arg(long)]
+    pub main_url: Option<String>,
+    /// Path to an image file used as the avatar
+    #[arg(long)]
+    pub avatar: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MessageCommand {
+    /// Hide a message from every timeline and lookup
+    Hide { id: i64 },
+    /// Make a hidden message visible again
+    Unhide { id: i64 },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TimelineCommand {
+    /// Backfill every home timeline from the existing follows and messages
+    Rebuild,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum JobCommand {
+    /// List jobs that ran out of attempts, newest first
+    Dead {
+        #[arg(long, default_value_t = 20)]
+        limit: i64,
+    },
+    /// Queue a dead job again with a fresh set of attempts
+    Retry { id: i64 },
+}
+
+#[derive(Debug, Args)]
+pub struct SeedArgs {
+    /// The same seed and options always generate the same dataset
+    #[arg(long, default_value_t = SeedOptions::default().seed)]
+    pub seed: u64,
+    #[arg(long, default_value_t = SeedOptions::default().profiles)]
+    pub profiles: usize,
+    /// Average number of accounts followed by each profile
+    #[arg(long, default_value_t = SeedOptions::default().follows_per_profile)]
+    pub follows_per_profile: usize,
+    /// Average number of messages written by each profile
+    #[arg(long, default_value_t = SeedOptions::default().messages_per_profile)]
+    pub messages_per_profile: usize,
+    #[arg(long, default_value_t = SeedOptions::default().reply_ratio, value_parser = parse_ratio)]
+    pub reply_ratio: f64,
+    #[arg(long, default_value_t = SeedOptions::default().broadcast_ratio, value_parser = parse_ratio)]
+    pub broadcast_ratio: f64,
+    /// Share of messages posted to a circle
+    #[arg(long, default_value_t = SeedOptions::default().circle_message_ratio, value_parser = parse_ratio)]
+    pub circle_message_ratio: f64,
+    /// Share of profiles owning a circle
+    #[arg(long, default_value_t = SeedOptions::default().circle_owner_ratio, value_parser = parse_ratio)]
+    pub circle_owner_ratio: f64,
+    /// Average number of members per circle
+    #[arg(long, default_value_t = SeedOptions::default().circle_size)]
+    pub circle_size: usize,
+}
+
+impl From<SeedArgs> for SeedOptions {
+    fn from(args: SeedArgs) -> Self {
+        SeedOptions {
+            seed: args.seed,
+            profiles: args.profiles,
+            follows_per_profile: args.follows_per_profile,
+            messages_per_profile: args.messages_per_profile,
+            reply_ratio: args.reply_ratio,
+            broadcast_ratio: args.broadcast_ratio,
+            circle_message_ratio: args.circle_message_ratio,
+            circle_owner_ratio: args.circle_owner_ratio,
+            circle_size: args.circle_size,
+        }
+    }
+}
+
+fn parse_ratio(value: &str) -> std::result::Result<f64, String> {
+    let ratio = value.parse::<f64>().map_err(|err| err.to_string())?;
+    if (0.0..=1.0).contains(&ratio) {
+        Ok(ratio)
+    } else {
+        Err(format!("{ratio} is not between 0 and 1"))
+    }
+}
+
+/// Runs a single admin command. Migrations never run implicitly here, whatever
+/// `database.auto_migrate` is set to.
+pub async fn execute(cli: Cli, settings: Settings) -> Result<()> {
+    let mut database = settings.database;
+    database.auto_migrate = false;
+    let db_repo = DbRepo::init(&database)
+        .await
+        .into_client_result()?
+        .with_timeline(settings.timeline);
+
+    match cli.command {
+        Command::Migrate(command) => migrate(&db_repo, command).await,
+        Command::Profile(command) => profile(&db_repo, command).await,
+        Command::Message(command) => message(&db_repo, command).await,
+        Command::Seed(args) => {
+            let plan = SeedPlan::generate(&args.into());
+            let summary = seed::apply(&db_repo, &plan).await?;
+            println!("{summary:#?}");
+            Ok(())
+        },
+        Command::Timeline(TimelineCommand::Rebuild) => {
+            let entries = db_repo.rebuild_home_timelines().await?;
+            println!("Rebuilt home timelines with {entries} entries");
+            Ok(())
+        },
+        Command::Job(command) => job(&db_repo, command).await,
+    }
+}
+
+async fn migrate(db_repo: &DbRepo, command: MigrateCommand) -> Result<()> {
+    match command {
+        MigrateCommand::Run => {
+            run_migrations(db_repo.get_conn())
+                .await
+                .into_client_result()?;
+            println!("Migrations applied");
+        },
+        MigrateCommand::Revert { target } => {
+            let target = match target {
+                Some(target) => target,
+                None => {
+                    let mut versions = applied_migrations(db_repo)
+                        .await?
+                        .into_iter()
+                        .map(|m| m.version)
+                        .collect::<Vec<i64>>();
+                    versions.sort_unstable();
+                    versions.into_iter().rev().nth(1).unwrap_or(0)
+                },
+            };
+            MIGRATOR
+                .undo(db_repo.get_conn(), target)
+                .await
+                .map_err(ServerSideError::from)?;
+            println!("Reverted migrations newer than {target}");
+        },
+        MigrateCommand::Status => {
+            let applied = applied_migrations(db_repo).await?;
+            for migration in MIGRATOR
+                .iter()
+                .filter(|m| m.migration_type.is_up_migration())
+            {
+                let status = match applied.iter().find(|a| a.version == migration.version) {
+                    Some(a) if a.checksum != migration.checksum => "applied (checksum mismatch)",
+                    Some(_) => "applied",
+                    None => "pending",
+                };
+                println!(
+                    "{:<16} {:<24} {status}",
+                    migration.version, migration.description
+                );
+            }
+        },
+    }
+    Ok(())
+}
+
+async fn applied_migrations(db_repo: &DbRepo) -> Result<Vec<AppliedMigration>> {
+    let mut conn = db_repo
+        .get_conn()
+        .acquire()
+        .await
+        .map_err(ServerSideError::from)?;
+    conn.ensure_migrations_table()
+        .await
+        .map_err(ServerSideError::from)?;
+    conn.list_applied_migrations()
+        .await
+        .map_err(ServerSideError::from)
+        .into_client_result()
+}
+
+async fn profile(db_repo: &DbRepo, command: ProfileCommand) -> Result<()> {
+    match command {
+        ProfileCommand::Create(args) => {
+            let id = db_repo.insert_profile(args.try_into()?).await?;
+            println!("Created profile {id}");
+        },
+        ProfileCommand::Delete { id } => {
+            db_repo.delete_profile(id).await?;
+            println!("Deleted profile {id}");
+        },
+        ProfileCommand::ResetAvatar { id } => {
+            db_repo.update_profile_avatar(id, None).await?;
+            println!("Reset avatar of profile {id}");
+        },
+    }
+    Ok(())
+}
+
+async fn message(db_repo: &DbRepo, command: MessageCommand) -> Result<()> {
+    match command {
+        MessageCommand::Hide { id } => {
+            db_repo.hide_message(id, true).await?;
+            println!("Hid message {id}");
+        },
+        MessageCommand::Unhide { id } => {
+            db_repo.hide_message(id, false).await?;
+            println!("Unhid message {id}");
+        },
+    }
+    Ok(())
+}
+
+async fn job(db_repo: &DbRepo, command: JobCommand) -> Result<()> {
+    match command {
+        JobCommand::Dead { limit } => {
+            for job in db_repo.query_dead_jobs(limit).await? {
+                println!(
+                    "{:<8} {:<24} attempts={} failed_at={} request_id={} payload={} error={}",
+                    job.id,
+                    job.kind,
+                    job.attempts,
+                    job.updated_at.to_rfc3339(),
+                    job.request_id.as_deref().unwrap_or("-"),
+                    job.payload,
+                    job.last_error.as_deref().unwrap_or("-")
+                );
+            }
+        },
+        JobCommand::Retry { id } => {
+            db_repo.retry_dead_job(id).await?;
+            println!("Queued job {id} again");
+        },
+    }
+    Ok(())
+}
+
+impl TryFrom<ProfileCreateArgs> for ProfileCreate {
+    type Error = ServerSideError;
+
+    fn try_from(args: ProfileCreateArgs) -> std::result::Result<Self, Self::Error> {
+        let avatar = match args.avatar {
+            Some(path) => Some(fs::read(&path).map_err(|err| {
+                ServerSideError::FileReadError(format!(
+                    "Failed to read avatar file {}: {err}",
+                    path.display()
+                ))
+            })?),
+            None => None,
+        };
+
+        Ok(ProfileCreate {
+            user_name: args.user_name,
+            full_name: args.full_name,
+            description: args.description,
+            region: args.region,
+            main_url: args.main_url,
+            avatar,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_migrate_revert() {
+        let cli =
+            Cli::try_parse_from(["twitter-admin", "migrate", "revert", "--target", "3"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Migrate(MigrateCommand::Revert { target: Some(3) })
+        ));
+    }
+
+    #[test]
+    fn test_parse_timeline_rebuild() {
+        let cli = Cli::try_parse_from(["twitter-admin", "timeline", "rebuild"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Timeline(TimelineCommand::Rebuild)
+        ));
+    }
+
+    #[test]
+    fn test_parse_job_commands() {
+        let cli = Cli::try_parse_from(["twitter-admin", "job", "dead"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Job(JobCommand::Dead { limit: 20 })
+        ));
+
+        let cli = Cli::try_parse_from(["twitter-admin", "job", "retry", "12"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Job(JobCommand::Retry { id: 12 })
+        ));
+    }
+
+    #[test]
+    fn test_parse_seed() {
+        let cli = Cli::try_parse_from(["twitter-admin", "seed", "--seed", "7", "--profiles", "5"])
+            .unwrap();
+        let Command::Seed(args) = cli.command else {
+            panic!("expected seed");
+        };
+        let options = SeedOptions::from(args);
+        assert_eq!(options.seed, 7);
+        assert_eq!(options.profiles, 5);
+        assert_eq!(options.circle_size, SeedOptions::default().circle_size);
+
+        assert!(Cli::try_parse_from(["twitter-admin", "seed", "--reply-ratio", "1.5"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_profile_create() {
+        let cli = Cli::try_parse_from([
+            "twitter-admin",
+            "profile",
+            "create",
+            "--user-name",
+            "dave",
+            "--full-name",
+            "Dave Choi",
+        ])
+        .unwrap();
+        let Command::Profile(ProfileCommand::Create(args)) = cli.command else {
+            panic!("expected profile create");
+        };
+        let profile = ProfileCreate::try_from(args).unwrap();
+        assert_eq!(profile.user_name, "dave");
+        assert_eq!(profile.description, "");
+        assert!(profile.avatar.is_none());
+    }
+
+    #[test]
+    fn test_missing_avatar_file_is_reported() {
+        let cli = Cli::try_parse_from([
+            "twitter-admin",
+            "profile",
+            "create",
+            "--user-name",
+            "dave",
+            "--full-name",
+            "Dave Choi",
+            "--avatar",
+            "does/not/exist.jpeg",
+        ])
+        .unwrap();
+        let Command::Profile(ProfileCommand::Create(args)) = cli.command else {
+            panic!("expected profile create");
+        };
+        assert!(matches!(
+            ProfileCreate::try_from(args),
+            Err(ServerSideError::FileReadError(_))
+        ));
+    }
+}
@@ -0,0 +1,109 @@
+//! Operator endpoints under `/api/v1/admin`, for now changing log levels without a restart.
+//!
+//! They are off unless `admin.token` is set, and then need it as a bearer token. The webhook
+//! endpoints are guarded by the same token, since subscriptions see every event. Log levels are
+//! the `EnvFilter` directives of the sinks set up by `tracing_config`, by sink name: `file`,
+//! `stdout`, `stderr` and `otlp` when spans are exported.
+
+use std::time::Duration;
+
+use actix_web::{http::header::AUTHORIZATION, HttpRequest};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+use tracing_config::{LogFilters, SinkFilter, TracingError};
+
+use crate::{error::ClientSideError, settings::AdminSettings};
+
+/// Admin state shared by the handlers, kept in [`crate::app_state::AppState`].
+#[derive(Debug, Clone)]
+pub struct AdminApi {
+    settings: AdminSettings,
+    log_filters: LogFilters,
+}
+
+impl AdminApi {
+    /// `log_filters` are those of the guard returned when tracing was set up.
+    pub fn new(settings: AdminSettings, log_filters: LogFilters) -> Self {
+        Self { settings, log_filters }
+    }
+
+    /// Not found while no token is configured, unauthorized without the right bearer token.
+    pub fn authorize(&self, req: &HttpRequest) -> Result<(), ClientSideError> {
+        let Some(token) = &self.settings.token else {
+            return Err(ClientSideError::NotFound(
+                "The admin api is disabled".to_string(),
+            ));
+        };
+        let given = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match given {
+            Some(given) if tokens_match(given, token) => Ok(()),
+            _ => Err(ClientSideError::Unauthorized(
+                "A valid admin bearer token is required".to_string(),
+            )),
+        }
+    }
+
+    pub fn log_levels(&self) -> Vec<SinkFilter> {
+        self.log_filters.list()
+    }
+
+    /// Sets the directives of `sink`, returning the previous ones. With `revert_after` they are
+    /// set back once it has passed, unless they were changed again in the meantime.
+    pub fn set_log_level(
+        &self,
+        sink: &str,
+        directives: &str,
+        revert_after: Option<Duration>,
+    ) -> Result<SinkFilter, ClientSideError> {
+        let previous = self
+            .log_filters
+            .set(sink, directives)
+            .map_err(|err| match err {
+                TracingError::UnknownSink(_) => ClientSideError::NotFound(err.to_string()),
+                _ => ClientSideError::BadRequest(err.to_string()),
+            })?;
+        let current = self.log_filters.get(sink).map(|filter| filter.directives);
+        info!(sink, directives = ?current, previous = %previous.directives, "Log level changed");
+
+        if let (Some(revert_after), Some(current)) = (revert_after, current) {
+            let log_filters = self.log_filters.clone();
+            let previous = previous.clone();
+            actix_web::rt::spawn(async move {
+                actix_web::rt::time::sleep(revert_after).await;
+                let unchanged = log_filters
+                    .get(&previous.sink)
+                    .is_some_and(|filter| filter.directives == current);
+                if !unchanged {
+                    return;
+                }
+                match log_filters.set(&previous.sink, &previous.directives) {
+                    Ok(_) => info!(
+                        sink = %previous.sink,
+                        directives = %previous.directives,
+                        "Log level reverted"
+                    ),
+                    Err(err) => {
+                        warn!(sink = %previous.sink, error = %err, "Log level not reverted")
+                    },
+                }
+            });
+        }
+        Ok(previous)
+    }
+}
+
+/// Compares digests of both in constant time, so the response time tells nothing about how much
+/// of the token was right.
+fn tokens_match(given: &str, token: &str) -> bool {
+    let given = Sha256::digest(given.as_bytes());
+    let token = Sha256::digest(token.as_bytes());
+    given
+        .iter()
+        .zip(token.iter())
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
@@ -1,7 +1,13 @@
 use std::fmt::Debug;
 
+use crate::{admin_api::AdminApi, federation::Federation, link_preview::LinkPreviews};
+
 #[derive(Debug)]
 pub struct AppState<T: Debug> {
+    /// Built by [`crate::outbound::http_client`], only reaches public addresses.
     pub client: reqwest::Client,
+    pub link_previews: LinkPreviews,
+    pub federation: Federation,
+    pub admin: AdminApi,
     pub db_repo: T,
 }
@@ -0,0 +1,35 @@
+use std::process::ExitCode;
+
+use clap::Parser;
+use tracing_config::{init_tracing_with, TracingSettings};
+use twitter_clone::{
+    admin::{execute, Cli},
+    settings::Settings,
+};
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let settings = match Settings::load() {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        },
+    };
+
+    // Command output goes to stdout, so only warnings and errors are logged to the terminal.
+    let _guard = init_tracing_with(&TracingSettings {
+        stdout_level: "off".to_string(),
+        ..settings.tracing_settings()
+    });
+
+    match execute(cli, settings).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        },
+    }
+}
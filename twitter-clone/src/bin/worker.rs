@@ -0,0 +1,54 @@
+use std::process::ExitCode;
+
+use tracing::{error, info};
+use tracing_config::TracingConfig;
+use twitter_clone::{
+    common::entities::base::DbRepo, outbound, settings::Settings, worker::default_worker,
+};
+
+/// Runs the background job workers on their own, for deployments that keep them out of the
+/// server process with `jobs.in_process = false`.
+#[tokio::main]
+async fn main() -> ExitCode {
+    let settings = match Settings::load() {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        },
+    };
+    let _guard = match TracingConfig::from_settings(&settings.tracing_settings()).try_init() {
+        Ok(guard) => guard,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        },
+    };
+
+    let mut database = settings.database.clone();
+    database.auto_migrate = false;
+    let db_repo = match DbRepo::init(&database).await {
+        Ok(db_repo) => db_repo
+            .with_timeline(settings.timeline.clone())
+            .with_federation(settings.federation.clone()),
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        },
+    };
+
+    let client = match outbound::http_client(&settings.outbound) {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        },
+    };
+    let workers = default_worker(db_repo, client, &settings).spawn();
+    if let Err(err) = tokio::signal::ctrl_c().await {
+        error!("Failed to listen for shutdown signal: {}", err);
+    }
+    info!("Shutting down, waiting for running jobs");
+    workers.shutdown().await;
+    ExitCode::SUCCESS
+}
@@ -1,3 +1,13 @@
 pub mod base;
+pub mod circles;
+pub mod drafts;
+pub mod federation;
+pub mod in_memory;
+pub mod jobs;
 pub mod messages;
+pub mod polls;
 pub mod profile;
+pub mod repository;
+pub mod sqlite;
+pub mod timeline;
+pub mod webhooks;
@@ -1,8 +1,30 @@
-use std::env;
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
 use serde::Deserialize;
-use sqlx::{migrate, prelude::FromRow, Pool, Postgres};
-use tracing::{error, info};
+use sqlx::{
+    migrate,
+    migrate::Migrator,
+    postgres::{PgConnectOptions, PgPoolOptions},
+    prelude::FromRow,
+    Pool, Postgres,
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    error::ServerSideError,
+    settings::{
+        DatabaseSettings, FederationSettings, PoolSettings, RetrySettings, TimelineSettings,
+    },
+};
+
+/// Postgres reports this SQLSTATE while it is still starting up and refusing connections.
+const CANNOT_CONNECT_NOW: &str = "57P03";
+
+/// Migrations embedded from `./migrations`, shared by the server and `twitter-admin`.
+pub static MIGRATOR: Migrator = migrate!("./migrations");
 
 #[derive(Debug, FromRow, Deserialize)]
 pub struct EntityId {
@@ -19,11 +41,51 @@ pub trait DbConnGetter {
 #[derive(Debug, Clone)]
 pub struct DbRepo {
     conn: Pool<Postgres>,
+    timeline: TimelineSettings,
+    federation: FederationSettings,
 }
 
 impl DbRepo {
-    pub async fn init() -> Self {
-        Self { conn: get_db_conn().await }
+    /// Connects with the default `pull` timelines and federation disabled, see
+    /// [`DbRepo::with_timeline`] and [`DbRepo::with_federation`].
+    pub async fn init(settings: &DatabaseSettings) -> Result<Self, ServerSideError> {
+        Ok(Self {
+            conn: get_db_conn(settings).await?,
+            timeline: TimelineSettings::default(),
+            federation: FederationSettings::default(),
+        })
+    }
+
+    /// Chooses how home timelines are written and read.
+    pub fn with_timeline(mut self, timeline: TimelineSettings) -> Self {
+        self.timeline = timeline;
+        self
+    }
+
+    pub fn timeline(&self) -> &TimelineSettings {
+        &self.timeline
+    }
+
+    /// Enables sending new public messages to remote followers.
+    pub fn with_federation(mut self, federation: FederationSettings) -> Self {
+        self.federation = federation;
+        self
+    }
+
+    pub fn federation(&self) -> &FederationSettings {
+        &self.federation
+    }
+}
+
+#[cfg(test)]
+impl DbRepo {
+    /// Wraps a pool set up by `#[sqlx::test]`, with the same defaults as [`DbRepo::init`].
+    pub(crate) fn from_pool(conn: Pool<Postgres>) -> Self {
+        Self {
+            conn,
+            timeline: TimelineSettings::default(),
+            federation: FederationSettings::default(),
+        }
     }
 }
 
@@ -35,31 +97,173 @@ impl DbConnGetter for DbRepo {
     }
 }
 
-pub async fn get_db_conn() -> Pool<Postgres> {
-    dotenv::dotenv().ok();
-
-    let postgres_host = env::var("POSTGRES_HOST").unwrap();
-    let postgres_port = env::var("POSTGRES_PORT")
-        .unwrap_or("5432".to_string())
-        .parse::<u16>()
-        .unwrap_or_default();
-    let postgres_password =
-        env::var("POSTGRES_PASSWORD").expect("There should be a valid password for database");
-    let postgres_user =
-        env::var("POSTGRES_USER").expect("There should be a valid user defined for database.");
-    let postgres_db =
-        env::var("POSTGRES_DB").expect("There should be valid postgres database defined.");
-
-    let postgres_url = format!("postgres://{postgres_user}:{postgres_password}@{postgres_host}:{postgres_port}/{postgres_db}");
-
-    let conn = sqlx::postgres::PgPool::connect(&postgres_url)
-        .await
-        .expect("Connection to database shouldn't fail.");
-    let migrate = migrate!("./migrations").run(&conn).await;
-
-    match migrate {
-        Ok(()) => info!("sqlx migration success"),
-        Err(e) => error!("Sqlx migration error: {:?}", e),
+/// Connects to postgres, retrying transient failures until `settings.retry.deadline_secs`. The
+/// migrations only run here when `database.auto_migrate` is enabled, otherwise they are applied
+/// with `twitter-admin migrate run`.
+pub async fn get_db_conn(settings: &DatabaseSettings) -> Result<Pool<Postgres>, ServerSideError> {
+    let mut connect_options = PgConnectOptions::from_str(&settings.connection_url())?;
+    if let Some(timeout_ms) = settings.pool.statement_timeout_ms {
+        connect_options = connect_options.options([("statement_timeout", timeout_ms.to_string())]);
+    }
+
+    let conn = connect_with_retry(
+        pool_options(&settings.pool),
+        connect_options,
+        &settings.retry,
+    )
+    .await?;
+
+    if settings.auto_migrate {
+        run_migrations(&conn).await?;
+    } else {
+        info!("Auto migration disabled, skipping sqlx migrations");
+    }
+
+    Ok(conn)
+}
+
+/// Applies pending migrations. A failure is returned as an error instead of letting the caller
+/// continue against a partially migrated schema.
+pub async fn run_migrations(conn: &Pool<Postgres>) -> Result<(), ServerSideError> {
+    MIGRATOR.run(conn).await.map_err(|e| {
+        error!("Sqlx migration error: {:?}", e);
+        ServerSideError::from(e)
+    })?;
+    info!("sqlx migration success");
+    Ok(())
+}
+
+fn pool_options(settings: &PoolSettings) -> PgPoolOptions {
+    let options = PgPoolOptions::new()
+        .max_connections(settings.max_connections)
+        .min_connections(settings.min_connections)
+        .acquire_timeout(Duration::from_secs(settings.acquire_timeout_secs));
+
+    match settings.idle_timeout_secs {
+        Some(secs) => options.idle_timeout(Duration::from_secs(secs)),
+        None => options,
+    }
+}
+
+async fn connect_with_retry(
+    pool_options: PgPoolOptions,
+    connect_options: PgConnectOptions,
+    retry: &RetrySettings,
+) -> Result<Pool<Postgres>, ServerSideError> {
+    let deadline = Instant::now() + Duration::from_secs(retry.deadline_secs);
+    let max_backoff = Duration::from_millis(retry.max_backoff_ms);
+    let mut backoff = Duration::from_millis(retry.initial_backoff_ms);
+    let mut attempt: u32 = 1;
+
+    loop {
+        match pool_options
+            .clone()
+            .connect_with(connect_options.clone())
+            .await
+        {
+            Ok(conn) => {
+                info!(attempt, "Connected to database");
+                return Ok(conn);
+            },
+            Err(e) if is_transient(&e) && Instant::now() + backoff < deadline => {
+                warn!(
+                    attempt,
+                    backoff_ms = backoff.as_millis() as u64,
+                    error = %e,
+                    "Database unavailable, retrying"
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = next_backoff(backoff, max_backoff);
+                attempt += 1;
+            },
+            Err(e) => {
+                error!(attempt, "Failed to connect to database: {:?}", e);
+                return Err(ServerSideError::from(e));
+            },
+        }
+    }
+}
+
+fn next_backoff(current: Duration, max: Duration) -> Duration {
+    current.saturating_mul(2).min(max)
+}
+
+/// Errors worth retrying while postgres is booting or briefly unreachable. Anything else, like
+/// bad credentials or an unknown database, fails immediately.
+fn is_transient(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(db_err) => db_err.code().as_deref() == Some(CANNOT_CONNECT_NOW),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use tracing_config::capture::LogCapture;
+
+    use super::*;
+
+    #[test]
+    fn test_next_backoff_doubles_until_max() {
+        let max = Duration::from_millis(1000);
+        let mut backoff = Duration::from_millis(250);
+        let mut schedule = vec![];
+        for _ in 0..4 {
+            schedule.push(backoff.as_millis());
+            backoff = next_backoff(backoff, max);
+        }
+        assert_eq!(schedule, vec![250, 500, 1000, 1000]);
+    }
+
+    #[test]
+    fn test_is_transient() {
+        let refused = sqlx::Error::Io(io::Error::from(io::ErrorKind::ConnectionRefused));
+        assert!(is_transient(&refused));
+        assert!(is_transient(&sqlx::Error::PoolTimedOut));
+        assert!(!is_transient(&sqlx::Error::RowNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_connect_retries_until_the_deadline() {
+        let capture = LogCapture::new();
+        let _guard = capture.set_default();
+        // Nothing answers on this address, every attempt times out or is refused.
+        let connect_options =
+            PgConnectOptions::from_str("postgres://tester@10.255.255.1:5432/twitter").unwrap();
+        let retry = RetrySettings {
+            deadline_secs: 1,
+            initial_backoff_ms: 50,
+            max_backoff_ms: 200,
+        };
+
+        let started = Instant::now();
+        let result = connect_with_retry(
+            PgPoolOptions::new().acquire_timeout(Duration::from_millis(200)),
+            connect_options,
+            &retry,
+        )
+        .await;
+        let elapsed = started.elapsed();
+
+        assert!(matches!(
+            result,
+            Err(ServerSideError::DatabaseError(
+                sqlx::Error::PoolTimedOut | sqlx::Error::Io(_)
+            ))
+        ));
+        let retries = capture
+            .events()
+            .iter()
+            .filter(|event| event.message.as_deref() == Some("Database unavailable, retrying"))
+            .count();
+        assert!(retries >= 2, "retried {retries} times in {elapsed:?}");
+        // It stops once the next backoff would pass the deadline, after at most one more attempt.
+        assert!(
+            elapsed >= Duration::from_millis(700) && elapsed < Duration::from_secs(2),
+            "gave up after {elapsed:?}"
+        );
     }
-    conn
 }
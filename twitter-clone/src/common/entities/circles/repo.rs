@@ -0,0 +1,74 @@
+use crate::common::entities::base::{DbConnGetter, DbRepo, EntityId};
+use crate::error::{IntoClientResult, Result, ServerSideError};
+use async_trait::async_trait;
+use mockall::automock;
+use sqlx::{Pool, Postgres};
+use tracing::{error, instrument};
+
+mod private_members {
+
+    use super::*;
+
+    #[instrument(skip())]
+    pub(crate) async fn insert_circle_inner(conn: &Pool<Postgres>, owner_id: i64) -> Result<i64> {
+        sqlx::query_as::<_, EntityId>(
+            "insert into circle_group (owner_id) values ($1) returning id",
+        )
+        .bind(owner_id)
+        .fetch_one(conn)
+        .await
+        .map(|row: EntityId| row.id)
+        .map_err(|e| {
+            error!("Failed to insert circle: {:?}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
+    }
+
+    #[instrument(skip())]
+    pub(crate) async fn add_circle_member_inner(
+        conn: &Pool<Postgres>,
+        circle_group_id: i64,
+        member_id: i64,
+    ) -> Result<i64> {
+        sqlx::query_as::<_, EntityId>(
+            "insert into circle_group_member (circle_group_id, member_id) values ($1, $2) returning id",
+        )
+        .bind(circle_group_id)
+        .bind(member_id)
+        .fetch_one(conn)
+        .await
+        .map(|row: EntityId| row.id)
+        .map_err(|e| {
+            error!("Failed to add circle member: {:?}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait InsertCircleFn {
+    async fn insert_circle(&self, owner_id: i64) -> Result<i64>;
+}
+
+#[async_trait]
+impl InsertCircleFn for DbRepo {
+    async fn insert_circle(&self, owner_id: i64) -> Result<i64> {
+        private_members::insert_circle_inner(self.get_conn(), owner_id).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait AddCircleMemberFn {
+    async fn add_circle_member(&self, circle_group_id: i64, member_id: i64) -> Result<i64>;
+}
+
+#[async_trait]
+impl AddCircleMemberFn for DbRepo {
+    async fn add_circle_member(&self, circle_group_id: i64, member_id: i64) -> Result<i64> {
+        private_members::add_circle_member_inner(self.get_conn(), circle_group_id, member_id).await
+    }
+}
@@ -0,0 +1,26 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+#[derive(Deserialize, Serialize, FromRow, Clone, Debug)]
+pub struct DraftQueryResult {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub user_id: i64,
+    pub body: String,
+    pub msg_group_type: i32,
+    /// Set when the draft broadcasts a message, cleared if that message is deleted.
+    pub broadcasting_msg_id: Option<i64>,
+    /// Set when the draft answers a message, cleared if that message is deleted.
+    pub original_msg_id: Option<i64>,
+}
+
+/// Content of a new or updated draft. At most one of the targets is set.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct DraftCreate {
+    pub body: String,
+    pub msg_group_type: i32,
+    pub broadcasting_msg_id: Option<i64>,
+    pub original_msg_id: Option<i64>,
+}
@@ -0,0 +1,390 @@
+use super::model::{DraftCreate, DraftQueryResult};
+use crate::common::entities::base::{DbConnGetter, DbRepo, EntityId};
+use crate::common::entities::messages::{
+    model::NewMessage,
+    repo::{insert_message_tx, insert_response_message_tx},
+};
+use crate::error::{ClientSideError, IntoClientResult, Result, ServerSideError};
+use async_trait::async_trait;
+use mockall::automock;
+use sqlx::{Pool, Postgres};
+use tracing::{error, instrument};
+
+mod private_members {
+
+    use super::*;
+
+    #[instrument(skip(draft))]
+    pub(crate) async fn insert_draft_inner(
+        conn: &Pool<Postgres>,
+        user_id: i64,
+        draft: DraftCreate,
+    ) -> Result<i64> {
+        sqlx::query_as::<_, EntityId>(
+            r"
+            insert into draft (user_id, body, msg_group_type, broadcasting_msg_id, original_msg_id)
+                values ($1, $2, $3, $4, $5)
+                returning id
+            ",
+        )
+        .bind(user_id)
+        .bind(&draft.body)
+        .bind(draft.msg_group_type)
+        .bind(draft.broadcasting_msg_id)
+        .bind(draft.original_msg_id)
+        .fetch_one(conn)
+        .await
+        .map(|row: EntityId| row.id)
+        .map_err(|e| {
+            error!("Failed to insert draft: {:?}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
+    }
+
+    #[instrument(skip())]
+    pub(crate) async fn query_draft_inner(
+        conn: &Pool<Postgres>,
+        id: i64,
+        user_id: i64,
+    ) -> Result<Option<DraftQueryResult>> {
+        sqlx::query_as::<_, DraftQueryResult>("select * from draft where id = $1 and user_id = $2")
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(conn)
+            .await
+            .map_err(ServerSideError::from)
+            .into_client_result()
+    }
+
+    #[instrument(skip())]
+    pub(crate) async fn query_drafts_inner(
+        conn: &Pool<Postgres>,
+        user_id: i64,
+    ) -> Result<Vec<DraftQueryResult>> {
+        sqlx::query_as::<_, DraftQueryResult>(
+            "select * from draft where user_id = $1 order by updated_at desc, id desc",
+        )
+        .bind(user_id)
+        .fetch_all(conn)
+        .await
+        .map_err(ServerSideError::from)
+        .into_client_result()
+    }
+
+    #[instrument(skip(draft))]
+    pub(crate) async fn update_draft_inner(
+        conn: &Pool<Postgres>,
+        id: i64,
+        user_id: i64,
+        draft: DraftCreate,
+    ) -> Result<()> {
+        let result = sqlx::query::<_>(
+            r"
+            update draft
+                set
+                    body = $3,
+                    msg_group_type = $4,
+                    broadcasting_msg_id = $5,
+                    original_msg_id = $6,
+                    updated_at = now()
+                where id = $1 and user_id = $2
+            ",
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(&draft.body)
+        .bind(draft.msg_group_type)
+        .bind(draft.broadcasting_msg_id)
+        .bind(draft.original_msg_id)
+        .execute(conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to update draft: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(draft_not_found(id));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip())]
+    pub(crate) async fn delete_draft_inner(
+        conn: &Pool<Postgres>,
+        id: i64,
+        user_id: i64,
+    ) -> Result<()> {
+        let result = sqlx::query::<_>("delete from draft where id = $1 and user_id = $2")
+            .bind(id)
+            .bind(user_id)
+            .execute(conn)
+            .await
+            .map_err(|e| {
+                error!("Failed to delete draft: {:?}", e);
+                ServerSideError::from(e)
+            })?;
+
+        if result.rows_affected() == 0 {
+            return Err(draft_not_found(id));
+        }
+        Ok(())
+    }
+
+    /// Removes the draft and inserts its message in the same transaction, so a draft is
+    /// published at most once and is kept if the insert fails.
+    #[instrument(skip())]
+    pub(crate) async fn publish_draft_inner(
+        conn: &Pool<Postgres>,
+        id: i64,
+        user_id: i64,
+    ) -> Result<(i64, DraftQueryResult)> {
+        let mut tx = conn.begin().await.map_err(ServerSideError::from)?;
+
+        let draft = sqlx::query_as::<_, DraftQueryResult>(
+            "delete from draft where id = $1 and user_id = $2 returning *",
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to publish draft: {:?}", e);
+            ServerSideError::from(e)
+        })?
+        .ok_or_else(|| draft_not_found(id))?;
+
+        let message_id = match draft.original_msg_id {
+            Some(original_msg_id) => {
+                insert_response_message_tx(
+                    &mut tx,
+                    draft.user_id,
+                    &draft.body,
+                    draft.msg_group_type,
+                    original_msg_id,
+                )
+                .await?
+            },
+            None => {
+                insert_message_tx(
+                    &mut tx,
+                    draft.user_id,
+                    &draft.body,
+                    draft.msg_group_type,
+                    draft.broadcasting_msg_id,
+                    None,
+                )
+                .await?
+            },
+        };
+
+        tx.commit().await.map_err(ServerSideError::from)?;
+        Ok((message_id, draft))
+    }
+}
+
+pub(crate) fn draft_not_found(id: i64) -> ClientSideError {
+    ServerSideError::DraftNotFound(format!("No draft found with id: {id}")).into()
+}
+
+#[automock]
+#[async_trait]
+pub trait InsertDraftFn {
+    async fn insert_draft(&self, user_id: i64, draft: DraftCreate) -> Result<i64>;
+}
+
+#[async_trait]
+impl InsertDraftFn for DbRepo {
+    async fn insert_draft(&self, user_id: i64, draft: DraftCreate) -> Result<i64> {
+        private_members::insert_draft_inner(self.get_conn(), user_id, draft).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait QueryDraftFn {
+    /// Only returns the draft when it belongs to `user_id`.
+    async fn query_draft(&self, id: i64, user_id: i64) -> Result<Option<DraftQueryResult>>;
+}
+
+#[async_trait]
+impl QueryDraftFn for DbRepo {
+    async fn query_draft(&self, id: i64, user_id: i64) -> Result<Option<DraftQueryResult>> {
+        private_members::query_draft_inner(self.get_conn(), id, user_id).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait QueryDraftsFn {
+    /// Drafts of `user_id`, last edited first.
+    async fn query_drafts(&self, user_id: i64) -> Result<Vec<DraftQueryResult>>;
+}
+
+#[async_trait]
+impl QueryDraftsFn for DbRepo {
+    async fn query_drafts(&self, user_id: i64) -> Result<Vec<DraftQueryResult>> {
+        private_members::query_drafts_inner(self.get_conn(), user_id).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait UpdateDraftFn {
+    async fn update_draft(&self, id: i64, user_id: i64, draft: DraftCreate) -> Result<()>;
+}
+
+#[async_trait]
+impl UpdateDraftFn for DbRepo {
+    async fn update_draft(&self, id: i64, user_id: i64, draft: DraftCreate) -> Result<()> {
+        private_members::update_draft_inner(self.get_conn(), id, user_id, draft).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait DeleteDraftFn {
+    async fn delete_draft(&self, id: i64, user_id: i64) -> Result<()>;
+}
+
+#[async_trait]
+impl DeleteDraftFn for DbRepo {
+    async fn delete_draft(&self, id: i64, user_id: i64) -> Result<()> {
+        private_members::delete_draft_inner(self.get_conn(), id, user_id).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait PublishDraftFn {
+    /// Turns the draft into a message, an answer when it has an `original_msg_id`, and returns
+    /// the message id. The draft is gone once published.
+    async fn publish_draft(&self, id: i64, user_id: i64) -> Result<i64>;
+}
+
+#[async_trait]
+impl PublishDraftFn for DbRepo {
+    async fn publish_draft(&self, id: i64, user_id: i64) -> Result<i64> {
+        let (message_id, draft) =
+            private_members::publish_draft_inner(self.get_conn(), id, user_id).await?;
+        self.fan_out(message_id, None).await;
+        self.announce_message(&NewMessage {
+            id: message_id,
+            user_id: draft.user_id,
+            body: &draft.body,
+            msg_group_type: draft.msg_group_type,
+            broadcasting_msg_id: draft.broadcasting_msg_id,
+            original_msg_id: draft.original_msg_id,
+            publish_at: None,
+        })
+        .await;
+        Ok(message_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::{
+        common::entities::{messages::repo::InsertMessageFn, profile::repo::InsertProfileFn},
+        common_tests::profile,
+        schemas::message::MessageGroupTypes,
+    };
+
+    const PUBLIC: i32 = MessageGroupTypes::Public as i32;
+
+    fn answer(body: &str, original_msg_id: i64) -> DraftCreate {
+        DraftCreate {
+            body: body.to_string(),
+            msg_group_type: PUBLIC,
+            broadcasting_msg_id: None,
+            original_msg_id: Some(original_msg_id),
+        }
+    }
+
+    async fn messages_of(repo: &DbRepo, user_id: i64) -> Vec<(i64, String)> {
+        sqlx::query_as::<_, (i64, String)>(
+            "select id, body from message where user_id = $1 order by id",
+        )
+        .bind(user_id)
+        .fetch_all(repo.get_conn())
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test(migrator = "crate::common::entities::base::MIGRATOR")]
+    #[ignore = "needs postgres at DATABASE_URL"]
+    async fn test_draft_is_published_once_as_an_answer(pool: PgPool) {
+        let repo = DbRepo::from_pool(pool);
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let writer = repo.insert_profile(profile("writer")).await.unwrap();
+        let original = repo
+            .insert_message(author, "question", PUBLIC, None)
+            .await
+            .unwrap();
+        let draft = repo
+            .insert_draft(writer, answer("answer", original))
+            .await
+            .unwrap();
+
+        // Only the owner publishes the draft.
+        assert!(matches!(
+            repo.publish_draft(draft, author).await,
+            Err(ClientSideError::NotFound(_))
+        ));
+
+        let message = repo.publish_draft(draft, writer).await.unwrap();
+        assert_eq!(
+            messages_of(&repo, writer).await,
+            vec![(message, "answer".to_string())]
+        );
+        let (responding,) = sqlx::query_as::<_, (i64,)>(
+            "select responding_msg_id from message_response where original_msg_id = $1",
+        )
+        .bind(original)
+        .fetch_one(repo.get_conn())
+        .await
+        .unwrap();
+        assert_eq!(responding, message);
+        assert!(repo.query_draft(draft, writer).await.unwrap().is_none());
+
+        // The draft went away with the first publish, a second one inserts nothing.
+        assert!(matches!(
+            repo.publish_draft(draft, writer).await,
+            Err(ClientSideError::NotFound(_))
+        ));
+        assert_eq!(messages_of(&repo, writer).await.len(), 1);
+    }
+
+    #[sqlx::test(migrator = "crate::common::entities::base::MIGRATOR")]
+    #[ignore = "needs postgres at DATABASE_URL"]
+    async fn test_draft_is_kept_when_its_message_is_not_inserted(pool: PgPool) {
+        let repo = DbRepo::from_pool(pool);
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let writer = repo.insert_profile(profile("writer")).await.unwrap();
+        let original = repo
+            .insert_message(author, "question", PUBLIC, None)
+            .await
+            .unwrap();
+        let draft = repo
+            .insert_draft(writer, answer("answer", original))
+            .await
+            .unwrap();
+        // Fails the answer's second insert, after the draft row was deleted.
+        sqlx::query("alter table message_response add constraint no_answers check (false)")
+            .execute(repo.get_conn())
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            repo.publish_draft(draft, writer).await,
+            Err(ClientSideError::InternalServerError)
+        ));
+        assert!(messages_of(&repo, writer).await.is_empty());
+        let kept = repo.query_draft(draft, writer).await.unwrap().unwrap();
+        assert_eq!(kept.original_msg_id, Some(original));
+    }
+}
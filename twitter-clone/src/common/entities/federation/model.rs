@@ -0,0 +1,87 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::common::entities::messages::model::AuthorMessageQueryResult;
+
+#[derive(Deserialize, Serialize, FromRow, Clone, Debug)]
+pub struct ActorKeyQueryResult {
+    pub profile_id: i64,
+    pub created_at: DateTime<Utc>,
+    pub public_key_pem: String,
+    pub private_key_pem: String,
+}
+
+/// The parts of a remote actor document the server uses, see [`crate::federation::Federation`].
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct RemoteActor {
+    pub id: String,
+    pub inbox: String,
+    pub shared_inbox: Option<String>,
+    pub preferred_username: Option<String>,
+    pub public_key_id: String,
+    pub public_key_pem: String,
+}
+
+impl RemoteActor {
+    /// Where public activities go, shared by every follower on the same server when it has one.
+    pub fn delivery_inbox(&self) -> &str {
+        self.shared_inbox.as_deref().unwrap_or(&self.inbox)
+    }
+}
+
+/// A remote note delivered in a `Create`.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct RemoteNoteCreate {
+    pub ap_id: String,
+    pub content: String,
+    pub in_reply_to: Option<String>,
+    pub published: DateTime<Utc>,
+}
+
+/// An inbox activity the server acts on. Anything else is accepted and dropped.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum InboundActivity {
+    /// Answered with an `Accept`.
+    Follow {
+        id: String,
+    },
+    /// The remote actor accepted the follow with `follow_id`.
+    Accept {
+        follow_id: String,
+    },
+    Create(RemoteNoteCreate),
+    Like {
+        id: String,
+        message_id: i64,
+    },
+    Announce {
+        id: String,
+        message_id: i64,
+    },
+    /// Takes back the follow, like or announce with `object_id`.
+    Undo {
+        object_id: String,
+    },
+}
+
+#[derive(Deserialize, Serialize, FromRow, Clone, Debug)]
+pub struct RemoteNoteQueryResult {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub ap_id: String,
+    pub actor_id: String,
+    pub content: String,
+    pub in_reply_to: Option<String>,
+    pub published: DateTime<Utc>,
+}
+
+/// A local message as a `Note`, with the likes and announces remote actors sent.
+#[derive(Deserialize, Serialize, FromRow, Clone, Debug)]
+pub struct NoteQueryResult {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub message: AuthorMessageQueryResult,
+    pub remote_likes: i64,
+    pub remote_announces: i64,
+}
@@ -0,0 +1,810 @@
+use super::model::{
+    ActorKeyQueryResult, InboundActivity, NoteQueryResult, RemoteActor, RemoteNoteCreate,
+    RemoteNoteQueryResult,
+};
+use crate::common::entities::base::{DbConnGetter, DbRepo, EntityId};
+use crate::common::entities::jobs::{model::NewJob, repo::EnqueueJobFn};
+use crate::common::entities::messages::model::{AuthorMessageQueryResult, NewMessage};
+use crate::error::{ClientSideError, IntoClientResult, Result, ServerSideError};
+use crate::federation::{activities, delivery::DeliveryPayload, signatures};
+use crate::schemas::message::MessageGroupTypes;
+use crate::worker::DELIVER_ACTIVITY;
+use async_trait::async_trait;
+use chrono::Utc;
+use mockall::automock;
+use serde_json::Value;
+use sqlx::{FromRow, Pool, Postgres};
+use tracing::{error, info, instrument};
+
+// Activities are sent by `deliver_activity` jobs, so federation needs the postgres backend like
+// every other job.
+mod private_members {
+
+    use super::*;
+
+    #[instrument(skip())]
+    pub(crate) async fn query_actor_key_inner(
+        conn: &Pool<Postgres>,
+        profile_id: i64,
+    ) -> Result<Option<ActorKeyQueryResult>> {
+        sqlx::query_as::<_, ActorKeyQueryResult>("select * from actor_key where profile_id = $1")
+            .bind(profile_id)
+            .fetch_optional(conn)
+            .await
+            .map_err(ServerSideError::from)
+            .into_client_result()
+    }
+
+    /// Keeps the key of a concurrent request that stored one first.
+    #[instrument(skip(public_key_pem, private_key_pem))]
+    pub(crate) async fn insert_actor_key_inner(
+        conn: &Pool<Postgres>,
+        profile_id: i64,
+        public_key_pem: &str,
+        private_key_pem: &str,
+    ) -> Result<()> {
+        sqlx::query::<_>(
+            r"
+            insert into actor_key (profile_id, public_key_pem, private_key_pem)
+                values ($1, $2, $3)
+                on conflict (profile_id) do nothing
+            ",
+        )
+        .bind(profile_id)
+        .bind(public_key_pem)
+        .bind(private_key_pem)
+        .execute(conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to insert actor key: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+        Ok(())
+    }
+
+    /// A public, published message with the reactions remote actors sent.
+    #[instrument(skip())]
+    pub(crate) async fn query_note_inner(
+        conn: &Pool<Postgres>,
+        id: i64,
+    ) -> Result<Option<NoteQueryResult>> {
+        sqlx::query_as::<_, NoteQueryResult>(
+            r"
+            select m.id, coalesce(m.publish_at, m.created_at) as published_at, m.body,
+                    m.msg_group_type, m.user_id, p.user_name,
+                    mb.broadcasting_msg_id as broadcast_msg_id, mr.original_msg_id,
+                    (select count(*) from remote_reaction r
+                        where r.message_id = m.id and r.kind = 'like') as remote_likes,
+                    (select count(*) from remote_reaction r
+                        where r.message_id = m.id and r.kind = 'announce') as remote_announces
+                from message m
+                    join profile p on p.id = m.user_id
+                    left join message_broadcast mb on mb.main_msg_id = m.id
+                    left join message_response mr on mr.responding_msg_id = m.id
+                where
+                    m.id = $1
+                    and m.msg_group_type = $2
+                    and m.hidden_at is null
+                    and (m.publish_at is null or m.publish_at <= now())
+            ",
+        )
+        .bind(id)
+        .bind(MessageGroupTypes::Public as i32)
+        .fetch_optional(conn)
+        .await
+        .map_err(ServerSideError::from)
+        .into_client_result()
+    }
+
+    #[derive(Debug, FromRow)]
+    pub(crate) struct FollowerInbox {
+        pub(crate) user_name: String,
+        pub(crate) inbox: String,
+    }
+
+    /// The inboxes of the profile's remote followers, once per shared inbox.
+    #[instrument(skip())]
+    pub(crate) async fn query_follower_inboxes_inner(
+        conn: &Pool<Postgres>,
+        profile_id: i64,
+    ) -> Result<Vec<FollowerInbox>> {
+        sqlx::query_as::<_, FollowerInbox>(
+            r"
+            select distinct p.user_name, f.inbox
+                from remote_follower f
+                    join profile p on p.id = f.profile_id
+                where f.profile_id = $1
+            ",
+        )
+        .bind(profile_id)
+        .fetch_all(conn)
+        .await
+        .map_err(ServerSideError::from)
+        .into_client_result()
+    }
+
+    /// A repeated `Follow` replaces the previous one, its `Undo` names the new id.
+    #[instrument(skip(actor), fields(actor = %actor.id))]
+    pub(crate) async fn insert_remote_follower_inner(
+        conn: &Pool<Postgres>,
+        profile_id: i64,
+        actor: &RemoteActor,
+        follow_activity_id: &str,
+    ) -> Result<()> {
+        sqlx::query::<_>(
+            r"
+            insert into remote_follower (profile_id, actor_id, inbox, follow_activity_id)
+                values ($1, $2, $3, $4)
+                on conflict (profile_id, actor_id)
+                    do update set inbox = excluded.inbox,
+                        follow_activity_id = excluded.follow_activity_id
+            ",
+        )
+        .bind(profile_id)
+        .bind(&actor.id)
+        .bind(actor.delivery_inbox())
+        .bind(follow_activity_id)
+        .execute(conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to insert remote follower: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+        Ok(())
+    }
+
+    /// Following again asks for a new `Accept`.
+    #[instrument(skip(actor), fields(actor = %actor.id))]
+    pub(crate) async fn insert_remote_following_inner(
+        conn: &Pool<Postgres>,
+        profile_id: i64,
+        actor: &RemoteActor,
+        follow_activity_id: &str,
+    ) -> Result<i64> {
+        sqlx::query_as::<_, EntityId>(
+            r"
+            insert into remote_following (profile_id, actor_id, inbox, follow_activity_id)
+                values ($1, $2, $3, $4)
+                on conflict (profile_id, actor_id)
+                    do update set inbox = excluded.inbox,
+                        follow_activity_id = excluded.follow_activity_id,
+                        accepted = false,
+                        updated_at = now()
+                returning id
+            ",
+        )
+        .bind(profile_id)
+        .bind(&actor.id)
+        .bind(&actor.inbox)
+        .bind(follow_activity_id)
+        .fetch_one(conn)
+        .await
+        .map(|row: EntityId| row.id)
+        .map_err(|e| {
+            error!("Failed to insert remote following: {:?}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
+    }
+
+    #[instrument(skip())]
+    pub(crate) async fn accept_remote_following_inner(
+        conn: &Pool<Postgres>,
+        profile_id: i64,
+        actor_id: &str,
+        follow_activity_id: &str,
+    ) -> Result<()> {
+        sqlx::query::<_>(
+            r"
+            update remote_following
+                set accepted = true, updated_at = now()
+                where profile_id = $1 and actor_id = $2 and follow_activity_id = $3
+            ",
+        )
+        .bind(profile_id)
+        .bind(actor_id)
+        .bind(follow_activity_id)
+        .execute(conn)
+        .await
+        .map_err(ServerSideError::from)?;
+        Ok(())
+    }
+
+    /// Keeps the first delivery of a note that arrives more than once.
+    #[instrument(skip(note), fields(ap_id = %note.ap_id))]
+    pub(crate) async fn insert_remote_note_inner(
+        conn: &Pool<Postgres>,
+        actor_id: &str,
+        note: &RemoteNoteCreate,
+    ) -> Result<()> {
+        sqlx::query::<_>(
+            r"
+            insert into remote_note (ap_id, actor_id, content, in_reply_to, published)
+                values ($1, $2, $3, $4, $5)
+                on conflict (ap_id) do nothing
+            ",
+        )
+        .bind(&note.ap_id)
+        .bind(actor_id)
+        .bind(&note.content)
+        .bind(&note.in_reply_to)
+        .bind(note.published)
+        .execute(conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to insert remote note: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+        Ok(())
+    }
+
+    /// Only kept for public messages that can be seen, published ones only so a scheduled message
+    /// doesn't give away that it exists, and once per actor and kind.
+    #[instrument(skip())]
+    pub(crate) async fn insert_remote_reaction_inner(
+        conn: &Pool<Postgres>,
+        activity_id: &str,
+        kind: &str,
+        message_id: i64,
+        actor_id: &str,
+    ) -> Result<()> {
+        sqlx::query::<_>(
+            r"
+            insert into remote_reaction (activity_id, kind, message_id, actor_id)
+                select $1, $2, $3, $4
+                    where exists (
+                        select 1 from message
+                            where
+                                id = $3
+                                and msg_group_type = $5
+                                and hidden_at is null
+                                and (publish_at is null or publish_at <= now())
+                    )
+                on conflict do nothing
+            ",
+        )
+        .bind(activity_id)
+        .bind(kind)
+        .bind(message_id)
+        .bind(actor_id)
+        .bind(MessageGroupTypes::Public as i32)
+        .execute(conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to insert remote reaction: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+        Ok(())
+    }
+
+    /// Removes whichever follow, like or announce of the actor has `activity_id`.
+    #[instrument(skip())]
+    pub(crate) async fn undo_activity_inner(
+        conn: &Pool<Postgres>,
+        profile_id: i64,
+        actor_id: &str,
+        activity_id: &str,
+    ) -> Result<()> {
+        let mut tx = conn.begin().await.map_err(ServerSideError::from)?;
+        sqlx::query::<_>(
+            r"
+            delete from remote_follower
+                where profile_id = $1 and actor_id = $2 and follow_activity_id = $3
+            ",
+        )
+        .bind(profile_id)
+        .bind(actor_id)
+        .bind(activity_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(ServerSideError::from)?;
+        sqlx::query::<_>("delete from remote_reaction where actor_id = $1 and activity_id = $2")
+            .bind(actor_id)
+            .bind(activity_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(ServerSideError::from)?;
+        tx.commit().await.map_err(ServerSideError::from)?;
+        Ok(())
+    }
+
+    /// Notes of the remote actors that accepted the profile's follow, newest first.
+    #[instrument(skip())]
+    pub(crate) async fn query_remote_notes_inner(
+        conn: &Pool<Postgres>,
+        profile_id: i64,
+        limit: i64,
+    ) -> Result<Vec<RemoteNoteQueryResult>> {
+        sqlx::query_as::<_, RemoteNoteQueryResult>(
+            r"
+            select n.*
+                from remote_note n
+                    join remote_following f on f.actor_id = n.actor_id
+                where f.profile_id = $1 and f.accepted
+                order by n.id desc
+                limit $2
+            ",
+        )
+        .bind(profile_id)
+        .bind(limit)
+        .fetch_all(conn)
+        .await
+        .map_err(ServerSideError::from)
+        .into_client_result()
+    }
+}
+
+/// Returned by the backends without a job queue to send activities with.
+pub(crate) fn federation_unsupported() -> ClientSideError {
+    ServerSideError::InvalidInput("Federation needs the postgres storage backend".to_string())
+        .into()
+}
+
+impl DbRepo {
+    /// Queues the activity of a new public message for every remote follower's inbox. Scheduled
+    /// messages are announced once published, see [`DbRepo::announce_later`]. Like the fan-out, a
+    /// failure is only logged.
+    pub(crate) async fn federate_message(&self, message: &NewMessage<'_>) {
+        if !self.federation().enabled || message.msg_group_type != MessageGroupTypes::Public as i32
+        {
+            return;
+        }
+        let inboxes =
+            match private_members::query_follower_inboxes_inner(self.get_conn(), message.user_id)
+                .await
+            {
+                Ok(inboxes) => inboxes,
+                Err(e) => {
+                    error!("Message {} not federated: {}", message.id, e);
+                    return;
+                },
+            };
+        let Some(user_name) = inboxes.first().map(|inbox| inbox.user_name.clone()) else {
+            return;
+        };
+
+        let urls = activities::Urls::new(&self.federation().base_url);
+        let activity = activities::message_activity(
+            &urls,
+            &AuthorMessageQueryResult {
+                id: message.id,
+                published_at: message.publish_at.unwrap_or_else(Utc::now),
+                body: Some(message.body.to_string()),
+                msg_group_type: message.msg_group_type,
+                user_id: message.user_id,
+                user_name,
+                broadcast_msg_id: message.broadcasting_msg_id,
+                original_msg_id: message.original_msg_id,
+            },
+        );
+        for inbox in inboxes {
+            let payload = DeliveryPayload {
+                profile_id: message.user_id,
+                inbox: inbox.inbox,
+                activity: activity.clone(),
+                message_id: Some(message.id),
+            };
+            let job = match serde_json::to_value(&payload) {
+                Ok(payload) => NewJob::new(DELIVER_ACTIVITY, payload),
+                Err(e) => {
+                    error!("Message {} not federated: {}", message.id, e);
+                    return;
+                },
+            };
+            if let Err(e) = self.enqueue_job(job).await {
+                error!("Activity for {} not queued: {}", payload.inbox, e);
+            }
+        }
+    }
+
+    async fn enqueue_activity(&self, profile_id: i64, inbox: &str, activity: Value) -> Result<i64> {
+        let payload = DeliveryPayload {
+            profile_id,
+            inbox: inbox.to_string(),
+            activity,
+            message_id: None,
+        };
+        self.enqueue_job(NewJob::new(
+            DELIVER_ACTIVITY,
+            serde_json::to_value(payload).map_err(ServerSideError::from)?,
+        ))
+        .await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait QueryActorKeyFn {
+    /// The profile's key pair, created on first use.
+    async fn query_actor_key(&self, profile_id: i64) -> Result<ActorKeyQueryResult>;
+}
+
+#[async_trait]
+impl QueryActorKeyFn for DbRepo {
+    async fn query_actor_key(&self, profile_id: i64) -> Result<ActorKeyQueryResult> {
+        if let Some(key) =
+            private_members::query_actor_key_inner(self.get_conn(), profile_id).await?
+        {
+            return Ok(key);
+        }
+
+        // Generating a key takes long enough to keep it off the async workers.
+        let (public_key_pem, private_key_pem) =
+            tokio::task::spawn_blocking(signatures::generate_key_pair)
+                .await
+                .map_err(|e| ServerSideError::InternalServerError(e.to_string()))?
+                .map_err(ServerSideError::InternalServerError)?;
+        private_members::insert_actor_key_inner(
+            self.get_conn(),
+            profile_id,
+            &public_key_pem,
+            &private_key_pem,
+        )
+        .await?;
+        info!(profile_id, "Actor key created");
+
+        private_members::query_actor_key_inner(self.get_conn(), profile_id)
+            .await?
+            .ok_or_else(|| {
+                ServerSideError::ProfileNotFound(format!("No profile found with id: {profile_id}"))
+                    .into()
+            })
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait QueryNoteFn {
+    /// A public message as served to other instances, none once hidden or not published yet.
+    async fn query_note(&self, id: i64) -> Result<Option<NoteQueryResult>>;
+}
+
+#[async_trait]
+impl QueryNoteFn for DbRepo {
+    async fn query_note(&self, id: i64) -> Result<Option<NoteQueryResult>> {
+        private_members::query_note_inner(self.get_conn(), id).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait ReceiveActivityFn {
+    /// Applies an activity `actor` sent to the inbox of the local profile. A `Follow` is
+    /// answered with an `Accept`.
+    async fn receive_activity(
+        &self,
+        profile_id: i64,
+        user_name: &str,
+        actor: &RemoteActor,
+        activity: InboundActivity,
+    ) -> Result<()>;
+}
+
+#[async_trait]
+impl ReceiveActivityFn for DbRepo {
+    async fn receive_activity(
+        &self,
+        profile_id: i64,
+        user_name: &str,
+        actor: &RemoteActor,
+        activity: InboundActivity,
+    ) -> Result<()> {
+        let conn = self.get_conn();
+        match activity {
+            InboundActivity::Follow { id } => {
+                private_members::insert_remote_follower_inner(conn, profile_id, actor, &id).await?;
+                let urls = activities::Urls::new(&self.federation().base_url);
+                let accept = activities::accept(&urls, user_name, &id, &actor.id);
+                self.enqueue_activity(profile_id, &actor.inbox, accept)
+                    .await?;
+            },
+            InboundActivity::Accept { follow_id } => {
+                private_members::accept_remote_following_inner(
+                    conn, profile_id, &actor.id, &follow_id,
+                )
+                .await?;
+            },
+            InboundActivity::Create(note) => {
+                private_members::insert_remote_note_inner(conn, &actor.id, &note).await?;
+            },
+            InboundActivity::Like { id, message_id } => {
+                private_members::insert_remote_reaction_inner(
+                    conn, &id, "like", message_id, &actor.id,
+                )
+                .await?;
+            },
+            InboundActivity::Announce { id, message_id } => {
+                private_members::insert_remote_reaction_inner(
+                    conn, &id, "announce", message_id, &actor.id,
+                )
+                .await?;
+            },
+            InboundActivity::Undo { object_id } => {
+                private_members::undo_activity_inner(conn, profile_id, &actor.id, &object_id)
+                    .await?;
+            },
+        }
+        Ok(())
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait FollowRemoteFn {
+    /// Sends a `Follow` from the local profile to `actor`, the follow counts once accepted.
+    /// Returns the id of the follow.
+    async fn follow_remote(
+        &self,
+        profile_id: i64,
+        user_name: &str,
+        actor: &RemoteActor,
+    ) -> Result<i64>;
+}
+
+#[async_trait]
+impl FollowRemoteFn for DbRepo {
+    async fn follow_remote(
+        &self,
+        profile_id: i64,
+        user_name: &str,
+        actor: &RemoteActor,
+    ) -> Result<i64> {
+        let urls = activities::Urls::new(&self.federation().base_url);
+        let follow_id = urls.activity();
+        let id = private_members::insert_remote_following_inner(
+            self.get_conn(),
+            profile_id,
+            actor,
+            &follow_id,
+        )
+        .await?;
+        let follow = activities::follow(&urls, user_name, &follow_id, &actor.id);
+        self.enqueue_activity(profile_id, &actor.inbox, follow)
+            .await?;
+        Ok(id)
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait QueryRemoteNotesFn {
+    /// The latest `limit` notes of the remote actors the profile follows, newest first.
+    async fn query_remote_notes(
+        &self,
+        profile_id: i64,
+        limit: i64,
+    ) -> Result<Vec<RemoteNoteQueryResult>>;
+}
+
+#[async_trait]
+impl QueryRemoteNotesFn for DbRepo {
+    async fn query_remote_notes(
+        &self,
+        profile_id: i64,
+        limit: i64,
+    ) -> Result<Vec<RemoteNoteQueryResult>> {
+        private_members::query_remote_notes_inner(self.get_conn(), profile_id, limit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::{
+        common::entities::{
+            messages::repo::{InsertMessageFn, ScheduleMessageFn},
+            profile::repo::InsertProfileFn,
+        },
+        common_tests::profile,
+        settings::FederationSettings,
+    };
+
+    const PUBLIC: i32 = MessageGroupTypes::Public as i32;
+
+    fn federated_repo(pool: PgPool) -> DbRepo {
+        DbRepo::from_pool(pool).with_federation(FederationSettings {
+            enabled: true,
+            base_url: "https://local.example".to_string(),
+            ..FederationSettings::default()
+        })
+    }
+
+    /// The activities queued for delivery, oldest first.
+    async fn queued_activities(repo: &DbRepo) -> Vec<DeliveryPayload> {
+        sqlx::query_as::<_, (Value,)>("select payload from job where kind = $1 order by id")
+            .bind(DELIVER_ACTIVITY)
+            .fetch_all(repo.get_conn())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(payload,)| serde_json::from_value(payload).unwrap())
+            .collect()
+    }
+
+    fn remote_actor(name: &str) -> RemoteActor {
+        let id = format!("https://remote.example/users/{name}");
+        RemoteActor {
+            inbox: format!("{id}/inbox"),
+            shared_inbox: Some("https://remote.example/inbox".to_string()),
+            preferred_username: Some(name.to_string()),
+            public_key_id: format!("{id}#main-key"),
+            public_key_pem: String::new(),
+            id,
+        }
+    }
+
+    #[sqlx::test(migrator = "crate::common::entities::base::MIGRATOR")]
+    #[ignore = "needs postgres at DATABASE_URL"]
+    async fn test_reactions_only_count_for_published_public_messages(pool: PgPool) {
+        let repo = federated_repo(pool);
+        let dave = repo.insert_profile(profile("dave")).await.unwrap();
+        let erin = remote_actor("erin");
+        let public = repo
+            .insert_message(dave, "public", PUBLIC, None)
+            .await
+            .unwrap();
+        let circle = repo
+            .insert_message(dave, "circle", MessageGroupTypes::Circle as i32, None)
+            .await
+            .unwrap();
+        let scheduled = repo
+            .schedule_message(
+                dave,
+                "later",
+                PUBLIC,
+                None,
+                Utc::now() + TimeDelta::hours(1),
+            )
+            .await
+            .unwrap();
+        let receive = |activity| repo.receive_activity(dave, "dave", &erin, activity);
+
+        for (id, message_id) in [
+            ("l1", public),
+            // Delivered twice.
+            ("l1", public),
+            ("l2", circle),
+            ("l3", scheduled),
+        ] {
+            receive(InboundActivity::Like { id: id.to_string(), message_id })
+                .await
+                .unwrap();
+        }
+        for (id, message_id) in [("a1", public), ("a2", scheduled)] {
+            receive(InboundActivity::Announce { id: id.to_string(), message_id })
+                .await
+                .unwrap();
+        }
+        let reactions = sqlx::query_as::<_, (String, i64)>(
+            "select activity_id, message_id from remote_reaction order by activity_id",
+        )
+        .fetch_all(repo.get_conn())
+        .await
+        .unwrap();
+        assert_eq!(
+            reactions,
+            [("a1".to_string(), public), ("l1".to_string(), public)]
+        );
+        let note = repo.query_note(public).await.unwrap().unwrap();
+        assert_eq!((note.remote_likes, note.remote_announces), (1, 1));
+
+        receive(InboundActivity::Undo { object_id: "l1".to_string() })
+            .await
+            .unwrap();
+        let note = repo.query_note(public).await.unwrap().unwrap();
+        assert_eq!((note.remote_likes, note.remote_announces), (0, 1));
+    }
+
+    #[sqlx::test(migrator = "crate::common::entities::base::MIGRATOR")]
+    #[ignore = "needs postgres at DATABASE_URL"]
+    async fn test_remote_follow_is_accepted_and_undone(pool: PgPool) {
+        let repo = federated_repo(pool);
+        let dave = repo.insert_profile(profile("dave")).await.unwrap();
+        let erin = remote_actor("erin");
+        let follow_id = "https://remote.example/follows/1";
+
+        repo.receive_activity(
+            dave,
+            "dave",
+            &erin,
+            InboundActivity::Follow { id: follow_id.to_string() },
+        )
+        .await
+        .unwrap();
+        let inboxes = private_members::query_follower_inboxes_inner(repo.get_conn(), dave)
+            .await
+            .unwrap();
+        assert_eq!(
+            inboxes.iter().map(|f| f.inbox.as_str()).collect::<Vec<_>>(),
+            ["https://remote.example/inbox"]
+        );
+        let [accept] = queued_activities(&repo).await.try_into().unwrap();
+        assert_eq!(accept.profile_id, dave);
+        assert_eq!(accept.inbox, erin.inbox);
+        assert_eq!(accept.activity["type"], "Accept");
+        assert_eq!(accept.activity["object"]["id"], follow_id);
+
+        // Public messages now go to the follower's shared inbox, circle messages don't.
+        let message = repo
+            .insert_message(dave, "hello", PUBLIC, None)
+            .await
+            .unwrap();
+        repo.insert_message(dave, "circle", MessageGroupTypes::Circle as i32, None)
+            .await
+            .unwrap();
+        let [_, create] = queued_activities(&repo).await.try_into().unwrap();
+        assert_eq!(create.inbox, "https://remote.example/inbox");
+        assert_eq!(create.message_id, Some(message));
+        assert_eq!(create.activity["type"], "Create");
+        assert_eq!(create.activity["object"]["content"], "<p>hello</p>");
+
+        // An `Undo` of another follow leaves this one alone.
+        for object_id in ["https://remote.example/follows/0", follow_id] {
+            repo.receive_activity(
+                dave,
+                "dave",
+                &erin,
+                InboundActivity::Undo { object_id: object_id.to_string() },
+            )
+            .await
+            .unwrap();
+        }
+        assert!(
+            private_members::query_follower_inboxes_inner(repo.get_conn(), dave)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[sqlx::test(migrator = "crate::common::entities::base::MIGRATOR")]
+    #[ignore = "needs postgres at DATABASE_URL"]
+    async fn test_notes_of_remote_actors_are_read_once_the_follow_is_accepted(pool: PgPool) {
+        let repo = federated_repo(pool);
+        let dave = repo.insert_profile(profile("dave")).await.unwrap();
+        let erin = remote_actor("erin");
+        let receive = |activity| repo.receive_activity(dave, "dave", &erin, activity);
+
+        repo.follow_remote(dave, "dave", &erin).await.unwrap();
+        let [follow] = queued_activities(&repo).await.try_into().unwrap();
+        assert_eq!(follow.inbox, erin.inbox);
+        assert_eq!(follow.activity["type"], "Follow");
+        assert_eq!(follow.activity["object"], erin.id);
+        let follow_id = follow.activity["id"].as_str().unwrap().to_string();
+
+        let note = RemoteNoteCreate {
+            ap_id: "https://remote.example/notes/1".to_string(),
+            content: "hello from afar".to_string(),
+            in_reply_to: None,
+            published: Utc::now(),
+        };
+        // Delivered twice.
+        for _ in 0..2 {
+            receive(InboundActivity::Create(note.clone()))
+                .await
+                .unwrap();
+        }
+        assert!(repo.query_remote_notes(dave, 10).await.unwrap().is_empty());
+
+        receive(InboundActivity::Accept {
+            follow_id: "https://remote.example/follows/other".to_string(),
+        })
+        .await
+        .unwrap();
+        assert!(repo.query_remote_notes(dave, 10).await.unwrap().is_empty());
+
+        receive(InboundActivity::Accept { follow_id })
+            .await
+            .unwrap();
+        let notes = repo.query_remote_notes(dave, 10).await.unwrap();
+        let [read] = notes.as_slice() else {
+            panic!("expected one note: {notes:?}");
+        };
+        assert_eq!(read.ap_id, note.ap_id);
+        assert_eq!(read.actor_id, erin.id);
+        assert_eq!(read.content, "hello from afar");
+    }
+}
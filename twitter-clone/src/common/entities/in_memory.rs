@@ -0,0 +1,1389 @@
+use std::{
+    iter,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    },
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, SubsecRound, Utc};
+use dashmap::DashMap;
+
+use crate::{
+    common::entities::{
+        circles::repo::{AddCircleMemberFn, InsertCircleFn},
+        drafts::{
+            model::{DraftCreate, DraftQueryResult},
+            repo::{
+                draft_not_found, DeleteDraftFn, InsertDraftFn, PublishDraftFn, QueryDraftFn,
+                QueryDraftsFn, UpdateDraftFn,
+            },
+        },
+        federation::{
+            model::{
+                ActorKeyQueryResult, InboundActivity, NoteQueryResult, RemoteActor,
+                RemoteNoteQueryResult,
+            },
+            repo::{
+                federation_unsupported, FollowRemoteFn, QueryActorKeyFn, QueryNoteFn,
+                QueryRemoteNotesFn, ReceiveActivityFn,
+            },
+        },
+        messages::{
+            model::{
+                AuthorMessageQueryResult, MessageWithFollowingAndBroadcastQueryResult,
+                ResponseTargetQueryResult, ScheduledMessageQueryResult,
+            },
+            repo::{
+                CancelScheduledMessageFn, HideMessageFn, InsertMessageFn, InsertPollMessageFn,
+                InsertResponseMessageFn, QueryAuthorMessagesFn, QueryFeedMessagesFn,
+                QueryMessageFn, QueryMessagesFn, QueryResponseTargetsFn, QueryScheduledMessagesFn,
+                RescheduleMessageFn, ScheduleMessageFn,
+            },
+        },
+        polls::{
+            model::{PollCreate, PollOptionQueryResult},
+            repo::{already_voted, poll_closed, poll_option_not_found, QueryPollsFn, VotePollFn},
+        },
+        profile::{
+            model::{ProfileCountsQueryResult, ProfileCreate, ProfileQueryResult},
+            repo::{
+                DeleteProfileFn, FollowUserFn, InsertProfileFn, QueryProfileByUserFn,
+                QueryProfileCountsFn, QueryProfileFn, QueryProfilesFn, UpdateProfileAvatarFn,
+            },
+        },
+        webhooks::{
+            model::{WebhookCreate, WebhookDeliveryQueryResult, WebhookQueryResult},
+            repo::{
+                webhooks_unsupported, DeleteWebhookFn, InsertWebhookFn, QueryWebhookDeliveriesFn,
+                QueryWebhooksFn, RedeliverWebhookFn,
+            },
+        },
+    },
+    error::{ClientSideError, Result, ServerSideError},
+    schemas::message::MessageGroupTypes,
+};
+
+/// Repository keeping every table in concurrent maps, for running the api without postgres.
+///
+/// It mirrors the behavior of [`super::base::DbRepo`]: ids come from per table sequences,
+/// timestamps are truncated to milliseconds like `timestamptz(3)`, foreign keys are checked and
+/// the timeline is the same join of `message` against `follow`. Clones share the same storage.
+#[derive(Debug, Default, Clone)]
+pub struct InMemoryRepo {
+    store: Arc<Store>,
+}
+
+#[derive(Debug, Default)]
+struct Store {
+    profiles: Table<ProfileQueryResult>,
+    follows: Table<FollowRow>,
+    messages: Table<MessageRow>,
+    message_responses: Table<MessageLinkRow>,
+    message_broadcasts: Table<MessageLinkRow>,
+    circle_groups: Table<CircleGroupRow>,
+    circle_group_members: Table<CircleGroupMemberRow>,
+    drafts: Table<DraftQueryResult>,
+    polls: Table<PollRow>,
+    poll_options: Table<PollOptionRow>,
+    poll_votes: Table<PollVoteRow>,
+}
+
+/// A map of rows with its own id sequence, like a `bigserial` primary key.
+#[derive(Debug)]
+struct Table<T> {
+    sequence: AtomicI64,
+    rows: DashMap<i64, T>,
+}
+
+impl<T> Default for Table<T> {
+    fn default() -> Self {
+        Self {
+            sequence: AtomicI64::new(0),
+            rows: DashMap::new(),
+        }
+    }
+}
+
+impl<T> Table<T> {
+    fn insert(&self, build: impl FnOnce(i64) -> T) -> i64 {
+        let id = self.sequence.fetch_add(1, Ordering::SeqCst) + 1;
+        self.rows.insert(id, build(id));
+        id
+    }
+
+    fn contains(&self, id: i64) -> bool {
+        self.rows.contains_key(&id)
+    }
+
+    fn remove_if(&self, id: i64, condition: impl FnOnce(&T) -> bool) -> Option<T> {
+        self.rows
+            .remove_if(&id, |_, row| condition(row))
+            .map(|(_, row)| row)
+    }
+}
+
+impl<T: Clone> Table<T> {
+    fn get(&self, id: i64) -> Option<T> {
+        self.rows.get(&id).map(|row| row.value().clone())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FollowRow {
+    follower_id: i64,
+    following_id: i64,
+}
+
+#[derive(Debug, Clone)]
+struct MessageRow {
+    id: i64,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    user_id: i64,
+    body: Option<String>,
+    likes: i32,
+    msg_group_type: i32,
+    hidden_at: Option<DateTime<Utc>>,
+    publish_at: Option<DateTime<Utc>>,
+}
+
+impl MessageRow {
+    fn is_published(&self) -> bool {
+        self.publish_at
+            .is_none_or(|publish_at| publish_at <= Utc::now())
+    }
+
+    fn is_scheduled(&self) -> bool {
+        !self.is_published() && self.hidden_at.is_none()
+    }
+}
+
+/// Row of `message_response` or `message_broadcast`, both link two messages.
+#[derive(Debug, Clone)]
+struct MessageLinkRow {
+    main_msg_id: i64,
+    linked_msg_id: i64,
+}
+
+#[derive(Debug, Clone)]
+struct PollRow {
+    message_id: i64,
+    closes_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+struct PollOptionRow {
+    poll_id: i64,
+    position: i32,
+    label: String,
+}
+
+#[derive(Debug, Clone)]
+struct PollVoteRow {
+    poll_id: i64,
+    option_id: i64,
+    user_id: i64,
+}
+
+#[derive(Debug, Clone)]
+struct CircleGroupRow {
+    owner_id: i64,
+}
+
+#[derive(Debug, Clone)]
+struct CircleGroupMemberRow {
+    circle_group_id: i64,
+    member_id: i64,
+}
+
+impl InMemoryRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn insert_message_row(
+        &self,
+        user_id: i64,
+        body: &str,
+        group_type: i32,
+        publish_at: Option<DateTime<Utc>>,
+    ) -> Result<i64> {
+        if !self.store.profiles.contains(user_id) {
+            return Err(foreign_key_violation("message", "fk_profile"));
+        }
+        let created_at = now();
+        let publish_at = publish_at.map(|at| at.trunc_subsecs(3));
+        Ok(self.store.messages.insert(|id| MessageRow {
+            id,
+            created_at,
+            updated_at: publish_at.unwrap_or(created_at),
+            user_id,
+            body: Some(body.to_string()),
+            likes: 0,
+            msg_group_type: group_type,
+            hidden_at: None,
+            publish_at,
+        }))
+    }
+
+    fn insert_broadcast_message(
+        &self,
+        user_id: i64,
+        body: &str,
+        group_type: i32,
+        broadcasting_msg_id: Option<i64>,
+        publish_at: Option<DateTime<Utc>>,
+        poll: Option<PollCreate>,
+    ) -> Result<i64> {
+        // Checked up front, the postgres transaction would roll the message back instead.
+        if let Some(bm_id) = broadcasting_msg_id {
+            if !self.store.messages.contains(bm_id) {
+                return Err(foreign_key_violation(
+                    "message_broadcast",
+                    "fk_broadcasting_message",
+                ));
+            }
+        }
+
+        let message_id = self.insert_message_row(user_id, body, group_type, publish_at)?;
+        if let Some(bm_id) = broadcasting_msg_id {
+            self.store.message_broadcasts.insert(|_| MessageLinkRow {
+                main_msg_id: message_id,
+                linked_msg_id: bm_id,
+            });
+        }
+        if let Some(poll) = poll {
+            let closes_at = poll.closes_at.trunc_subsecs(3);
+            let poll_id = self
+                .store
+                .polls
+                .insert(|_| PollRow { message_id, closes_at });
+            for (position, label) in (1..).zip(poll.options) {
+                self.store
+                    .poll_options
+                    .insert(|_| PollOptionRow { poll_id, position, label });
+            }
+        }
+        Ok(message_id)
+    }
+
+    /// Drops the polls of removed messages with their options and votes, the `on delete cascade`
+    /// of the poll tables.
+    fn remove_polls(&self, message_ids: &[i64]) {
+        let store = &self.store;
+        let poll_ids = store
+            .polls
+            .rows
+            .iter()
+            .filter(|poll| message_ids.contains(&poll.message_id))
+            .map(|poll| *poll.key())
+            .collect::<Vec<i64>>();
+        store
+            .poll_votes
+            .rows
+            .retain(|_, vote| !poll_ids.contains(&vote.poll_id));
+        store
+            .poll_options
+            .rows
+            .retain(|_, option| !poll_ids.contains(&option.poll_id));
+        store.polls.rows.retain(|id, _| !poll_ids.contains(id));
+    }
+
+    fn visible_message(&self, id: i64) -> Option<MessageRow> {
+        self.store
+            .messages
+            .get(id)
+            .filter(|message| message.hidden_at.is_none() && message.is_published())
+    }
+
+    fn scheduled_not_found(id: i64) -> ClientSideError {
+        ServerSideError::MessageNotFound(format!("No scheduled message found with id: {id}")).into()
+    }
+
+    /// Checks the draft targets like the `draft` foreign keys do.
+    fn check_draft(&self, user_id: i64, draft: &DraftCreate) -> Result<()> {
+        if !self.store.profiles.contains(user_id) {
+            return Err(foreign_key_violation("draft", "fk_profile"));
+        }
+        let targets = [
+            (draft.broadcasting_msg_id, "fk_broadcasting_message"),
+            (draft.original_msg_id, "fk_original_message"),
+        ];
+        for (target, constraint) in targets {
+            if target.is_some_and(|id| !self.store.messages.contains(id)) {
+                return Err(foreign_key_violation("draft", constraint));
+            }
+        }
+        Ok(())
+    }
+
+    /// `on delete set null` of the draft targets, for messages that were just removed.
+    fn clear_draft_targets(&self, message_ids: &[i64]) {
+        for mut draft in self.store.drafts.rows.iter_mut() {
+            if draft
+                .broadcasting_msg_id
+                .is_some_and(|id| message_ids.contains(&id))
+            {
+                draft.broadcasting_msg_id = None;
+            }
+            if draft
+                .original_msg_id
+                .is_some_and(|id| message_ids.contains(&id))
+            {
+                draft.original_msg_id = None;
+            }
+        }
+    }
+
+    /// Joins a message with its author and resolves the message it broadcasts, if any.
+    fn message_with_broadcast(
+        &self,
+        message: MessageRow,
+    ) -> Option<MessageWithFollowingAndBroadcastQueryResult> {
+        let profile = self.store.profiles.get(message.user_id)?;
+        let broadcast_msg_id = self
+            .store
+            .message_broadcasts
+            .rows
+            .iter()
+            .find(|link| link.main_msg_id == message.id)
+            .map(|link| link.linked_msg_id);
+
+        let mut result = MessageWithFollowingAndBroadcastQueryResult {
+            id: message.id,
+            updated_at: message.updated_at,
+            published_at: message.publish_at.unwrap_or(message.created_at),
+            body: message.body,
+            likes: message.likes,
+            msg_group_type: message.msg_group_type,
+            user_id: profile.id,
+            user_name: profile.user_name,
+            full_name: profile.full_name,
+            broadcast_msg_id: None,
+            broadcast_msg_updated_at: None,
+            broadcast_msg_published_at: None,
+            broadcast_msg_body: None,
+            broadcast_msg_likes: None,
+            broadcast_msg_user_id: None,
+            broadcast_msg_user_name: None,
+            broadcast_msg_full_name: None,
+        };
+
+        let broadcast = broadcast_msg_id
+            .and_then(|id| self.visible_message(id))
+            .and_then(|source| Some((self.store.profiles.get(source.user_id)?, source)));
+        if let Some((source_profile, source)) = broadcast {
+            result.broadcast_msg_id = Some(source.id);
+            result.broadcast_msg_updated_at = Some(source.updated_at);
+            result.broadcast_msg_published_at =
+                Some(source.publish_at.unwrap_or(source.created_at));
+            result.broadcast_msg_body = source.body;
+            result.broadcast_msg_likes = Some(source.likes);
+            result.broadcast_msg_user_id = Some(source_profile.id);
+            result.broadcast_msg_user_name = Some(source_profile.user_name);
+            result.broadcast_msg_full_name = Some(source_profile.full_name);
+        }
+
+        Some(result)
+    }
+}
+
+#[async_trait]
+impl InsertMessageFn for InMemoryRepo {
+    async fn insert_message(
+        &self,
+        user_id: i64,
+        body: &str,
+        group_type: i32,
+        broadcasting_msg_id: Option<i64>,
+    ) -> Result<i64> {
+        self.insert_broadcast_message(user_id, body, group_type, broadcasting_msg_id, None, None)
+    }
+}
+
+#[async_trait]
+impl ScheduleMessageFn for InMemoryRepo {
+    async fn schedule_message(
+        &self,
+        user_id: i64,
+        body: &str,
+        group_type: i32,
+        broadcasting_msg_id: Option<i64>,
+        publish_at: DateTime<Utc>,
+    ) -> Result<i64> {
+        self.insert_broadcast_message(
+            user_id,
+            body,
+            group_type,
+            broadcasting_msg_id,
+            Some(publish_at),
+            None,
+        )
+    }
+}
+
+#[async_trait]
+impl InsertPollMessageFn for InMemoryRepo {
+    async fn insert_poll_message(
+        &self,
+        user_id: i64,
+        body: &str,
+        group_type: i32,
+        broadcasting_msg_id: Option<i64>,
+        poll: PollCreate,
+    ) -> Result<i64> {
+        self.insert_broadcast_message(
+            user_id,
+            body,
+            group_type,
+            broadcasting_msg_id,
+            None,
+            Some(poll),
+        )
+    }
+}
+
+#[async_trait]
+impl QueryScheduledMessagesFn for InMemoryRepo {
+    async fn query_scheduled_messages(
+        &self,
+        user_id: i64,
+    ) -> Result<Vec<ScheduledMessageQueryResult>> {
+        let mut scheduled = self
+            .store
+            .messages
+            .rows
+            .iter()
+            .filter(|message| message.user_id == user_id && message.is_scheduled())
+            .map(|message| ScheduledMessageQueryResult {
+                id: message.id,
+                created_at: message.created_at,
+                user_id: message.user_id,
+                body: message.body.clone(),
+                msg_group_type: message.msg_group_type,
+                broadcast_msg_id: self
+                    .store
+                    .message_broadcasts
+                    .rows
+                    .iter()
+                    .find(|link| link.main_msg_id == message.id)
+                    .map(|link| link.linked_msg_id),
+                // Kept equal to `publish_at` until the message is published.
+                publish_at: message.updated_at,
+            })
+            .collect::<Vec<ScheduledMessageQueryResult>>();
+        scheduled.sort_by(|a, b| a.publish_at.cmp(&b.publish_at).then(a.id.cmp(&b.id)));
+        Ok(scheduled)
+    }
+}
+
+#[async_trait]
+impl RescheduleMessageFn for InMemoryRepo {
+    async fn reschedule_message(&self, id: i64, publish_at: DateTime<Utc>) -> Result<()> {
+        match self.store.messages.rows.get_mut(&id) {
+            Some(mut message) if message.is_scheduled() => {
+                let publish_at = publish_at.trunc_subsecs(3);
+                message.publish_at = Some(publish_at);
+                message.updated_at = publish_at;
+                Ok(())
+            },
+            _ => Err(Self::scheduled_not_found(id)),
+        }
+    }
+}
+
+#[async_trait]
+impl CancelScheduledMessageFn for InMemoryRepo {
+    async fn cancel_scheduled_message(&self, id: i64) -> Result<()> {
+        if self
+            .store
+            .messages
+            .remove_if(id, MessageRow::is_scheduled)
+            .is_none()
+        {
+            return Err(Self::scheduled_not_found(id));
+        }
+
+        let store = &self.store;
+        for links in [&store.message_responses, &store.message_broadcasts] {
+            links
+                .rows
+                .retain(|_, link| link.main_msg_id != id && link.linked_msg_id != id);
+        }
+        self.clear_draft_targets(&[id]);
+        self.remove_polls(&[id]);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl InsertResponseMessageFn for InMemoryRepo {
+    async fn insert_response_message(
+        &self,
+        user_id: i64,
+        body: &str,
+        group_type: i32,
+        original_msg_id: i64,
+    ) -> Result<i64> {
+        if !self.store.messages.contains(original_msg_id) {
+            return Err(foreign_key_violation(
+                "message_response",
+                "fk_original_message",
+            ));
+        }
+
+        let message_id = self.insert_message_row(user_id, body, group_type, None)?;
+        self.store.message_responses.insert(|_| MessageLinkRow {
+            main_msg_id: original_msg_id,
+            linked_msg_id: message_id,
+        });
+        Ok(message_id)
+    }
+}
+
+#[async_trait]
+impl QueryMessageFn for InMemoryRepo {
+    async fn query_message(
+        &self,
+        id: i64,
+    ) -> Result<Option<MessageWithFollowingAndBroadcastQueryResult>> {
+        Ok(self
+            .visible_message(id)
+            .and_then(|message| self.message_with_broadcast(message)))
+    }
+}
+
+#[async_trait]
+impl QueryMessagesFn for InMemoryRepo {
+    async fn query_messages(
+        &self,
+        user_id: i64,
+        last_updated_at: DateTime<Utc>,
+        page_size: i16,
+    ) -> Result<Vec<MessageWithFollowingAndBroadcastQueryResult>> {
+        let limit = usize::try_from(page_size).map_err(|_| {
+            ClientSideError::from(ServerSideError::InternalServerError(
+                "LIMIT must not be negative".to_string(),
+            ))
+        })?;
+
+        let following_ids = self
+            .store
+            .follows
+            .rows
+            .iter()
+            .filter(|follow| follow.follower_id == user_id)
+            .map(|follow| follow.following_id)
+            .collect::<Vec<i64>>();
+
+        // One row per matching follow, like the `join follow` of the postgres query.
+        let mut messages = following_ids
+            .iter()
+            .flat_map(|following_id| {
+                self.store
+                    .messages
+                    .rows
+                    .iter()
+                    .filter(|message| {
+                        message.user_id == *following_id
+                            && message.updated_at < last_updated_at
+                            && message.hidden_at.is_none()
+                            && message.is_published()
+                    })
+                    .map(|message| message.value().clone())
+                    .collect::<Vec<MessageRow>>()
+            })
+            .collect::<Vec<MessageRow>>();
+        messages.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then(b.id.cmp(&a.id)));
+
+        Ok(messages
+            .into_iter()
+            .filter_map(|message| self.message_with_broadcast(message))
+            .take(limit)
+            .collect())
+    }
+}
+
+#[async_trait]
+impl QueryAuthorMessagesFn for InMemoryRepo {
+    async fn query_author_messages(
+        &self,
+        user_id: i64,
+        before_id: Option<i64>,
+        page_size: i16,
+    ) -> Result<Vec<AuthorMessageQueryResult>> {
+        let limit = usize::try_from(page_size).map_err(|_| {
+            ClientSideError::from(ServerSideError::InternalServerError(
+                "LIMIT must not be negative".to_string(),
+            ))
+        })?;
+        let Some(profile) = self.store.profiles.get(user_id) else {
+            return Ok(Vec::new());
+        };
+
+        let mut messages = self
+            .store
+            .messages
+            .rows
+            .iter()
+            .filter(|message| {
+                message.user_id == user_id
+                    && before_id.is_none_or(|before_id| message.id < before_id)
+                    && message.hidden_at.is_none()
+                    && message.is_published()
+            })
+            .map(|message| message.value().clone())
+            .collect::<Vec<MessageRow>>();
+        messages.sort_by(|a, b| b.id.cmp(&a.id));
+
+        let store = &self.store;
+        Ok(messages
+            .into_iter()
+            .take(limit)
+            .map(|message| AuthorMessageQueryResult {
+                id: message.id,
+                published_at: message.publish_at.unwrap_or(message.created_at),
+                body: message.body,
+                msg_group_type: message.msg_group_type,
+                user_id,
+                user_name: profile.user_name.clone(),
+                broadcast_msg_id: store
+                    .message_broadcasts
+                    .rows
+                    .iter()
+                    .find(|link| link.main_msg_id == message.id)
+                    .map(|link| link.linked_msg_id),
+                original_msg_id: store
+                    .message_responses
+                    .rows
+                    .iter()
+                    .find(|link| link.linked_msg_id == message.id)
+                    .map(|link| link.main_msg_id),
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl QueryFeedMessagesFn for InMemoryRepo {
+    async fn query_feed_messages(
+        &self,
+        user_id: i64,
+        page_size: i16,
+    ) -> Result<Vec<MessageWithFollowingAndBroadcastQueryResult>> {
+        let limit = usize::try_from(page_size).map_err(|_| {
+            ClientSideError::from(ServerSideError::InternalServerError(
+                "LIMIT must not be negative".to_string(),
+            ))
+        })?;
+
+        let mut messages = self
+            .store
+            .messages
+            .rows
+            .iter()
+            .filter(|message| {
+                message.user_id == user_id
+                    && message.msg_group_type == MessageGroupTypes::Public as i32
+                    && message.hidden_at.is_none()
+                    && message.is_published()
+            })
+            .map(|message| message.value().clone())
+            .collect::<Vec<MessageRow>>();
+        messages.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then(b.id.cmp(&a.id)));
+
+        Ok(messages
+            .into_iter()
+            .filter_map(|message| self.message_with_broadcast(message))
+            .take(limit)
+            .collect())
+    }
+}
+
+#[async_trait]
+impl QueryResponseTargetsFn for InMemoryRepo {
+    async fn query_response_targets(
+        &self,
+        message_ids: &[i64],
+    ) -> Result<Vec<ResponseTargetQueryResult>> {
+        let store = &self.store;
+        Ok(store
+            .message_responses
+            .rows
+            .iter()
+            .filter(|link| message_ids.contains(&link.linked_msg_id))
+            .filter_map(|link| {
+                Some(ResponseTargetQueryResult {
+                    responding_msg_id: link.linked_msg_id,
+                    original_msg_id: link.main_msg_id,
+                    original_user_id: store.messages.get(link.main_msg_id)?.user_id,
+                })
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl HideMessageFn for InMemoryRepo {
+    async fn hide_message(&self, id: i64, hidden: bool) -> Result<()> {
+        let author = match self.store.messages.rows.get_mut(&id) {
+            Some(mut message) => {
+                message.hidden_at = match hidden {
+                    true => message.hidden_at.or(Some(now())),
+                    false => None,
+                };
+                message.user_id
+            },
+            None => {
+                return Err(ServerSideError::MessageNotFound(format!(
+                    "No message found with id: {id}"
+                ))
+                .into())
+            },
+        };
+
+        let broadcasters = self
+            .store
+            .message_broadcasts
+            .rows
+            .iter()
+            .filter(|link| link.linked_msg_id == id)
+            .filter_map(|link| self.store.messages.get(link.main_msg_id))
+            .map(|message| message.user_id)
+            .collect::<Vec<i64>>();
+        for user_id in iter::once(author).chain(broadcasters) {
+            if let Some(mut profile) = self.store.profiles.rows.get_mut(&user_id) {
+                profile.updated_at = now();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl InsertProfileFn for InMemoryRepo {
+    async fn insert_profile(&self, params: ProfileCreate) -> Result<i64> {
+        let now = now();
+        Ok(self.store.profiles.insert(|id| ProfileQueryResult {
+            id,
+            created_at: now,
+            updated_at: now,
+            user_name: params.user_name,
+            full_name: params.full_name,
+            description: params.description,
+            region: params.region,
+            main_url: params.main_url,
+            avatar: params.avatar,
+        }))
+    }
+}
+
+#[async_trait]
+impl UpdateProfileAvatarFn for InMemoryRepo {
+    async fn update_profile_avatar(&self, user_id: i64, avatar: Option<Vec<u8>>) -> Result<()> {
+        match self.store.profiles.rows.get_mut(&user_id) {
+            Some(mut profile) => {
+                profile.avatar = avatar;
+                Ok(())
+            },
+            None => Err(ServerSideError::ProfileNotFound(format!(
+                "No profile found with id: {user_id}"
+            ))
+            .into()),
+        }
+    }
+}
+
+#[async_trait]
+impl QueryProfileFn for InMemoryRepo {
+    async fn query_profile(&self, id: i64) -> Result<Option<ProfileQueryResult>> {
+        Ok(self.store.profiles.get(id))
+    }
+}
+
+#[async_trait]
+impl QueryProfilesFn for InMemoryRepo {
+    async fn query_profiles(&self, ids: &[i64]) -> Result<Vec<ProfileQueryResult>> {
+        Ok(ids
+            .iter()
+            .filter_map(|id| self.store.profiles.get(*id))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl QueryProfileByUserFn for InMemoryRepo {
+    async fn query_profile_by_user(&self, user_name: String) -> Result<Option<ProfileQueryResult>> {
+        Ok(self
+            .store
+            .profiles
+            .rows
+            .iter()
+            .filter(|profile| profile.user_name == user_name)
+            .min_by_key(|profile| profile.id)
+            .map(|profile| profile.value().clone()))
+    }
+}
+
+#[async_trait]
+impl QueryProfileCountsFn for InMemoryRepo {
+    async fn query_profile_counts(
+        &self,
+        profile_ids: &[i64],
+    ) -> Result<Vec<ProfileCountsQueryResult>> {
+        let store = &self.store;
+        let count = |matches: &dyn Fn(&FollowRow) -> bool| {
+            store
+                .follows
+                .rows
+                .iter()
+                .filter(|follow| matches(follow))
+                .count() as i64
+        };
+        Ok(profile_ids
+            .iter()
+            .filter(|id| store.profiles.contains(**id))
+            .map(|&profile_id| ProfileCountsQueryResult {
+                profile_id,
+                followers: count(&|follow| follow.following_id == profile_id),
+                following: count(&|follow| follow.follower_id == profile_id),
+                messages: store
+                    .messages
+                    .rows
+                    .iter()
+                    .filter(|message| {
+                        message.user_id == profile_id
+                            && message.hidden_at.is_none()
+                            && message.is_published()
+                    })
+                    .count() as i64,
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl FollowUserFn for InMemoryRepo {
+    async fn follow_user(&self, follower_id: i64, following_id: i64) -> Result<i64> {
+        if !self.store.profiles.contains(follower_id) {
+            return Err(foreign_key_violation("follow", "fk_profile_follower"));
+        }
+        if !self.store.profiles.contains(following_id) {
+            return Err(foreign_key_violation("follow", "fk_profile_following"));
+        }
+        Ok(self
+            .store
+            .follows
+            .insert(|_| FollowRow { follower_id, following_id }))
+    }
+}
+
+#[async_trait]
+impl DeleteProfileFn for InMemoryRepo {
+    async fn delete_profile(&self, id: i64) -> Result<()> {
+        if !self.store.profiles.contains(id) {
+            return Err(ServerSideError::ProfileNotFound(format!(
+                "No profile found with id: {id}"
+            ))
+            .into());
+        }
+
+        let store = &self.store;
+        let owned_circles = store
+            .circle_groups
+            .rows
+            .iter()
+            .filter(|circle| circle.owner_id == id)
+            .map(|circle| *circle.key())
+            .collect::<Vec<i64>>();
+        store.circle_group_members.rows.retain(|_, member| {
+            member.member_id != id && !owned_circles.contains(&member.circle_group_id)
+        });
+        store
+            .circle_groups
+            .rows
+            .retain(|_, circle| circle.owner_id != id);
+        store
+            .follows
+            .rows
+            .retain(|_, follow| follow.follower_id != id && follow.following_id != id);
+        store.drafts.rows.retain(|_, draft| draft.user_id != id);
+        store.poll_votes.rows.retain(|_, vote| vote.user_id != id);
+
+        let messages = store
+            .messages
+            .rows
+            .iter()
+            .filter(|message| message.user_id == id)
+            .map(|message| *message.key())
+            .collect::<Vec<i64>>();
+        for links in [&store.message_responses, &store.message_broadcasts] {
+            links.rows.retain(|_, link| {
+                !messages.contains(&link.main_msg_id) && !messages.contains(&link.linked_msg_id)
+            });
+        }
+        store
+            .messages
+            .rows
+            .retain(|_, message| message.user_id != id);
+        self.clear_draft_targets(&messages);
+        self.remove_polls(&messages);
+        store.profiles.rows.remove(&id);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl InsertDraftFn for InMemoryRepo {
+    async fn insert_draft(&self, user_id: i64, draft: DraftCreate) -> Result<i64> {
+        self.check_draft(user_id, &draft)?;
+        let now = now();
+        Ok(self.store.drafts.insert(|id| DraftQueryResult {
+            id,
+            created_at: now,
+            updated_at: now,
+            user_id,
+            body: draft.body,
+            msg_group_type: draft.msg_group_type,
+            broadcasting_msg_id: draft.broadcasting_msg_id,
+            original_msg_id: draft.original_msg_id,
+        }))
+    }
+}
+
+#[async_trait]
+impl QueryDraftFn for InMemoryRepo {
+    async fn query_draft(&self, id: i64, user_id: i64) -> Result<Option<DraftQueryResult>> {
+        Ok(self
+            .store
+            .drafts
+            .get(id)
+            .filter(|draft| draft.user_id == user_id))
+    }
+}
+
+#[async_trait]
+impl QueryDraftsFn for InMemoryRepo {
+    async fn query_drafts(&self, user_id: i64) -> Result<Vec<DraftQueryResult>> {
+        let mut drafts = self
+            .store
+            .drafts
+            .rows
+            .iter()
+            .filter(|draft| draft.user_id == user_id)
+            .map(|draft| draft.value().clone())
+            .collect::<Vec<DraftQueryResult>>();
+        drafts.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then(b.id.cmp(&a.id)));
+        Ok(drafts)
+    }
+}
+
+#[async_trait]
+impl UpdateDraftFn for InMemoryRepo {
+    async fn update_draft(&self, id: i64, user_id: i64, draft: DraftCreate) -> Result<()> {
+        self.check_draft(user_id, &draft)?;
+        match self.store.drafts.rows.get_mut(&id) {
+            Some(mut row) if row.user_id == user_id => {
+                row.body = draft.body;
+                row.msg_group_type = draft.msg_group_type;
+                row.broadcasting_msg_id = draft.broadcasting_msg_id;
+                row.original_msg_id = draft.original_msg_id;
+                row.updated_at = now();
+                Ok(())
+            },
+            _ => Err(draft_not_found(id)),
+        }
+    }
+}
+
+#[async_trait]
+impl DeleteDraftFn for InMemoryRepo {
+    async fn delete_draft(&self, id: i64, user_id: i64) -> Result<()> {
+        self.store
+            .drafts
+            .remove_if(id, |draft| draft.user_id == user_id)
+            .map(|_| ())
+            .ok_or_else(|| draft_not_found(id))
+    }
+}
+
+#[async_trait]
+impl PublishDraftFn for InMemoryRepo {
+    async fn publish_draft(&self, id: i64, user_id: i64) -> Result<i64> {
+        // Taking the row out first keeps two concurrent publications from both succeeding.
+        let draft = self
+            .store
+            .drafts
+            .remove_if(id, |draft| draft.user_id == user_id)
+            .ok_or_else(|| draft_not_found(id))?;
+
+        let inserted = match draft.original_msg_id {
+            Some(original_msg_id) => {
+                self.insert_response_message(
+                    draft.user_id,
+                    &draft.body,
+                    draft.msg_group_type,
+                    original_msg_id,
+                )
+                .await
+            },
+            None => self.insert_broadcast_message(
+                draft.user_id,
+                &draft.body,
+                draft.msg_group_type,
+                draft.broadcasting_msg_id,
+                None,
+                None,
+            ),
+        };
+        if inserted.is_err() {
+            self.store.drafts.rows.insert(id, draft);
+        }
+        inserted
+    }
+}
+
+#[async_trait]
+impl QueryPollsFn for InMemoryRepo {
+    async fn query_polls(
+        &self,
+        message_ids: &[i64],
+        viewer_id: Option<i64>,
+    ) -> Result<Vec<PollOptionQueryResult>> {
+        let store = &self.store;
+        let mut options = store
+            .poll_options
+            .rows
+            .iter()
+            .filter_map(|option| {
+                let poll = store
+                    .polls
+                    .get(option.poll_id)
+                    .filter(|poll| message_ids.contains(&poll.message_id))?;
+                let votes = store
+                    .poll_votes
+                    .rows
+                    .iter()
+                    .filter(|vote| vote.option_id == *option.key())
+                    .map(|vote| vote.user_id)
+                    .collect::<Vec<i64>>();
+                Some(PollOptionQueryResult {
+                    poll_id: option.poll_id,
+                    message_id: poll.message_id,
+                    closes_at: poll.closes_at,
+                    option_id: *option.key(),
+                    position: option.position,
+                    label: option.label.clone(),
+                    votes: votes.len() as i64,
+                    viewer_voted: viewer_id.is_some_and(|viewer_id| votes.contains(&viewer_id)),
+                })
+            })
+            .collect::<Vec<PollOptionQueryResult>>();
+        options.sort_by_key(|option| (option.poll_id, option.position));
+        Ok(options)
+    }
+}
+
+#[async_trait]
+impl VotePollFn for InMemoryRepo {
+    async fn vote_poll(&self, message_id: i64, user_id: i64, option_id: i64) -> Result<i64> {
+        let store = &self.store;
+        let (poll_id, poll) = store
+            .poll_options
+            .get(option_id)
+            .and_then(|option| Some((option.poll_id, store.polls.get(option.poll_id)?)))
+            .filter(|(_, poll)| {
+                poll.message_id == message_id
+                    && self
+                        .store
+                        .messages
+                        .get(message_id)
+                        .is_some_and(|message| message.hidden_at.is_none())
+            })
+            .ok_or_else(|| poll_option_not_found(message_id, option_id))?;
+        if poll.closes_at <= Utc::now() {
+            return Err(poll_closed());
+        }
+        if !store.profiles.contains(user_id) {
+            return Err(foreign_key_violation("poll_vote", "fk_profile"));
+        }
+        if store
+            .poll_votes
+            .rows
+            .iter()
+            .any(|vote| vote.poll_id == poll_id && vote.user_id == user_id)
+        {
+            return Err(already_voted(user_id));
+        }
+
+        Ok(store
+            .poll_votes
+            .insert(|_| PollVoteRow { poll_id, option_id, user_id }))
+    }
+}
+
+#[async_trait]
+impl InsertCircleFn for InMemoryRepo {
+    async fn insert_circle(&self, owner_id: i64) -> Result<i64> {
+        if !self.store.profiles.contains(owner_id) {
+            return Err(foreign_key_violation("circle_group", "fk_profile"));
+        }
+        Ok(self
+            .store
+            .circle_groups
+            .insert(|_| CircleGroupRow { owner_id }))
+    }
+}
+
+#[async_trait]
+impl AddCircleMemberFn for InMemoryRepo {
+    async fn add_circle_member(&self, circle_group_id: i64, member_id: i64) -> Result<i64> {
+        if !self.store.circle_groups.contains(circle_group_id) {
+            return Err(foreign_key_violation(
+                "circle_group_member",
+                "fk_circle_group",
+            ));
+        }
+        if !self.store.profiles.contains(member_id) {
+            return Err(foreign_key_violation("circle_group_member", "fk_profile"));
+        }
+        Ok(self
+            .store
+            .circle_group_members
+            .insert(|_| CircleGroupMemberRow { circle_group_id, member_id }))
+    }
+}
+
+// Without a job queue there is nothing to send deliveries with, see `webhooks_unsupported`.
+
+#[async_trait]
+impl InsertWebhookFn for InMemoryRepo {
+    async fn insert_webhook(&self, _webhook: WebhookCreate) -> Result<i64> {
+        Err(webhooks_unsupported())
+    }
+}
+
+#[async_trait]
+impl QueryWebhooksFn for InMemoryRepo {
+    async fn query_webhooks(&self) -> Result<Vec<WebhookQueryResult>> {
+        Err(webhooks_unsupported())
+    }
+}
+
+#[async_trait]
+impl DeleteWebhookFn for InMemoryRepo {
+    async fn delete_webhook(&self, _id: i64) -> Result<()> {
+        Err(webhooks_unsupported())
+    }
+}
+
+#[async_trait]
+impl QueryWebhookDeliveriesFn for InMemoryRepo {
+    async fn query_webhook_deliveries(
+        &self,
+        _webhook_id: i64,
+        _limit: i64,
+    ) -> Result<Vec<WebhookDeliveryQueryResult>> {
+        Err(webhooks_unsupported())
+    }
+}
+
+#[async_trait]
+impl RedeliverWebhookFn for InMemoryRepo {
+    async fn redeliver_webhook(&self, _webhook_id: i64, _delivery_id: i64) -> Result<i64> {
+        Err(webhooks_unsupported())
+    }
+}
+
+// Same for the activities of federation, see `federation_unsupported`.
+
+#[async_trait]
+impl QueryActorKeyFn for InMemoryRepo {
+    async fn query_actor_key(&self, _profile_id: i64) -> Result<ActorKeyQueryResult> {
+        Err(federation_unsupported())
+    }
+}
+
+#[async_trait]
+impl QueryNoteFn for InMemoryRepo {
+    async fn query_note(&self, _id: i64) -> Result<Option<NoteQueryResult>> {
+        Err(federation_unsupported())
+    }
+}
+
+#[async_trait]
+impl ReceiveActivityFn for InMemoryRepo {
+    async fn receive_activity(
+        &self,
+        _profile_id: i64,
+        _user_name: &str,
+        _actor: &RemoteActor,
+        _activity: InboundActivity,
+    ) -> Result<()> {
+        Err(federation_unsupported())
+    }
+}
+
+#[async_trait]
+impl FollowRemoteFn for InMemoryRepo {
+    async fn follow_remote(
+        &self,
+        _profile_id: i64,
+        _user_name: &str,
+        _actor: &RemoteActor,
+    ) -> Result<i64> {
+        Err(federation_unsupported())
+    }
+}
+
+#[async_trait]
+impl QueryRemoteNotesFn for InMemoryRepo {
+    async fn query_remote_notes(
+        &self,
+        _profile_id: i64,
+        _limit: i64,
+    ) -> Result<Vec<RemoteNoteQueryResult>> {
+        Err(federation_unsupported())
+    }
+}
+
+/// Current time at the `timestamptz(3)` precision postgres stores.
+fn now() -> DateTime<Utc> {
+    Utc::now().trunc_subsecs(3)
+}
+
+/// Same outcome as a postgres foreign key violation, an internal server error for the client.
+fn foreign_key_violation(table: &str, constraint: &str) -> ClientSideError {
+    ServerSideError::InternalServerError(format!(
+        "insert or update on table \"{table}\" violates foreign key constraint \"{constraint}\""
+    ))
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common_tests::profile,
+        schemas::message::MessageGroupTypes,
+        seed::{self, SeedOptions, SeedPlan},
+    };
+
+    const PUBLIC: i32 = MessageGroupTypes::Public as i32;
+
+    #[tokio::test]
+    async fn test_timeline_joins_follows_and_resolves_broadcasts() {
+        let repo = InMemoryRepo::new();
+        let reader = repo.insert_profile(profile("reader")).await.unwrap();
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let stranger = repo.insert_profile(profile("stranger")).await.unwrap();
+        repo.follow_user(reader, author).await.unwrap();
+
+        let original = repo
+            .insert_message(stranger, "original", PUBLIC, None)
+            .await
+            .unwrap();
+        let broadcast = repo
+            .insert_message(author, "look", PUBLIC, Some(original))
+            .await
+            .unwrap();
+        repo.insert_message(stranger, "unseen", PUBLIC, None)
+            .await
+            .unwrap();
+
+        let timeline = repo
+            .query_messages(reader, Utc::now() + chrono::Duration::seconds(1), 10)
+            .await
+            .unwrap();
+        assert_eq!(timeline.len(), 1);
+        assert_eq!(timeline[0].id, broadcast);
+        assert_eq!(timeline[0].broadcast_msg_id, Some(original));
+        assert_eq!(
+            timeline[0].broadcast_msg_user_name.as_deref(),
+            Some("stranger")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_timeline_orders_and_pages() {
+        let repo = InMemoryRepo::new();
+        let reader = repo.insert_profile(profile("reader")).await.unwrap();
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        repo.follow_user(reader, author).await.unwrap();
+        for body in ["one", "two", "three"] {
+            repo.insert_message(author, body, PUBLIC, None)
+                .await
+                .unwrap();
+        }
+
+        let later = Utc::now() + chrono::Duration::seconds(1);
+        let first_page = repo.query_messages(reader, later, 2).await.unwrap();
+        let bodies = first_page
+            .iter()
+            .map(|m| m.body.clone().unwrap())
+            .collect::<Vec<String>>();
+        assert_eq!(bodies.len(), 2);
+        assert!(first_page[0].updated_at >= first_page[1].updated_at);
+        assert!(first_page[0].id > first_page[1].id);
+
+        let cursor = first_page.last().unwrap().updated_at;
+        let next_page = repo.query_messages(reader, cursor, 2).await.unwrap();
+        assert!(next_page.iter().all(|m| m.updated_at < cursor));
+    }
+
+    #[tokio::test]
+    async fn test_hidden_messages_are_not_returned() {
+        let repo = InMemoryRepo::new();
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let id = repo
+            .insert_message(author, "hello", PUBLIC, None)
+            .await
+            .unwrap();
+
+        repo.hide_message(id, true).await.unwrap();
+        assert!(repo.query_message(id).await.unwrap().is_none());
+        repo.hide_message(id, false).await.unwrap();
+        assert!(repo.query_message(id).await.unwrap().is_some());
+        assert!(repo.hide_message(id + 1, true).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_foreign_keys_are_enforced() {
+        let repo = InMemoryRepo::new();
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+
+        assert!(repo.insert_message(99, "x", PUBLIC, None).await.is_err());
+        assert!(repo
+            .insert_message(author, "x", PUBLIC, Some(99))
+            .await
+            .is_err());
+        assert!(repo
+            .insert_response_message(author, "x", PUBLIC, 99)
+            .await
+            .is_err());
+        assert!(repo.follow_user(author, 99).await.is_err());
+        // Failed inserts must not leave a message behind.
+        assert!(repo.query_message(1).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_delete_profile_cascades() {
+        let repo = InMemoryRepo::new();
+        let options = SeedOptions { profiles: 20, ..SeedOptions::default() };
+        let summary = seed::apply(&repo, &SeedPlan::generate(&options))
+            .await
+            .unwrap();
+        assert_eq!(summary.profiles, 20);
+
+        for id in 1..=20 {
+            repo.delete_profile(id).await.unwrap();
+        }
+        assert!(repo.store.messages.rows.is_empty());
+        assert!(repo.store.follows.rows.is_empty());
+        assert!(repo.store.message_broadcasts.rows.is_empty());
+        assert!(repo.store.circle_group_members.rows.is_empty());
+        assert!(repo.delete_profile(1).await.is_err());
+    }
+}
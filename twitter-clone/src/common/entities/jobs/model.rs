@@ -0,0 +1,53 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+
+tokio::task_local! {
+    /// `request_id` of the http request being handled, set by the middleware in `serve()` so that
+    /// jobs enqueued on its behalf can be traced back to it.
+    pub static REQUEST_ID: String;
+}
+
+/// A job to enqueue, see [`super::repo::EnqueueJobFn`].
+#[derive(Debug, Clone)]
+pub struct NewJob {
+    pub kind: String,
+    pub payload: Value,
+    /// Runs as soon as a worker is free when unset.
+    pub run_at: Option<DateTime<Utc>>,
+    pub request_id: Option<String>,
+}
+
+impl NewJob {
+    /// A job due now, linked to the current request if there is one.
+    pub fn new(kind: &str, payload: Value) -> Self {
+        Self {
+            kind: kind.to_string(),
+            payload,
+            run_at: None,
+            request_id: REQUEST_ID.try_with(Clone::clone).ok(),
+        }
+    }
+
+    /// Delays the job until `run_at`.
+    pub fn run_at(mut self, run_at: DateTime<Utc>) -> Self {
+        self.run_at = Some(run_at);
+        self
+    }
+}
+
+#[derive(Deserialize, Serialize, FromRow, Clone, Debug)]
+pub struct JobQueryResult {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub kind: String,
+    pub payload: Value,
+    pub status: String,
+    /// Includes the current one once the job is claimed.
+    pub attempts: i32,
+    pub run_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub request_id: Option<String>,
+}
@@ -0,0 +1,348 @@
+use crate::common::entities::base::{DbConnGetter, DbRepo};
+use crate::common::entities::jobs::model::{JobQueryResult, NewJob};
+use crate::error::{IntoClientResult, Result, ServerSideError};
+use async_trait::async_trait;
+use mockall::automock;
+use sqlx::{Pool, Postgres};
+use std::fmt::Debug;
+use tracing::{error, info};
+
+// Delivery is at least once: a job whose worker dies, or outlives its lease, is claimed again, so
+// handlers must be safe to run twice.
+mod private_members {
+
+    use tracing::instrument;
+
+    use super::*;
+
+    #[instrument(skip(job), fields(kind = %job.kind))]
+    pub(crate) async fn enqueue_job_inner(conn: &Pool<Postgres>, job: NewJob) -> Result<i64> {
+        let (id,) = sqlx::query_as::<_, (i64,)>(
+            r"
+            insert into job (kind, payload, run_at, request_id)
+                values ($1, $2, coalesce($3, now()), $4)
+                returning id
+            ",
+        )
+        .bind(&job.kind)
+        .bind(&job.payload)
+        .bind(job.run_at)
+        .bind(&job.request_id)
+        .fetch_one(conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to enqueue job: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+
+        info!(job_id = id, "Job enqueued");
+        Ok(id)
+    }
+
+    /// Locks the next due job for `lease_secs`, skipping the ones other workers hold. A running
+    /// job whose lease expired is taken over, unless that was its last attempt: its worker
+    /// crashed or hung every time, so it is dead-lettered instead of being retried forever.
+    #[instrument(skip())]
+    pub(crate) async fn claim_job_inner(
+        conn: &Pool<Postgres>,
+        lease_secs: i64,
+        max_attempts: i32,
+    ) -> Result<Option<JobQueryResult>> {
+        sqlx::query_as::<_, JobQueryResult>(
+            r"
+            with exhausted as (
+                update job
+                    set
+                        status = 'dead',
+                        locked_until = null,
+                        last_error = 'Lease expired on the last attempt',
+                        updated_at = now()
+                    where status = 'running' and locked_until < now() and attempts >= $2
+            ), next as (
+                select id
+                    from job
+                    where
+                        (status = 'queued' and run_at <= now())
+                        or (status = 'running' and locked_until < now() and attempts < $2)
+                    order by run_at
+                    limit 1
+                    for update skip locked
+            )
+            update job j
+                set
+                    status = 'running',
+                    attempts = j.attempts + 1,
+                    locked_until = now() + make_interval(secs => $1),
+                    updated_at = now()
+                from next
+                where j.id = next.id
+                returning j.*
+            ",
+        )
+        .bind(lease_secs as f64)
+        .bind(max_attempts)
+        .fetch_optional(conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to claim job: {:?}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
+    }
+
+    #[instrument(skip())]
+    pub(crate) async fn complete_job_inner(conn: &Pool<Postgres>, id: i64) -> Result<()> {
+        sqlx::query::<_>("delete from job where id = $1")
+            .bind(id)
+            .execute(conn)
+            .await
+            .map(|_| ())
+            .map_err(|e| {
+                error!("Failed to complete job: {:?}", e);
+                ServerSideError::from(e)
+            })
+            .into_client_result()
+    }
+
+    /// Schedules the next attempt in `backoff_secs`, or moves the job to the dead letters once it
+    /// has used `max_attempts`. Returns whether it was dead-lettered.
+    #[instrument(skip())]
+    pub(crate) async fn fail_job_inner(
+        conn: &Pool<Postgres>,
+        id: i64,
+        error: &str,
+        max_attempts: i32,
+        backoff_secs: i64,
+    ) -> Result<bool> {
+        sqlx::query_as::<_, (bool,)>(
+            r"
+            update job
+                set
+                    status = case when attempts >= $3 then 'dead' else 'queued' end,
+                    run_at = now() + make_interval(secs => $4),
+                    locked_until = null,
+                    last_error = $2,
+                    updated_at = now()
+                where id = $1
+                returning status = 'dead'
+            ",
+        )
+        .bind(id)
+        .bind(error)
+        .bind(max_attempts)
+        .bind(backoff_secs as f64)
+        .fetch_optional(conn)
+        .await
+        .map(|row| row.is_some_and(|(dead,)| dead))
+        .map_err(|e| {
+            error!("Failed to record job failure: {:?}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
+    }
+
+    #[instrument(skip())]
+    pub(crate) async fn query_dead_jobs_inner(
+        conn: &Pool<Postgres>,
+        limit: i64,
+    ) -> Result<Vec<JobQueryResult>> {
+        sqlx::query_as::<_, JobQueryResult>(
+            "select * from job where status = 'dead' order by id desc limit $1",
+        )
+        .bind(limit)
+        .fetch_all(conn)
+        .await
+        .map_err(ServerSideError::from)
+        .into_client_result()
+    }
+
+    /// Queues a dead job again with a fresh set of attempts.
+    #[instrument(skip())]
+    pub(crate) async fn retry_dead_job_inner(conn: &Pool<Postgres>, id: i64) -> Result<()> {
+        let result = sqlx::query::<_>(
+            r"
+            update job
+                set status = 'queued', attempts = 0, run_at = now(), updated_at = now()
+                where id = $1 and status = 'dead'
+            ",
+        )
+        .bind(id)
+        .execute(conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to retry job: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(
+                ServerSideError::JobNotFound(format!("No dead job found with id: {id}")).into(),
+            );
+        }
+        Ok(())
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait EnqueueJobFn {
+    async fn enqueue_job(&self, job: NewJob) -> Result<i64>;
+}
+
+#[async_trait]
+impl EnqueueJobFn for DbRepo {
+    async fn enqueue_job(&self, job: NewJob) -> Result<i64> {
+        private_members::enqueue_job_inner(self.get_conn(), job).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait ClaimJobFn {
+    /// Claims the next due job, dead-lettering expired leases that were on `max_attempts`.
+    async fn claim_job(&self, lease_secs: i64, max_attempts: i32)
+        -> Result<Option<JobQueryResult>>;
+}
+
+#[async_trait]
+impl ClaimJobFn for DbRepo {
+    async fn claim_job(
+        &self,
+        lease_secs: i64,
+        max_attempts: i32,
+    ) -> Result<Option<JobQueryResult>> {
+        private_members::claim_job_inner(self.get_conn(), lease_secs, max_attempts).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait CompleteJobFn {
+    async fn complete_job(&self, id: i64) -> Result<()>;
+}
+
+#[async_trait]
+impl CompleteJobFn for DbRepo {
+    async fn complete_job(&self, id: i64) -> Result<()> {
+        private_members::complete_job_inner(self.get_conn(), id).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait FailJobFn {
+    /// Returns true when the job ran out of attempts and was dead-lettered.
+    async fn fail_job(
+        &self,
+        id: i64,
+        error: &str,
+        max_attempts: i32,
+        backoff_secs: i64,
+    ) -> Result<bool>;
+}
+
+#[async_trait]
+impl FailJobFn for DbRepo {
+    async fn fail_job(
+        &self,
+        id: i64,
+        error: &str,
+        max_attempts: i32,
+        backoff_secs: i64,
+    ) -> Result<bool> {
+        private_members::fail_job_inner(self.get_conn(), id, error, max_attempts, backoff_secs)
+            .await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait QueryDeadJobsFn {
+    async fn query_dead_jobs(&self, limit: i64) -> Result<Vec<JobQueryResult>>;
+}
+
+#[async_trait]
+impl QueryDeadJobsFn for DbRepo {
+    async fn query_dead_jobs(&self, limit: i64) -> Result<Vec<JobQueryResult>> {
+        private_members::query_dead_jobs_inner(self.get_conn(), limit).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait RetryDeadJobFn {
+    async fn retry_dead_job(&self, id: i64) -> Result<()>;
+}
+
+#[async_trait]
+impl RetryDeadJobFn for DbRepo {
+    async fn retry_dead_job(&self, id: i64) -> Result<()> {
+        private_members::retry_dead_job_inner(self.get_conn(), id).await
+    }
+}
+
+/// What a worker needs from the queue, implemented automatically like
+/// [`crate::common::entities::repository::Repository`].
+pub trait JobQueue: ClaimJobFn + CompleteJobFn + FailJobFn + Debug + Send + Sync + 'static {}
+
+impl<T> JobQueue for T where
+    T: ClaimJobFn + CompleteJobFn + FailJobFn + Debug + Send + Sync + 'static
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, Utc};
+    use serde_json::json;
+    use sqlx::PgPool;
+
+    use super::*;
+
+    async fn expire_leases(repo: &DbRepo) {
+        sqlx::query("update job set locked_until = now() - interval '1 second'")
+            .execute(repo.get_conn())
+            .await
+            .unwrap();
+    }
+
+    #[sqlx::test(migrator = "crate::common::entities::base::MIGRATOR")]
+    #[ignore = "needs postgres at DATABASE_URL"]
+    async fn test_expired_leases_are_dead_lettered_on_the_last_attempt(pool: PgPool) {
+        let repo = DbRepo::from_pool(pool);
+        let id = repo
+            .enqueue_job(NewJob::new("hangs", json!({})))
+            .await
+            .unwrap();
+
+        let job = repo.claim_job(60, 2).await.unwrap().unwrap();
+        assert_eq!((job.id, job.attempts), (id, 1));
+        // Still leased, nothing else is due.
+        assert!(repo.claim_job(60, 2).await.unwrap().is_none());
+
+        // The worker hung: the lease runs out and the job is taken over.
+        expire_leases(&repo).await;
+        let job = repo.claim_job(60, 2).await.unwrap().unwrap();
+        assert_eq!((job.id, job.attempts), (id, 2));
+
+        // That was the last attempt, it is not handed out a third time.
+        expire_leases(&repo).await;
+        // `run_at` has millisecond precision and may be rounded up past `now()`, so the job is
+        // made due a little earlier to be claimed right away.
+        let other = repo
+            .enqueue_job(NewJob::new("other", json!({})).run_at(Utc::now() - TimeDelta::seconds(1)))
+            .await
+            .unwrap();
+        let job = repo.claim_job(60, 2).await.unwrap().unwrap();
+        assert_eq!(job.id, other);
+
+        let dead = repo.query_dead_jobs(10).await.unwrap();
+        let [dead] = dead.as_slice() else {
+            panic!("expected one dead job, got {dead:?}");
+        };
+        assert_eq!((dead.id, dead.attempts), (id, 2));
+        assert_eq!(
+            dead.last_error.as_deref(),
+            Some("Lease expired on the last attempt")
+        );
+    }
+}
@@ -14,46 +14,75 @@ pub struct MessageQueryResult {
     pub msg_group_type: i32,
 }
 
+/// A message with its author and the message it broadcasts, if any, as shown in timelines. Only
+/// the columns needed by `MessageResponder` are selected, the image and avatar blobs are not.
 #[derive(Deserialize, Serialize, FromRow, Clone, Debug)]
-pub struct MessageWithProfileQueryResult {
+pub struct MessageWithFollowingAndBroadcastQueryResult {
     // message fields
     pub id: i64,
     pub updated_at: DateTime<Utc>,
+    /// `publish_at` for scheduled messages, `created_at` for the others.
+    pub published_at: DateTime<Utc>,
     pub body: Option<String>,
     pub likes: i32,
-    pub image: Option<Vec<u8>>,
     pub msg_group_type: i32,
     // profile fields
     pub user_id: i64,
     pub user_name: String,
     pub full_name: String,
-    pub avatar: Option<Vec<u8>>,
     // broadcast message fields
     pub broadcast_msg_id: Option<i64>,
+    pub broadcast_msg_updated_at: Option<DateTime<Utc>>,
+    pub broadcast_msg_published_at: Option<DateTime<Utc>>,
+    pub broadcast_msg_body: Option<String>,
+    pub broadcast_msg_likes: Option<i32>,
+    pub broadcast_msg_user_id: Option<i64>,
+    pub broadcast_msg_user_name: Option<String>,
+    pub broadcast_msg_full_name: Option<String>,
 }
 
+/// A message waiting for its `publish_at`, with the message it will broadcast if any.
 #[derive(Deserialize, Serialize, FromRow, Clone, Debug)]
-pub struct MessageWithFollowingAndBroadcastQueryResult {
-    // message fields
+pub struct ScheduledMessageQueryResult {
     pub id: i64,
-    pub updated_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub user_id: i64,
+    pub body: Option<String>,
+    pub msg_group_type: i32,
+    pub broadcast_msg_id: Option<i64>,
+    pub publish_at: DateTime<Utc>,
+}
+
+/// A published message of one author, with the message it broadcasts or answers if any.
+#[derive(Deserialize, Serialize, FromRow, Clone, Debug, PartialEq)]
+pub struct AuthorMessageQueryResult {
+    pub id: i64,
+    /// `publish_at` of a scheduled message, `created_at` otherwise.
+    pub published_at: DateTime<Utc>,
     pub body: Option<String>,
-    pub likes: i32,
-    pub image: Option<Vec<u8>>,
     pub msg_group_type: i32,
-    // profile fields
     pub user_id: i64,
     pub user_name: String,
-    pub full_name: String,
-    pub avatar: Option<Vec<u8>>,
-    // broadcast message fields
     pub broadcast_msg_id: Option<i64>,
-    pub broadcast_msg_updated_at: Option<DateTime<Utc>>,
-    pub broadcast_msg_body: Option<String>,
-    pub broadcast_msg_likes: Option<i32>,
-    pub broadcast_msg_image: Option<Vec<u8>>,
-    pub broadcast_msg_user_id: Option<i64>,
-    pub broadcast_msg_user_name: Option<String>,
-    pub broadcast_msg_full_name: Option<String>,
-    pub broadcast_msg_avatar: Option<Vec<u8>>,
+    pub original_msg_id: Option<i64>,
+}
+
+/// The message a response answers, from `message_response`.
+#[derive(Deserialize, Serialize, FromRow, Clone, Debug, PartialEq)]
+pub struct ResponseTargetQueryResult {
+    pub responding_msg_id: i64,
+    pub original_msg_id: i64,
+    pub original_user_id: i64,
+}
+
+/// A message just stored, as announced to webhooks and remote followers.
+#[derive(Clone, Copy, Debug)]
+pub struct NewMessage<'a> {
+    pub id: i64,
+    pub user_id: i64,
+    pub body: &'a str,
+    pub msg_group_type: i32,
+    pub broadcasting_msg_id: Option<i64>,
+    pub original_msg_id: Option<i64>,
+    pub publish_at: Option<DateTime<Utc>>,
 }
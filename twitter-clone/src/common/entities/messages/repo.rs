@@ -1,11 +1,20 @@
-use super::model::MessageWithFollowingAndBroadcastQueryResult;
+use super::model::{
+    AuthorMessageQueryResult, MessageWithFollowingAndBroadcastQueryResult, NewMessage,
+    ResponseTargetQueryResult, ScheduledMessageQueryResult,
+};
 use crate::common::entities::base::{DbConnGetter, DbRepo, EntityId};
-use crate::common::entities::messages::model::MessageWithProfileQueryResult;
+use crate::common::entities::jobs::{model::NewJob, repo::EnqueueJobFn};
+use crate::common::entities::polls::{model::PollCreate, repo::insert_poll_tx};
+use crate::common::entities::timeline::repo::query_home_timeline_inner;
 use crate::error::{IntoClientResult, Result, ServerSideError};
+use crate::schemas::message::MessageGroupTypes;
+use crate::settings::TimelineMode;
+use crate::worker::{ANNOUNCE_MESSAGE, FAN_OUT_MESSAGE};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use mockall::automock;
-use sqlx::{Pool, Postgres};
+use serde_json::json;
+use sqlx::{PgConnection, Pool, Postgres};
 use tracing::error;
 // 1. we create a single logical container where multiple related members can exist
 // 2. we create repeatable structure to our code
@@ -16,54 +25,79 @@ mod private_members {
 
     use super::*;
 
-    #[instrument(skip())]
+    /// Inserts a message, published right away unless `publish_at` is set, with its poll if
+    /// there is one.
+    #[instrument(skip(body, poll))]
     pub(crate) async fn insert_message_inner(
         conn: &Pool<Postgres>,
         user_id: i64,
         body: &str,
         group_type: i32,
         broadcasting_msg_id: Option<i64>,
+        publish_at: Option<DateTime<Utc>>,
+        poll: Option<&PollCreate>,
     ) -> Result<i64> {
         let mut tx = conn.begin().await.map_err(ServerSideError::from)?;
+        let message_id = insert_message_tx(
+            &mut tx,
+            user_id,
+            body,
+            group_type,
+            broadcasting_msg_id,
+            publish_at,
+        )
+        .await?;
+        if let Some(poll) = poll {
+            insert_poll_tx(&mut tx, message_id, poll).await?;
+        }
+        tx.commit().await.map_err(ServerSideError::from)?;
+        Ok(message_id)
+    }
 
-        let insert_msg_result = sqlx::query_as::<_, EntityId>(
-            "insert into message (user_id, body, msg_group_type) values ($1, $2, $3) returning id",
+    /// The message and its `message_broadcast` row, inside the caller's transaction so that
+    /// publishing a draft goes through the same statements.
+    pub(crate) async fn insert_message_tx(
+        tx: &mut PgConnection,
+        user_id: i64,
+        body: &str,
+        group_type: i32,
+        broadcasting_msg_id: Option<i64>,
+        publish_at: Option<DateTime<Utc>>,
+    ) -> Result<i64> {
+        let message_id = sqlx::query_as::<_, EntityId>(
+            r"
+            insert into message (user_id, body, msg_group_type, publish_at, updated_at)
+                values ($1, $2, $3, $4, coalesce($4, now()))
+                returning id
+            ",
         )
         .bind(user_id)
         .bind(body)
         .bind(group_type)
+        .bind(publish_at)
         .fetch_one(&mut *tx)
-        .await;
-
-        let message_id_result = match insert_msg_result {
-            Ok(r) => Ok(r.id),
-            Err(e) => {
-                error!("insert_message error: {}", e);
-                return Err(ServerSideError::from(e).into());
-            },
-        };
+        .await
+        .map_err(|e| {
+            error!("insert_message error: {}", e);
+            ServerSideError::from(e)
+        })?
+        .id;
 
         if let Some(bm_id) = broadcasting_msg_id {
-            let message_broadcast_result = sqlx
-                ::query_as::<_, EntityId>(
-                    "insert into message_broadcast (main_msg_id, broadcasting_msg_id) values ($1, $2) returning id"
-                )
-                .bind(message_id_result.as_ref().unwrap())
-                .bind(bm_id)
-                .fetch_one(&mut *tx).await;
-
-            if message_broadcast_result.is_err() {
-                _ = tx.rollback().await;
-                return Err(ServerSideError::from(message_broadcast_result.err().unwrap()).into());
-            }
+            sqlx::query::<_>(
+                "insert into message_broadcast (main_msg_id, broadcasting_msg_id) values ($1, $2)",
+            )
+            .bind(message_id)
+            .bind(bm_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(ServerSideError::from)?;
         }
 
-        _ = tx.commit().await;
-
-        message_id_result.into_client_result()
+        Ok(message_id)
     }
 
-    #[instrument(skip())]
+    #[instrument(skip(body))]
     pub(crate) async fn insert_response_message_inner(
         conn: &Pool<Postgres>,
         user_id: i64,
@@ -71,82 +105,91 @@ mod private_members {
         group_type: i32,
         original_msg_id: i64,
     ) -> Result<i64> {
-        let mut tx = conn.begin().await.unwrap();
+        let mut tx = conn.begin().await.map_err(ServerSideError::from)?;
+        let message_id =
+            insert_response_message_tx(&mut tx, user_id, body, group_type, original_msg_id).await?;
+        tx.commit().await.map_err(ServerSideError::from)?;
+        Ok(message_id)
+    }
 
-        let insert_result = sqlx::query_as::<_, EntityId>(
+    /// The answer and its `message_response` row, inside the caller's transaction.
+    pub(crate) async fn insert_response_message_tx(
+        tx: &mut PgConnection,
+        user_id: i64,
+        body: &str,
+        group_type: i32,
+        original_msg_id: i64,
+    ) -> Result<i64> {
+        let message_id = sqlx::query_as::<_, EntityId>(
             "insert into message (user_id, body, msg_group_type) values ($1, $2, $3) returning id",
         )
         .bind(user_id)
         .bind(body)
         .bind(group_type)
         .fetch_one(&mut *tx)
-        .await;
+        .await
+        .map_err(|e| {
+            error!("insert_message error: {}", e);
+            ServerSideError::from(e)
+        })?
+        .id;
 
-        let msg_id = match insert_result {
-            Ok(r) => r.id,
-            Err(e) => {
-                error!("insert_message error: {}", e);
-                return Err(ServerSideError::from(e).into());
-            },
-        };
+        sqlx::query::<_>(
+            "insert into message_response (original_msg_id, responding_msg_id) values ($1, $2)",
+        )
+        .bind(original_msg_id)
+        .bind(message_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(ServerSideError::from)?;
 
-        let insert_msg_response_result = sqlx
-            ::query_as::<_, EntityId>(
-                "insert into message_response (original_msg_id, responding_msg_id) values ($1, $2) returning id"
-            )
-            .bind(original_msg_id)
-            .bind(msg_id)
-            .fetch_one(&mut *tx).await;
-
-        match insert_msg_response_result {
-            Ok(_) => {
-                tx.commit().await.map_err(ServerSideError::from)?;
-                return Ok(msg_id);
-            },
-            Err(e) => {
-                tx.rollback().await.map_err(ServerSideError::from)?;
-                return Err(ServerSideError::from(e).into());
-            },
-        }
+        Ok(message_id)
     }
 
+    /// Loads a message with its author and broadcast source in one round trip. The broadcast
+    /// source is left out when it is hidden.
     #[instrument(skip())]
     pub(crate) async fn query_message_inner(
         conn: &Pool<Postgres>,
         id: i64,
     ) -> Result<Option<MessageWithFollowingAndBroadcastQueryResult>> {
-        let message_result = sqlx
-            ::query_as::<_, MessageWithProfileQueryResult>(
-                r"
-                select m.id, m.updated_at, m.body, m.likes, m.image, m.msg_group_type, m.user_id, p.user_name, p.full_name, p.avatar, mb.id as broadcast_msg_id
-                    from message m
-                        join profile p on m.user_id = p.id
-                        left join message_broadcast mb on m.id = mb.main_msg_id
-                    where
-                        m.id = $1
-            "
-            )
-            .bind(id)
-            .fetch_optional(conn).await;
-
-        match message_result {
-            Ok(message) => {
-                if let Some(msg) = message {
-                    let optional_matching_broadcast_message =
-                        get_broadcasting_message_of_message(conn, &msg).await;
-                    let final_message = append_broadcast_msg_to_msg(
-                        optional_matching_broadcast_message.as_ref(),
-                        &msg,
-                    );
-                    Ok(Some(final_message))
-                } else {
-                    Ok(None)
-                }
-            },
-            Err(e) => Err(ServerSideError::from(e).into()),
-        }
+        sqlx::query_as!(
+            MessageWithFollowingAndBroadcastQueryResult,
+            r#"
+            select m.id, m.updated_at,
+                    coalesce(m.publish_at, m.created_at) as "published_at!", m.body, m.likes,
+                    m.msg_group_type as "msg_group_type!", m.user_id, p.user_name, p.full_name,
+                    bm.id as "broadcast_msg_id?", bm.updated_at as "broadcast_msg_updated_at?",
+                    coalesce(bm.publish_at, bm.created_at) as "broadcast_msg_published_at?",
+                    bm.body as "broadcast_msg_body?", bm.likes as "broadcast_msg_likes?",
+                    bm.user_id as "broadcast_msg_user_id?", bp.user_name as "broadcast_msg_user_name?",
+                    bp.full_name as "broadcast_msg_full_name?"
+                from message m
+                    join profile p on p.id = m.user_id
+                    left join message_broadcast mb on mb.main_msg_id = m.id
+                    left join message bm on bm.id = mb.broadcasting_msg_id
+                        and bm.hidden_at is null
+                        and (bm.publish_at is null or bm.publish_at <= now())
+                    left join profile bp on bp.id = bm.user_id
+                where
+                    m.id = $1
+                    and m.hidden_at is null
+                    and (m.publish_at is null or m.publish_at <= now())
+            "#,
+            id
+        )
+        .fetch_optional(conn)
+        .await
+        .map_err(|e| {
+            error!("query_message error: {}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
     }
 
+    /// Loads a page of the follower's timeline, newest first, with every broadcast source joined
+    /// in the same query. Backed by the `follow(follower_id)` and `message(user_id, updated_at)`
+    /// indexes.
     #[instrument(skip())]
     pub(crate) async fn query_messages_inner(
         conn: &Pool<Postgres>,
@@ -154,178 +197,380 @@ mod private_members {
         last_updated_at: DateTime<Utc>,
         page_size: i16,
     ) -> Result<Vec<MessageWithFollowingAndBroadcastQueryResult>> {
-        let following_messages_with_profiles_result = sqlx
-            ::query_as::<_, MessageWithProfileQueryResult>(
-                r"
-                select m.id, m.updated_at, m.body, m.likes, m.image, m.msg_group_type, m.user_id, p.user_name, p.full_name, p.avatar, mb.id as broadcast_msg_id
-                    from message m
-                        join follow f on m.user_id = f.following_id
-                        join profile p on p.id = f.following_id
-                        left join message_broadcast mb on m.id = mb.main_msg_id
-                        where
-                            f.follower_id = $1
-                            and m.updated_at < $2
-                        order by m.updated_at desc
-                        limit $3
-            "
-            )
-            .bind(user_id)
-            .bind(last_updated_at)
-            .bind(page_size)
-            .fetch_all(conn).await;
-
-        match following_messages_with_profiles_result {
-            Ok(following_messages) => {
-                let following_messages_with_broadcasts = following_messages
-                    .clone()
-                    .into_iter()
-                    .filter(|msg| {
-                        msg.broadcast_msg_id.is_some() && msg.broadcast_msg_id.unwrap() > 0
-                    })
-                    .collect::<Vec<MessageWithProfileQueryResult>>();
-
-                let optional_matching_broadcast_messages = get_broadcasting_messages_of_messages(
-                    conn,
-                    &following_messages_with_broadcasts,
-                )
-                .await;
-                let final_message_list = append_broadcast_msgs_to_msgs(
-                    &optional_matching_broadcast_messages,
-                    following_messages,
-                );
-                Ok(final_message_list)
-            },
-            Err(e) => Err(ServerSideError::from(e).into()),
-        }
+        sqlx::query_as!(
+            MessageWithFollowingAndBroadcastQueryResult,
+            r#"
+            select m.id, m.updated_at,
+                    coalesce(m.publish_at, m.created_at) as "published_at!", m.body, m.likes,
+                    m.msg_group_type as "msg_group_type!", m.user_id, p.user_name, p.full_name,
+                    bm.id as "broadcast_msg_id?", bm.updated_at as "broadcast_msg_updated_at?",
+                    coalesce(bm.publish_at, bm.created_at) as "broadcast_msg_published_at?",
+                    bm.body as "broadcast_msg_body?", bm.likes as "broadcast_msg_likes?",
+                    bm.user_id as "broadcast_msg_user_id?", bp.user_name as "broadcast_msg_user_name?",
+                    bp.full_name as "broadcast_msg_full_name?"
+                from follow f
+                    join message m on m.user_id = f.following_id
+                    join profile p on p.id = m.user_id
+                    left join message_broadcast mb on mb.main_msg_id = m.id
+                    left join message bm on bm.id = mb.broadcasting_msg_id
+                        and bm.hidden_at is null
+                        and (bm.publish_at is null or bm.publish_at <= now())
+                    left join profile bp on bp.id = bm.user_id
+                where
+                    f.follower_id = $1
+                    and m.updated_at < $2
+                    and m.hidden_at is null
+                    and (m.publish_at is null or m.publish_at <= now())
+                order by m.updated_at desc, m.id desc
+                limit $3
+            "#,
+            user_id,
+            last_updated_at,
+            i64::from(page_size)
+        )
+        .fetch_all(conn)
+        .await
+        .map_err(|e| {
+            error!("query_messages error: {}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
     }
 
     #[instrument(skip())]
-    async fn get_broadcasting_messages_of_messages(
+    pub(crate) async fn query_author_messages_inner(
         conn: &Pool<Postgres>,
-        following_messages_with_broadcasts: &Vec<MessageWithProfileQueryResult>,
-    ) -> Option<Vec<MessageWithProfileQueryResult>> {
-        let following_broadcast_message_ids = following_messages_with_broadcasts
-            .iter()
-            .map(|msg| msg.broadcast_msg_id.unwrap())
-            .collect::<Vec<i64>>();
-
-        let broadcasting_msg_result = sqlx
-            ::query_as::<_, MessageWithProfileQueryResult>(
-                r"
-                select m.id, m.updated_at, m.body, m.likes, m.image, m.msg_group_type, m.user_id, p.user_name, p.full_name, p.avatar, mb.id as broadcast_msg_id
-                    from message m
-                        join profile p on m.user_id = p.id
-                        left join message_broadcast mb on m.id = mb.main_msg_id
-                    where m.id = ANY($1)
-            "
-            )
-            .bind(following_broadcast_message_ids)
-            .fetch_all(conn).await;
-
-        match broadcasting_msg_result {
-            Ok(broadcast_messages) => Some(broadcast_messages),
-            Err(e) => {
-                println!("get_broadcasting_messages_of_messages: {}", e);
-                None
-            },
-        }
+        user_id: i64,
+        before_id: Option<i64>,
+        page_size: i16,
+    ) -> Result<Vec<AuthorMessageQueryResult>> {
+        sqlx::query_as::<_, AuthorMessageQueryResult>(
+            r"
+            select m.id, coalesce(m.publish_at, m.created_at) as published_at, m.body,
+                    m.msg_group_type, m.user_id, p.user_name,
+                    mb.broadcasting_msg_id as broadcast_msg_id, mr.original_msg_id
+                from message m
+                    join profile p on p.id = m.user_id
+                    left join message_broadcast mb on mb.main_msg_id = m.id
+                    left join message_response mr on mr.responding_msg_id = m.id
+                where
+                    m.user_id = $1
+                    and ($2::bigint is null or m.id < $2)
+                    and m.hidden_at is null
+                    and (m.publish_at is null or m.publish_at <= now())
+                order by m.id desc
+                limit $3
+            ",
+        )
+        .bind(user_id)
+        .bind(before_id)
+        .bind(i64::from(page_size))
+        .fetch_all(conn)
+        .await
+        .map_err(|e| {
+            error!("query_author_messages error: {}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
     }
 
+    /// The newest public messages of one author with their broadcast source, backed by the
+    /// `message(user_id, updated_at)` index.
     #[instrument(skip())]
-    async fn get_broadcasting_message_of_message(
+    pub(crate) async fn query_feed_messages_inner(
         conn: &Pool<Postgres>,
-        message: &MessageWithProfileQueryResult,
-    ) -> Option<MessageWithProfileQueryResult> {
-        let broadcasting_msg_result = sqlx
-            ::query_as::<_, MessageWithProfileQueryResult>(
-                r"
-                select m.id, m.updated_at, m.body, m.likes, m.image, m.msg_group_type, m.user_id, p.user_name, p.full_name, p.avatar, mb.id as broadcast_msg_id
-                    from message m
-                        join profile p on m.user_id = p.id
-                        left join message_broadcast mb on m.id = mb.main_msg_id
-                    where m.id = $1
-            "
-            )
-            .bind(message.broadcast_msg_id)
-            .fetch_optional(conn).await;
-
-        match broadcasting_msg_result {
-            Ok(broadcast_message) => broadcast_message,
-            Err(e) => {
-                println!("get_broadcasting_messages_of_messages: {}", e);
-                None
-            },
+        user_id: i64,
+        page_size: i16,
+    ) -> Result<Vec<MessageWithFollowingAndBroadcastQueryResult>> {
+        sqlx::query_as::<_, MessageWithFollowingAndBroadcastQueryResult>(
+            r"
+            select m.id, m.updated_at, coalesce(m.publish_at, m.created_at) as published_at,
+                    m.body, m.likes, m.msg_group_type, m.user_id, p.user_name, p.full_name,
+                    bm.id as broadcast_msg_id, bm.updated_at as broadcast_msg_updated_at,
+                    coalesce(bm.publish_at, bm.created_at) as broadcast_msg_published_at,
+                    bm.body as broadcast_msg_body, bm.likes as broadcast_msg_likes,
+                    bm.user_id as broadcast_msg_user_id, bp.user_name as broadcast_msg_user_name,
+                    bp.full_name as broadcast_msg_full_name
+                from message m
+                    join profile p on p.id = m.user_id
+                    left join message_broadcast mb on mb.main_msg_id = m.id
+                    left join message bm on bm.id = mb.broadcasting_msg_id
+                        and bm.hidden_at is null
+                        and (bm.publish_at is null or bm.publish_at <= now())
+                    left join profile bp on bp.id = bm.user_id
+                where
+                    m.user_id = $1
+                    and m.msg_group_type = $2
+                    and m.hidden_at is null
+                    and (m.publish_at is null or m.publish_at <= now())
+                order by m.updated_at desc, m.id desc
+                limit $3
+            ",
+        )
+        .bind(user_id)
+        .bind(MessageGroupTypes::Public as i32)
+        .bind(i64::from(page_size))
+        .fetch_all(conn)
+        .await
+        .map_err(|e| {
+            error!("query_feed_messages error: {}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
+    }
+
+    #[instrument(skip())]
+    pub(crate) async fn query_response_targets_inner(
+        conn: &Pool<Postgres>,
+        message_ids: &[i64],
+    ) -> Result<Vec<ResponseTargetQueryResult>> {
+        sqlx::query_as::<_, ResponseTargetQueryResult>(
+            r"
+            select mr.responding_msg_id, mr.original_msg_id, om.user_id as original_user_id
+                from message_response mr
+                    join message om on om.id = mr.original_msg_id
+                where mr.responding_msg_id = any($1)
+            ",
+        )
+        .bind(message_ids)
+        .fetch_all(conn)
+        .await
+        .map_err(|e| {
+            error!("query_response_targets error: {}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
+    }
+
+    #[instrument(skip())]
+    pub(crate) async fn query_scheduled_messages_inner(
+        conn: &Pool<Postgres>,
+        user_id: i64,
+    ) -> Result<Vec<ScheduledMessageQueryResult>> {
+        sqlx::query_as::<_, ScheduledMessageQueryResult>(
+            r"
+            select m.id, m.created_at, m.user_id, m.body, m.msg_group_type,
+                    mb.broadcasting_msg_id as broadcast_msg_id, m.publish_at
+                from message m
+                    left join message_broadcast mb on mb.main_msg_id = m.id
+                where
+                    m.user_id = $1
+                    and m.publish_at > now()
+                    and m.hidden_at is null
+                order by m.publish_at, m.id
+            ",
+        )
+        .bind(user_id)
+        .fetch_all(conn)
+        .await
+        .map_err(|e| {
+            error!("query_scheduled_messages error: {}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
+    }
+
+    /// Moves a message that is not published yet to `publish_at`, along with the home timeline
+    /// rows it may already have been fanned out to.
+    #[instrument(skip())]
+    pub(crate) async fn reschedule_message_inner(
+        conn: &Pool<Postgres>,
+        id: i64,
+        publish_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut tx = conn.begin().await.map_err(ServerSideError::from)?;
+
+        let result = sqlx::query::<_>(
+            r"
+            update message
+                set publish_at = $2, updated_at = $2
+                where id = $1 and publish_at > now() and hidden_at is null
+            ",
+        )
+        .bind(id)
+        .bind(publish_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to reschedule message: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(ServerSideError::MessageNotFound(format!(
+                "No scheduled message found with id: {id}"
+            ))
+            .into());
         }
+
+        sqlx::query::<_>("update home_timeline set updated_at = $2 where message_id = $1")
+            .bind(id)
+            .bind(publish_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(ServerSideError::from)?;
+
+        // Webhooks and remote followers hear of it when it gets published.
+        sqlx::query::<_>(
+            r"
+            update job
+                set run_at = $2, updated_at = now()
+                where kind = $3 and status = 'queued' and payload->>'message_id' = $1::text
+            ",
+        )
+        .bind(id)
+        .bind(publish_at)
+        .bind(ANNOUNCE_MESSAGE)
+        .execute(&mut *tx)
+        .await
+        .map_err(ServerSideError::from)?;
+
+        tx.commit().await.map_err(ServerSideError::from)?;
+        Ok(())
     }
 
+    /// Deletes a message that is not published yet, with everything referencing it.
     #[instrument(skip())]
-    fn append_broadcast_msgs_to_msgs(
-        optional_broadcast_messages: &Option<Vec<MessageWithProfileQueryResult>>,
-        following_messages_with_broadcasts: Vec<MessageWithProfileQueryResult>,
-    ) -> Vec<MessageWithFollowingAndBroadcastQueryResult> {
-        let mut final_list_of_messages: Vec<MessageWithFollowingAndBroadcastQueryResult> = vec![];
+    pub(crate) async fn cancel_scheduled_message_inner(
+        conn: &Pool<Postgres>,
+        id: i64,
+    ) -> Result<()> {
+        let mut tx = conn.begin().await.map_err(ServerSideError::from)?;
 
-        following_messages_with_broadcasts
-            .iter()
-            .for_each(|following_message_with_broadcast| {
-                let matching_broadcast_msg =
-                    if let Some(broadcast_messages) = optional_broadcast_messages {
-                        broadcast_messages.iter().find(|bm| {
-                            Some(bm.id) == following_message_with_broadcast.broadcast_msg_id
-                        })
-                    } else {
-                        None
-                    };
+        let scheduled = sqlx::query_as::<_, EntityId>(
+            "select id from message where id = $1 and publish_at > now() for update",
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(ServerSideError::from)?;
+        if scheduled.is_none() {
+            return Err(ServerSideError::MessageNotFound(format!(
+                "No scheduled message found with id: {id}"
+            ))
+            .into());
+        }
 
-                final_list_of_messages.push(append_broadcast_msg_to_msg(
-                    matching_broadcast_msg,
-                    following_message_with_broadcast,
-                ));
-            });
+        for statement in [
+            "delete from home_timeline where message_id = $1",
+            "delete from message_response where original_msg_id = $1 or responding_msg_id = $1",
+            "delete from message_broadcast where main_msg_id = $1 or broadcasting_msg_id = $1",
+            "delete from message where id = $1",
+        ] {
+            sqlx::query::<_>(statement)
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| {
+                    error!("Failed to cancel scheduled message: {:?}", e);
+                    ServerSideError::from(e)
+                })?;
+        }
+        sqlx::query::<_>(
+            "delete from job where kind = $2 and status = 'queued' and payload->>'message_id' = $1::text",
+        )
+        .bind(id)
+        .bind(ANNOUNCE_MESSAGE)
+        .execute(&mut *tx)
+        .await
+        .map_err(ServerSideError::from)?;
 
-        final_list_of_messages
+        tx.commit().await.map_err(ServerSideError::from)?;
+        Ok(())
     }
 
     #[instrument(skip())]
-    fn append_broadcast_msg_to_msg(
-        broadcast_message: Option<&MessageWithProfileQueryResult>,
-        message_with_broadcast: &MessageWithProfileQueryResult,
-    ) -> MessageWithFollowingAndBroadcastQueryResult {
-        let mut final_message = MessageWithFollowingAndBroadcastQueryResult {
-            id: message_with_broadcast.id,
-            updated_at: message_with_broadcast.updated_at,
-            body: message_with_broadcast.body.clone(),
-            likes: message_with_broadcast.likes,
-            image: message_with_broadcast.image.clone(),
-            msg_group_type: message_with_broadcast.msg_group_type,
-            user_id: message_with_broadcast.user_id,
-            user_name: message_with_broadcast.user_name.clone(),
-            full_name: message_with_broadcast.full_name.clone(),
-            avatar: message_with_broadcast.avatar.clone(),
-            broadcast_msg_id: None,
-            broadcast_msg_updated_at: None,
-            broadcast_msg_user_id: None,
-            broadcast_msg_body: None,
-            broadcast_msg_likes: None,
-            broadcast_msg_image: None,
-            broadcast_msg_user_name: None,
-            broadcast_msg_full_name: None,
-            broadcast_msg_avatar: None,
-        };
+    pub(crate) async fn hide_message_inner(
+        conn: &Pool<Postgres>,
+        id: i64,
+        hidden: bool,
+    ) -> Result<()> {
+        let mut tx = conn.begin().await.map_err(ServerSideError::from)?;
 
-        if let Some(matching_broadcast) = broadcast_message {
-            final_message.broadcast_msg_id = Some(matching_broadcast.id);
-            final_message.broadcast_msg_updated_at = Some(matching_broadcast.updated_at);
-            final_message.broadcast_msg_body = matching_broadcast.body.to_owned();
-            final_message.broadcast_msg_likes = Some(matching_broadcast.likes);
-            final_message.broadcast_msg_image = matching_broadcast.image.to_owned();
-            final_message.broadcast_msg_user_id = Some(matching_broadcast.user_id);
-            final_message.broadcast_msg_user_name = Some(matching_broadcast.user_name.to_string());
-            final_message.broadcast_msg_full_name = Some(matching_broadcast.full_name.to_string());
-            final_message.broadcast_msg_avatar = matching_broadcast.avatar.to_owned();
+        let result = sqlx::query::<_>(
+            r"
+            update message
+                set hidden_at = case when $2 then coalesce(hidden_at, now()) else null end
+                where id = $1
+            ",
+        )
+        .bind(id)
+        .bind(hidden)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to update message visibility: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(ServerSideError::MessageNotFound(format!(
+                "No message found with id: {id}"
+            ))
+            .into());
+        }
+
+        sqlx::query::<_>(
+            r"
+            update profile set updated_at = now()
+                where id in (
+                    select user_id from message where id = $1
+                    union
+                    select m.user_id from message_broadcast mb
+                        join message m on m.id = mb.main_msg_id
+                        where mb.broadcasting_msg_id = $1
+                )
+            ",
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to touch the profiles of a hidden message: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+
+        tx.commit().await.map_err(ServerSideError::from)?;
+        Ok(())
+    }
+}
+
+pub(crate) use private_members::{insert_message_tx, insert_response_message_tx};
+
+impl DbRepo {
+    /// Queues the fan-out of a new message when timelines are materialized, for when it gets
+    /// published. The message is posted either way: until a worker fans it out it is merged into
+    /// timelines at read time.
+    pub(crate) async fn fan_out(&self, message_id: i64, publish_at: Option<DateTime<Utc>>) {
+        if self.timeline().mode == TimelineMode::Fanout {
+            let mut job = NewJob::new(FAN_OUT_MESSAGE, json!({ "message_id": message_id }));
+            if let Some(publish_at) = publish_at {
+                job = job.run_at(publish_at);
+            }
+            if let Err(e) = self.enqueue_job(job).await {
+                error!(
+                    "Fan-out not queued, message stays merged at read time: {}",
+                    e
+                );
+            }
         }
+    }
 
-        final_message
+    /// Tells webhooks and remote followers about a new message. Like the fan-out, this never
+    /// fails the insert.
+    pub(crate) async fn announce_message(&self, message: &NewMessage<'_>) {
+        self.emit_message_event(message).await;
+        self.federate_message(message).await;
+    }
+
+    /// Queues the announcement of a scheduled message for `publish_at`, so nothing of it leaves
+    /// the server before then. Like the fan-out, a failure is only logged.
+    pub(crate) async fn announce_later(&self, message_id: i64, publish_at: DateTime<Utc>) {
+        let job =
+            NewJob::new(ANNOUNCE_MESSAGE, json!({ "message_id": message_id })).run_at(publish_at);
+        if let Err(e) = self.enqueue_job(job).await {
+            error!("Message {} not announced: {}", message_id, e);
+        }
     }
 }
 
@@ -350,14 +595,28 @@ impl InsertMessageFn for DbRepo {
         group_type: i32,
         broadcasting_msg_id: Option<i64>,
     ) -> Result<i64> {
-        private_members::insert_message_inner(
+        let id = private_members::insert_message_inner(
             self.get_conn(),
             user_id,
             body,
             group_type,
             broadcasting_msg_id,
+            None,
+            None,
         )
-        .await
+        .await?;
+        self.fan_out(id, None).await;
+        self.announce_message(&NewMessage {
+            id,
+            user_id,
+            body,
+            msg_group_type: group_type,
+            broadcasting_msg_id,
+            original_msg_id: None,
+            publish_at: None,
+        })
+        .await;
+        Ok(id)
     }
 }
 
@@ -382,14 +641,164 @@ impl InsertResponseMessageFn for DbRepo {
         group_type: i32,
         original_msg_id: i64,
     ) -> Result<i64> {
-        private_members::insert_response_message_inner(
+        let id = private_members::insert_response_message_inner(
             self.get_conn(),
             user_id,
             body,
             group_type,
             original_msg_id,
         )
-        .await
+        .await?;
+        self.fan_out(id, None).await;
+        self.announce_message(&NewMessage {
+            id,
+            user_id,
+            body,
+            msg_group_type: group_type,
+            broadcasting_msg_id: None,
+            original_msg_id: Some(original_msg_id),
+            publish_at: None,
+        })
+        .await;
+        Ok(id)
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait ScheduleMessageFn {
+    /// Stores a message that stays out of every query until `publish_at`, then shows up in
+    /// timelines as if it had been posted at that time.
+    async fn schedule_message(
+        &self,
+        user_id: i64,
+        body: &str,
+        group_type: i32,
+        broadcasting_msg_id: Option<i64>,
+        publish_at: DateTime<Utc>,
+    ) -> Result<i64>;
+}
+
+#[async_trait]
+impl ScheduleMessageFn for DbRepo {
+    async fn schedule_message(
+        &self,
+        user_id: i64,
+        body: &str,
+        group_type: i32,
+        broadcasting_msg_id: Option<i64>,
+        publish_at: DateTime<Utc>,
+    ) -> Result<i64> {
+        let id = private_members::insert_message_inner(
+            self.get_conn(),
+            user_id,
+            body,
+            group_type,
+            broadcasting_msg_id,
+            Some(publish_at),
+            None,
+        )
+        .await?;
+        self.fan_out(id, Some(publish_at)).await;
+        self.announce_later(id, publish_at).await;
+        Ok(id)
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait InsertPollMessageFn {
+    /// Inserts a message carrying a poll, both in the same transaction.
+    async fn insert_poll_message(
+        &self,
+        user_id: i64,
+        body: &str,
+        group_type: i32,
+        broadcasting_msg_id: Option<i64>,
+        poll: PollCreate,
+    ) -> Result<i64>;
+}
+
+#[async_trait]
+impl InsertPollMessageFn for DbRepo {
+    async fn insert_poll_message(
+        &self,
+        user_id: i64,
+        body: &str,
+        group_type: i32,
+        broadcasting_msg_id: Option<i64>,
+        poll: PollCreate,
+    ) -> Result<i64> {
+        let id = private_members::insert_message_inner(
+            self.get_conn(),
+            user_id,
+            body,
+            group_type,
+            broadcasting_msg_id,
+            None,
+            Some(&poll),
+        )
+        .await?;
+        self.fan_out(id, None).await;
+        self.announce_message(&NewMessage {
+            id,
+            user_id,
+            body,
+            msg_group_type: group_type,
+            broadcasting_msg_id,
+            original_msg_id: None,
+            publish_at: None,
+        })
+        .await;
+        Ok(id)
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait QueryScheduledMessagesFn {
+    /// The profile's messages that are not published yet, next to be published first.
+    async fn query_scheduled_messages(
+        &self,
+        user_id: i64,
+    ) -> Result<Vec<ScheduledMessageQueryResult>>;
+}
+
+#[async_trait]
+impl QueryScheduledMessagesFn for DbRepo {
+    async fn query_scheduled_messages(
+        &self,
+        user_id: i64,
+    ) -> Result<Vec<ScheduledMessageQueryResult>> {
+        private_members::query_scheduled_messages_inner(self.get_conn(), user_id).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait RescheduleMessageFn {
+    /// Fails with not found once the message is published.
+    async fn reschedule_message(&self, id: i64, publish_at: DateTime<Utc>) -> Result<()>;
+}
+
+#[async_trait]
+impl RescheduleMessageFn for DbRepo {
+    async fn reschedule_message(&self, id: i64, publish_at: DateTime<Utc>) -> Result<()> {
+        private_members::reschedule_message_inner(self.get_conn(), id, publish_at).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait CancelScheduledMessageFn {
+    /// Deletes a message before it is published, fails with not found once it is.
+    async fn cancel_scheduled_message(&self, id: i64) -> Result<()>;
+}
+
+#[async_trait]
+impl CancelScheduledMessageFn for DbRepo {
+    async fn cancel_scheduled_message(&self, id: i64) -> Result<()> {
+        private_members::cancel_scheduled_message_inner(self.get_conn(), id).await
     }
 }
 
@@ -431,7 +840,250 @@ impl QueryMessagesFn for DbRepo {
         last_updated_at: DateTime<Utc>,
         page_size: i16,
     ) -> Result<Vec<MessageWithFollowingAndBroadcastQueryResult>> {
-        private_members::query_messages_inner(self.get_conn(), user_id, last_updated_at, page_size)
+        match self.timeline().mode {
+            TimelineMode::Pull => {
+                private_members::query_messages_inner(
+                    self.get_conn(),
+                    user_id,
+                    last_updated_at,
+                    page_size,
+                )
+                .await
+            },
+            TimelineMode::Fanout => {
+                query_home_timeline_inner(self.get_conn(), user_id, last_updated_at, page_size)
+                    .await
+            },
+        }
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait QueryAuthorMessagesFn {
+    /// The published messages of one author, newest first, older than `before_id` when set.
+    async fn query_author_messages(
+        &self,
+        user_id: i64,
+        before_id: Option<i64>,
+        page_size: i16,
+    ) -> Result<Vec<AuthorMessageQueryResult>>;
+}
+
+#[async_trait]
+impl QueryAuthorMessagesFn for DbRepo {
+    async fn query_author_messages(
+        &self,
+        user_id: i64,
+        before_id: Option<i64>,
+        page_size: i16,
+    ) -> Result<Vec<AuthorMessageQueryResult>> {
+        private_members::query_author_messages_inner(self.get_conn(), user_id, before_id, page_size)
+            .await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait QueryFeedMessagesFn {
+    /// The newest published public messages of one author, by `updated_at`, as shown in feeds.
+    async fn query_feed_messages(
+        &self,
+        user_id: i64,
+        page_size: i16,
+    ) -> Result<Vec<MessageWithFollowingAndBroadcastQueryResult>>;
+}
+
+#[async_trait]
+impl QueryFeedMessagesFn for DbRepo {
+    async fn query_feed_messages(
+        &self,
+        user_id: i64,
+        page_size: i16,
+    ) -> Result<Vec<MessageWithFollowingAndBroadcastQueryResult>> {
+        private_members::query_feed_messages_inner(self.get_conn(), user_id, page_size).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait QueryResponseTargetsFn {
+    /// The messages answered by the given messages, for those of them that are responses.
+    async fn query_response_targets(
+        &self,
+        message_ids: &[i64],
+    ) -> Result<Vec<ResponseTargetQueryResult>>;
+}
+
+#[async_trait]
+impl QueryResponseTargetsFn for DbRepo {
+    async fn query_response_targets(
+        &self,
+        message_ids: &[i64],
+    ) -> Result<Vec<ResponseTargetQueryResult>> {
+        private_members::query_response_targets_inner(self.get_conn(), message_ids).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait HideMessageFn {
+    /// Hides a message from every query, or makes a hidden message visible again.
+    /// Also moves the `updated_at` of the profiles whose feeds show the message, its author and
+    /// those broadcasting it, so their feeds are no longer reported as unmodified.
+    async fn hide_message(&self, id: i64, hidden: bool) -> Result<()>;
+}
+
+#[async_trait]
+impl HideMessageFn for DbRepo {
+    async fn hide_message(&self, id: i64, hidden: bool) -> Result<()> {
+        private_members::hide_message_inner(self.get_conn(), id, hidden).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::{
+        common::entities::{
+            profile::repo::{FollowUserFn, InsertProfileFn, QueryProfilesFn},
+            webhooks::{
+                model::{WebhookCreate, WebhookEvent},
+                repo::{InsertWebhookFn, QueryWebhookDeliveriesFn},
+            },
+        },
+        common_tests::profile,
+    };
+
+    const PUBLIC: i32 = MessageGroupTypes::Public as i32;
+
+    async fn queued_announcements(repo: &DbRepo) -> Vec<DateTime<Utc>> {
+        sqlx::query_as::<_, (DateTime<Utc>,)>(
+            "select run_at from job where kind = $1 and status = 'queued'",
+        )
+        .bind(ANNOUNCE_MESSAGE)
+        .fetch_all(repo.get_conn())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(run_at,)| run_at)
+        .collect()
+    }
+
+    // The broadcast source used to be read as `message_broadcast.id`, the link row's own id. The
+    // source here is the second message, so the two ids differ.
+    #[sqlx::test(migrator = "crate::common::entities::base::MIGRATOR")]
+    #[ignore = "needs postgres at DATABASE_URL"]
+    async fn test_broadcast_sources_are_read_back(pool: PgPool) {
+        let repo = DbRepo::from_pool(pool);
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let reader = repo.insert_profile(profile("reader")).await.unwrap();
+        repo.follow_user(reader, author).await.unwrap();
+        repo.insert_message(author, "first", PUBLIC, None)
+            .await
+            .unwrap();
+        let source = repo
+            .insert_message(author, "source", PUBLIC, None)
+            .await
+            .unwrap();
+        let broadcast = repo
+            .insert_message(author, "broadcast", PUBLIC, Some(source))
+            .await
+            .unwrap();
+
+        let message = repo.query_message(broadcast).await.unwrap().unwrap();
+        assert_eq!(message.broadcast_msg_id, Some(source));
+        assert_eq!(message.broadcast_msg_body.as_deref(), Some("source"));
+
+        let timeline = repo
+            .query_messages(reader, Utc::now() + TimeDelta::seconds(1), 10)
+            .await
+            .unwrap();
+        let message = timeline
+            .iter()
+            .find(|message| message.id == broadcast)
+            .unwrap();
+        assert_eq!(message.broadcast_msg_id, Some(source));
+        assert_eq!(message.broadcast_msg_user_id, Some(author));
+    }
+
+    #[sqlx::test(migrator = "crate::common::entities::base::MIGRATOR")]
+    #[ignore = "needs postgres at DATABASE_URL"]
+    async fn test_hiding_a_message_touches_the_profiles_showing_it(pool: PgPool) {
+        let repo = DbRepo::from_pool(pool);
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let broadcaster = repo.insert_profile(profile("broadcaster")).await.unwrap();
+        let bystander = repo.insert_profile(profile("bystander")).await.unwrap();
+        let source = repo
+            .insert_message(author, "source", PUBLIC, None)
+            .await
+            .unwrap();
+        repo.insert_message(broadcaster, "", PUBLIC, Some(source))
+            .await
+            .unwrap();
+        let ids = [author, broadcaster, bystander];
+        let updated_at = || async {
+            let profiles = repo.query_profiles(&ids).await.unwrap();
+            ids.map(|id| {
+                profiles
+                    .iter()
+                    .find(|profile| profile.id == id)
+                    .unwrap()
+                    .updated_at
+            })
+        };
+        let before = updated_at().await;
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+        repo.hide_message(source, true).await.unwrap();
+        let after = updated_at().await;
+        assert!(after[0] > before[0]);
+        assert!(after[1] > before[1]);
+        assert_eq!(after[2], before[2]);
+    }
+
+    #[sqlx::test(migrator = "crate::common::entities::base::MIGRATOR")]
+    #[ignore = "needs postgres at DATABASE_URL"]
+    async fn test_scheduled_messages_are_announced_once_published(pool: PgPool) {
+        let repo = DbRepo::from_pool(pool);
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let webhook = repo
+            .insert_webhook(WebhookCreate {
+                url: "https://hooks.example/messages".to_string(),
+                secret: "secret".to_string(),
+                events: vec![WebhookEvent::MessageCreated],
+            })
+            .await
+            .unwrap();
+        // Whole milliseconds, like `publish_at` and `run_at` store them.
+        let in_an_hour =
+            DateTime::from_timestamp_millis((Utc::now() + TimeDelta::hours(1)).timestamp_millis())
+                .unwrap();
+
+        let id = repo
+            .schedule_message(author, "secret-future", PUBLIC, None, in_an_hour)
+            .await
+            .unwrap();
+        assert!(repo
+            .query_webhook_deliveries(webhook, 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(queued_announcements(&repo).await, [in_an_hour]);
+
+        let later = in_an_hour + TimeDelta::hours(1);
+        repo.reschedule_message(id, later).await.unwrap();
+        assert_eq!(queued_announcements(&repo).await, [later]);
+
+        repo.cancel_scheduled_message(id).await.unwrap();
+        assert!(queued_announcements(&repo).await.is_empty());
+        assert!(repo
+            .query_webhook_deliveries(webhook, 10)
             .await
+            .unwrap()
+            .is_empty());
     }
 }
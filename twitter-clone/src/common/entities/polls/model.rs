@@ -0,0 +1,26 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// A poll to create with its message, see
+/// [`crate::common::entities::messages::repo::InsertPollMessageFn`].
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct PollCreate {
+    /// Labels in display order.
+    pub options: Vec<String>,
+    pub closes_at: DateTime<Utc>,
+}
+
+/// One option of a poll with its vote count, a poll comes back as one row per option.
+#[derive(Deserialize, Serialize, FromRow, Clone, Debug)]
+pub struct PollOptionQueryResult {
+    pub poll_id: i64,
+    pub message_id: i64,
+    pub closes_at: DateTime<Utc>,
+    pub option_id: i64,
+    pub position: i32,
+    pub label: String,
+    pub votes: i64,
+    /// Whether the viewer the polls were loaded for picked this option.
+    pub viewer_voted: bool,
+}
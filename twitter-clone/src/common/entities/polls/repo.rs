@@ -0,0 +1,304 @@
+use super::model::{PollCreate, PollOptionQueryResult};
+use crate::common::entities::base::{DbConnGetter, DbRepo, EntityId};
+use crate::error::{ClientSideError, IntoClientResult, Result, ServerSideError};
+use async_trait::async_trait;
+use mockall::automock;
+use sqlx::{PgConnection, Pool, Postgres};
+use tracing::{error, instrument};
+
+mod private_members {
+
+    use super::*;
+
+    /// The poll of a new message and its options, inside the message's transaction.
+    pub(crate) async fn insert_poll_tx(
+        tx: &mut PgConnection,
+        message_id: i64,
+        poll: &PollCreate,
+    ) -> Result<i64> {
+        let poll_id = sqlx::query_as::<_, EntityId>(
+            "insert into poll (message_id, closes_at) values ($1, $2) returning id",
+        )
+        .bind(message_id)
+        .bind(poll.closes_at)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to insert poll: {:?}", e);
+            ServerSideError::from(e)
+        })?
+        .id;
+
+        sqlx::query::<_>(
+            r"
+            insert into poll_option (poll_id, position, label)
+                select $1, position::int, label
+                    from unnest($2::varchar[]) with ordinality as option(label, position)
+            ",
+        )
+        .bind(poll_id)
+        .bind(&poll.options)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to insert poll options: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+
+        Ok(poll_id)
+    }
+
+    #[instrument(skip())]
+    pub(crate) async fn query_polls_inner(
+        conn: &Pool<Postgres>,
+        message_ids: &[i64],
+        viewer_id: Option<i64>,
+    ) -> Result<Vec<PollOptionQueryResult>> {
+        sqlx::query_as::<_, PollOptionQueryResult>(
+            r"
+            select p.id as poll_id, p.message_id, p.closes_at, o.id as option_id, o.position,
+                    o.label,
+                    (select count(*) from poll_vote v where v.option_id = o.id) as votes,
+                    exists (
+                        select 1 from poll_vote v where v.option_id = o.id and v.user_id = $2
+                    ) as viewer_voted
+                from poll p
+                    join poll_option o on o.poll_id = p.id
+                where p.message_id = any($1)
+                order by p.id, o.position
+            ",
+        )
+        .bind(message_ids)
+        .bind(viewer_id)
+        .fetch_all(conn)
+        .await
+        .map_err(ServerSideError::from)
+        .into_client_result()
+    }
+
+    #[instrument(skip())]
+    pub(crate) async fn vote_poll_inner(
+        conn: &Pool<Postgres>,
+        message_id: i64,
+        user_id: i64,
+        option_id: i64,
+    ) -> Result<i64> {
+        let (poll_id, closed) = sqlx::query_as::<_, (i64, bool)>(
+            r"
+            select p.id, p.closes_at <= now()
+                from poll p
+                    join poll_option o on o.poll_id = p.id
+                    join message m on m.id = p.message_id
+                where p.message_id = $1 and o.id = $2 and m.hidden_at is null
+            ",
+        )
+        .bind(message_id)
+        .bind(option_id)
+        .fetch_optional(conn)
+        .await
+        .map_err(ServerSideError::from)?
+        .ok_or_else(|| poll_option_not_found(message_id, option_id))?;
+        if closed {
+            return Err(poll_closed());
+        }
+
+        // The unique key on (poll_id, user_id) settles concurrent votes of the same profile.
+        sqlx::query_as::<_, EntityId>(
+            r"
+            insert into poll_vote (poll_id, option_id, user_id)
+                values ($1, $2, $3)
+                on conflict (poll_id, user_id) do nothing
+                returning id
+            ",
+        )
+        .bind(poll_id)
+        .bind(option_id)
+        .bind(user_id)
+        .fetch_optional(conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to record vote: {:?}", e);
+            ServerSideError::from(e)
+        })?
+        .map(|row| row.id)
+        .ok_or_else(|| already_voted(user_id))
+    }
+}
+
+pub(crate) use private_members::insert_poll_tx;
+
+pub(crate) fn poll_option_not_found(message_id: i64, option_id: i64) -> ClientSideError {
+    ServerSideError::PollNotFound(format!(
+        "No poll option {option_id} found on message {message_id}"
+    ))
+    .into()
+}
+
+pub(crate) fn poll_closed() -> ClientSideError {
+    ServerSideError::InvalidInput("The poll is closed".to_string()).into()
+}
+
+pub(crate) fn already_voted(user_id: i64) -> ClientSideError {
+    ServerSideError::InvalidInput(format!("Profile {user_id} already voted in this poll")).into()
+}
+
+#[automock]
+#[async_trait]
+pub trait QueryPollsFn {
+    /// Polls of the given messages, one row per option ordered by poll and position. Vote
+    /// counts are always returned, hiding them is up to the caller.
+    async fn query_polls(
+        &self,
+        message_ids: &[i64],
+        viewer_id: Option<i64>,
+    ) -> Result<Vec<PollOptionQueryResult>>;
+}
+
+#[async_trait]
+impl QueryPollsFn for DbRepo {
+    async fn query_polls(
+        &self,
+        message_ids: &[i64],
+        viewer_id: Option<i64>,
+    ) -> Result<Vec<PollOptionQueryResult>> {
+        private_members::query_polls_inner(self.get_conn(), message_ids, viewer_id).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait VotePollFn {
+    /// Records the vote of `user_id` for `option_id` of the poll on `message_id`, once per
+    /// profile and only while the poll is open. Returns the vote id.
+    async fn vote_poll(&self, message_id: i64, user_id: i64, option_id: i64) -> Result<i64>;
+}
+
+#[async_trait]
+impl VotePollFn for DbRepo {
+    async fn vote_poll(&self, message_id: i64, user_id: i64, option_id: i64) -> Result<i64> {
+        private_members::vote_poll_inner(self.get_conn(), message_id, user_id, option_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, Utc};
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::{
+        common::entities::{messages::repo::InsertPollMessageFn, profile::repo::InsertProfileFn},
+        common_tests::profile,
+        schemas::message::MessageGroupTypes,
+    };
+
+    const PUBLIC: i32 = MessageGroupTypes::Public as i32;
+
+    fn poll(options: &[&str], closes_in: TimeDelta) -> PollCreate {
+        PollCreate {
+            options: options.iter().map(|label| label.to_string()).collect(),
+            closes_at: Utc::now() + closes_in,
+        }
+    }
+
+    async fn message_count(repo: &DbRepo) -> i64 {
+        sqlx::query_as::<_, (i64,)>("select count(*) from message")
+            .fetch_one(repo.get_conn())
+            .await
+            .unwrap()
+            .0
+    }
+
+    #[sqlx::test(migrator = "crate::common::entities::base::MIGRATOR")]
+    #[ignore = "needs postgres at DATABASE_URL"]
+    async fn test_poll_is_inserted_with_its_message_or_not_at_all(pool: PgPool) {
+        let repo = DbRepo::from_pool(pool);
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+
+        let message = repo
+            .insert_poll_message(
+                author,
+                "lunch?",
+                PUBLIC,
+                None,
+                poll(&["pizza", "salad", "soup"], TimeDelta::hours(1)),
+            )
+            .await
+            .unwrap();
+        let options = repo.query_polls(&[message], None).await.unwrap();
+        let labels: Vec<_> = options.iter().map(|o| o.label.as_str()).collect();
+        assert_eq!(labels, vec!["pizza", "salad", "soup"]);
+        assert!(options
+            .iter()
+            .all(|o| o.message_id == message && o.votes == 0));
+
+        // The label is too long for its column, the message goes away with the poll.
+        let too_long = "a".repeat(26);
+        assert!(repo
+            .insert_poll_message(
+                author,
+                "dinner?",
+                PUBLIC,
+                None,
+                poll(&["pasta", &too_long], TimeDelta::hours(1)),
+            )
+            .await
+            .is_err());
+        assert_eq!(message_count(&repo).await, 1);
+    }
+
+    #[sqlx::test(migrator = "crate::common::entities::base::MIGRATOR")]
+    #[ignore = "needs postgres at DATABASE_URL"]
+    async fn test_profile_votes_once_while_the_poll_is_open(pool: PgPool) {
+        let repo = DbRepo::from_pool(pool);
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let voter = repo.insert_profile(profile("voter")).await.unwrap();
+        let message = repo
+            .insert_poll_message(
+                author,
+                "lunch?",
+                PUBLIC,
+                None,
+                poll(&["pizza", "salad"], TimeDelta::hours(1)),
+            )
+            .await
+            .unwrap();
+        let options = repo.query_polls(&[message], None).await.unwrap();
+        let (pizza, salad) = (options[0].option_id, options[1].option_id);
+
+        repo.vote_poll(message, voter, pizza).await.unwrap();
+        // Neither the same nor another option counts twice.
+        for option_id in [pizza, salad] {
+            assert!(matches!(
+                repo.vote_poll(message, voter, option_id).await,
+                Err(ClientSideError::BadRequest(_))
+            ));
+        }
+        repo.vote_poll(message, author, salad).await.unwrap();
+
+        let options = repo.query_polls(&[message], Some(voter)).await.unwrap();
+        let counted: Vec<_> = options.iter().map(|o| (o.votes, o.viewer_voted)).collect();
+        assert_eq!(counted, vec![(1, true), (1, false)]);
+
+        let closed = repo
+            .insert_poll_message(
+                author,
+                "breakfast?",
+                PUBLIC,
+                None,
+                poll(&["eggs", "toast"], -TimeDelta::minutes(1)),
+            )
+            .await
+            .unwrap();
+        let eggs = repo.query_polls(&[closed], None).await.unwrap()[0].option_id;
+        assert!(matches!(
+            repo.vote_poll(closed, voter, eggs).await,
+            Err(ClientSideError::BadRequest(_))
+        ));
+        // An option of another poll is not found on this one.
+        assert!(matches!(
+            repo.vote_poll(message, voter, eggs).await,
+            Err(ClientSideError::NotFound(_))
+        ));
+    }
+}
@@ -15,7 +15,16 @@ pub struct ProfileQueryResult {
     pub avatar: Option<Vec<u8>>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// Follows in both directions and published messages of a profile.
+#[derive(Debug, Deserialize, Serialize, FromRow, Clone, PartialEq)]
+pub struct ProfileCountsQueryResult {
+    pub profile_id: i64,
+    pub followers: i64,
+    pub following: i64,
+    pub messages: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct ProfileCreate {
     pub user_name: String,
     pub full_name: String,
@@ -1,13 +1,17 @@
 use crate::common::entities::base::{DbConnGetter, DbRepo};
+use crate::common::entities::timeline::repo::backfill_follow_inner;
+use crate::common::entities::webhooks::model::WebhookEvent;
 use crate::common::entities::{base::EntityId, profile::model::ProfileCreate};
 use crate::error::Result;
+use crate::settings::TimelineMode;
 use async_trait::async_trait;
 use mockall::automock;
+use serde_json::json;
 use sqlx::{Pool, Postgres};
 use tracing::{error, instrument};
 
 use crate::{
-    common::entities::profile::model::ProfileQueryResult,
+    common::entities::profile::model::{ProfileCountsQueryResult, ProfileQueryResult},
     error::{IntoClientResult, ServerSideError},
 };
 
@@ -15,7 +19,7 @@ mod private_members {
 
     use super::*;
 
-    #[instrument(skip())]
+    #[instrument(skip(params), fields(user_name = %params.user_name))]
     pub(crate) async fn insert_profile_inner(
         conn: &Pool<Postgres>,
         params: ProfileCreate,
@@ -47,13 +51,13 @@ mod private_members {
         result.into_client_result()
     }
 
-    #[instrument(skip())]
+    #[instrument(skip(avatar), fields(avatar_bytes = avatar.as_ref().map(Vec::len)))]
     pub(crate) async fn update_profile_avatar_inner(
         conn: &Pool<Postgres>,
         profile_id: i64,
-        avatar: Vec<u8>,
+        avatar: Option<Vec<u8>>,
     ) -> Result<()> {
-        sqlx::query::<_>(
+        let result = sqlx::query::<_>(
             r"
             update Profile
                 set avatar = $1 where id = $2
@@ -63,12 +67,81 @@ mod private_members {
         .bind(profile_id)
         .execute(conn)
         .await
-        .map(|_| ())
         .map_err(|e| {
             error!("Failed to update profile avatar: {:?}", e);
             ServerSideError::from(e)
-        })
-        .into_client_result()
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(ServerSideError::ProfileNotFound(format!(
+                "No profile found with id: {profile_id}"
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Deletes a profile together with its follows, circles, timeline entries and messages,
+    /// including the response and broadcast links pointing at those messages.
+    #[instrument(skip())]
+    pub(crate) async fn delete_profile_inner(conn: &Pool<Postgres>, profile_id: i64) -> Result<()> {
+        let mut tx = conn.begin().await.map_err(ServerSideError::from)?;
+
+        let dependent_statements = [
+            "delete from home_timeline where follower_id = $1 or author_id = $1",
+            r"
+            delete from circle_group_member
+                where member_id = $1
+                    or circle_group_id in (select id from circle_group where owner_id = $1)
+            ",
+            "delete from circle_group where owner_id = $1",
+            "delete from follow where follower_id = $1 or following_id = $1",
+            "delete from draft where user_id = $1",
+            "delete from poll_vote where user_id = $1",
+            r"
+            delete from message_response
+                where original_msg_id in (select id from message where user_id = $1)
+                    or responding_msg_id in (select id from message where user_id = $1)
+            ",
+            r"
+            delete from message_broadcast
+                where main_msg_id in (select id from message where user_id = $1)
+                    or broadcasting_msg_id in (select id from message where user_id = $1)
+            ",
+            "delete from message where user_id = $1",
+        ];
+
+        for statement in dependent_statements {
+            if let Err(e) = sqlx::query::<_>(statement)
+                .bind(profile_id)
+                .execute(&mut *tx)
+                .await
+            {
+                error!("Failed to delete profile: {:?}", e);
+                tx.rollback().await.map_err(ServerSideError::from)?;
+                return Err(ServerSideError::from(e).into());
+            }
+        }
+
+        let result = sqlx::query::<_>("delete from profile where id = $1")
+            .bind(profile_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Failed to delete profile: {:?}", e);
+                ServerSideError::from(e)
+            })?;
+
+        if result.rows_affected() == 0 {
+            tx.rollback().await.map_err(ServerSideError::from)?;
+            return Err(ServerSideError::ProfileNotFound(format!(
+                "No profile found with id: {profile_id}"
+            ))
+            .into());
+        }
+
+        tx.commit().await.map_err(ServerSideError::from)?;
+        Ok(())
     }
 
     #[instrument(skip())]
@@ -105,6 +178,19 @@ mod private_members {
             .into_client_result()
     }
 
+    #[instrument(skip())]
+    pub(crate) async fn query_profiles_inner(
+        conn: &Pool<Postgres>,
+        ids: &[i64],
+    ) -> Result<Vec<ProfileQueryResult>> {
+        sqlx::query_as::<_, ProfileQueryResult>("select * from profile where id = any($1)")
+            .bind(ids)
+            .fetch_all(conn)
+            .await
+            .map_err(ServerSideError::from)
+            .into_client_result()
+    }
+
     #[instrument(skip())]
     pub(crate) async fn query_profile_by_user_inner(
         conn: &Pool<Postgres>,
@@ -117,6 +203,34 @@ mod private_members {
             .map_err(ServerSideError::from)
             .into_client_result()
     }
+
+    #[instrument(skip())]
+    pub(crate) async fn query_profile_counts_inner(
+        conn: &Pool<Postgres>,
+        profile_ids: &[i64],
+    ) -> Result<Vec<ProfileCountsQueryResult>> {
+        sqlx::query_as::<_, ProfileCountsQueryResult>(
+            r"
+            select p.id as profile_id,
+                    (select count(*) from follow f where f.following_id = p.id) as followers,
+                    (select count(*) from follow f where f.follower_id = p.id) as following,
+                    (
+                        select count(*) from message m
+                            where
+                                m.user_id = p.id
+                                and m.hidden_at is null
+                                and (m.publish_at is null or m.publish_at <= now())
+                    ) as messages
+                from profile p
+                where p.id = any($1)
+            ",
+        )
+        .bind(profile_ids)
+        .fetch_all(conn)
+        .await
+        .map_err(ServerSideError::from)
+        .into_client_result()
+    }
 }
 
 #[automock]
@@ -128,19 +242,27 @@ pub trait InsertProfileFn {
 #[async_trait]
 impl InsertProfileFn for DbRepo {
     async fn insert_profile(&self, params: ProfileCreate) -> Result<i64> {
-        private_members::insert_profile_inner(self.get_conn(), params).await
+        let (user_name, full_name) = (params.user_name.clone(), params.full_name.clone());
+        let id = private_members::insert_profile_inner(self.get_conn(), params).await?;
+        self.emit_webhook_event(
+            WebhookEvent::ProfileCreated,
+            json!({ "id": id, "userName": user_name, "fullName": full_name }),
+        )
+        .await;
+        Ok(id)
     }
 }
 
 #[automock]
 #[async_trait]
 pub trait UpdateProfileAvatarFn {
-    async fn update_profile_avatar(&self, user_id: i64, avatar: Vec<u8>) -> Result<()>;
+    /// Replaces the avatar, `None` resets it.
+    async fn update_profile_avatar(&self, user_id: i64, avatar: Option<Vec<u8>>) -> Result<()>;
 }
 
 #[async_trait]
 impl UpdateProfileAvatarFn for DbRepo {
-    async fn update_profile_avatar(&self, user_id: i64, avatar: Vec<u8>) -> Result<()> {
+    async fn update_profile_avatar(&self, user_id: i64, avatar: Option<Vec<u8>>) -> Result<()> {
         private_members::update_profile_avatar_inner(self.get_conn(), user_id, avatar).await
     }
 }
@@ -158,6 +280,20 @@ impl QueryProfileFn for DbRepo {
     }
 }
 
+#[automock]
+#[async_trait]
+pub trait QueryProfilesFn {
+    /// The given profiles in no particular order, profiles that don't exist are left out.
+    async fn query_profiles(&self, ids: &[i64]) -> Result<Vec<ProfileQueryResult>>;
+}
+
+#[async_trait]
+impl QueryProfilesFn for DbRepo {
+    async fn query_profiles(&self, ids: &[i64]) -> Result<Vec<ProfileQueryResult>> {
+        private_members::query_profiles_inner(self.get_conn(), ids).await
+    }
+}
+
 #[automock]
 #[async_trait]
 pub trait QueryProfileByUserFn {
@@ -180,6 +316,49 @@ pub trait FollowUserFn {
 #[async_trait]
 impl FollowUserFn for DbRepo {
     async fn follow_user(&self, follower_id: i64, following_id: i64) -> Result<i64> {
-        private_members::follow_user_inner(self.get_conn(), follower_id, following_id).await
+        let id =
+            private_members::follow_user_inner(self.get_conn(), follower_id, following_id).await?;
+        if self.timeline().mode == TimelineMode::Fanout {
+            backfill_follow_inner(self.get_conn(), follower_id, following_id).await?;
+        }
+        self.emit_webhook_event(
+            WebhookEvent::ProfileFollowed,
+            json!({ "id": id, "followerId": follower_id, "followingId": following_id }),
+        )
+        .await;
+        Ok(id)
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait DeleteProfileFn {
+    async fn delete_profile(&self, id: i64) -> Result<()>;
+}
+
+#[async_trait]
+impl DeleteProfileFn for DbRepo {
+    async fn delete_profile(&self, id: i64) -> Result<()> {
+        private_members::delete_profile_inner(self.get_conn(), id).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait QueryProfileCountsFn {
+    /// Counts of the given profiles, profiles that don't exist are left out.
+    async fn query_profile_counts(
+        &self,
+        profile_ids: &[i64],
+    ) -> Result<Vec<ProfileCountsQueryResult>>;
+}
+
+#[async_trait]
+impl QueryProfileCountsFn for DbRepo {
+    async fn query_profile_counts(
+        &self,
+        profile_ids: &[i64],
+    ) -> Result<Vec<ProfileCountsQueryResult>> {
+        private_members::query_profile_counts_inner(self.get_conn(), profile_ids).await
     }
 }
@@ -0,0 +1,129 @@
+use std::fmt::Debug;
+
+use crate::common::entities::{
+    circles::repo::{AddCircleMemberFn, InsertCircleFn},
+    drafts::repo::{
+        DeleteDraftFn, InsertDraftFn, PublishDraftFn, QueryDraftFn, QueryDraftsFn, UpdateDraftFn,
+    },
+    federation::repo::{
+        FollowRemoteFn, QueryActorKeyFn, QueryNoteFn, QueryRemoteNotesFn, ReceiveActivityFn,
+    },
+    messages::repo::{
+        CancelScheduledMessageFn, HideMessageFn, InsertMessageFn, InsertPollMessageFn,
+        InsertResponseMessageFn, QueryAuthorMessagesFn, QueryFeedMessagesFn, QueryMessageFn,
+        QueryMessagesFn, QueryResponseTargetsFn, QueryScheduledMessagesFn, RescheduleMessageFn,
+        ScheduleMessageFn,
+    },
+    polls::repo::{QueryPollsFn, VotePollFn},
+    profile::repo::{
+        DeleteProfileFn, FollowUserFn, InsertProfileFn, QueryProfileByUserFn, QueryProfileCountsFn,
+        QueryProfileFn, QueryProfilesFn, UpdateProfileAvatarFn,
+    },
+    webhooks::repo::{
+        DeleteWebhookFn, InsertWebhookFn, QueryWebhookDeliveriesFn, QueryWebhooksFn,
+        RedeliverWebhookFn,
+    },
+};
+
+/// A storage backend implementing every repo trait, so it can serve the whole route tree.
+///
+/// Handlers keep asking for the narrow `XxxFn` traits they use; this bound is only for the places
+/// that mount all of them, like `routes::config` and the server setup in `run()`. It is
+/// implemented automatically for any type that implements all of the traits.
+pub trait Repository:
+    InsertMessageFn
+    + InsertResponseMessageFn
+    + QueryMessageFn
+    + QueryMessagesFn
+    + QueryAuthorMessagesFn
+    + QueryFeedMessagesFn
+    + QueryResponseTargetsFn
+    + HideMessageFn
+    + ScheduleMessageFn
+    + QueryScheduledMessagesFn
+    + RescheduleMessageFn
+    + CancelScheduledMessageFn
+    + InsertPollMessageFn
+    + QueryPollsFn
+    + VotePollFn
+    + InsertProfileFn
+    + UpdateProfileAvatarFn
+    + QueryProfileFn
+    + QueryProfilesFn
+    + QueryProfileByUserFn
+    + QueryProfileCountsFn
+    + FollowUserFn
+    + DeleteProfileFn
+    + InsertCircleFn
+    + AddCircleMemberFn
+    + InsertDraftFn
+    + QueryDraftFn
+    + QueryDraftsFn
+    + UpdateDraftFn
+    + DeleteDraftFn
+    + PublishDraftFn
+    + InsertWebhookFn
+    + QueryWebhooksFn
+    + DeleteWebhookFn
+    + QueryWebhookDeliveriesFn
+    + RedeliverWebhookFn
+    + QueryActorKeyFn
+    + QueryNoteFn
+    + ReceiveActivityFn
+    + FollowRemoteFn
+    + QueryRemoteNotesFn
+    + Debug
+    + Send
+    + Sync
+    + 'static
+{
+}
+
+impl<T> Repository for T where
+    T: InsertMessageFn
+        + InsertResponseMessageFn
+        + QueryMessageFn
+        + QueryMessagesFn
+        + QueryAuthorMessagesFn
+        + QueryFeedMessagesFn
+        + QueryResponseTargetsFn
+        + HideMessageFn
+        + ScheduleMessageFn
+        + QueryScheduledMessagesFn
+        + RescheduleMessageFn
+        + CancelScheduledMessageFn
+        + InsertPollMessageFn
+        + QueryPollsFn
+        + VotePollFn
+        + InsertProfileFn
+        + UpdateProfileAvatarFn
+        + QueryProfileFn
+        + QueryProfilesFn
+        + QueryProfileByUserFn
+        + QueryProfileCountsFn
+        + FollowUserFn
+        + DeleteProfileFn
+        + InsertCircleFn
+        + AddCircleMemberFn
+        + InsertDraftFn
+        + QueryDraftFn
+        + QueryDraftsFn
+        + UpdateDraftFn
+        + DeleteDraftFn
+        + PublishDraftFn
+        + InsertWebhookFn
+        + QueryWebhooksFn
+        + DeleteWebhookFn
+        + QueryWebhookDeliveriesFn
+        + RedeliverWebhookFn
+        + QueryActorKeyFn
+        + QueryNoteFn
+        + ReceiveActivityFn
+        + FollowRemoteFn
+        + QueryRemoteNotesFn
+        + Debug
+        + Send
+        + Sync
+        + 'static
+{
+}
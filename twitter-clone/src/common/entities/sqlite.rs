@@ -0,0 +1,142 @@
+mod circles;
+mod drafts;
+mod federation;
+mod messages;
+mod polls;
+mod profile;
+mod webhooks;
+
+use std::{str::FromStr, time::Duration};
+
+use chrono::{DateTime, SubsecRound, TimeDelta, Utc};
+use sqlx::{
+    migrate,
+    migrate::Migrator,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    Pool, Sqlite,
+};
+use tracing::{error, info};
+
+use crate::{
+    common::entities::base::DbConnGetter, error::ServerSideError, settings::SqliteSettings,
+};
+
+/// Migrations embedded from `./migrations_sqlite`, the SQLite counterpart of [`super::base::MIGRATOR`].
+pub static SQLITE_MIGRATOR: Migrator = migrate!("./migrations_sqlite");
+
+/// Layout of the stored timestamps, matching `strftime('%Y-%m-%dT%H:%M:%fZ', 'now')` used as
+/// column default. Being fixed width UTC text, they compare and sort correctly as strings.
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3fZ";
+
+/// Repository backed by a single SQLite database, for small self-hosted instances and fast local
+/// tests.
+///
+/// It implements the same repo traits as [`super::base::DbRepo`] with its own queries: ids come
+/// from `last_insert_rowid()` instead of `returning`, parameters are `?N` instead of `$N`, and
+/// timestamps are millisecond precision text instead of `timestamptz(3)`.
+#[derive(Debug, Clone)]
+pub struct SqliteRepo {
+    conn: Pool<Sqlite>,
+}
+
+impl SqliteRepo {
+    pub async fn init(settings: &SqliteSettings) -> Result<Self, ServerSideError> {
+        Ok(Self { conn: get_sqlite_conn(settings).await? })
+    }
+}
+
+impl DbConnGetter for SqliteRepo {
+    type Output = Pool<Sqlite>;
+
+    fn get_conn(&self) -> &Self::Output {
+        &self.conn
+    }
+}
+
+/// Opens the database, creating the file when missing, and runs the SQLite migrations when
+/// `storage.sqlite.auto_migrate` is enabled.
+pub async fn get_sqlite_conn(settings: &SqliteSettings) -> Result<Pool<Sqlite>, ServerSideError> {
+    let connect_options = SqliteConnectOptions::from_str(&settings.url)?
+        .create_if_missing(true)
+        .foreign_keys(true)
+        .journal_mode(SqliteJournalMode::Wal)
+        .busy_timeout(Duration::from_secs(5));
+
+    // An in-memory database only lives as long as one of its connections, so they are never
+    // recycled.
+    let conn = SqlitePoolOptions::new()
+        .max_connections(settings.max_connections)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(connect_options)
+        .await
+        .map_err(|e| {
+            error!("Failed to open sqlite database: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+    info!("Connected to sqlite database");
+
+    if settings.auto_migrate {
+        run_sqlite_migrations(&conn).await?;
+    } else {
+        info!("Auto migration disabled, skipping sqlite migrations");
+    }
+
+    Ok(conn)
+}
+
+pub async fn run_sqlite_migrations(conn: &Pool<Sqlite>) -> Result<(), ServerSideError> {
+    SQLITE_MIGRATOR.run(conn).await.map_err(|e| {
+        error!("Sqlite migration error: {:?}", e);
+        ServerSideError::from(e)
+    })?;
+    info!("sqlite migration success");
+    Ok(())
+}
+
+/// Formats `at` like the stored timestamps, truncated to milliseconds like `timestamptz(3)`.
+fn format_timestamp(at: DateTime<Utc>) -> String {
+    at.format(TIMESTAMP_FORMAT).to_string()
+}
+
+/// Formats `at` like the stored timestamps, rounded up to the next millisecond so that
+/// `column < bound` keeps the meaning it has against the unrounded value.
+fn timestamp_upper_bound(at: DateTime<Utc>) -> String {
+    let truncated = at.trunc_subsecs(3);
+    let bound = if truncated < at {
+        truncated + TimeDelta::milliseconds(1)
+    } else {
+        truncated
+    };
+    format_timestamp(bound)
+}
+
+/// A migrated in-memory database for the backend tests.
+#[cfg(test)]
+async fn test_repo() -> SqliteRepo {
+    SqliteRepo::init(&SqliteSettings {
+        url: "sqlite::memory:".to_string(),
+        max_connections: 1,
+        auto_migrate: true,
+    })
+    .await
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_timestamp_upper_bound() {
+        let exact =
+            Utc.with_ymd_and_hms(2026, 10, 19, 12, 0, 0).unwrap() + TimeDelta::milliseconds(5);
+        assert_eq!(timestamp_upper_bound(exact), "2026-10-19T12:00:00.005Z");
+        assert_eq!(
+            timestamp_upper_bound(exact + TimeDelta::microseconds(1)),
+            "2026-10-19T12:00:00.006Z"
+        );
+    }
+}
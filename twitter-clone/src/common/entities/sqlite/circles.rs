@@ -0,0 +1,59 @@
+use async_trait::async_trait;
+use sqlx::{Pool, Sqlite};
+use tracing::{error, instrument};
+
+use super::SqliteRepo;
+use crate::{
+    common::entities::{
+        base::DbConnGetter,
+        circles::repo::{AddCircleMemberFn, InsertCircleFn},
+    },
+    error::{IntoClientResult, Result, ServerSideError},
+};
+
+#[instrument(skip())]
+async fn insert_circle_inner(conn: &Pool<Sqlite>, owner_id: i64) -> Result<i64> {
+    sqlx::query::<_>("insert into circle_group (owner_id) values (?1)")
+        .bind(owner_id)
+        .execute(conn)
+        .await
+        .map(|result| result.last_insert_rowid())
+        .map_err(|e| {
+            error!("Failed to insert circle: {:?}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
+}
+
+#[instrument(skip())]
+async fn add_circle_member_inner(
+    conn: &Pool<Sqlite>,
+    circle_group_id: i64,
+    member_id: i64,
+) -> Result<i64> {
+    sqlx::query::<_>("insert into circle_group_member (circle_group_id, member_id) values (?1, ?2)")
+        .bind(circle_group_id)
+        .bind(member_id)
+        .execute(conn)
+        .await
+        .map(|result| result.last_insert_rowid())
+        .map_err(|e| {
+            error!("Failed to add circle member: {:?}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
+}
+
+#[async_trait]
+impl InsertCircleFn for SqliteRepo {
+    async fn insert_circle(&self, owner_id: i64) -> Result<i64> {
+        insert_circle_inner(self.get_conn(), owner_id).await
+    }
+}
+
+#[async_trait]
+impl AddCircleMemberFn for SqliteRepo {
+    async fn add_circle_member(&self, circle_group_id: i64, member_id: i64) -> Result<i64> {
+        add_circle_member_inner(self.get_conn(), circle_group_id, member_id).await
+    }
+}
@@ -0,0 +1,302 @@
+use async_trait::async_trait;
+use sqlx::{Pool, Sqlite};
+use tracing::{error, instrument};
+
+use super::{
+    messages::{insert_message_tx, insert_response_message_tx},
+    SqliteRepo,
+};
+use crate::{
+    common::entities::{
+        base::DbConnGetter,
+        drafts::{
+            model::{DraftCreate, DraftQueryResult},
+            repo::{
+                draft_not_found, DeleteDraftFn, InsertDraftFn, PublishDraftFn, QueryDraftFn,
+                QueryDraftsFn, UpdateDraftFn,
+            },
+        },
+    },
+    error::{IntoClientResult, Result, ServerSideError},
+};
+
+#[instrument(skip(draft))]
+async fn insert_draft_inner(conn: &Pool<Sqlite>, user_id: i64, draft: DraftCreate) -> Result<i64> {
+    sqlx::query::<_>(
+        r"
+        insert into draft (user_id, body, msg_group_type, broadcasting_msg_id, original_msg_id)
+            values (?1, ?2, ?3, ?4, ?5)
+        ",
+    )
+    .bind(user_id)
+    .bind(&draft.body)
+    .bind(draft.msg_group_type)
+    .bind(draft.broadcasting_msg_id)
+    .bind(draft.original_msg_id)
+    .execute(conn)
+    .await
+    .map(|result| result.last_insert_rowid())
+    .map_err(|e| {
+        error!("Failed to insert draft: {:?}", e);
+        ServerSideError::from(e)
+    })
+    .into_client_result()
+}
+
+#[instrument(skip())]
+async fn query_draft_inner(
+    conn: &Pool<Sqlite>,
+    id: i64,
+    user_id: i64,
+) -> Result<Option<DraftQueryResult>> {
+    sqlx::query_as::<_, DraftQueryResult>("select * from draft where id = ?1 and user_id = ?2")
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(conn)
+        .await
+        .map_err(ServerSideError::from)
+        .into_client_result()
+}
+
+#[instrument(skip())]
+async fn query_drafts_inner(conn: &Pool<Sqlite>, user_id: i64) -> Result<Vec<DraftQueryResult>> {
+    sqlx::query_as::<_, DraftQueryResult>(
+        "select * from draft where user_id = ?1 order by updated_at desc, id desc",
+    )
+    .bind(user_id)
+    .fetch_all(conn)
+    .await
+    .map_err(ServerSideError::from)
+    .into_client_result()
+}
+
+#[instrument(skip(draft))]
+async fn update_draft_inner(
+    conn: &Pool<Sqlite>,
+    id: i64,
+    user_id: i64,
+    draft: DraftCreate,
+) -> Result<()> {
+    let result = sqlx::query::<_>(
+        r"
+        update draft
+            set
+                body = ?3,
+                msg_group_type = ?4,
+                broadcasting_msg_id = ?5,
+                original_msg_id = ?6,
+                updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            where id = ?1 and user_id = ?2
+        ",
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(&draft.body)
+    .bind(draft.msg_group_type)
+    .bind(draft.broadcasting_msg_id)
+    .bind(draft.original_msg_id)
+    .execute(conn)
+    .await
+    .map_err(|e| {
+        error!("Failed to update draft: {:?}", e);
+        ServerSideError::from(e)
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err(draft_not_found(id));
+    }
+    Ok(())
+}
+
+#[instrument(skip())]
+async fn delete_draft_inner(conn: &Pool<Sqlite>, id: i64, user_id: i64) -> Result<()> {
+    let result = sqlx::query::<_>("delete from draft where id = ?1 and user_id = ?2")
+        .bind(id)
+        .bind(user_id)
+        .execute(conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to delete draft: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+
+    if result.rows_affected() == 0 {
+        return Err(draft_not_found(id));
+    }
+    Ok(())
+}
+
+/// Same transaction as the postgres version, the draft is only removed with its message.
+#[instrument(skip())]
+async fn publish_draft_inner(conn: &Pool<Sqlite>, id: i64, user_id: i64) -> Result<i64> {
+    let mut tx = conn.begin().await.map_err(ServerSideError::from)?;
+
+    let draft = sqlx::query_as::<_, DraftQueryResult>(
+        "delete from draft where id = ?1 and user_id = ?2 returning *",
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| {
+        error!("Failed to publish draft: {:?}", e);
+        ServerSideError::from(e)
+    })?
+    .ok_or_else(|| draft_not_found(id))?;
+
+    let message_id = match draft.original_msg_id {
+        Some(original_msg_id) => {
+            insert_response_message_tx(
+                &mut tx,
+                draft.user_id,
+                &draft.body,
+                draft.msg_group_type,
+                original_msg_id,
+            )
+            .await?
+        },
+        None => {
+            insert_message_tx(
+                &mut tx,
+                draft.user_id,
+                &draft.body,
+                draft.msg_group_type,
+                draft.broadcasting_msg_id,
+                None,
+            )
+            .await?
+        },
+    };
+
+    tx.commit().await.map_err(ServerSideError::from)?;
+    Ok(message_id)
+}
+
+#[async_trait]
+impl InsertDraftFn for SqliteRepo {
+    async fn insert_draft(&self, user_id: i64, draft: DraftCreate) -> Result<i64> {
+        insert_draft_inner(self.get_conn(), user_id, draft).await
+    }
+}
+
+#[async_trait]
+impl QueryDraftFn for SqliteRepo {
+    async fn query_draft(&self, id: i64, user_id: i64) -> Result<Option<DraftQueryResult>> {
+        query_draft_inner(self.get_conn(), id, user_id).await
+    }
+}
+
+#[async_trait]
+impl QueryDraftsFn for SqliteRepo {
+    async fn query_drafts(&self, user_id: i64) -> Result<Vec<DraftQueryResult>> {
+        query_drafts_inner(self.get_conn(), user_id).await
+    }
+}
+
+#[async_trait]
+impl UpdateDraftFn for SqliteRepo {
+    async fn update_draft(&self, id: i64, user_id: i64, draft: DraftCreate) -> Result<()> {
+        update_draft_inner(self.get_conn(), id, user_id, draft).await
+    }
+}
+
+#[async_trait]
+impl DeleteDraftFn for SqliteRepo {
+    async fn delete_draft(&self, id: i64, user_id: i64) -> Result<()> {
+        delete_draft_inner(self.get_conn(), id, user_id).await
+    }
+}
+
+#[async_trait]
+impl PublishDraftFn for SqliteRepo {
+    async fn publish_draft(&self, id: i64, user_id: i64) -> Result<i64> {
+        publish_draft_inner(self.get_conn(), id, user_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::entities::{
+            messages::repo::{InsertMessageFn, QueryMessageFn},
+            profile::repo::InsertProfileFn,
+            sqlite::test_repo,
+        },
+        common_tests::profile,
+    };
+
+    fn draft(body: &str, broadcasting_msg_id: Option<i64>) -> DraftCreate {
+        DraftCreate {
+            body: body.to_string(),
+            msg_group_type: 1,
+            broadcasting_msg_id,
+            original_msg_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_draft_inserts_message_once() {
+        let repo = test_repo().await;
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let other = repo.insert_profile(profile("other")).await.unwrap();
+        let original = repo
+            .insert_message(other, "original", 1, None)
+            .await
+            .unwrap();
+
+        let id = repo
+            .insert_draft(author, draft("first", None))
+            .await
+            .unwrap();
+        repo.update_draft(id, author, draft("look", Some(original)))
+            .await
+            .unwrap();
+        assert!(repo.query_draft(id, other).await.unwrap().is_none());
+        assert!(repo.publish_draft(id, other).await.is_err());
+
+        let message_id = repo.publish_draft(id, author).await.unwrap();
+        let message = repo.query_message(message_id).await.unwrap().unwrap();
+        assert_eq!(message.body.as_deref(), Some("look"));
+        assert_eq!(message.broadcast_msg_id, Some(original));
+
+        assert!(repo.query_drafts(author).await.unwrap().is_empty());
+        assert!(repo.publish_draft(id, author).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_draft_targets_are_checked_and_cleared() {
+        let repo = test_repo().await;
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let original = repo
+            .insert_message(author, "original", 1, None)
+            .await
+            .unwrap();
+
+        let id = repo
+            .insert_draft(author, draft("look", Some(original)))
+            .await
+            .unwrap();
+        let dangling = repo
+            .insert_draft(author, draft("dangling", None))
+            .await
+            .unwrap();
+        let mut update = draft("dangling", None);
+        update.original_msg_id = Some(99);
+        assert!(repo.update_draft(dangling, author, update).await.is_err());
+
+        // A deleted target is dropped from the draft instead of failing its publication.
+        sqlx::query::<_>("delete from message where id = ?1")
+            .bind(original)
+            .execute(repo.get_conn())
+            .await
+            .unwrap();
+        let kept = repo.query_draft(id, author).await.unwrap().unwrap();
+        assert_eq!(kept.broadcasting_msg_id, None);
+
+        let drafts = repo.query_drafts(author).await.unwrap();
+        assert_eq!(drafts.len(), 2);
+        repo.delete_draft(dangling, author).await.unwrap();
+        assert!(repo.delete_draft(dangling, author).await.is_err());
+    }
+}
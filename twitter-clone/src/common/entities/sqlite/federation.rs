@@ -0,0 +1,69 @@
+use async_trait::async_trait;
+
+use super::SqliteRepo;
+use crate::{
+    common::entities::federation::{
+        model::{
+            ActorKeyQueryResult, InboundActivity, NoteQueryResult, RemoteActor,
+            RemoteNoteQueryResult,
+        },
+        repo::{
+            federation_unsupported, FollowRemoteFn, QueryActorKeyFn, QueryNoteFn,
+            QueryRemoteNotesFn, ReceiveActivityFn,
+        },
+    },
+    error::Result,
+};
+
+// Activities are sent by the postgres job queue, which SQLite has no counterpart of, so the
+// settings refuse to enable federation here and these only answer with an error.
+
+#[async_trait]
+impl QueryActorKeyFn for SqliteRepo {
+    async fn query_actor_key(&self, _profile_id: i64) -> Result<ActorKeyQueryResult> {
+        Err(federation_unsupported())
+    }
+}
+
+#[async_trait]
+impl QueryNoteFn for SqliteRepo {
+    async fn query_note(&self, _id: i64) -> Result<Option<NoteQueryResult>> {
+        Err(federation_unsupported())
+    }
+}
+
+#[async_trait]
+impl ReceiveActivityFn for SqliteRepo {
+    async fn receive_activity(
+        &self,
+        _profile_id: i64,
+        _user_name: &str,
+        _actor: &RemoteActor,
+        _activity: InboundActivity,
+    ) -> Result<()> {
+        Err(federation_unsupported())
+    }
+}
+
+#[async_trait]
+impl FollowRemoteFn for SqliteRepo {
+    async fn follow_remote(
+        &self,
+        _profile_id: i64,
+        _user_name: &str,
+        _actor: &RemoteActor,
+    ) -> Result<i64> {
+        Err(federation_unsupported())
+    }
+}
+
+#[async_trait]
+impl QueryRemoteNotesFn for SqliteRepo {
+    async fn query_remote_notes(
+        &self,
+        _profile_id: i64,
+        _limit: i64,
+    ) -> Result<Vec<RemoteNoteQueryResult>> {
+        Err(federation_unsupported())
+    }
+}
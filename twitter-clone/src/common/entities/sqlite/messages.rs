@@ -0,0 +1,884 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Sqlite, SqliteConnection};
+use tracing::{error, instrument};
+
+use super::{format_timestamp, polls::insert_poll_tx, timestamp_upper_bound, SqliteRepo};
+use crate::{
+    common::entities::{
+        base::DbConnGetter,
+        messages::{
+            model::{
+                AuthorMessageQueryResult, MessageWithFollowingAndBroadcastQueryResult,
+                ResponseTargetQueryResult, ScheduledMessageQueryResult,
+            },
+            repo::{
+                CancelScheduledMessageFn, HideMessageFn, InsertMessageFn, InsertPollMessageFn,
+                InsertResponseMessageFn, QueryAuthorMessagesFn, QueryFeedMessagesFn,
+                QueryMessageFn, QueryMessagesFn, QueryResponseTargetsFn, QueryScheduledMessagesFn,
+                RescheduleMessageFn, ScheduleMessageFn,
+            },
+        },
+        polls::model::PollCreate,
+    },
+    error::{ClientSideError, IntoClientResult, Result, ServerSideError},
+    schemas::message::MessageGroupTypes,
+};
+
+/// Messages joined with their author and, through `message_broadcast`, the visible message they
+/// broadcast, the same single query shape as the postgres backend.
+const MESSAGE_WITH_BROADCAST_SELECT: &str = r"
+    select m.id, m.updated_at, coalesce(m.publish_at, m.created_at) as published_at, m.body,
+        m.likes, m.msg_group_type, m.user_id, p.user_name, p.full_name,
+        bm.id as broadcast_msg_id, bm.updated_at as broadcast_msg_updated_at,
+        coalesce(bm.publish_at, bm.created_at) as broadcast_msg_published_at, bm.body as broadcast_msg_body,
+        bm.likes as broadcast_msg_likes, bm.user_id as broadcast_msg_user_id,
+        bp.user_name as broadcast_msg_user_name, bp.full_name as broadcast_msg_full_name
+        from message m
+            join profile p on p.id = m.user_id
+            left join message_broadcast mb on mb.main_msg_id = m.id
+            left join message bm on bm.id = mb.broadcasting_msg_id
+                and bm.hidden_at is null
+                and (bm.publish_at is null or bm.publish_at <= strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+            left join profile bp on bp.id = bm.user_id
+";
+
+/// Matches the messages of [`MESSAGE_WITH_BROADCAST_SELECT`] that are published.
+const PUBLISHED: &str =
+    "(m.publish_at is null or m.publish_at <= strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))";
+
+#[instrument(skip(body, poll))]
+async fn insert_message_inner(
+    conn: &Pool<Sqlite>,
+    user_id: i64,
+    body: &str,
+    group_type: i32,
+    broadcasting_msg_id: Option<i64>,
+    publish_at: Option<DateTime<Utc>>,
+    poll: Option<&PollCreate>,
+) -> Result<i64> {
+    let mut tx = conn.begin().await.map_err(ServerSideError::from)?;
+    let message_id = insert_message_tx(
+        &mut tx,
+        user_id,
+        body,
+        group_type,
+        broadcasting_msg_id,
+        publish_at,
+    )
+    .await?;
+    if let Some(poll) = poll {
+        insert_poll_tx(&mut tx, message_id, poll).await?;
+    }
+    tx.commit().await.map_err(ServerSideError::from)?;
+    Ok(message_id)
+}
+
+/// Same as the postgres `insert_message_tx`, shared with publishing drafts.
+pub(super) async fn insert_message_tx(
+    tx: &mut SqliteConnection,
+    user_id: i64,
+    body: &str,
+    group_type: i32,
+    broadcasting_msg_id: Option<i64>,
+    publish_at: Option<DateTime<Utc>>,
+) -> Result<i64> {
+    let message_id = sqlx::query::<_>(
+        r"
+        insert into message (user_id, body, msg_group_type, publish_at, updated_at)
+            values (?1, ?2, ?3, ?4, coalesce(?4, strftime('%Y-%m-%dT%H:%M:%fZ', 'now')))
+        ",
+    )
+    .bind(user_id)
+    .bind(body)
+    .bind(group_type)
+    .bind(publish_at.map(format_timestamp))
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| {
+        error!("insert_message error: {}", e);
+        ServerSideError::from(e)
+    })?
+    .last_insert_rowid();
+
+    if let Some(bm_id) = broadcasting_msg_id {
+        sqlx::query::<_>(
+            "insert into message_broadcast (main_msg_id, broadcasting_msg_id) values (?1, ?2)",
+        )
+        .bind(message_id)
+        .bind(bm_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(ServerSideError::from)?;
+    }
+
+    Ok(message_id)
+}
+
+#[instrument(skip(body))]
+async fn insert_response_message_inner(
+    conn: &Pool<Sqlite>,
+    user_id: i64,
+    body: &str,
+    group_type: i32,
+    original_msg_id: i64,
+) -> Result<i64> {
+    let mut tx = conn.begin().await.map_err(ServerSideError::from)?;
+    let message_id =
+        insert_response_message_tx(&mut tx, user_id, body, group_type, original_msg_id).await?;
+    tx.commit().await.map_err(ServerSideError::from)?;
+    Ok(message_id)
+}
+
+/// Same as the postgres `insert_response_message_tx`, shared with publishing drafts.
+pub(super) async fn insert_response_message_tx(
+    tx: &mut SqliteConnection,
+    user_id: i64,
+    body: &str,
+    group_type: i32,
+    original_msg_id: i64,
+) -> Result<i64> {
+    let message_id =
+        sqlx::query::<_>("insert into message (user_id, body, msg_group_type) values (?1, ?2, ?3)")
+            .bind(user_id)
+            .bind(body)
+            .bind(group_type)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("insert_message error: {}", e);
+                ServerSideError::from(e)
+            })?
+            .last_insert_rowid();
+
+    sqlx::query::<_>(
+        "insert into message_response (original_msg_id, responding_msg_id) values (?1, ?2)",
+    )
+    .bind(original_msg_id)
+    .bind(message_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(ServerSideError::from)?;
+
+    Ok(message_id)
+}
+
+#[instrument(skip())]
+async fn query_message_inner(
+    conn: &Pool<Sqlite>,
+    id: i64,
+) -> Result<Option<MessageWithFollowingAndBroadcastQueryResult>> {
+    sqlx::query_as::<_, MessageWithFollowingAndBroadcastQueryResult>(&format!(
+        "{MESSAGE_WITH_BROADCAST_SELECT} where m.id = ?1 and m.hidden_at is null and {PUBLISHED}"
+    ))
+    .bind(id)
+    .fetch_optional(conn)
+    .await
+    .map_err(ServerSideError::from)
+    .into_client_result()
+}
+
+#[instrument(skip())]
+async fn query_author_messages_inner(
+    conn: &Pool<Sqlite>,
+    user_id: i64,
+    before_id: Option<i64>,
+    page_size: i16,
+) -> Result<Vec<AuthorMessageQueryResult>> {
+    if page_size < 0 {
+        return Err(ClientSideError::from(ServerSideError::InternalServerError(
+            "LIMIT must not be negative".to_string(),
+        )));
+    }
+
+    sqlx::query_as::<_, AuthorMessageQueryResult>(&format!(
+        r"
+        select m.id, coalesce(m.publish_at, m.created_at) as published_at, m.body,
+                m.msg_group_type, m.user_id, p.user_name,
+                mb.broadcasting_msg_id as broadcast_msg_id, mr.original_msg_id
+            from message m
+                join profile p on p.id = m.user_id
+                left join message_broadcast mb on mb.main_msg_id = m.id
+                left join message_response mr on mr.responding_msg_id = m.id
+            where
+                m.user_id = ?1
+                and (?2 is null or m.id < ?2)
+                and m.hidden_at is null
+                and {PUBLISHED}
+            order by m.id desc
+            limit ?3
+        "
+    ))
+    .bind(user_id)
+    .bind(before_id)
+    .bind(page_size)
+    .fetch_all(conn)
+    .await
+    .map_err(ServerSideError::from)
+    .into_client_result()
+}
+
+#[instrument(skip())]
+async fn query_messages_inner(
+    conn: &Pool<Sqlite>,
+    user_id: i64,
+    last_updated_at: DateTime<Utc>,
+    page_size: i16,
+) -> Result<Vec<MessageWithFollowingAndBroadcastQueryResult>> {
+    // SQLite treats a negative LIMIT as no limit at all, postgres rejects it.
+    if page_size < 0 {
+        return Err(ClientSideError::from(ServerSideError::InternalServerError(
+            "LIMIT must not be negative".to_string(),
+        )));
+    }
+
+    sqlx::query_as::<_, MessageWithFollowingAndBroadcastQueryResult>(&format!(
+        r"
+        {MESSAGE_WITH_BROADCAST_SELECT}
+            join follow f on f.following_id = m.user_id
+            where
+                f.follower_id = ?1
+                and m.updated_at < ?2
+                and m.hidden_at is null
+                and {PUBLISHED}
+            order by m.updated_at desc, m.id desc
+            limit ?3
+        "
+    ))
+    .bind(user_id)
+    .bind(timestamp_upper_bound(last_updated_at))
+    .bind(page_size)
+    .fetch_all(conn)
+    .await
+    .map_err(ServerSideError::from)
+    .into_client_result()
+}
+
+#[instrument(skip())]
+async fn query_feed_messages_inner(
+    conn: &Pool<Sqlite>,
+    user_id: i64,
+    page_size: i16,
+) -> Result<Vec<MessageWithFollowingAndBroadcastQueryResult>> {
+    if page_size < 0 {
+        return Err(ClientSideError::from(ServerSideError::InternalServerError(
+            "LIMIT must not be negative".to_string(),
+        )));
+    }
+
+    sqlx::query_as::<_, MessageWithFollowingAndBroadcastQueryResult>(&format!(
+        r"
+        {MESSAGE_WITH_BROADCAST_SELECT}
+            where
+                m.user_id = ?1
+                and m.msg_group_type = ?2
+                and m.hidden_at is null
+                and {PUBLISHED}
+            order by m.updated_at desc, m.id desc
+            limit ?3
+        "
+    ))
+    .bind(user_id)
+    .bind(MessageGroupTypes::Public as i32)
+    .bind(page_size)
+    .fetch_all(conn)
+    .await
+    .map_err(ServerSideError::from)
+    .into_client_result()
+}
+
+/// The message ids are passed as one JSON array, SQLite has no array parameters.
+#[instrument(skip())]
+async fn query_response_targets_inner(
+    conn: &Pool<Sqlite>,
+    message_ids: &[i64],
+) -> Result<Vec<ResponseTargetQueryResult>> {
+    let message_ids = serde_json::to_string(message_ids).map_err(ServerSideError::from)?;
+
+    sqlx::query_as::<_, ResponseTargetQueryResult>(
+        r"
+        select mr.responding_msg_id, mr.original_msg_id, om.user_id as original_user_id
+            from message_response mr
+                join message om on om.id = mr.original_msg_id
+            where mr.responding_msg_id in (select value from json_each(?1))
+        ",
+    )
+    .bind(message_ids)
+    .fetch_all(conn)
+    .await
+    .map_err(ServerSideError::from)
+    .into_client_result()
+}
+
+#[instrument(skip())]
+async fn query_scheduled_messages_inner(
+    conn: &Pool<Sqlite>,
+    user_id: i64,
+) -> Result<Vec<ScheduledMessageQueryResult>> {
+    sqlx::query_as::<_, ScheduledMessageQueryResult>(
+        r"
+        select m.id, m.created_at, m.user_id, m.body, m.msg_group_type,
+                mb.broadcasting_msg_id as broadcast_msg_id, m.publish_at
+            from message m
+                left join message_broadcast mb on mb.main_msg_id = m.id
+            where
+                m.user_id = ?1
+                and m.publish_at > strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+                and m.hidden_at is null
+            order by m.publish_at, m.id
+        ",
+    )
+    .bind(user_id)
+    .fetch_all(conn)
+    .await
+    .map_err(ServerSideError::from)
+    .into_client_result()
+}
+
+#[instrument(skip())]
+async fn reschedule_message_inner(
+    conn: &Pool<Sqlite>,
+    id: i64,
+    publish_at: DateTime<Utc>,
+) -> Result<()> {
+    let result = sqlx::query::<_>(
+        r"
+        update message
+            set publish_at = ?2, updated_at = ?2
+            where
+                id = ?1
+                and publish_at > strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+                and hidden_at is null
+        ",
+    )
+    .bind(id)
+    .bind(format_timestamp(publish_at))
+    .execute(conn)
+    .await
+    .map_err(|e| {
+        error!("Failed to reschedule message: {:?}", e);
+        ServerSideError::from(e)
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err(ServerSideError::MessageNotFound(format!(
+            "No scheduled message found with id: {id}"
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+#[instrument(skip())]
+async fn cancel_scheduled_message_inner(conn: &Pool<Sqlite>, id: i64) -> Result<()> {
+    let mut tx = conn.begin().await.map_err(ServerSideError::from)?;
+
+    let scheduled = sqlx::query_as::<_, (i64,)>(
+        "select id from message where id = ?1 and publish_at > strftime('%Y-%m-%dT%H:%M:%fZ', 'now')",
+    )
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(ServerSideError::from)?;
+    if scheduled.is_none() {
+        return Err(ServerSideError::MessageNotFound(format!(
+            "No scheduled message found with id: {id}"
+        ))
+        .into());
+    }
+
+    for statement in [
+        "delete from message_response where original_msg_id = ?1 or responding_msg_id = ?1",
+        "delete from message_broadcast where main_msg_id = ?1 or broadcasting_msg_id = ?1",
+        "delete from message where id = ?1",
+    ] {
+        sqlx::query::<_>(statement)
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Failed to cancel scheduled message: {:?}", e);
+                ServerSideError::from(e)
+            })?;
+    }
+
+    tx.commit().await.map_err(ServerSideError::from)?;
+    Ok(())
+}
+
+#[instrument(skip())]
+async fn hide_message_inner(conn: &Pool<Sqlite>, id: i64, hidden: bool) -> Result<()> {
+    let mut tx = conn.begin().await.map_err(ServerSideError::from)?;
+
+    let result = sqlx::query::<_>(
+        r"
+        update message
+            set hidden_at = case
+                when ?2 then coalesce(hidden_at, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+                else null
+            end
+            where id = ?1
+        ",
+    )
+    .bind(id)
+    .bind(hidden)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| {
+        error!("Failed to update message visibility: {:?}", e);
+        ServerSideError::from(e)
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err(
+            ServerSideError::MessageNotFound(format!("No message found with id: {id}")).into(),
+        );
+    }
+
+    sqlx::query::<_>(
+        r"
+        update profile set updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            where id in (
+                select user_id from message where id = ?1
+                union
+                select m.user_id from message_broadcast mb
+                    join message m on m.id = mb.main_msg_id
+                    where mb.broadcasting_msg_id = ?1
+            )
+        ",
+    )
+    .bind(id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| {
+        error!("Failed to touch the profiles of a hidden message: {:?}", e);
+        ServerSideError::from(e)
+    })?;
+
+    tx.commit().await.map_err(ServerSideError::from)?;
+    Ok(())
+}
+
+#[async_trait]
+impl InsertMessageFn for SqliteRepo {
+    async fn insert_message(
+        &self,
+        user_id: i64,
+        body: &str,
+        group_type: i32,
+        broadcasting_msg_id: Option<i64>,
+    ) -> Result<i64> {
+        insert_message_inner(
+            self.get_conn(),
+            user_id,
+            body,
+            group_type,
+            broadcasting_msg_id,
+            None,
+            None,
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl ScheduleMessageFn for SqliteRepo {
+    async fn schedule_message(
+        &self,
+        user_id: i64,
+        body: &str,
+        group_type: i32,
+        broadcasting_msg_id: Option<i64>,
+        publish_at: DateTime<Utc>,
+    ) -> Result<i64> {
+        insert_message_inner(
+            self.get_conn(),
+            user_id,
+            body,
+            group_type,
+            broadcasting_msg_id,
+            Some(publish_at),
+            None,
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl InsertPollMessageFn for SqliteRepo {
+    async fn insert_poll_message(
+        &self,
+        user_id: i64,
+        body: &str,
+        group_type: i32,
+        broadcasting_msg_id: Option<i64>,
+        poll: PollCreate,
+    ) -> Result<i64> {
+        insert_message_inner(
+            self.get_conn(),
+            user_id,
+            body,
+            group_type,
+            broadcasting_msg_id,
+            None,
+            Some(&poll),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl QueryScheduledMessagesFn for SqliteRepo {
+    async fn query_scheduled_messages(
+        &self,
+        user_id: i64,
+    ) -> Result<Vec<ScheduledMessageQueryResult>> {
+        query_scheduled_messages_inner(self.get_conn(), user_id).await
+    }
+}
+
+#[async_trait]
+impl RescheduleMessageFn for SqliteRepo {
+    async fn reschedule_message(&self, id: i64, publish_at: DateTime<Utc>) -> Result<()> {
+        reschedule_message_inner(self.get_conn(), id, publish_at).await
+    }
+}
+
+#[async_trait]
+impl CancelScheduledMessageFn for SqliteRepo {
+    async fn cancel_scheduled_message(&self, id: i64) -> Result<()> {
+        cancel_scheduled_message_inner(self.get_conn(), id).await
+    }
+}
+
+#[async_trait]
+impl InsertResponseMessageFn for SqliteRepo {
+    async fn insert_response_message(
+        &self,
+        user_id: i64,
+        body: &str,
+        group_type: i32,
+        original_msg_id: i64,
+    ) -> Result<i64> {
+        insert_response_message_inner(self.get_conn(), user_id, body, group_type, original_msg_id)
+            .await
+    }
+}
+
+#[async_trait]
+impl QueryMessageFn for SqliteRepo {
+    async fn query_message(
+        &self,
+        id: i64,
+    ) -> Result<Option<MessageWithFollowingAndBroadcastQueryResult>> {
+        query_message_inner(self.get_conn(), id).await
+    }
+}
+
+#[async_trait]
+impl QueryMessagesFn for SqliteRepo {
+    async fn query_messages(
+        &self,
+        user_id: i64,
+        last_updated_at: DateTime<Utc>,
+        page_size: i16,
+    ) -> Result<Vec<MessageWithFollowingAndBroadcastQueryResult>> {
+        query_messages_inner(self.get_conn(), user_id, last_updated_at, page_size).await
+    }
+}
+
+#[async_trait]
+impl QueryAuthorMessagesFn for SqliteRepo {
+    async fn query_author_messages(
+        &self,
+        user_id: i64,
+        before_id: Option<i64>,
+        page_size: i16,
+    ) -> Result<Vec<AuthorMessageQueryResult>> {
+        query_author_messages_inner(self.get_conn(), user_id, before_id, page_size).await
+    }
+}
+
+#[async_trait]
+impl QueryFeedMessagesFn for SqliteRepo {
+    async fn query_feed_messages(
+        &self,
+        user_id: i64,
+        page_size: i16,
+    ) -> Result<Vec<MessageWithFollowingAndBroadcastQueryResult>> {
+        query_feed_messages_inner(self.get_conn(), user_id, page_size).await
+    }
+}
+
+#[async_trait]
+impl QueryResponseTargetsFn for SqliteRepo {
+    async fn query_response_targets(
+        &self,
+        message_ids: &[i64],
+    ) -> Result<Vec<ResponseTargetQueryResult>> {
+        query_response_targets_inner(self.get_conn(), message_ids).await
+    }
+}
+
+#[async_trait]
+impl HideMessageFn for SqliteRepo {
+    async fn hide_message(&self, id: i64, hidden: bool) -> Result<()> {
+        hide_message_inner(self.get_conn(), id, hidden).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{SubsecRound, TimeDelta};
+
+    use super::*;
+    use crate::{
+        common::entities::{
+            profile::repo::{FollowUserFn, InsertProfileFn, QueryProfilesFn},
+            sqlite::test_repo,
+        },
+        common_tests::profile,
+    };
+
+    fn later() -> DateTime<Utc> {
+        Utc::now() + TimeDelta::seconds(1)
+    }
+
+    #[tokio::test]
+    async fn test_timeline_joins_follows_and_resolves_broadcasts() {
+        let repo = test_repo().await;
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let broadcaster = repo.insert_profile(profile("broadcaster")).await.unwrap();
+        let reader = repo.insert_profile(profile("reader")).await.unwrap();
+        repo.follow_user(reader, broadcaster).await.unwrap();
+
+        let original = repo
+            .insert_message(author, "original", 1, None)
+            .await
+            .unwrap();
+        let broadcast = repo
+            .insert_message(broadcaster, "look", 1, Some(original))
+            .await
+            .unwrap();
+        repo.insert_message(author, "not followed", 1, None)
+            .await
+            .unwrap();
+
+        let timeline = repo.query_messages(reader, later(), 10).await.unwrap();
+        assert_eq!(timeline.len(), 1);
+        assert_eq!(timeline[0].id, broadcast);
+        assert_eq!(timeline[0].user_name, "broadcaster");
+        assert_eq!(timeline[0].broadcast_msg_id, Some(original));
+        assert_eq!(timeline[0].broadcast_msg_user_id, Some(author));
+        assert_eq!(timeline[0].broadcast_msg_body.as_deref(), Some("original"));
+
+        repo.hide_message(original, true).await.unwrap();
+        let message = repo.query_message(broadcast).await.unwrap().unwrap();
+        assert_eq!(message.broadcast_msg_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_timeline_orders_and_pages() {
+        let repo = test_repo().await;
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let reader = repo.insert_profile(profile("reader")).await.unwrap();
+        repo.follow_user(reader, author).await.unwrap();
+
+        let mut ids = vec![];
+        for body in ["one", "two", "three"] {
+            ids.push(repo.insert_message(author, body, 1, None).await.unwrap());
+        }
+
+        let page = repo.query_messages(reader, later(), 2).await.unwrap();
+        assert_eq!(
+            page.iter().map(|m| m.id).collect::<Vec<i64>>(),
+            vec![ids[2], ids[1]]
+        );
+
+        let first = repo.query_message(ids[0]).await.unwrap().unwrap();
+        let older = repo
+            .query_messages(reader, first.updated_at, 10)
+            .await
+            .unwrap();
+        assert!(older.iter().all(|m| m.updated_at < first.updated_at));
+
+        assert!(repo.query_messages(reader, later(), -1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_author_messages_page_by_id() {
+        let repo = test_repo().await;
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let other = repo.insert_profile(profile("other")).await.unwrap();
+
+        let first = repo.insert_message(author, "one", 1, None).await.unwrap();
+        let reply = repo
+            .insert_response_message(author, "two", 1, first)
+            .await
+            .unwrap();
+        let broadcast = repo
+            .insert_message(author, "three", 1, Some(first))
+            .await
+            .unwrap();
+        repo.insert_message(other, "not mine", 1, None)
+            .await
+            .unwrap();
+        repo.schedule_message(author, "later", 1, None, later() + TimeDelta::days(1))
+            .await
+            .unwrap();
+
+        let page = repo.query_author_messages(author, None, 2).await.unwrap();
+        assert_eq!(
+            page.iter().map(|m| m.id).collect::<Vec<i64>>(),
+            vec![broadcast, reply]
+        );
+        assert_eq!(page[0].broadcast_msg_id, Some(first));
+        assert_eq!(page[1].original_msg_id, Some(first));
+        assert_eq!(page[1].user_name, "author");
+
+        let older = repo
+            .query_author_messages(author, Some(reply), 10)
+            .await
+            .unwrap();
+        assert_eq!(older.len(), 1);
+        assert_eq!(older[0].id, first);
+        assert!(older[0].published_at <= Utc::now());
+
+        let targets = repo
+            .query_response_targets(&[first, reply, broadcast])
+            .await
+            .unwrap();
+        assert_eq!(
+            targets,
+            [ResponseTargetQueryResult {
+                responding_msg_id: reply,
+                original_msg_id: first,
+                original_user_id: author,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hidden_messages_are_not_returned() {
+        let repo = test_repo().await;
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let id = repo.insert_message(author, "oops", 1, None).await.unwrap();
+
+        repo.hide_message(id, true).await.unwrap();
+        assert!(repo.query_message(id).await.unwrap().is_none());
+
+        repo.hide_message(id, false).await.unwrap();
+        assert!(repo.query_message(id).await.unwrap().is_some());
+
+        assert!(repo.hide_message(id + 1, true).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_hiding_a_message_touches_the_profiles_showing_it() {
+        let repo = test_repo().await;
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let broadcaster = repo.insert_profile(profile("broadcaster")).await.unwrap();
+        let bystander = repo.insert_profile(profile("bystander")).await.unwrap();
+        let source = repo
+            .insert_message(author, "source", 1, None)
+            .await
+            .unwrap();
+        repo.insert_message(broadcaster, "", 1, Some(source))
+            .await
+            .unwrap();
+        repo.insert_message(bystander, "elsewhere", 1, None)
+            .await
+            .unwrap();
+        let ids = [author, broadcaster, bystander];
+        let before = profiles_updated_at(&repo, &ids).await;
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+        repo.hide_message(source, true).await.unwrap();
+        let after = profiles_updated_at(&repo, &ids).await;
+        assert!(after[0] > before[0]);
+        assert!(after[1] > before[1]);
+        assert_eq!(after[2], before[2]);
+    }
+
+    async fn profiles_updated_at(repo: &SqliteRepo, ids: &[i64]) -> Vec<DateTime<Utc>> {
+        let profiles = repo.query_profiles(ids).await.unwrap();
+        ids.iter()
+            .map(|id| {
+                profiles
+                    .iter()
+                    .find(|profile| profile.id == *id)
+                    .unwrap()
+                    .updated_at
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_scheduled_messages_stay_hidden_until_published() {
+        let repo = test_repo().await;
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let reader = repo.insert_profile(profile("reader")).await.unwrap();
+        repo.follow_user(reader, author).await.unwrap();
+
+        let publish_at = Utc::now() + TimeDelta::hours(1);
+        let id = repo
+            .schedule_message(author, "soon", 1, None, publish_at)
+            .await
+            .unwrap();
+        assert!(repo.query_message(id).await.unwrap().is_none());
+        assert!(repo
+            .query_messages(reader, publish_at + TimeDelta::seconds(1), 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let later_at = publish_at + TimeDelta::hours(1);
+        repo.reschedule_message(id, later_at).await.unwrap();
+        let scheduled = repo.query_scheduled_messages(author).await.unwrap();
+        assert_eq!(scheduled.len(), 1);
+        assert_eq!(scheduled[0].publish_at, later_at.trunc_subsecs(3));
+
+        repo.cancel_scheduled_message(id).await.unwrap();
+        assert!(repo
+            .query_scheduled_messages(author)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(repo.cancel_scheduled_message(id).await.is_err());
+
+        // Published messages can't be rescheduled, and sort by their publish time.
+        let posted = repo.insert_message(author, "now", 1, None).await.unwrap();
+        assert!(repo.reschedule_message(posted, later_at).await.is_err());
+        let past = repo
+            .schedule_message(author, "due", 1, None, Utc::now() - TimeDelta::hours(1))
+            .await
+            .unwrap();
+        let timeline = repo.query_messages(reader, later(), 10).await.unwrap();
+        assert_eq!(
+            timeline.iter().map(|m| m.id).collect::<Vec<i64>>(),
+            vec![posted, past]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_foreign_keys_are_enforced() {
+        let repo = test_repo().await;
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+
+        assert!(repo
+            .insert_message(author + 1, "nobody", 1, None)
+            .await
+            .is_err());
+        assert!(repo
+            .insert_message(author, "dangling", 1, Some(99))
+            .await
+            .is_err());
+        assert!(repo
+            .insert_response_message(author, "reply", 1, 99)
+            .await
+            .is_err());
+
+        // The failed transactions left nothing behind.
+        assert!(repo.query_message(1).await.unwrap().is_none());
+    }
+}
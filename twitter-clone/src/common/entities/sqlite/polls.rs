@@ -0,0 +1,227 @@
+use async_trait::async_trait;
+use sqlx::{Pool, Sqlite, SqliteConnection};
+use tracing::{error, instrument};
+
+use super::{format_timestamp, SqliteRepo};
+use crate::{
+    common::entities::{
+        base::DbConnGetter,
+        polls::{
+            model::{PollCreate, PollOptionQueryResult},
+            repo::{already_voted, poll_closed, poll_option_not_found, QueryPollsFn, VotePollFn},
+        },
+    },
+    error::{IntoClientResult, Result, ServerSideError},
+};
+
+/// Same as the postgres `insert_poll_tx`, one insert per option instead of `unnest`.
+pub(super) async fn insert_poll_tx(
+    tx: &mut SqliteConnection,
+    message_id: i64,
+    poll: &PollCreate,
+) -> Result<i64> {
+    let poll_id = sqlx::query::<_>("insert into poll (message_id, closes_at) values (?1, ?2)")
+        .bind(message_id)
+        .bind(format_timestamp(poll.closes_at))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to insert poll: {:?}", e);
+            ServerSideError::from(e)
+        })?
+        .last_insert_rowid();
+
+    for (position, label) in (1_i32..).zip(&poll.options) {
+        sqlx::query::<_>("insert into poll_option (poll_id, position, label) values (?1, ?2, ?3)")
+            .bind(poll_id)
+            .bind(position)
+            .bind(label)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Failed to insert poll options: {:?}", e);
+                ServerSideError::from(e)
+            })?;
+    }
+
+    Ok(poll_id)
+}
+
+/// The message ids are passed as one JSON array, SQLite has no array parameters.
+#[instrument(skip())]
+async fn query_polls_inner(
+    conn: &Pool<Sqlite>,
+    message_ids: &[i64],
+    viewer_id: Option<i64>,
+) -> Result<Vec<PollOptionQueryResult>> {
+    let message_ids = serde_json::to_string(message_ids).map_err(ServerSideError::from)?;
+
+    sqlx::query_as::<_, PollOptionQueryResult>(
+        r"
+        select p.id as poll_id, p.message_id, p.closes_at, o.id as option_id, o.position, o.label,
+                (select count(*) from poll_vote v where v.option_id = o.id) as votes,
+                exists (
+                    select 1 from poll_vote v where v.option_id = o.id and v.user_id = ?2
+                ) as viewer_voted
+            from poll p
+                join poll_option o on o.poll_id = p.id
+            where p.message_id in (select value from json_each(?1))
+            order by p.id, o.position
+        ",
+    )
+    .bind(message_ids)
+    .bind(viewer_id)
+    .fetch_all(conn)
+    .await
+    .map_err(ServerSideError::from)
+    .into_client_result()
+}
+
+#[instrument(skip())]
+async fn vote_poll_inner(
+    conn: &Pool<Sqlite>,
+    message_id: i64,
+    user_id: i64,
+    option_id: i64,
+) -> Result<i64> {
+    let (poll_id, closed) = sqlx::query_as::<_, (i64, bool)>(
+        r"
+        select p.id, p.closes_at <= strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+            from poll p
+                join poll_option o on o.poll_id = p.id
+                join message m on m.id = p.message_id
+            where p.message_id = ?1 and o.id = ?2 and m.hidden_at is null
+        ",
+    )
+    .bind(message_id)
+    .bind(option_id)
+    .fetch_optional(conn)
+    .await
+    .map_err(ServerSideError::from)?
+    .ok_or_else(|| poll_option_not_found(message_id, option_id))?;
+    if closed {
+        return Err(poll_closed());
+    }
+
+    let result = sqlx::query::<_>(
+        r"
+        insert into poll_vote (poll_id, option_id, user_id)
+            values (?1, ?2, ?3)
+            on conflict (poll_id, user_id) do nothing
+        ",
+    )
+    .bind(poll_id)
+    .bind(option_id)
+    .bind(user_id)
+    .execute(conn)
+    .await
+    .map_err(|e| {
+        error!("Failed to record vote: {:?}", e);
+        ServerSideError::from(e)
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err(already_voted(user_id));
+    }
+    Ok(result.last_insert_rowid())
+}
+
+#[async_trait]
+impl QueryPollsFn for SqliteRepo {
+    async fn query_polls(
+        &self,
+        message_ids: &[i64],
+        viewer_id: Option<i64>,
+    ) -> Result<Vec<PollOptionQueryResult>> {
+        query_polls_inner(self.get_conn(), message_ids, viewer_id).await
+    }
+}
+
+#[async_trait]
+impl VotePollFn for SqliteRepo {
+    async fn vote_poll(&self, message_id: i64, user_id: i64, option_id: i64) -> Result<i64> {
+        vote_poll_inner(self.get_conn(), message_id, user_id, option_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, Utc};
+
+    use super::*;
+    use crate::{
+        common::entities::{
+            messages::repo::InsertPollMessageFn,
+            profile::repo::{DeleteProfileFn, InsertProfileFn},
+            sqlite::test_repo,
+        },
+        common_tests::profile,
+    };
+
+    fn poll(closes_in: TimeDelta) -> PollCreate {
+        PollCreate {
+            options: vec!["tea".to_string(), "coffee".to_string(), "water".to_string()],
+            closes_at: Utc::now() + closes_in,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_votes_are_counted_once_per_profile() {
+        let repo = test_repo().await;
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let voter = repo.insert_profile(profile("voter")).await.unwrap();
+        let id = repo
+            .insert_poll_message(author, "which?", 1, None, poll(TimeDelta::hours(1)))
+            .await
+            .unwrap();
+
+        let options = repo.query_polls(&[id], None).await.unwrap();
+        assert_eq!(
+            options
+                .iter()
+                .map(|o| o.label.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["tea", "coffee", "water"]
+        );
+
+        let coffee = options[1].option_id;
+        repo.vote_poll(id, voter, coffee).await.unwrap();
+        assert!(repo
+            .vote_poll(id, voter, options[0].option_id)
+            .await
+            .is_err());
+        repo.vote_poll(id, author, coffee).await.unwrap();
+        assert!(repo.vote_poll(id + 1, voter, coffee).await.is_err());
+
+        let options = repo.query_polls(&[id, id + 1], Some(voter)).await.unwrap();
+        assert_eq!(
+            options
+                .iter()
+                .map(|o| (o.votes, o.viewer_voted))
+                .collect::<Vec<(i64, bool)>>(),
+            vec![(0, false), (2, true), (0, false)]
+        );
+
+        // Votes go with their profile, polls with their message.
+        repo.delete_profile(voter).await.unwrap();
+        assert_eq!(repo.query_polls(&[id], None).await.unwrap()[1].votes, 1);
+        repo.delete_profile(author).await.unwrap();
+        assert!(repo.query_polls(&[id], None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_closed_poll_rejects_votes() {
+        let repo = test_repo().await;
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let id = repo
+            .insert_poll_message(author, "too late", 1, None, poll(-TimeDelta::seconds(1)))
+            .await
+            .unwrap();
+
+        let options = repo.query_polls(&[id], Some(author)).await.unwrap();
+        assert!(repo
+            .vote_poll(id, author, options[0].option_id)
+            .await
+            .is_err());
+    }
+}
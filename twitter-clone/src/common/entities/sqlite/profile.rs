@@ -0,0 +1,404 @@
+use async_trait::async_trait;
+use sqlx::{Pool, Sqlite};
+use tracing::{error, instrument};
+
+use super::SqliteRepo;
+use crate::{
+    common::entities::{
+        base::DbConnGetter,
+        profile::{
+            model::{ProfileCountsQueryResult, ProfileCreate, ProfileQueryResult},
+            repo::{
+                DeleteProfileFn, FollowUserFn, InsertProfileFn, QueryProfileByUserFn,
+                QueryProfileCountsFn, QueryProfileFn, QueryProfilesFn, UpdateProfileAvatarFn,
+            },
+        },
+    },
+    error::{IntoClientResult, Result, ServerSideError},
+};
+
+#[instrument(skip(params), fields(user_name = %params.user_name))]
+async fn insert_profile_inner(conn: &Pool<Sqlite>, params: ProfileCreate) -> Result<i64> {
+    sqlx::query::<_>(
+        r"
+        insert into profile
+            (user_name, full_name, description, region, main_url, avatar)
+            values
+            (?1, ?2, ?3, ?4, ?5, ?6)
+        ",
+    )
+    .bind(&params.user_name)
+    .bind(&params.full_name)
+    .bind(&params.description)
+    .bind(&params.region)
+    .bind(&params.main_url)
+    .bind(&params.avatar)
+    .execute(conn)
+    .await
+    .map(|result| result.last_insert_rowid())
+    .map_err(|e| {
+        error!("Failed to insert profile: {:?}", e);
+        ServerSideError::from(e)
+    })
+    .into_client_result()
+}
+
+#[instrument(skip(avatar), fields(avatar_bytes = avatar.as_ref().map(Vec::len)))]
+async fn update_profile_avatar_inner(
+    conn: &Pool<Sqlite>,
+    profile_id: i64,
+    avatar: Option<Vec<u8>>,
+) -> Result<()> {
+    let result = sqlx::query::<_>("update profile set avatar = ?1 where id = ?2")
+        .bind(avatar)
+        .bind(profile_id)
+        .execute(conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to update profile avatar: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+
+    if result.rows_affected() == 0 {
+        return Err(ServerSideError::ProfileNotFound(format!(
+            "No profile found with id: {profile_id}"
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Same cascade as the postgres version, with `?1` reused for every reference to the profile.
+#[instrument(skip())]
+async fn delete_profile_inner(conn: &Pool<Sqlite>, profile_id: i64) -> Result<()> {
+    let mut tx = conn.begin().await.map_err(ServerSideError::from)?;
+
+    let dependent_statements = [
+        r"
+        delete from circle_group_member
+            where member_id = ?1
+                or circle_group_id in (select id from circle_group where owner_id = ?1)
+        ",
+        "delete from circle_group where owner_id = ?1",
+        "delete from follow where follower_id = ?1 or following_id = ?1",
+        "delete from draft where user_id = ?1",
+        "delete from poll_vote where user_id = ?1",
+        r"
+        delete from message_response
+            where original_msg_id in (select id from message where user_id = ?1)
+                or responding_msg_id in (select id from message where user_id = ?1)
+        ",
+        r"
+        delete from message_broadcast
+            where main_msg_id in (select id from message where user_id = ?1)
+                or broadcasting_msg_id in (select id from message where user_id = ?1)
+        ",
+        "delete from message where user_id = ?1",
+    ];
+
+    for statement in dependent_statements {
+        sqlx::query::<_>(statement)
+            .bind(profile_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Failed to delete profile: {:?}", e);
+                ServerSideError::from(e)
+            })?;
+    }
+
+    let result = sqlx::query::<_>("delete from profile where id = ?1")
+        .bind(profile_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to delete profile: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+
+    if result.rows_affected() == 0 {
+        return Err(ServerSideError::ProfileNotFound(format!(
+            "No profile found with id: {profile_id}"
+        ))
+        .into());
+    }
+
+    tx.commit().await.map_err(ServerSideError::from)?;
+    Ok(())
+}
+
+#[instrument(skip())]
+async fn follow_user_inner(
+    conn: &Pool<Sqlite>,
+    follower_id: i64,
+    following_id: i64,
+) -> Result<i64> {
+    sqlx::query::<_>("insert into follow (follower_id, following_id) values (?1, ?2)")
+        .bind(follower_id)
+        .bind(following_id)
+        .execute(conn)
+        .await
+        .map(|result| result.last_insert_rowid())
+        .map_err(|e| {
+            error!("Failed to follow user: {:?}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
+}
+
+#[instrument(skip())]
+async fn query_profile_inner(conn: &Pool<Sqlite>, id: i64) -> Result<Option<ProfileQueryResult>> {
+    sqlx::query_as::<_, ProfileQueryResult>("select * from profile where id = ?1")
+        .bind(id)
+        .fetch_optional(conn)
+        .await
+        .map_err(ServerSideError::from)
+        .into_client_result()
+}
+
+/// The ids are passed as one JSON array, like the profile ids of the counts.
+#[instrument(skip())]
+async fn query_profiles_inner(conn: &Pool<Sqlite>, ids: &[i64]) -> Result<Vec<ProfileQueryResult>> {
+    let ids = serde_json::to_string(ids).map_err(ServerSideError::from)?;
+
+    sqlx::query_as::<_, ProfileQueryResult>(
+        "select * from profile where id in (select value from json_each(?1))",
+    )
+    .bind(ids)
+    .fetch_all(conn)
+    .await
+    .map_err(ServerSideError::from)
+    .into_client_result()
+}
+
+#[instrument(skip())]
+async fn query_profile_by_user_inner(
+    conn: &Pool<Sqlite>,
+    user_name: String,
+) -> Result<Option<ProfileQueryResult>> {
+    sqlx::query_as::<_, ProfileQueryResult>("select * from profile where user_name = ?1")
+        .bind(user_name)
+        .fetch_optional(conn)
+        .await
+        .map_err(ServerSideError::from)
+        .into_client_result()
+}
+
+/// The profile ids are passed as one JSON array, SQLite has no array parameters.
+#[instrument(skip())]
+async fn query_profile_counts_inner(
+    conn: &Pool<Sqlite>,
+    profile_ids: &[i64],
+) -> Result<Vec<ProfileCountsQueryResult>> {
+    let profile_ids = serde_json::to_string(profile_ids).map_err(ServerSideError::from)?;
+
+    sqlx::query_as::<_, ProfileCountsQueryResult>(
+        r"
+        select p.id as profile_id,
+                (select count(*) from follow f where f.following_id = p.id) as followers,
+                (select count(*) from follow f where f.follower_id = p.id) as following,
+                (
+                    select count(*) from message m
+                        where
+                            m.user_id = p.id
+                            and m.hidden_at is null
+                            and (
+                                m.publish_at is null
+                                or m.publish_at <= strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+                            )
+                ) as messages
+            from profile p
+            where p.id in (select value from json_each(?1))
+        ",
+    )
+    .bind(profile_ids)
+    .fetch_all(conn)
+    .await
+    .map_err(ServerSideError::from)
+    .into_client_result()
+}
+
+#[async_trait]
+impl InsertProfileFn for SqliteRepo {
+    async fn insert_profile(&self, params: ProfileCreate) -> Result<i64> {
+        insert_profile_inner(self.get_conn(), params).await
+    }
+}
+
+#[async_trait]
+impl UpdateProfileAvatarFn for SqliteRepo {
+    async fn update_profile_avatar(&self, user_id: i64, avatar: Option<Vec<u8>>) -> Result<()> {
+        update_profile_avatar_inner(self.get_conn(), user_id, avatar).await
+    }
+}
+
+#[async_trait]
+impl QueryProfileFn for SqliteRepo {
+    async fn query_profile(&self, id: i64) -> Result<Option<ProfileQueryResult>> {
+        query_profile_inner(self.get_conn(), id).await
+    }
+}
+
+#[async_trait]
+impl QueryProfilesFn for SqliteRepo {
+    async fn query_profiles(&self, ids: &[i64]) -> Result<Vec<ProfileQueryResult>> {
+        query_profiles_inner(self.get_conn(), ids).await
+    }
+}
+
+#[async_trait]
+impl QueryProfileByUserFn for SqliteRepo {
+    async fn query_profile_by_user(&self, user_name: String) -> Result<Option<ProfileQueryResult>> {
+        query_profile_by_user_inner(self.get_conn(), user_name).await
+    }
+}
+
+#[async_trait]
+impl QueryProfileCountsFn for SqliteRepo {
+    async fn query_profile_counts(
+        &self,
+        profile_ids: &[i64],
+    ) -> Result<Vec<ProfileCountsQueryResult>> {
+        query_profile_counts_inner(self.get_conn(), profile_ids).await
+    }
+}
+
+#[async_trait]
+impl FollowUserFn for SqliteRepo {
+    async fn follow_user(&self, follower_id: i64, following_id: i64) -> Result<i64> {
+        follow_user_inner(self.get_conn(), follower_id, following_id).await
+    }
+}
+
+#[async_trait]
+impl DeleteProfileFn for SqliteRepo {
+    async fn delete_profile(&self, id: i64) -> Result<()> {
+        delete_profile_inner(self.get_conn(), id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, Utc};
+
+    use super::*;
+    use crate::{
+        common::entities::{
+            messages::repo::{InsertMessageFn, ScheduleMessageFn},
+            sqlite::test_repo,
+        },
+        common_tests::profile,
+        seed::{self, SeedOptions, SeedPlan},
+    };
+
+    #[tokio::test]
+    async fn test_profile_round_trip() {
+        let repo = test_repo().await;
+        let params = ProfileCreate {
+            user_name: "dave".to_string(),
+            full_name: "Dave Choi".to_string(),
+            description: "hi".to_string(),
+            region: Some("Korea".to_string()),
+            main_url: None,
+            avatar: Some(vec![1, 2, 3]),
+        };
+        let id = repo.insert_profile(params).await.unwrap();
+
+        let profile = repo
+            .query_profile_by_user("dave".to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(profile.id, id);
+        assert_eq!(profile.region.as_deref(), Some("Korea"));
+        assert_eq!(profile.avatar, Some(vec![1, 2, 3]));
+
+        repo.update_profile_avatar(id, None).await.unwrap();
+        let profile = repo.query_profile(id).await.unwrap().unwrap();
+        assert_eq!(profile.avatar, None);
+        assert!(repo.update_profile_avatar(id + 1, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delete_profile_cascades() {
+        let repo = test_repo().await;
+        let options = SeedOptions { profiles: 20, ..SeedOptions::default() };
+        let summary = seed::apply(&repo, &SeedPlan::generate(&options))
+            .await
+            .unwrap();
+        assert_eq!(summary.profiles, 20);
+
+        for id in 1..=20 {
+            repo.delete_profile(id).await.unwrap();
+        }
+        for table in [
+            "message",
+            "follow",
+            "message_broadcast",
+            "circle_group_member",
+        ] {
+            let (count,): (i64,) = sqlx::query_as(&format!("select count(*) from {table}"))
+                .fetch_one(repo.get_conn())
+                .await
+                .unwrap();
+            assert_eq!(count, 0, "{table} is not empty");
+        }
+        assert!(repo.delete_profile(1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_profiles() {
+        let repo = test_repo().await;
+        let dave = repo.insert_profile(profile("dave")).await.unwrap();
+        let erin = repo.insert_profile(profile("erin")).await.unwrap();
+
+        let mut profiles = repo.query_profiles(&[erin, dave, 99]).await.unwrap();
+        profiles.sort_by_key(|profile| profile.id);
+        let names = profiles
+            .iter()
+            .map(|profile| profile.user_name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(names, ["dave", "erin"]);
+        assert!(repo.query_profiles(&[]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_profile_counts() {
+        let repo = test_repo().await;
+        let profile = |user_name: &str| ProfileCreate {
+            user_name: user_name.to_string(),
+            full_name: user_name.to_string(),
+            description: String::new(),
+            region: None,
+            main_url: None,
+            avatar: None,
+        };
+        let dave = repo.insert_profile(profile("dave")).await.unwrap();
+        let erin = repo.insert_profile(profile("erin")).await.unwrap();
+        repo.follow_user(erin, dave).await.unwrap();
+        repo.insert_message(dave, "hi", 1, None).await.unwrap();
+        repo.schedule_message(dave, "later", 1, None, Utc::now() + TimeDelta::hours(1))
+            .await
+            .unwrap();
+
+        let mut counts = repo.query_profile_counts(&[dave, erin, 99]).await.unwrap();
+        counts.sort_by_key(|counts| counts.profile_id);
+        assert_eq!(
+            counts,
+            [
+                ProfileCountsQueryResult {
+                    profile_id: dave,
+                    followers: 1,
+                    following: 0,
+                    messages: 1,
+                },
+                ProfileCountsQueryResult {
+                    profile_id: erin,
+                    followers: 0,
+                    following: 1,
+                    messages: 0,
+                },
+            ]
+        );
+    }
+}
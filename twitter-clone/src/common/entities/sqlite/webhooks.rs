@@ -0,0 +1,55 @@
+use async_trait::async_trait;
+
+use super::SqliteRepo;
+use crate::{
+    common::entities::webhooks::{
+        model::{WebhookCreate, WebhookDeliveryQueryResult, WebhookQueryResult},
+        repo::{
+            webhooks_unsupported, DeleteWebhookFn, InsertWebhookFn, QueryWebhookDeliveriesFn,
+            QueryWebhooksFn, RedeliverWebhookFn,
+        },
+    },
+    error::Result,
+};
+
+// Deliveries are sent by the postgres job queue, which SQLite has no counterpart of, so the
+// webhook routes only answer with an error here.
+
+#[async_trait]
+impl InsertWebhookFn for SqliteRepo {
+    async fn insert_webhook(&self, _webhook: WebhookCreate) -> Result<i64> {
+        Err(webhooks_unsupported())
+    }
+}
+
+#[async_trait]
+impl QueryWebhooksFn for SqliteRepo {
+    async fn query_webhooks(&self) -> Result<Vec<WebhookQueryResult>> {
+        Err(webhooks_unsupported())
+    }
+}
+
+#[async_trait]
+impl DeleteWebhookFn for SqliteRepo {
+    async fn delete_webhook(&self, _id: i64) -> Result<()> {
+        Err(webhooks_unsupported())
+    }
+}
+
+#[async_trait]
+impl QueryWebhookDeliveriesFn for SqliteRepo {
+    async fn query_webhook_deliveries(
+        &self,
+        _webhook_id: i64,
+        _limit: i64,
+    ) -> Result<Vec<WebhookDeliveryQueryResult>> {
+        Err(webhooks_unsupported())
+    }
+}
+
+#[async_trait]
+impl RedeliverWebhookFn for SqliteRepo {
+    async fn redeliver_webhook(&self, _webhook_id: i64, _delivery_id: i64) -> Result<i64> {
+        Err(webhooks_unsupported())
+    }
+}
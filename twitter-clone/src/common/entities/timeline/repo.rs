@@ -0,0 +1,396 @@
+use crate::common::entities::base::{DbConnGetter, DbRepo};
+use crate::common::entities::messages::model::MessageWithFollowingAndBroadcastQueryResult;
+use crate::error::{IntoClientResult, Result, ServerSideError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mockall::automock;
+use sqlx::{Pool, Postgres};
+use tracing::error;
+
+// Fan-out on write: every message of an account below `timeline.fanout_follower_limit` is copied
+// into a `home_timeline` row per follower and flagged `fanned_out`. Messages that are not flagged,
+// because their fan-out hasn't run yet or their author has too many followers, are merged in at
+// read time, so a timeline never misses a message whatever state the fan-out is in.
+mod private_members {
+
+    use tracing::instrument;
+
+    use super::*;
+
+    /// Copies a message into its followers' timelines, unless it already was or its author has
+    /// reached `follower_limit`. Returns the number of timeline rows written.
+    #[instrument(skip())]
+    pub(crate) async fn fan_out_message_inner(
+        conn: &Pool<Postgres>,
+        message_id: i64,
+        follower_limit: i64,
+    ) -> Result<i64> {
+        sqlx::query_as::<_, (i64,)>(
+            r"
+            with source as (
+                select m.id, m.user_id, m.updated_at
+                    from message m
+                    where
+                        m.id = $1
+                        and not m.fanned_out
+                        and (select count(*) from follow f where f.following_id = m.user_id) < $2
+            ), inserted as (
+                insert into home_timeline (follower_id, message_id, author_id, updated_at)
+                    select distinct f.follower_id, s.id, s.user_id, s.updated_at
+                        from source s join follow f on f.following_id = s.user_id
+                    on conflict do nothing
+                    returning 1
+            ), marked as (
+                update message set fanned_out = true where id in (select id from source)
+            )
+            select count(*) from inserted
+            ",
+        )
+        .bind(message_id)
+        .bind(follower_limit)
+        .fetch_one(conn)
+        .await
+        .map(|(count,)| count)
+        .map_err(|e| {
+            error!("Failed to fan out message: {:?}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
+    }
+
+    /// Gives a new follower the messages of the followed account that were already fanned out.
+    /// The others are merged at read time anyway.
+    #[instrument(skip())]
+    pub(crate) async fn backfill_follow_inner(
+        conn: &Pool<Postgres>,
+        follower_id: i64,
+        following_id: i64,
+    ) -> Result<()> {
+        sqlx::query::<_>(
+            r"
+            insert into home_timeline (follower_id, message_id, author_id, updated_at)
+                select $1, m.id, m.user_id, m.updated_at
+                    from message m
+                    where m.user_id = $2 and m.fanned_out
+                on conflict do nothing
+            ",
+        )
+        .bind(follower_id)
+        .bind(following_id)
+        .execute(conn)
+        .await
+        .map(|_| ())
+        .map_err(|e| {
+            error!("Failed to backfill home timeline: {:?}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
+    }
+
+    /// Reads a page of the follower's materialized timeline merged with the followed messages
+    /// that were not fanned out. Each side is limited to the page size before merging.
+    #[instrument(skip())]
+    pub(crate) async fn query_home_timeline_inner(
+        conn: &Pool<Postgres>,
+        user_id: i64,
+        last_updated_at: DateTime<Utc>,
+        page_size: i16,
+    ) -> Result<Vec<MessageWithFollowingAndBroadcastQueryResult>> {
+        sqlx::query_as!(
+            MessageWithFollowingAndBroadcastQueryResult,
+            r#"
+            with page as (
+                (select ht.message_id as id
+                    from home_timeline ht
+                        join message hm on hm.id = ht.message_id
+                    where
+                        ht.follower_id = $1
+                        and ht.updated_at < $2
+                        and hm.hidden_at is null
+                        and (hm.publish_at is null or hm.publish_at <= now())
+                    order by ht.updated_at desc, ht.message_id desc
+                    limit $3)
+                union
+                (select mm.id
+                    from follow f
+                        join message mm on mm.user_id = f.following_id
+                    where
+                        f.follower_id = $1
+                        and mm.updated_at < $2
+                        and mm.hidden_at is null
+                        and (mm.publish_at is null or mm.publish_at <= now())
+                        and not mm.fanned_out
+                    order by mm.updated_at desc, mm.id desc
+                    limit $3)
+            )
+            select m.id, m.updated_at,
+                    coalesce(m.publish_at, m.created_at) as "published_at!", m.body, m.likes,
+                    m.msg_group_type as "msg_group_type!", m.user_id, p.user_name, p.full_name,
+                    bm.id as "broadcast_msg_id?", bm.updated_at as "broadcast_msg_updated_at?",
+                    coalesce(bm.publish_at, bm.created_at) as "broadcast_msg_published_at?",
+                    bm.body as "broadcast_msg_body?", bm.likes as "broadcast_msg_likes?",
+                    bm.user_id as "broadcast_msg_user_id?", bp.user_name as "broadcast_msg_user_name?",
+                    bp.full_name as "broadcast_msg_full_name?"
+                from page
+                    join message m on m.id = page.id
+                    join profile p on p.id = m.user_id
+                    left join message_broadcast mb on mb.main_msg_id = m.id
+                    left join message bm on bm.id = mb.broadcasting_msg_id
+                        and bm.hidden_at is null
+                        and (bm.publish_at is null or bm.publish_at <= now())
+                    left join profile bp on bp.id = bm.user_id
+                order by m.updated_at desc, m.id desc
+                limit $3
+            "#,
+            user_id,
+            last_updated_at,
+            i64::from(page_size)
+        )
+        .fetch_all(conn)
+        .await
+        .map_err(|e| {
+            error!("query_home_timeline error: {}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
+    }
+
+    /// Recomputes every home timeline from the current follows, dropping rows left behind by
+    /// accounts that crossed `follower_limit` since. Returns the number of timeline rows.
+    #[instrument(skip())]
+    pub(crate) async fn rebuild_home_timelines_inner(
+        conn: &Pool<Postgres>,
+        follower_limit: i64,
+    ) -> Result<i64> {
+        let mut tx = conn.begin().await.map_err(ServerSideError::from)?;
+
+        for statement in [
+            "delete from home_timeline",
+            "update message set fanned_out = false where fanned_out",
+        ] {
+            sqlx::query::<_>(statement)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| {
+                    error!("Failed to reset home timelines: {:?}", e);
+                    ServerSideError::from(e)
+                })?;
+        }
+
+        let (count,) = sqlx::query_as::<_, (i64,)>(
+            r"
+            with source as (
+                select m.id, m.user_id, m.updated_at
+                    from message m
+                    where (select count(*) from follow f where f.following_id = m.user_id) < $1
+            ), inserted as (
+                insert into home_timeline (follower_id, message_id, author_id, updated_at)
+                    select distinct f.follower_id, s.id, s.user_id, s.updated_at
+                        from source s join follow f on f.following_id = s.user_id
+                    on conflict do nothing
+                    returning 1
+            ), marked as (
+                update message set fanned_out = true where id in (select id from source)
+            )
+            select count(*) from inserted
+            ",
+        )
+        .bind(follower_limit)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to rebuild home timelines: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+
+        tx.commit().await.map_err(ServerSideError::from)?;
+        Ok(count)
+    }
+}
+
+pub(crate) use private_members::{backfill_follow_inner, query_home_timeline_inner};
+
+#[automock]
+#[async_trait]
+pub trait FanOutMessageFn {
+    /// Runs the fan-out of one message, returns the number of timeline rows written. Enqueued as
+    /// a job after posting, see [`crate::worker::FAN_OUT_MESSAGE`].
+    async fn fan_out_message(&self, message_id: i64) -> Result<i64>;
+}
+
+#[async_trait]
+impl FanOutMessageFn for DbRepo {
+    async fn fan_out_message(&self, message_id: i64) -> Result<i64> {
+        private_members::fan_out_message_inner(
+            self.get_conn(),
+            message_id,
+            self.timeline().fanout_follower_limit,
+        )
+        .await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait RebuildHomeTimelinesFn {
+    /// Backfills every home timeline from the existing follows, returns the number of entries.
+    async fn rebuild_home_timelines(&self) -> Result<i64>;
+}
+
+#[async_trait]
+impl RebuildHomeTimelinesFn for DbRepo {
+    async fn rebuild_home_timelines(&self) -> Result<i64> {
+        private_members::rebuild_home_timelines_inner(
+            self.get_conn(),
+            self.timeline().fanout_follower_limit,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::{
+        common::entities::{
+            messages::repo::{InsertMessageFn, QueryMessagesFn, ScheduleMessageFn},
+            profile::repo::{FollowUserFn, InsertProfileFn},
+        },
+        common_tests::profile,
+        schemas::message::MessageGroupTypes,
+        settings::{TimelineMode, TimelineSettings},
+    };
+
+    const PUBLIC: i32 = MessageGroupTypes::Public as i32;
+
+    fn fanout_repo(pool: PgPool) -> DbRepo {
+        DbRepo::from_pool(pool).with_timeline(TimelineSettings {
+            mode: TimelineMode::Fanout,
+            fanout_follower_limit: 2,
+        })
+    }
+
+    async fn timeline_rows(repo: &DbRepo, message_id: i64) -> i64 {
+        sqlx::query_as::<_, (i64,)>("select count(*) from home_timeline where message_id = $1")
+            .bind(message_id)
+            .fetch_one(repo.get_conn())
+            .await
+            .unwrap()
+            .0
+    }
+
+    async fn timeline_ids(repo: &DbRepo, user_id: i64, before: DateTime<Utc>) -> Vec<i64> {
+        repo.query_messages(user_id, before, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|message| message.id)
+            .collect()
+    }
+
+    #[sqlx::test(migrator = "crate::common::entities::base::MIGRATOR")]
+    #[ignore = "needs postgres at DATABASE_URL"]
+    async fn test_fan_out_runs_once_and_stops_at_the_follower_limit(pool: PgPool) {
+        let repo = fanout_repo(pool);
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let famous = repo.insert_profile(profile("famous")).await.unwrap();
+        let reader = repo.insert_profile(profile("reader")).await.unwrap();
+        let other = repo.insert_profile(profile("other")).await.unwrap();
+        repo.follow_user(reader, author).await.unwrap();
+        repo.follow_user(reader, famous).await.unwrap();
+        repo.follow_user(other, famous).await.unwrap();
+
+        let message = repo
+            .insert_message(author, "hello", PUBLIC, None)
+            .await
+            .unwrap();
+        assert_eq!(repo.fan_out_message(message).await.unwrap(), 1);
+        // Flagged `fanned_out`, a retried job writes nothing.
+        assert_eq!(repo.fan_out_message(message).await.unwrap(), 0);
+        assert_eq!(timeline_rows(&repo, message).await, 1);
+
+        // Two followers reach the limit of 2, the message is only merged at read time.
+        let popular = repo
+            .insert_message(famous, "to everyone", PUBLIC, None)
+            .await
+            .unwrap();
+        assert_eq!(repo.fan_out_message(popular).await.unwrap(), 0);
+        assert_eq!(timeline_rows(&repo, popular).await, 0);
+
+        let later = Utc::now() + TimeDelta::seconds(1);
+        assert_eq!(timeline_ids(&repo, reader, later).await, [popular, message]);
+        assert_eq!(timeline_ids(&repo, other, later).await, [popular]);
+    }
+
+    #[sqlx::test(migrator = "crate::common::entities::base::MIGRATOR")]
+    #[ignore = "needs postgres at DATABASE_URL"]
+    async fn test_unfanned_messages_are_merged_and_scheduled_ones_hidden(pool: PgPool) {
+        let repo = fanout_repo(pool);
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let reader = repo.insert_profile(profile("reader")).await.unwrap();
+        repo.follow_user(reader, author).await.unwrap();
+
+        let fanned = repo
+            .insert_message(author, "fanned", PUBLIC, None)
+            .await
+            .unwrap();
+        repo.fan_out_message(fanned).await.unwrap();
+        // Posted, but its fan-out job hasn't run.
+        let pending = repo
+            .insert_message(author, "pending", PUBLIC, None)
+            .await
+            .unwrap();
+        // Fanned out ahead of its publish time, the row exists but must not show.
+        let scheduled = repo
+            .schedule_message(
+                author,
+                "scheduled",
+                PUBLIC,
+                None,
+                Utc::now() + TimeDelta::hours(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(repo.fan_out_message(scheduled).await.unwrap(), 1);
+        let unfanned_scheduled = repo
+            .schedule_message(
+                author,
+                "also scheduled",
+                PUBLIC,
+                None,
+                Utc::now() + TimeDelta::hours(1),
+            )
+            .await
+            .unwrap();
+
+        // Past the scheduled messages' `updated_at`, only `publish_at` keeps them out.
+        let ids = timeline_ids(&repo, reader, Utc::now() + TimeDelta::hours(2)).await;
+        assert_eq!(ids, [pending, fanned]);
+        assert!(!ids.contains(&scheduled) && !ids.contains(&unfanned_scheduled));
+    }
+
+    #[sqlx::test(migrator = "crate::common::entities::base::MIGRATOR")]
+    #[ignore = "needs postgres at DATABASE_URL"]
+    async fn test_following_backfills_fanned_out_messages(pool: PgPool) {
+        let repo = fanout_repo(pool);
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let reader = repo.insert_profile(profile("reader")).await.unwrap();
+        let message = repo
+            .insert_message(author, "before the follow", PUBLIC, None)
+            .await
+            .unwrap();
+        // No followers yet, nothing is written but the message counts as fanned out.
+        assert_eq!(repo.fan_out_message(message).await.unwrap(), 0);
+        assert_eq!(timeline_rows(&repo, message).await, 0);
+
+        repo.follow_user(reader, author).await.unwrap();
+        assert_eq!(timeline_rows(&repo, message).await, 1);
+        assert_eq!(
+            timeline_ids(&repo, reader, Utc::now() + TimeDelta::seconds(1)).await,
+            [message]
+        );
+    }
+}
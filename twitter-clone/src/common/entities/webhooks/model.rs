@@ -0,0 +1,102 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{types::Json, FromRow};
+
+/// What a webhook can subscribe to. Each new message raises exactly one of the `message.` events.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WebhookEvent {
+    #[serde(rename = "message.created")]
+    MessageCreated,
+    #[serde(rename = "message.replied")]
+    MessageReplied,
+    #[serde(rename = "message.broadcast")]
+    MessageBroadcast,
+    #[serde(rename = "profile.created")]
+    ProfileCreated,
+    #[serde(rename = "profile.followed")]
+    ProfileFollowed,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::MessageCreated => "message.created",
+            WebhookEvent::MessageReplied => "message.replied",
+            WebhookEvent::MessageBroadcast => "message.broadcast",
+            WebhookEvent::ProfileCreated => "profile.created",
+            WebhookEvent::ProfileFollowed => "profile.followed",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebhookCreate {
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<WebhookEvent>,
+}
+
+#[derive(Deserialize, Serialize, FromRow, Clone, Debug)]
+pub struct WebhookQueryResult {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, FromRow, Clone, Debug)]
+pub struct WebhookDeliveryQueryResult {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub webhook_id: i64,
+    pub event: String,
+    pub payload: Value,
+    /// `pending` until the first attempt, then `delivered` or `failed` by the last attempt.
+    pub status: String,
+    pub attempts: i32,
+    /// Every attempt, oldest first.
+    pub log: Json<Vec<WebhookAttemptQueryResult>>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct WebhookAttemptQueryResult {
+    pub created_at: DateTime<Utc>,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub response_body: Option<String>,
+    pub duration_ms: i64,
+}
+
+/// A delivery with what is needed to send it, see [`super::repo::QueryWebhookDeliveryFn`].
+#[derive(Deserialize, Serialize, FromRow, Clone, Debug)]
+pub struct WebhookDeliveryTarget {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub event: String,
+    pub payload: Value,
+    pub url: String,
+    pub secret: String,
+}
+
+/// Outcome of one attempt to send a delivery.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct WebhookAttemptCreate {
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub response_body: Option<String>,
+    pub duration_ms: i64,
+}
+
+impl WebhookAttemptCreate {
+    /// Only a 2xx response counts as delivered.
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+            && self
+                .status_code
+                .is_some_and(|code| (200..300).contains(&code))
+    }
+}
@@ -0,0 +1,417 @@
+use super::model::{
+    WebhookAttemptCreate, WebhookCreate, WebhookDeliveryQueryResult, WebhookDeliveryTarget,
+    WebhookEvent, WebhookQueryResult,
+};
+use crate::common::entities::base::{DbConnGetter, DbRepo, EntityId};
+use crate::common::entities::jobs::{model::NewJob, repo::EnqueueJobFn};
+use crate::common::entities::messages::model::NewMessage;
+use crate::error::{ClientSideError, IntoClientResult, Result, ServerSideError};
+use crate::worker::DELIVER_WEBHOOK;
+use async_trait::async_trait;
+use mockall::automock;
+use serde_json::{json, Value};
+use sqlx::{Pool, Postgres};
+use tracing::{error, instrument};
+
+// Deliveries are sent by `deliver_webhook` jobs, so webhooks share the job queue's retries,
+// backoff and dead letters, and need the postgres backend like every other job.
+mod private_members {
+
+    use super::*;
+
+    #[instrument(skip(webhook), fields(url = %webhook.url))]
+    pub(crate) async fn insert_webhook_inner(
+        conn: &Pool<Postgres>,
+        webhook: WebhookCreate,
+    ) -> Result<i64> {
+        let events = webhook
+            .events
+            .iter()
+            .map(WebhookEvent::as_str)
+            .collect::<Vec<&str>>();
+
+        sqlx::query_as::<_, EntityId>(
+            "insert into webhook (url, secret, events) values ($1, $2, $3) returning id",
+        )
+        .bind(&webhook.url)
+        .bind(&webhook.secret)
+        .bind(&events)
+        .fetch_one(conn)
+        .await
+        .map(|row: EntityId| row.id)
+        .map_err(|e| {
+            error!("Failed to insert webhook: {:?}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
+    }
+
+    #[instrument(skip())]
+    pub(crate) async fn query_webhooks_inner(
+        conn: &Pool<Postgres>,
+    ) -> Result<Vec<WebhookQueryResult>> {
+        sqlx::query_as::<_, WebhookQueryResult>("select * from webhook order by id")
+            .fetch_all(conn)
+            .await
+            .map_err(ServerSideError::from)
+            .into_client_result()
+    }
+
+    /// Deletes the webhook along with its delivery log. Its queued jobs find nothing to send.
+    #[instrument(skip())]
+    pub(crate) async fn delete_webhook_inner(conn: &Pool<Postgres>, id: i64) -> Result<()> {
+        let result = sqlx::query::<_>("delete from webhook where id = $1")
+            .bind(id)
+            .execute(conn)
+            .await
+            .map_err(|e| {
+                error!("Failed to delete webhook: {:?}", e);
+                ServerSideError::from(e)
+            })?;
+
+        if result.rows_affected() == 0 {
+            return Err(webhook_not_found(id));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip())]
+    pub(crate) async fn query_webhook_deliveries_inner(
+        conn: &Pool<Postgres>,
+        webhook_id: i64,
+        limit: i64,
+    ) -> Result<Vec<WebhookDeliveryQueryResult>> {
+        sqlx::query_as::<_, WebhookDeliveryQueryResult>(
+            r"
+            select d.*,
+                    coalesce(
+                        (
+                            select json_agg(
+                                json_build_object(
+                                    'created_at', a.created_at,
+                                    'status_code', a.status_code,
+                                    'error', a.error,
+                                    'response_body', a.response_body,
+                                    'duration_ms', a.duration_ms
+                                )
+                                order by a.id
+                            )
+                                from webhook_attempt a
+                                where a.delivery_id = d.id
+                        ),
+                        '[]'
+                    ) as log
+                from webhook_delivery d
+                where d.webhook_id = $1
+                order by d.id desc
+                limit $2
+            ",
+        )
+        .bind(webhook_id)
+        .bind(limit)
+        .fetch_all(conn)
+        .await
+        .map_err(ServerSideError::from)
+        .into_client_result()
+    }
+
+    /// Puts a delivery of `webhook_id` back to `pending`, the caller queues its job.
+    #[instrument(skip())]
+    pub(crate) async fn reset_webhook_delivery_inner(
+        conn: &Pool<Postgres>,
+        webhook_id: i64,
+        delivery_id: i64,
+    ) -> Result<()> {
+        let result = sqlx::query::<_>(
+            r"
+            update webhook_delivery
+                set status = 'pending', updated_at = now()
+                where id = $2 and webhook_id = $1
+            ",
+        )
+        .bind(webhook_id)
+        .bind(delivery_id)
+        .execute(conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to reset webhook delivery: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(ServerSideError::WebhookNotFound(format!(
+                "No delivery {delivery_id} found for webhook {webhook_id}"
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// One delivery per webhook subscribed to `event`, all sharing `data`.
+    #[instrument(skip(data), fields(event = event.as_str()))]
+    pub(crate) async fn insert_webhook_deliveries_inner(
+        conn: &Pool<Postgres>,
+        event: WebhookEvent,
+        data: Value,
+    ) -> Result<Vec<i64>> {
+        sqlx::query_as::<_, EntityId>(
+            r"
+            insert into webhook_delivery (webhook_id, event, payload)
+                select id, $1, $2 from webhook where $1 = any(events)
+                returning id
+            ",
+        )
+        .bind(event.as_str())
+        .bind(data)
+        .fetch_all(conn)
+        .await
+        .map(|rows| rows.into_iter().map(|row| row.id).collect())
+        .map_err(|e| {
+            error!("Failed to insert webhook deliveries: {:?}", e);
+            ServerSideError::from(e)
+        })
+        .into_client_result()
+    }
+
+    #[instrument(skip())]
+    pub(crate) async fn query_webhook_delivery_inner(
+        conn: &Pool<Postgres>,
+        id: i64,
+    ) -> Result<Option<WebhookDeliveryTarget>> {
+        sqlx::query_as::<_, WebhookDeliveryTarget>(
+            r"
+            select d.id, d.created_at, d.event, d.payload, w.url, w.secret
+                from webhook_delivery d
+                    join webhook w on w.id = d.webhook_id
+                where d.id = $1
+            ",
+        )
+        .bind(id)
+        .fetch_optional(conn)
+        .await
+        .map_err(ServerSideError::from)
+        .into_client_result()
+    }
+
+    #[instrument(skip(attempt), fields(status_code = attempt.status_code))]
+    pub(crate) async fn record_webhook_attempt_inner(
+        conn: &Pool<Postgres>,
+        delivery_id: i64,
+        attempt: &WebhookAttemptCreate,
+    ) -> Result<()> {
+        let mut tx = conn.begin().await.map_err(ServerSideError::from)?;
+
+        sqlx::query::<_>(
+            r"
+            insert into webhook_attempt (delivery_id, status_code, error, response_body, duration_ms)
+                values ($1, $2, $3, $4, $5)
+            ",
+        )
+        .bind(delivery_id)
+        .bind(attempt.status_code)
+        .bind(&attempt.error)
+        .bind(&attempt.response_body)
+        .bind(attempt.duration_ms)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to record webhook attempt: {:?}", e);
+            ServerSideError::from(e)
+        })?;
+
+        sqlx::query::<_>(
+            r"
+            update webhook_delivery
+                set status = $2, attempts = attempts + 1, updated_at = now()
+                where id = $1
+            ",
+        )
+        .bind(delivery_id)
+        .bind(if attempt.succeeded() {
+            "delivered"
+        } else {
+            "failed"
+        })
+        .execute(&mut *tx)
+        .await
+        .map_err(ServerSideError::from)?;
+
+        tx.commit().await.map_err(ServerSideError::from)?;
+        Ok(())
+    }
+}
+
+pub(crate) fn webhook_not_found(id: i64) -> ClientSideError {
+    ServerSideError::WebhookNotFound(format!("No webhook found with id: {id}")).into()
+}
+
+/// Returned by the backends without a job queue to send deliveries with.
+pub(crate) fn webhooks_unsupported() -> ClientSideError {
+    ServerSideError::InvalidInput("Webhooks need the postgres storage backend".to_string()).into()
+}
+
+impl DbRepo {
+    /// Queues a delivery of `event` to every webhook subscribed to it. Like the fan-out, a failure
+    /// is only logged: the change that raised the event is already committed.
+    pub(crate) async fn emit_webhook_event(&self, event: WebhookEvent, data: Value) {
+        let deliveries =
+            match private_members::insert_webhook_deliveries_inner(self.get_conn(), event, data)
+                .await
+            {
+                Ok(deliveries) => deliveries,
+                Err(e) => {
+                    error!("Webhook event {} not delivered: {}", event.as_str(), e);
+                    return;
+                },
+            };
+        for delivery_id in deliveries {
+            if let Err(e) = self.enqueue_delivery(delivery_id).await {
+                error!("Webhook delivery {} not queued: {}", delivery_id, e);
+            }
+        }
+    }
+
+    /// Queues the webhook event of a new message: a reply when it answers `original_msg_id`, a
+    /// broadcast when it broadcasts `broadcasting_msg_id`, a plain message otherwise.
+    pub(crate) async fn emit_message_event(&self, message: &NewMessage<'_>) {
+        let event = match (message.original_msg_id, message.broadcasting_msg_id) {
+            (Some(_), _) => WebhookEvent::MessageReplied,
+            (None, Some(_)) => WebhookEvent::MessageBroadcast,
+            (None, None) => WebhookEvent::MessageCreated,
+        };
+        self.emit_webhook_event(
+            event,
+            json!({
+                "id": message.id,
+                "userId": message.user_id,
+                "body": message.body,
+                "broadcastingMsgId": message.broadcasting_msg_id,
+                "originalMsgId": message.original_msg_id,
+                "publishAt": message.publish_at,
+            }),
+        )
+        .await;
+    }
+
+    async fn enqueue_delivery(&self, delivery_id: i64) -> Result<i64> {
+        self.enqueue_job(NewJob::new(
+            DELIVER_WEBHOOK,
+            json!({ "delivery_id": delivery_id }),
+        ))
+        .await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait InsertWebhookFn {
+    async fn insert_webhook(&self, webhook: WebhookCreate) -> Result<i64>;
+}
+
+#[async_trait]
+impl InsertWebhookFn for DbRepo {
+    async fn insert_webhook(&self, webhook: WebhookCreate) -> Result<i64> {
+        private_members::insert_webhook_inner(self.get_conn(), webhook).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait QueryWebhooksFn {
+    async fn query_webhooks(&self) -> Result<Vec<WebhookQueryResult>>;
+}
+
+#[async_trait]
+impl QueryWebhooksFn for DbRepo {
+    async fn query_webhooks(&self) -> Result<Vec<WebhookQueryResult>> {
+        private_members::query_webhooks_inner(self.get_conn()).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait DeleteWebhookFn {
+    async fn delete_webhook(&self, id: i64) -> Result<()>;
+}
+
+#[async_trait]
+impl DeleteWebhookFn for DbRepo {
+    async fn delete_webhook(&self, id: i64) -> Result<()> {
+        private_members::delete_webhook_inner(self.get_conn(), id).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait QueryWebhookDeliveriesFn {
+    /// The latest `limit` deliveries of the webhook with their attempts, newest first.
+    async fn query_webhook_deliveries(
+        &self,
+        webhook_id: i64,
+        limit: i64,
+    ) -> Result<Vec<WebhookDeliveryQueryResult>>;
+}
+
+#[async_trait]
+impl QueryWebhookDeliveriesFn for DbRepo {
+    async fn query_webhook_deliveries(
+        &self,
+        webhook_id: i64,
+        limit: i64,
+    ) -> Result<Vec<WebhookDeliveryQueryResult>> {
+        private_members::query_webhook_deliveries_inner(self.get_conn(), webhook_id, limit).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait RedeliverWebhookFn {
+    /// Sends a delivery of `webhook_id` again, with the same delivery id and payload. Returns the
+    /// id of the queued job.
+    async fn redeliver_webhook(&self, webhook_id: i64, delivery_id: i64) -> Result<i64>;
+}
+
+#[async_trait]
+impl RedeliverWebhookFn for DbRepo {
+    async fn redeliver_webhook(&self, webhook_id: i64, delivery_id: i64) -> Result<i64> {
+        private_members::reset_webhook_delivery_inner(self.get_conn(), webhook_id, delivery_id)
+            .await?;
+        self.enqueue_delivery(delivery_id).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait QueryWebhookDeliveryFn {
+    /// `None` once the delivery is gone with its webhook.
+    async fn query_webhook_delivery(&self, id: i64) -> Result<Option<WebhookDeliveryTarget>>;
+}
+
+#[async_trait]
+impl QueryWebhookDeliveryFn for DbRepo {
+    async fn query_webhook_delivery(&self, id: i64) -> Result<Option<WebhookDeliveryTarget>> {
+        private_members::query_webhook_delivery_inner(self.get_conn(), id).await
+    }
+}
+
+#[automock]
+#[async_trait]
+pub trait RecordWebhookAttemptFn {
+    /// Adds the attempt to the delivery log and sets the delivery status from it.
+    async fn record_webhook_attempt(
+        &self,
+        delivery_id: i64,
+        attempt: &WebhookAttemptCreate,
+    ) -> Result<()>;
+}
+
+#[async_trait]
+impl RecordWebhookAttemptFn for DbRepo {
+    async fn record_webhook_attempt(
+        &self,
+        delivery_id: i64,
+        attempt: &WebhookAttemptCreate,
+    ) -> Result<()> {
+        private_members::record_webhook_attempt_inner(self.get_conn(), delivery_id, attempt).await
+    }
+}
@@ -70,20 +70,135 @@
 //     is_valid
 // }
 
+#[cfg(test)]
+pub mod stub_server;
+
 use std::fmt::Debug;
 
-use crate::app_state::AppState;
+use crate::{
+    admin_api::AdminApi,
+    app_state::AppState,
+    federation::Federation,
+    link_preview::LinkPreviews,
+    settings::{AdminSettings, FederationSettings, LinkPreviewSettings, OutboundSettings},
+};
 use actix_web::web;
+use tracing_config::LogFilters;
+
+#[cfg(test)]
+use crate::{
+    common::entities::profile::model::ProfileCreate,
+    federation::{signatures, ACTIVITY_JSON},
+};
+#[cfg(test)]
+use chrono::{DateTime, Utc};
+#[cfg(test)]
+use stub_server::{StubResponse, StubServer};
 
+/// Link previews, federation and the admin api are off, tests don't reach out to the network.
 #[allow(unused)]
 pub async fn get_app_state<T: Debug>(db_repo: T) -> AppState<T> {
-    AppState { client: reqwest::Client::new(), db_repo }
+    let client = reqwest::Client::new();
+    let link_previews = LinkPreviews::new(
+        client.clone(),
+        &OutboundSettings::default(),
+        LinkPreviewSettings {
+            enabled: false,
+            ..LinkPreviewSettings::default()
+        },
+    );
+    let federation = Federation::new(
+        client.clone(),
+        &OutboundSettings::default(),
+        FederationSettings::default(),
+    );
+    AppState {
+        client,
+        link_previews,
+        federation,
+        admin: AdminApi::new(AdminSettings::default(), LogFilters::default()),
+        db_repo,
+    }
 }
 
 pub async fn get_app_data<T: Debug>(db_repo: T) -> web::Data<AppState<T>> {
     web::Data::new(get_app_state(db_repo).await)
 }
 
+/// A profile with only its names set, the full name derived from `user_name`.
+#[cfg(test)]
+pub fn profile(user_name: &str) -> ProfileCreate {
+    ProfileCreate {
+        user_name: user_name.to_string(),
+        full_name: format!("{user_name} full"),
+        description: String::new(),
+        region: None,
+        main_url: None,
+        avatar: None,
+    }
+}
+
+/// The `Host`, `Date`, `Digest` and `Signature` headers of a POST of `body` to `path` on the
+/// default `federation.base_url`, dated `date` and signed by `key_id`.
+#[cfg(test)]
+pub fn signed_inbox_headers(
+    private_key_pem: &str,
+    key_id: &str,
+    path: &str,
+    date: DateTime<Utc>,
+    body: &str,
+) -> Vec<(&'static str, String)> {
+    let host = "localhost:8080";
+    let target = format!("post {path}");
+    let date = signatures::http_date(date);
+    let digest = signatures::digest(body.as_bytes());
+    let headers = signatures::REQUIRED_HEADERS.map(str::to_string);
+    let signed = signatures::signing_string(&headers, |name| match name {
+        "(request-target)" => Some(target.as_str()),
+        "host" => Some(host),
+        "date" => Some(date.as_str()),
+        "digest" => Some(digest.as_str()),
+        _ => None,
+    })
+    .unwrap();
+    let signature = signatures::sign(private_key_pem, key_id, &signed).unwrap();
+    vec![
+        ("host", host.to_string()),
+        ("date", date),
+        ("digest", digest),
+        ("signature", signature),
+    ]
+}
+
+/// Serves the actor `/users/erin`, whose key is `public_key_pem`, as a remote instance would.
+#[cfg(test)]
+pub async fn stub_actor(public_key_pem: String) -> StubServer {
+    StubServer::start(move |request| {
+        let id = format!(
+            "http://{}/users/erin",
+            request.header("host").unwrap_or_default()
+        );
+        match request.path() {
+            "/users/erin" => StubResponse::json(
+                ACTIVITY_JSON,
+                &serde_json::json!({
+                    "id": id,
+                    "type": "Person",
+                    "preferredUsername": "erin",
+                    "inbox": format!("{id}/inbox"),
+                    "publicKey": {
+                        "id": format!("{id}#main-key"),
+                        "owner": id,
+                        "publicKeyPem": public_key_pem,
+                    },
+                }),
+            ),
+            _ => StubResponse::status(reqwest::StatusCode::NOT_FOUND),
+        }
+    })
+    .await
+}
+
 // #[allow(unused)]
 // pub async fn get_app() -> impl Service<Request, Response = ServiceResponse, Error = Error> {
 //     let app_data = get_app_data(DbRepo::init().await).await;
@@ -0,0 +1,154 @@
+//! A bare HTTP/1.1 server standing in for a remote host in tests of outbound requests. It answers
+//! each request from a closure and keeps every request it got.
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use reqwest::StatusCode;
+use serde_json::Value;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// A request as the stub server read it.
+#[derive(Debug, Clone)]
+pub struct StubRequest {
+    /// The request line and the headers.
+    pub head: String,
+    pub body: String,
+}
+
+impl StubRequest {
+    pub fn method(&self) -> &str {
+        self.request_line().next().unwrap_or_default()
+    }
+
+    /// The path with its query, as sent.
+    pub fn path(&self) -> &str {
+        self.request_line().nth(1).unwrap_or_default()
+    }
+
+    /// The value of the first header named `name`, whatever its case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.head.lines().skip(1).find_map(|line| {
+            let (key, value) = line.split_once(": ")?;
+            key.eq_ignore_ascii_case(name).then_some(value)
+        })
+    }
+
+    fn request_line(&self) -> std::str::SplitWhitespace<'_> {
+        self.head
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .split_whitespace()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StubResponse {
+    status: StatusCode,
+    content_type: Option<String>,
+    body: String,
+}
+
+impl StubResponse {
+    /// An empty response with `status`.
+    pub fn status(status: StatusCode) -> Self {
+        Self {
+            status,
+            content_type: None,
+            body: String::new(),
+        }
+    }
+
+    /// A 200 with `document` as its body.
+    pub fn json(content_type: &str, document: &Value) -> Self {
+        Self {
+            status: StatusCode::OK,
+            content_type: Some(content_type.to_string()),
+            body: document.to_string(),
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let content_type = self
+            .content_type
+            .as_ref()
+            .map(|content_type| format!("content-type: {content_type}\r\n"))
+            .unwrap_or_default();
+        format!(
+            "HTTP/1.1 {}\r\n{content_type}content-length: {}\r\nconnection: close\r\n\r\n{}",
+            self.status,
+            self.body.len(),
+            self.body
+        )
+        .into_bytes()
+    }
+}
+
+/// Listens on a free port of 127.0.0.1 until the test's runtime shuts down.
+#[derive(Debug)]
+pub struct StubServer {
+    pub addr: SocketAddr,
+    requests: Arc<Mutex<Vec<StubRequest>>>,
+}
+
+impl StubServer {
+    pub async fn start(
+        respond: impl Fn(&StubRequest) -> StubResponse + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&requests);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let request = read_request(&mut socket).await;
+                let response = respond(&request);
+                received.lock().unwrap().push(request);
+                let _ = socket.write_all(&response.to_bytes()).await;
+            }
+        });
+        Self { addr, requests }
+    }
+
+    /// The url of `path` on this server.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{path}", self.addr)
+    }
+
+    /// The requests received so far, oldest first.
+    pub fn requests(&self) -> Vec<StubRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Reads until the whole body announced by `content-length` is in.
+async fn read_request(socket: &mut TcpStream) -> StubRequest {
+    let mut request = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        let read = socket.read(&mut buf).await.unwrap_or(0);
+        request.extend_from_slice(&buf[..read]);
+        let text = String::from_utf8_lossy(&request).to_string();
+        if let Some((head, body)) = text.split_once("\r\n\r\n") {
+            let request = StubRequest {
+                head: head.to_string(),
+                body: body.to_string(),
+            };
+            let length = request
+                .header("content-length")
+                .and_then(|length| length.parse().ok())
+                .unwrap_or(0);
+            if read == 0 || request.body.len() >= length {
+                return request;
+            }
+        } else if read == 0 {
+            return StubRequest { head: text, body: String::new() };
+        }
+    }
+}
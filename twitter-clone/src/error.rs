@@ -7,6 +7,8 @@ use serde::Serialize;
 use serde_json::json;
 use tracing::error;
 
+use crate::metrics::metrics;
+
 #[derive(Debug, thiserror::Error)]
 pub enum ServerSideError {
     #[error("Unknown Internal Server Error: {0}")]
@@ -19,12 +21,28 @@ pub enum ServerSideError {
     ServerRunError(String),
     #[error("Database Error: {0}")]
     DatabaseError(#[from] sqlx::Error),
+    #[error("Migration Error: {0}")]
+    MigrationError(#[from] sqlx::migrate::MigrateError),
     #[error("Message Not Found: {0}")]
     MessageNotFound(String),
     #[error("Profile Not Found: {0}")]
     ProfileNotFound(String),
+    #[error("Job Not Found: {0}")]
+    JobNotFound(String),
+    #[error("Draft Not Found: {0}")]
+    DraftNotFound(String),
+    #[error("Poll Not Found: {0}")]
+    PollNotFound(String),
+    #[error("Webhook Not Found: {0}")]
+    WebhookNotFound(String),
     #[error("File Read Error: {0}")]
     FileReadError(String),
+    #[error("Configuration Error: {0}")]
+    ConfigError(String),
+    #[error("Invalid Input: {0}")]
+    InvalidInput(String),
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
 }
 
 #[derive(Debug, Serialize, thiserror::Error)]
@@ -35,6 +53,8 @@ pub enum ClientSideError {
     NotFound(String),
     #[error("Bad Request: {0}")]
     BadRequest(String),
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
 }
 
 impl From<ServerSideError> for ClientSideError {
@@ -47,11 +67,30 @@ impl From<ServerSideError> for ClientSideError {
             | ServerSideError::SerializationError(_)
             | ServerSideError::HostBindingError(_)
             | ServerSideError::ServerRunError(_)
-            | ServerSideError::DatabaseError(_) => ClientSideError::InternalServerError,
-            ServerSideError::MessageNotFound(msg) | ServerSideError::ProfileNotFound(msg) => {
-                ClientSideError::NotFound(msg)
+            | ServerSideError::DatabaseError(_)
+            | ServerSideError::MigrationError(_)
+            | ServerSideError::ConfigError(_) => ClientSideError::InternalServerError,
+            ServerSideError::MessageNotFound(msg)
+            | ServerSideError::ProfileNotFound(msg)
+            | ServerSideError::JobNotFound(msg)
+            | ServerSideError::DraftNotFound(msg)
+            | ServerSideError::PollNotFound(msg)
+            | ServerSideError::WebhookNotFound(msg) => ClientSideError::NotFound(msg),
+            ServerSideError::FileReadError(msg) | ServerSideError::InvalidInput(msg) => {
+                ClientSideError::BadRequest(msg)
             },
-            ServerSideError::FileReadError(msg) => ClientSideError::BadRequest(msg),
+            ServerSideError::Unauthorized(msg) => ClientSideError::Unauthorized(msg),
+        }
+    }
+}
+
+impl ClientSideError {
+    fn variant(&self) -> &'static str {
+        match self {
+            ClientSideError::InternalServerError => "InternalServerError",
+            ClientSideError::NotFound(_) => "NotFound",
+            ClientSideError::BadRequest(_) => "BadRequest",
+            ClientSideError::Unauthorized(_) => "Unauthorized",
         }
     }
 }
@@ -62,10 +101,12 @@ impl ResponseError for ClientSideError {
             ClientSideError::InternalServerError => http::StatusCode::INTERNAL_SERVER_ERROR,
             ClientSideError::NotFound(_) => http::StatusCode::NOT_FOUND,
             ClientSideError::BadRequest(_) => http::StatusCode::BAD_REQUEST,
+            ClientSideError::Unauthorized(_) => http::StatusCode::UNAUTHORIZED,
         }
     }
 
     fn error_response(&self) -> HttpResponse<body::BoxBody> {
+        metrics().client_error(self.variant());
         let json_body = json!({"error": self.to_string()});
         HttpResponse::build(self.status_code())
             .content_type(ContentType::json())
@@ -84,3 +125,44 @@ impl<T> IntoClientResult<T> for std::result::Result<T, ServerSideError> {
         self.map_err(ClientSideError::from)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tracing::Level;
+    use tracing_config::capture::{ExpectedEvent, LogCapture};
+
+    use super::*;
+
+    #[test]
+    fn test_server_side_errors_are_logged_with_an_id() {
+        let capture = LogCapture::new();
+        let _guard = capture.set_default();
+
+        let err = ClientSideError::from(ServerSideError::DatabaseError(sqlx::Error::RowNotFound));
+        assert!(matches!(err, ClientSideError::InternalServerError));
+        let result: std::result::Result<(), _> = Err(ServerSideError::ProfileNotFound(
+            "No profile found with id: 7".to_string(),
+        ));
+        assert!(matches!(
+            result.into_client_result(),
+            Err(ClientSideError::NotFound(msg)) if msg == "No profile found with id: 7"
+        ));
+
+        // The client only sees `Internal Server Error`, the cause is in the log under an id.
+        let database = capture.assert_event(
+            &ExpectedEvent::new(Level::ERROR).field("error_id").field_eq(
+                "error",
+                format!("Database Error: {}", sqlx::Error::RowNotFound),
+            ),
+        );
+        let not_found = capture.assert_event(
+            &ExpectedEvent::new(Level::ERROR)
+                .field_eq("error", "Profile Not Found: No profile found with id: 7"),
+        );
+        let error_id = |event: &tracing_config::capture::CapturedEvent| {
+            uuid::Uuid::parse_str(event.field("error_id").unwrap()).unwrap()
+        };
+        assert_ne!(error_id(&database), error_id(&not_found));
+        assert_eq!(database.target, "twitter_clone::error");
+    }
+}
@@ -0,0 +1,323 @@
+//! ActivityPub federation with other instances.
+//!
+//! Local profiles are `Person` actors found through WebFinger as `acct:user_name@domain`, where
+//! the domain is the host of `federation.base_url`. Their public messages are published in an
+//! outbox and delivered to the inboxes of remote followers by `deliver_activity` jobs, see
+//! [`delivery`]. Inboxes take `Follow`, `Undo`, `Accept`, `Create`, `Announce` and `Like`
+//! activities, and only with a valid HTTP signature from the activity's actor.
+
+pub mod activities;
+pub mod delivery;
+pub mod signatures;
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use actix_web::HttpRequest;
+use chrono::Utc;
+use dashmap::DashMap;
+use reqwest::{header::ACCEPT, Client, Url};
+use serde_json::Value;
+
+use crate::{
+    common::entities::federation::model::RemoteActor,
+    error::ClientSideError,
+    outbound::{self, WithTraceContext},
+    settings::{FederationSettings, OutboundSettings},
+};
+use activities::Urls;
+use signatures::{SignatureHeader, REQUIRED_HEADERS};
+
+pub const ACTIVITY_JSON: &str = "application/activity+json";
+pub const JRD_JSON: &str = "application/jrd+json";
+const ACCEPT_ACTIVITY: &str = r#"application/activity+json, application/ld+json; profile="https://www.w3.org/ns/activitystreams""#;
+/// Actor and WebFinger documents are small, anything bigger is not one.
+const MAX_DOCUMENT_BYTES: usize = 256 * 1024;
+
+/// Federation state shared by the handlers, kept in [`crate::app_state::AppState`].
+#[derive(Debug, Clone)]
+pub struct Federation {
+    settings: FederationSettings,
+    urls: Urls,
+    client: Client,
+    allow_private_networks: bool,
+    /// Remote actors by id, so a busy sender's key isn't fetched for every activity.
+    actors: Arc<DashMap<String, CachedActor>>,
+}
+
+#[derive(Debug, Clone)]
+struct CachedActor {
+    actor: RemoteActor,
+    fetched_at: Instant,
+}
+
+impl Federation {
+    /// `client` is the outbound client of [`crate::outbound::http_client`], remote hosts are
+    /// picked by whoever sends an activity.
+    pub fn new(client: Client, outbound: &OutboundSettings, settings: FederationSettings) -> Self {
+        Self {
+            urls: Urls::new(&settings.base_url),
+            settings,
+            client,
+            allow_private_networks: outbound.allow_private_networks,
+            actors: Arc::new(DashMap::new()),
+        }
+    }
+
+    pub fn urls(&self) -> &Urls {
+        &self.urls
+    }
+
+    pub fn domain(&self) -> String {
+        self.settings.domain()
+    }
+
+    /// The federation endpoints answer not found while federation is disabled.
+    pub fn check_enabled(&self) -> Result<(), ClientSideError> {
+        match self.settings.enabled {
+            true => Ok(()),
+            false => Err(ClientSideError::NotFound(
+                "Federation is disabled".to_string(),
+            )),
+        }
+    }
+
+    /// The actor that signed `req`. The signature must cover the request target, `Host`, `Date`
+    /// and a `Digest` of `body`, and the date must be within `federation.max_clock_skew_secs`.
+    pub async fn verify_request(
+        &self,
+        req: &HttpRequest,
+        body: &[u8],
+    ) -> Result<RemoteActor, String> {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+
+        let signature =
+            SignatureHeader::parse(header("signature").ok_or("request is not signed")?)?;
+        for required in REQUIRED_HEADERS {
+            if !signature.headers.iter().any(|name| name == required) {
+                return Err(format!("signature must cover `{required}`"));
+            }
+        }
+        if header("digest") != Some(signatures::digest(body).as_str()) {
+            return Err("digest does not match the body".to_string());
+        }
+        let date = header("date")
+            .and_then(signatures::parse_http_date)
+            .ok_or("request has no valid date")?;
+        if (Utc::now() - date).num_seconds().abs() > self.settings.max_clock_skew_secs {
+            return Err("request date is too far from the server time".to_string());
+        }
+
+        let request_target = format!(
+            "{} {}",
+            req.method().as_str().to_lowercase(),
+            req.uri()
+                .path_and_query()
+                .map_or(req.path(), |path| path.as_str())
+        );
+        let signed = signatures::signing_string(&signature.headers, |name| match name {
+            "(request-target)" => Some(request_target.as_str()),
+            _ => header(name),
+        })?;
+
+        let owner = signature
+            .key_id
+            .split_once('#')
+            .map_or(signature.key_id.as_str(), |(owner, _)| owner);
+        let actor = self.fetch_actor(owner, false).await?;
+        if actor.public_key_id != signature.key_id {
+            return Err("signing key does not belong to its actor".to_string());
+        }
+        if signatures::verify(&actor.public_key_pem, &signed, &signature.signature).is_ok() {
+            return Ok(actor);
+        }
+        // The key may have changed since the actor was cached.
+        let actor = self.fetch_actor(owner, true).await?;
+        signatures::verify(&actor.public_key_pem, &signed, &signature.signature)?;
+        Ok(actor)
+    }
+
+    /// The actor document at `id`, from the cache unless `refresh` is set or it is older than
+    /// `federation.actor_cache_ttl_secs`.
+    pub async fn fetch_actor(&self, id: &str, refresh: bool) -> Result<RemoteActor, String> {
+        let ttl = Duration::from_secs(self.settings.actor_cache_ttl_secs);
+        if !refresh {
+            if let Some(cached) = self.actors.get(id) {
+                if cached.fetched_at.elapsed() < ttl {
+                    return Ok(cached.actor.clone());
+                }
+            }
+        }
+
+        let url = Url::parse(id).map_err(|err| format!("actor id is invalid: {err}"))?;
+        let document = self.get_json(url, ACCEPT_ACTIVITY).await?;
+        let actor = activities::parse_actor(&document)?;
+        if actor.id != id {
+            return Err("actor document has another id than its url".to_string());
+        }
+        self.actors.insert(
+            id.to_string(),
+            CachedActor {
+                actor: actor.clone(),
+                fetched_at: Instant::now(),
+            },
+        );
+        Ok(actor)
+    }
+
+    /// Looks up `user@host`, with or without a leading `@` or `acct:`, through WebFinger, or
+    /// fetches an actor given by its url. WebFinger uses the scheme of `federation.base_url`, so
+    /// instances served over plain http in development find each other.
+    pub async fn resolve_account(&self, account: &str) -> Result<RemoteActor, String> {
+        let account = account.trim();
+        if account.starts_with("https://") || account.starts_with("http://") {
+            return self.fetch_actor(account, false).await;
+        }
+
+        let account = account.trim_start_matches("acct:").trim_start_matches('@');
+        let (user, host) = account
+            .split_once('@')
+            .filter(|(user, host)| !user.is_empty() && !host.is_empty())
+            .ok_or("account must look like user@host")?;
+        let scheme = Url::parse(&self.settings.base_url)
+            .map(|url| url.scheme().to_string())
+            .unwrap_or_else(|_| "https".to_string());
+        let url = Url::parse_with_params(
+            &format!("{scheme}://{host}/.well-known/webfinger"),
+            [("resource", format!("acct:{user}@{host}"))],
+        )
+        .map_err(|err| format!("account host is invalid: {err}"))?;
+
+        let document = self.get_json(url, JRD_JSON).await?;
+        let actor_id = document
+            .get("links")
+            .and_then(Value::as_array)
+            .and_then(|links| {
+                links.iter().find(|link| {
+                    link.get("rel").and_then(Value::as_str) == Some("self")
+                        && link
+                            .get("type")
+                            .and_then(Value::as_str)
+                            .is_some_and(|kind| {
+                                kind.contains("activity+json") || kind.contains("ld+json")
+                            })
+                })
+            })
+            .and_then(|link| link.get("href").and_then(Value::as_str))
+            .ok_or("account has no ActivityPub actor")?;
+        self.fetch_actor(actor_id, false).await
+    }
+
+    async fn get_json(&self, url: Url, accept: &str) -> Result<Value, String> {
+        outbound::check_url(&url, self.allow_private_networks)?;
+        let mut response = self
+            .client
+            .get(url)
+            .header(ACCEPT, accept)
+            .with_trace_context()
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("fetch answered with status {}", response.status()));
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|err| err.to_string())? {
+            if body.len() + chunk.len() > MAX_DOCUMENT_BYTES {
+                return Err("document is too large".to_string());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        serde_json::from_slice(&body).map_err(|err| format!("document is not json: {err}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::common_tests::{signed_inbox_headers, stub_actor};
+
+    const INBOX: &str = "/ap/users/dave/inbox";
+    const BODY: &str = r#"{"type":"Like"}"#;
+
+    fn federation() -> Federation {
+        let outbound = OutboundSettings {
+            allow_private_networks: true,
+            ..OutboundSettings::default()
+        };
+        Federation::new(
+            outbound::http_client(&outbound).unwrap(),
+            &outbound,
+            FederationSettings {
+                enabled: true,
+                ..FederationSettings::default()
+            },
+        )
+    }
+
+    fn request(headers: Vec<(&'static str, String)>) -> HttpRequest {
+        headers
+            .into_iter()
+            .fold(TestRequest::post().uri(INBOX), |req, header| {
+                req.insert_header(header)
+            })
+            .to_http_request()
+    }
+
+    #[actix_web::test]
+    async fn test_verify_request() {
+        let (public_key_pem, private_key_pem) = signatures::generate_key_pair().unwrap();
+        let actor = stub_actor(public_key_pem).await;
+        let actor_id = actor.url("/users/erin");
+        let key_id = format!("{actor_id}#main-key");
+        let federation = federation();
+        let sign =
+            |key_id: &str, date| signed_inbox_headers(&private_key_pem, key_id, INBOX, date, BODY);
+
+        let req = request(sign(&key_id, Utc::now()));
+        let verified = federation
+            .verify_request(&req, BODY.as_bytes())
+            .await
+            .unwrap();
+        assert_eq!(verified.id, actor_id);
+
+        // The signature covers a digest of another body.
+        let err = federation
+            .verify_request(&req, br#"{"type":"Undo"}"#)
+            .await
+            .unwrap_err();
+        assert_eq!(err, "digest does not match the body");
+
+        let stale = request(sign(&key_id, Utc::now() - TimeDelta::minutes(10)));
+        let err = federation
+            .verify_request(&stale, BODY.as_bytes())
+            .await
+            .unwrap_err();
+        assert_eq!(err, "request date is too far from the server time");
+
+        // Signed with a key the actor document doesn't list as its own.
+        let other_key = request(sign(&format!("{actor_id}#other-key"), Utc::now()));
+        let err = federation
+            .verify_request(&other_key, BODY.as_bytes())
+            .await
+            .unwrap_err();
+        assert_eq!(err, "signing key does not belong to its actor");
+
+        let unsigned = request(vec![("date", signatures::http_date(Utc::now()))]);
+        let err = federation
+            .verify_request(&unsigned, BODY.as_bytes())
+            .await
+            .unwrap_err();
+        assert_eq!(err, "request is not signed");
+    }
+}
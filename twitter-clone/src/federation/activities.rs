@@ -0,0 +1,449 @@
+//! ActivityStreams documents: the ones this server serves and sends, and the parsing of those it
+//! receives on an inbox.
+//!
+//! Messages are `Note`s, answers carry the message they respond to as `inReplyTo`, and a
+//! broadcast is sent as an `Announce` of the broadcast message instead of a `Create`. Only public
+//! messages federate, circle messages stay on this server.
+
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use serde_json::{json, Value};
+
+use crate::common::entities::{
+    federation::model::{InboundActivity, RemoteActor, RemoteNoteCreate},
+    messages::model::AuthorMessageQueryResult,
+    profile::model::ProfileQueryResult,
+};
+
+pub const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+const ACTIVITY_STREAMS: &str = "https://www.w3.org/ns/activitystreams";
+const SECURITY: &str = "https://w3id.org/security/v1";
+
+/// Ids of the local actors and objects, all under `federation.base_url`.
+#[derive(Debug, Clone)]
+pub struct Urls {
+    base: String,
+}
+
+impl Urls {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn actor(&self, user_name: &str) -> String {
+        format!("{}/ap/users/{user_name}", self.base)
+    }
+
+    pub fn key_id(&self, user_name: &str) -> String {
+        format!("{}#main-key", self.actor(user_name))
+    }
+
+    pub fn inbox(&self, user_name: &str) -> String {
+        format!("{}/inbox", self.actor(user_name))
+    }
+
+    pub fn outbox(&self, user_name: &str) -> String {
+        format!("{}/outbox", self.actor(user_name))
+    }
+
+    /// The native avatar of a profile, linked from the accounts of the Mastodon api.
+    pub fn avatar(&self, profile_id: i64) -> String {
+        format!("{}/api/v1/profile/{profile_id}/avatar", self.base)
+    }
+
+    pub fn message(&self, id: i64) -> String {
+        format!("{}/ap/messages/{id}", self.base)
+    }
+
+    /// Id of the `Create` or `Announce` of a message, the same on every delivery attempt.
+    pub fn message_activity(&self, id: i64) -> String {
+        format!("{}/activity", self.message(id))
+    }
+
+    /// Id of a one-off activity like a `Follow` or an `Accept`.
+    pub fn activity(&self) -> String {
+        format!("{}/ap/activities/{}", self.base, uuid::Uuid::new_v4())
+    }
+
+    /// The local message id of a [`Urls::message`] url.
+    pub fn message_id(&self, url: &str) -> Option<i64> {
+        url.strip_prefix(&self.base)?
+            .strip_prefix("/ap/messages/")?
+            .parse()
+            .ok()
+    }
+}
+
+/// The WebFinger answer for `acct:{user_name}@{domain}`.
+pub fn webfinger(urls: &Urls, user_name: &str, domain: &str) -> Value {
+    json!({
+        "subject": format!("acct:{user_name}@{domain}"),
+        "aliases": [urls.actor(user_name)],
+        "links": [{
+            "rel": "self",
+            "type": "application/activity+json",
+            "href": urls.actor(user_name),
+        }],
+    })
+}
+
+pub fn actor(urls: &Urls, profile: &ProfileQueryResult, public_key_pem: &str) -> Value {
+    let id = urls.actor(&profile.user_name);
+    json!({
+        "@context": [ACTIVITY_STREAMS, SECURITY],
+        "id": id,
+        "type": "Person",
+        "preferredUsername": profile.user_name,
+        "name": profile.full_name,
+        "summary": html(&profile.description),
+        "url": profile.main_url,
+        "published": profile.created_at,
+        "inbox": urls.inbox(&profile.user_name),
+        "outbox": urls.outbox(&profile.user_name),
+        "publicKey": {
+            "id": urls.key_id(&profile.user_name),
+            "owner": id,
+            "publicKeyPem": public_key_pem,
+        },
+    })
+}
+
+pub fn note(urls: &Urls, message: &AuthorMessageQueryResult) -> Value {
+    json!({
+        "id": urls.message(message.id),
+        "type": "Note",
+        "attributedTo": urls.actor(&message.user_name),
+        "content": html(message.body.as_deref().unwrap_or_default()),
+        "published": message.published_at,
+        "inReplyTo": message.original_msg_id.map(|id| urls.message(id)),
+        "to": [PUBLIC],
+        "url": urls.message(message.id),
+    })
+}
+
+/// The `Create` of a message's note, or the `Announce` of the message it broadcasts.
+pub fn message_activity(urls: &Urls, message: &AuthorMessageQueryResult) -> Value {
+    let object = match message.broadcast_msg_id {
+        Some(broadcast_msg_id) => json!(urls.message(broadcast_msg_id)),
+        None => note(urls, message),
+    };
+    json!({
+        "@context": ACTIVITY_STREAMS,
+        "id": urls.message_activity(message.id),
+        "type": if message.broadcast_msg_id.is_some() { "Announce" } else { "Create" },
+        "actor": urls.actor(&message.user_name),
+        "published": message.published_at,
+        "to": [PUBLIC],
+        "object": object,
+    })
+}
+
+pub fn follow(urls: &Urls, user_name: &str, id: &str, object: &str) -> Value {
+    json!({
+        "@context": ACTIVITY_STREAMS,
+        "id": id,
+        "type": "Follow",
+        "actor": urls.actor(user_name),
+        "object": object,
+    })
+}
+
+/// Accepts the `Follow` with `follow_id` that `follower` sent to `user_name`.
+pub fn accept(urls: &Urls, user_name: &str, follow_id: &str, follower: &str) -> Value {
+    json!({
+        "@context": ACTIVITY_STREAMS,
+        "id": urls.activity(),
+        "type": "Accept",
+        "actor": urls.actor(user_name),
+        "object": {
+            "id": follow_id,
+            "type": "Follow",
+            "actor": follower,
+            "object": urls.actor(user_name),
+        },
+    })
+}
+
+/// The outbox itself only points at its first page.
+pub fn outbox(urls: &Urls, user_name: &str) -> Value {
+    let outbox = urls.outbox(user_name);
+    json!({
+        "@context": ACTIVITY_STREAMS,
+        "id": outbox,
+        "type": "OrderedCollection",
+        "first": format!("{outbox}?page=true"),
+    })
+}
+
+/// A page of messages, newest first, followed by the page of messages older than `next_before`
+/// when there may be more.
+pub fn outbox_page(
+    urls: &Urls,
+    user_name: &str,
+    before_id: Option<i64>,
+    messages: &[AuthorMessageQueryResult],
+    next_before: Option<i64>,
+) -> Value {
+    let outbox = urls.outbox(user_name);
+    let id = match before_id {
+        Some(before_id) => format!("{outbox}?page=true&before={before_id}"),
+        None => format!("{outbox}?page=true"),
+    };
+    let next = next_before.map(|next_before| format!("{outbox}?page=true&before={next_before}"));
+    json!({
+        "@context": ACTIVITY_STREAMS,
+        "id": id,
+        "type": "OrderedCollectionPage",
+        "partOf": outbox,
+        "next": next,
+        "orderedItems": messages
+            .iter()
+            .map(|message| message_activity(urls, message))
+            .collect::<Vec<Value>>(),
+    })
+}
+
+/// What `activity`, received on the inbox of `user_name`, asks for. `None` for well formed
+/// activities the server has no use for, which are accepted and dropped.
+pub fn parse_inbound(
+    urls: &Urls,
+    user_name: &str,
+    activity: &Value,
+) -> Result<Option<InboundActivity>, String> {
+    let id = string(activity, "id").ok_or("activity has no id")?;
+    let actor = string(activity, "actor").ok_or("activity has no actor")?;
+    let object = activity.get("object").ok_or("activity has no object")?;
+    let object_id = object_id(object);
+    let local_message = || object_id.as_deref().and_then(|url| urls.message_id(url));
+
+    let inbound = match string(activity, "type").as_deref() {
+        Some("Follow") if object_id == Some(urls.actor(user_name)) => {
+            Some(InboundActivity::Follow { id })
+        },
+        Some("Accept") => object_id.map(|follow_id| InboundActivity::Accept { follow_id }),
+        Some("Create") if string(object, "type").as_deref() == Some("Note") => {
+            if string(object, "attributedTo").as_deref() != Some(actor.as_str()) {
+                return Err("note is not attributed to the activity's actor".to_string());
+            }
+            let ap_id = object_id.ok_or("note has no id")?;
+            if host(&ap_id).is_none() || host(&ap_id) != host(&actor) {
+                return Err("note's id is not on the actor's server".to_string());
+            }
+            let published = string(object, "published")
+                .and_then(|at| DateTime::parse_from_rfc3339(&at).ok())
+                .map_or_else(Utc::now, |at| at.with_timezone(&Utc));
+            Some(InboundActivity::Create(RemoteNoteCreate {
+                ap_id,
+                content: string(object, "content").unwrap_or_default(),
+                in_reply_to: string(object, "inReplyTo"),
+                published,
+            }))
+        },
+        Some("Like") => local_message().map(|message_id| InboundActivity::Like { id, message_id }),
+        Some("Announce") => {
+            local_message().map(|message_id| InboundActivity::Announce { id, message_id })
+        },
+        Some("Undo") => object_id.map(|object_id| InboundActivity::Undo { object_id }),
+        _ => None,
+    };
+    Ok(inbound)
+}
+
+/// The parts of a remote actor document the server needs. Its key must be owned by the actor.
+pub fn parse_actor(document: &Value) -> Result<RemoteActor, String> {
+    let id = string(document, "id").ok_or("actor has no id")?;
+    let key = document.get("publicKey").ok_or("actor has no public key")?;
+    if string(key, "owner").as_deref() != Some(id.as_str()) {
+        return Err("actor's public key is owned by someone else".to_string());
+    }
+    Ok(RemoteActor {
+        inbox: string(document, "inbox").ok_or("actor has no inbox")?,
+        shared_inbox: document
+            .get("endpoints")
+            .and_then(|endpoints| string(endpoints, "sharedInbox")),
+        preferred_username: string(document, "preferredUsername"),
+        public_key_id: string(key, "id").ok_or("actor's public key has no id")?,
+        public_key_pem: string(key, "publicKeyPem").ok_or("actor's public key has no pem")?,
+        id,
+    })
+}
+
+/// The id of an object given either inline or by reference.
+fn object_id(object: &Value) -> Option<String> {
+    match object {
+        Value::String(id) => Some(id.clone()),
+        _ => string(object, "id"),
+    }
+}
+
+/// The host of `url`, to tell whether two ids come from the same server.
+fn host(url: &str) -> Option<String> {
+    Url::parse(url)
+        .ok()?
+        .host_str()
+        .map(str::to_ascii_lowercase)
+}
+
+fn string(value: &Value, field: &str) -> Option<String> {
+    value.get(field)?.as_str().map(str::to_string)
+}
+
+/// Plain text as the html of a `content` or `summary`.
+pub fn html(text: &str) -> String {
+    if text.is_empty() {
+        return String::new();
+    }
+    let escaped = text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "<br>");
+    format!("<p>{escaped}</p>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls() -> Urls {
+        Urls::new("https://a.example/")
+    }
+
+    fn message(
+        broadcast_msg_id: Option<i64>,
+        original_msg_id: Option<i64>,
+    ) -> AuthorMessageQueryResult {
+        AuthorMessageQueryResult {
+            id: 7,
+            published_at: Utc::now(),
+            body: Some("1 < 2 & \"hi\"".to_string()),
+            msg_group_type: 1,
+            user_id: 1,
+            user_name: "dave".to_string(),
+            broadcast_msg_id,
+            original_msg_id,
+        }
+    }
+
+    #[test]
+    fn test_messages_map_to_create_and_announce() {
+        let urls = urls();
+        let reply = message_activity(&urls, &message(None, Some(3)));
+        assert_eq!(reply["type"], "Create");
+        assert_eq!(reply["id"], "https://a.example/ap/messages/7/activity");
+        assert_eq!(reply["actor"], "https://a.example/ap/users/dave");
+        assert_eq!(reply["object"]["type"], "Note");
+        assert_eq!(
+            reply["object"]["inReplyTo"],
+            "https://a.example/ap/messages/3"
+        );
+        assert_eq!(
+            reply["object"]["content"],
+            "<p>1 &lt; 2 &amp; &quot;hi&quot;</p>"
+        );
+
+        let broadcast = message_activity(&urls, &message(Some(5), None));
+        assert_eq!(broadcast["type"], "Announce");
+        assert_eq!(broadcast["object"], "https://a.example/ap/messages/5");
+
+        assert_eq!(urls.message_id("https://a.example/ap/messages/5"), Some(5));
+        assert_eq!(urls.message_id("https://b.example/ap/messages/5"), None);
+    }
+
+    #[test]
+    fn test_actor_round_trip() {
+        let urls = urls();
+        let profile = ProfileQueryResult {
+            id: 1,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            user_name: "dave".to_string(),
+            full_name: "Dave".to_string(),
+            description: String::new(),
+            region: None,
+            main_url: None,
+            avatar: None,
+        };
+        let mut document = actor(&urls, &profile, "PEM");
+        let parsed = parse_actor(&document).unwrap();
+        assert_eq!(parsed.id, "https://a.example/ap/users/dave");
+        assert_eq!(parsed.inbox, "https://a.example/ap/users/dave/inbox");
+        assert_eq!(parsed.public_key_id, urls.key_id("dave"));
+        assert_eq!(parsed.delivery_inbox(), parsed.inbox);
+
+        document["publicKey"]["owner"] = json!("https://b.example/users/eve");
+        assert!(parse_actor(&document).is_err());
+    }
+
+    #[test]
+    fn test_outbox_page_links_the_next_page() {
+        let urls = urls();
+        let messages = vec![message(None, None)];
+        let page = outbox_page(&urls, "dave", None, &messages, Some(7));
+        assert_eq!(
+            page["next"],
+            "https://a.example/ap/users/dave/outbox?page=true&before=7"
+        );
+        assert_eq!(page["orderedItems"][0]["type"], "Create");
+        let last = outbox_page(&urls, "dave", Some(7), &[], None);
+        assert!(last["next"].is_null());
+    }
+
+    #[test]
+    fn test_inbound_activities_are_parsed() {
+        let urls = urls();
+        let actor = "https://b.example/users/erin";
+        let parse = |activity: Value| parse_inbound(&urls, "dave", &activity);
+
+        let follow =
+            json!({"id": "f1", "type": "Follow", "actor": actor, "object": urls.actor("dave")});
+        assert_eq!(
+            parse(follow).unwrap(),
+            Some(InboundActivity::Follow { id: "f1".to_string() })
+        );
+        let other =
+            json!({"id": "f2", "type": "Follow", "actor": actor, "object": urls.actor("eve")});
+        assert_eq!(parse(other).unwrap(), None);
+
+        let like = json!({"id": "l1", "type": "Like", "actor": actor, "object": urls.message(7)});
+        assert_eq!(
+            parse(like).unwrap(),
+            Some(InboundActivity::Like { id: "l1".to_string(), message_id: 7 })
+        );
+        let undo = json!({"id": "u1", "type": "Undo", "actor": actor, "object": {"id": "l1", "type": "Like"}});
+        assert_eq!(
+            parse(undo).unwrap(),
+            Some(InboundActivity::Undo { object_id: "l1".to_string() })
+        );
+
+        let create = json!({
+            "id": "c1", "type": "Create", "actor": actor,
+            "object": {"id": "https://b.example/notes/n1", "type": "Note", "attributedTo": actor,
+                "content": "<p>hi</p>", "inReplyTo": urls.message(7),
+                "published": "2026-10-19T09:00:00Z"},
+        });
+        let Some(InboundActivity::Create(note)) = parse(create).unwrap() else {
+            panic!("expected a create");
+        };
+        assert_eq!(note.ap_id, "https://b.example/notes/n1");
+        assert_eq!(note.in_reply_to, Some(urls.message(7)));
+
+        let forged = json!({
+            "id": "c2", "type": "Create", "actor": actor,
+            "object": {"id": "n2", "type": "Note", "attributedTo": "https://b.example/users/eve"},
+        });
+        assert!(parse(forged).is_err());
+        // Only the actor's own server may mint the note's id, so it cannot claim someone else's.
+        for ap_id in ["https://c.example/notes/n3", "n3"] {
+            let spoofed = json!({
+                "id": "c3", "type": "Create", "actor": actor,
+                "object": {"id": ap_id, "type": "Note", "attributedTo": actor},
+            });
+            assert!(parse(spoofed).is_err(), "{ap_id}");
+        }
+        assert!(parse(json!({"type": "Like"})).is_err());
+    }
+}
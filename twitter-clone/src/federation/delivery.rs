@@ -0,0 +1,262 @@
+//! Sends activities to remote inboxes, one `deliver_activity` job per inbox.
+//!
+//! Each POST is signed with the key of the local actor sending it, see [`super::signatures`]. A
+//! failed delivery fails its job, so the queue retries it with backoff like a webhook delivery.
+
+use std::fmt;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use reqwest::{
+    header::{CONTENT_TYPE, DATE, HOST},
+    Client, Url,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::info;
+
+use super::{signatures, ACTIVITY_JSON};
+use crate::{
+    common::entities::{federation::repo::QueryActorKeyFn, messages::repo::QueryMessageFn},
+    error::{Result, ServerSideError},
+    outbound::{self, WithTraceContext},
+    worker::JobHandler,
+};
+
+/// How much of an inbox's error response is kept in the job's `last_error`.
+const MAX_RESPONSE_BYTES: usize = 512;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DeliveryPayload {
+    /// The local profile sending the activity, whose key signs it.
+    pub profile_id: i64,
+    pub inbox: String,
+    pub activity: Value,
+    /// Set for the activity of a message, which isn't sent once the message is hidden or gone.
+    pub message_id: Option<i64>,
+}
+
+/// Runs the `deliver_activity` jobs.
+#[derive(Debug)]
+pub struct ActivityDeliveryHandler<T> {
+    db_repo: T,
+    client: Client,
+    allow_private_networks: bool,
+}
+
+impl<T> ActivityDeliveryHandler<T> {
+    /// `client` is the outbound client of [`crate::outbound::http_client`], inboxes are named by
+    /// the remote actors.
+    pub fn new(db_repo: T, client: Client, allow_private_networks: bool) -> Self {
+        Self { db_repo, client, allow_private_networks }
+    }
+}
+
+#[async_trait]
+impl<T> JobHandler for ActivityDeliveryHandler<T>
+where
+    T: QueryActorKeyFn + QueryMessageFn + fmt::Debug + Send + Sync,
+{
+    async fn run(&self, payload: Value) -> Result<()> {
+        let payload =
+            serde_json::from_value::<DeliveryPayload>(payload).map_err(ServerSideError::from)?;
+        if let Some(message_id) = payload.message_id {
+            if self.db_repo.query_message(message_id).await?.is_none() {
+                info!(message_id, "Message is gone, activity not delivered");
+                return Ok(());
+            }
+        }
+
+        let key = self.db_repo.query_actor_key(payload.profile_id).await?;
+        let key_id = payload
+            .activity
+            .get("actor")
+            .and_then(Value::as_str)
+            .map(|actor| format!("{actor}#main-key"))
+            .ok_or_else(|| ServerSideError::InvalidInput("activity has no actor".to_string()))?;
+        let body = payload.activity.to_string();
+
+        send(
+            &self.client,
+            self.allow_private_networks,
+            &payload.inbox,
+            &body,
+            &key.private_key_pem,
+            &key_id,
+        )
+        .await
+        .map_err(ServerSideError::InternalServerError)?;
+        info!(inbox = %payload.inbox, "Activity delivered");
+        Ok(())
+    }
+}
+
+/// POSTs `body` to `inbox` signed with `private_key_pem`, failing unless it answers with a 2xx.
+pub async fn send(
+    client: &Client,
+    allow_private_networks: bool,
+    inbox: &str,
+    body: &str,
+    private_key_pem: &str,
+    key_id: &str,
+) -> std::result::Result<(), String> {
+    let url = Url::parse(inbox).map_err(|err| format!("inbox is invalid: {err}"))?;
+    outbound::check_url(&url, allow_private_networks)?;
+    let host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{host}:{port}"),
+        (Some(host), None) => host.to_string(),
+        (None, _) => return Err("inbox has no host".to_string()),
+    };
+    let target = match url.query() {
+        Some(query) => format!("post {}?{query}", url.path()),
+        None => format!("post {}", url.path()),
+    };
+    let date = signatures::http_date(Utc::now());
+    let digest = signatures::digest(body.as_bytes());
+    let headers = signatures::REQUIRED_HEADERS.map(str::to_string);
+    let signed = signatures::signing_string(&headers, |name| match name {
+        "(request-target)" => Some(target.as_str()),
+        "host" => Some(host.as_str()),
+        "date" => Some(date.as_str()),
+        "digest" => Some(digest.as_str()),
+        _ => None,
+    })?;
+    let signature = signatures::sign(private_key_pem, key_id, &signed)?;
+
+    let mut response = client
+        .post(url)
+        .header(CONTENT_TYPE, ACTIVITY_JSON)
+        .header(HOST, &host)
+        .header(DATE, &date)
+        .header("Digest", &digest)
+        .header("Signature", signature)
+        .body(body.to_string())
+        .with_trace_context()
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+
+    let mut response_body = Vec::new();
+    while let Ok(Some(chunk)) = response.chunk().await {
+        let room = MAX_RESPONSE_BYTES - response_body.len();
+        response_body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        if response_body.len() == MAX_RESPONSE_BYTES {
+            break;
+        }
+    }
+    Err(format!(
+        "inbox answered with status {status}: {}",
+        String::from_utf8_lossy(&response_body)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use mockall::{mock, predicate::eq};
+    use reqwest::StatusCode;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        common::entities::{
+            federation::model::ActorKeyQueryResult,
+            messages::model::MessageWithFollowingAndBroadcastQueryResult,
+        },
+        common_tests::stub_server::{StubResponse, StubServer},
+        federation::signatures::{generate_key_pair, SignatureHeader},
+        settings::OutboundSettings,
+    };
+
+    mock! {
+        #[derive(Debug)]
+        Repo {}
+
+        #[async_trait]
+        impl QueryActorKeyFn for Repo {
+            async fn query_actor_key(&self, profile_id: i64) -> Result<ActorKeyQueryResult>;
+        }
+
+        #[async_trait]
+        impl QueryMessageFn for Repo {
+            async fn query_message(
+                &self,
+                id: i64,
+            ) -> Result<Option<MessageWithFollowingAndBroadcastQueryResult>>;
+        }
+    }
+
+    fn handler(repo: MockRepo) -> ActivityDeliveryHandler<MockRepo> {
+        let outbound = OutboundSettings {
+            allow_private_networks: true,
+            ..OutboundSettings::default()
+        };
+        ActivityDeliveryHandler::new(repo, outbound::http_client(&outbound).unwrap(), true)
+    }
+
+    fn payload(inbox: String, message_id: Option<i64>) -> Value {
+        json!({
+            "profile_id": 1,
+            "inbox": inbox,
+            "activity": {"id": "https://a.example/x", "type": "Follow", "actor": "https://a.example/ap/users/dave"},
+            "message_id": message_id,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_delivery_is_signed() {
+        let (public_key_pem, private_key_pem) = generate_key_pair().unwrap();
+        let inbox = StubServer::start(|_| StubResponse::status(StatusCode::ACCEPTED)).await;
+        let mut repo = MockRepo::new();
+        repo.expect_query_actor_key()
+            .with(eq(1))
+            .returning(move |profile_id| {
+                Ok(ActorKeyQueryResult {
+                    profile_id,
+                    created_at: Utc::now(),
+                    public_key_pem: String::new(),
+                    private_key_pem: private_key_pem.clone(),
+                })
+            });
+
+        handler(repo)
+            .run(payload(inbox.url("/ap/users/erin/inbox"), None))
+            .await
+            .unwrap();
+
+        let [request] = inbox.requests().try_into().unwrap();
+        let header = |name: &str| request.header(name);
+        assert_eq!(header("content-type"), Some(ACTIVITY_JSON));
+        assert_eq!(
+            header("digest"),
+            Some(signatures::digest(request.body.as_bytes()).as_str())
+        );
+
+        let signature = SignatureHeader::parse(header("signature").unwrap()).unwrap();
+        assert_eq!(signature.key_id, "https://a.example/ap/users/dave#main-key");
+        let signed = signatures::signing_string(&signature.headers, |name| match name {
+            "(request-target)" => Some("post /ap/users/erin/inbox"),
+            _ => header(name),
+        })
+        .unwrap();
+        signatures::verify(&public_key_pem, &signed, &signature.signature).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_gone_message_is_not_delivered() {
+        let mut repo = MockRepo::new();
+        repo.expect_query_message()
+            .with(eq(5))
+            .returning(|_| Ok(None));
+        repo.expect_query_actor_key().never();
+
+        handler(repo)
+            .run(payload("http://127.0.0.1:9/inbox".to_string(), Some(5)))
+            .await
+            .unwrap();
+    }
+}
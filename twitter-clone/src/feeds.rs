@@ -0,0 +1,266 @@
+//! RSS 2.0 and Atom feeds of a profile's public messages.
+//!
+//! Entries are dated by `message.updated_at`, which is the publish time of scheduled messages, and
+//! a broadcast shows the message it broadcasts as a quote below its own body.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::{
+    common::entities::{
+        messages::model::MessageWithFollowingAndBroadcastQueryResult,
+        profile::model::ProfileQueryResult,
+    },
+    federation::activities::html,
+};
+
+pub const RSS_XML: &str = "application/rss+xml; charset=utf-8";
+pub const ATOM_XML: &str = "application/atom+xml; charset=utf-8";
+/// Characters of a body kept in an Atom entry title.
+const MAX_TITLE_CHARS: usize = 80;
+
+/// Where the feed and what it links to are served.
+#[derive(Debug, Clone)]
+pub struct FeedLinks {
+    /// `scheme://host` of the api.
+    pub base: String,
+    /// The url the feed was requested at.
+    pub feed: String,
+}
+
+impl FeedLinks {
+    fn profile(&self, user_name: &str) -> String {
+        format!("{}/api/v1/profile/username/{user_name}", self.base)
+    }
+
+    fn message(&self, id: i64) -> String {
+        format!("{}/api/v1/messages/{id}", self.base)
+    }
+}
+
+/// When the feed last changed: its newest message, or the profile when that changed since. Hiding
+/// a message touches its profile, so a feed that lost its newest message still moves on.
+pub fn last_modified(
+    profile: &ProfileQueryResult,
+    messages: &[MessageWithFollowingAndBroadcastQueryResult],
+) -> DateTime<Utc> {
+    messages
+        .iter()
+        .map(|message| message.updated_at)
+        .fold(profile.updated_at, DateTime::max)
+}
+
+pub fn rss(
+    links: &FeedLinks,
+    profile: &ProfileQueryResult,
+    messages: &[MessageWithFollowingAndBroadcastQueryResult],
+) -> String {
+    let mut items = String::new();
+    for message in messages {
+        let link = xml(&links.message(message.id));
+        items.push_str(&format!(
+            "<item><link>{link}</link><guid isPermaLink=\"true\">{link}</guid>\
+             <pubDate>{}</pubDate><description>{}</description></item>",
+            message.updated_at.to_rfc2822(),
+            xml(&content(message)),
+        ));
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+         <rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\"><channel>\
+         <title>{}</title><link>{}</link><description>{}</description>\
+         <atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>\
+         <lastBuildDate>{}</lastBuildDate>{items}</channel></rss>",
+        xml(&title(profile)),
+        xml(&links.profile(&profile.user_name)),
+        xml(&profile.description),
+        xml(&links.feed),
+        last_modified(profile, messages).to_rfc2822(),
+    )
+}
+
+pub fn atom(
+    links: &FeedLinks,
+    profile: &ProfileQueryResult,
+    messages: &[MessageWithFollowingAndBroadcastQueryResult],
+) -> String {
+    let mut entries = String::new();
+    for message in messages {
+        let link = xml(&links.message(message.id));
+        entries.push_str(&format!(
+            "<entry><id>{link}</id><title>{}</title><updated>{}</updated>\
+             <link rel=\"alternate\" href=\"{link}\"/><content type=\"html\">{}</content></entry>",
+            xml(&entry_title(message)),
+            timestamp(message.updated_at),
+            xml(&content(message)),
+        ));
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\
+         <id>{feed}</id><title>{}</title><subtitle>{}</subtitle><updated>{}</updated>\
+         <link rel=\"self\" type=\"application/atom+xml\" href=\"{feed}\"/>\
+         <link rel=\"alternate\" href=\"{}\"/><author><name>{}</name></author>{entries}</feed>",
+        xml(&title(profile)),
+        xml(&profile.description),
+        timestamp(last_modified(profile, messages)),
+        xml(&links.profile(&profile.user_name)),
+        xml(&profile.full_name),
+        feed = xml(&links.feed),
+    )
+}
+
+fn title(profile: &ProfileQueryResult) -> String {
+    format!("{} (@{})", profile.full_name, profile.user_name)
+}
+
+/// Atom entries need a title, taken from the start of the body.
+fn entry_title(message: &MessageWithFollowingAndBroadcastQueryResult) -> String {
+    let body = message.body.as_deref().unwrap_or_default().trim();
+    match (body.is_empty(), &message.broadcast_msg_user_name) {
+        (true, Some(user_name)) => format!("Broadcast of @{user_name}"),
+        _ if body.chars().count() > MAX_TITLE_CHARS => {
+            format!(
+                "{}…",
+                body.chars().take(MAX_TITLE_CHARS).collect::<String>()
+            )
+        },
+        _ => body.to_string(),
+    }
+}
+
+/// The html of a message, with the message it broadcasts quoted below it.
+fn content(message: &MessageWithFollowingAndBroadcastQueryResult) -> String {
+    let mut content = html(message.body.as_deref().unwrap_or_default());
+    if let (Some(user_name), Some(full_name)) = (
+        &message.broadcast_msg_user_name,
+        &message.broadcast_msg_full_name,
+    ) {
+        content.push_str(&format!(
+            "<blockquote>{}<footer>{} (@{})</footer></blockquote>",
+            html(message.broadcast_msg_body.as_deref().unwrap_or_default()),
+            html_text(full_name),
+            html_text(user_name),
+        ));
+    }
+    content
+}
+
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Text inside an html element, without the paragraph of [`html`].
+fn html_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Text or markup as xml character data or an attribute value.
+fn xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile() -> ProfileQueryResult {
+        ProfileQueryResult {
+            id: 1,
+            created_at: "2026-01-01T00:00:00Z".parse().unwrap(),
+            updated_at: "2026-01-01T00:00:00Z".parse().unwrap(),
+            user_name: "dave".to_string(),
+            full_name: "Dave & Co".to_string(),
+            description: "hi".to_string(),
+            region: None,
+            main_url: None,
+            avatar: None,
+        }
+    }
+
+    fn message(
+        id: i64,
+        body: &str,
+        updated_at: &str,
+    ) -> MessageWithFollowingAndBroadcastQueryResult {
+        MessageWithFollowingAndBroadcastQueryResult {
+            id,
+            updated_at: updated_at.parse().unwrap(),
+            published_at: updated_at.parse().unwrap(),
+            body: Some(body.to_string()),
+            likes: 0,
+            msg_group_type: 1,
+            user_id: 1,
+            user_name: "dave".to_string(),
+            full_name: "Dave & Co".to_string(),
+            broadcast_msg_id: None,
+            broadcast_msg_updated_at: None,
+            broadcast_msg_published_at: None,
+            broadcast_msg_body: None,
+            broadcast_msg_likes: None,
+            broadcast_msg_user_id: None,
+            broadcast_msg_user_name: None,
+            broadcast_msg_full_name: None,
+        }
+    }
+
+    fn links() -> FeedLinks {
+        FeedLinks {
+            base: "http://localhost:8080".to_string(),
+            feed: "http://localhost:8080/api/v1/profile/dave/feed.atom".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_broadcast_is_quoted_and_escaped() {
+        let broadcast = MessageWithFollowingAndBroadcastQueryResult {
+            broadcast_msg_id: Some(7),
+            broadcast_msg_body: Some("a <b> c".to_string()),
+            broadcast_msg_user_name: Some("erin".to_string()),
+            broadcast_msg_full_name: Some("Erin".to_string()),
+            ..message(8, "", "2026-02-01T10:00:00Z")
+        };
+        let feed = atom(&links(), &profile(), &[broadcast]);
+
+        assert!(feed.contains("<title>Broadcast of @erin</title>"));
+        assert!(feed.contains(
+            "<content type=\"html\">&lt;blockquote&gt;&lt;p&gt;a &amp;lt;b&amp;gt; c&lt;/p&gt;\
+             &lt;footer&gt;Erin (@erin)&lt;/footer&gt;&lt;/blockquote&gt;</content>"
+        ));
+        assert!(feed.contains("<title>Dave &amp; Co (@dave)</title>"));
+    }
+
+    #[test]
+    fn test_feeds_are_dated_by_updated_at() {
+        let messages = [
+            message(2, "newer", "2026-02-02T10:00:00.250Z"),
+            message(1, "older", "2026-02-01T10:00:00Z"),
+        ];
+        assert_eq!(last_modified(&profile(), &messages), messages[0].updated_at);
+        assert_eq!(last_modified(&profile(), &[]), profile().updated_at);
+        // Edited, or a newer message was hidden, after the newest message in the feed.
+        let touched = ProfileQueryResult {
+            updated_at: "2026-02-03T08:00:00Z".parse().unwrap(),
+            ..profile()
+        };
+        assert_eq!(last_modified(&touched, &messages), touched.updated_at);
+
+        let feed = atom(&links(), &profile(), &messages);
+        assert!(feed.contains("<feed xmlns=\"http://www.w3.org/2005/Atom\"><id>http://localhost:8080/api/v1/profile/dave/feed.atom</id>"));
+        assert!(feed.contains("<subtitle>hi</subtitle><updated>2026-02-02T10:00:00.250Z</updated>"));
+        assert!(feed.contains("<updated>2026-02-01T10:00:00.000Z</updated>"));
+
+        let feed = rss(&links(), &profile(), &messages);
+        assert!(feed.contains("<lastBuildDate>Mon, 2 Feb 2026 10:00:00 +0000</lastBuildDate>"));
+        assert!(feed.contains(
+            "<guid isPermaLink=\"true\">http://localhost:8080/api/v1/messages/1</guid>\
+             <pubDate>Sun, 1 Feb 2026 10:00:00 +0000</pubDate>"
+        ));
+    }
+}
@@ -1,62 +1,139 @@
+pub mod admin;
+pub mod admin_api;
 pub mod api_response;
 pub mod app_state;
 pub mod common;
 pub mod common_tests;
 pub mod error;
+pub mod federation;
+pub mod feeds;
+pub mod link_preview;
+pub mod metrics;
+pub mod outbound;
 pub mod routes;
 pub mod schemas;
+pub mod seed;
+pub mod settings;
+pub mod webhooks;
+pub mod worker;
 
-use std::env;
-
-use actix_web::{http::StatusCode, web, App, HttpServer};
-use serde_json::{json, Value};
-use tracing_actix_web::TracingLogger;
-use tracing_config::init_tracing;
+use actix_web::{dev::Service, web, App, HttpMessage, HttpServer};
+use reqwest::Client;
+use tracing::info;
+use tracing_actix_web::{RequestId, TracingLogger};
+use tracing_config::{LogFilters, TracingConfig};
 
 use crate::{
-    api_response::ApiResponse,
-    common::entities::base::DbRepo,
+    admin_api::AdminApi,
+    common::entities::{
+        base::{DbConnGetter, DbRepo},
+        in_memory::InMemoryRepo,
+        jobs::model::REQUEST_ID,
+        repository::Repository,
+        sqlite::SqliteRepo,
+    },
     error::{IntoClientResult, Result, ServerSideError},
+    federation::Federation,
+    link_preview::LinkPreviews,
+    metrics::WatchedPool,
+    settings::{Settings, StorageBackend},
 };
 
-pub async fn run() -> Result<()> {
-    // Load environment variables
-    dotenv::dotenv().ok();
-
-    let port: u16 = env::var("PORT")
-        .unwrap_or("8080".to_string())
-        .parse()
-        .unwrap_or_default();
+pub async fn run(settings: Settings) -> Result<()> {
+    let guard = TracingConfig::from_settings(&settings.tracing_settings())
+        .layer(metrics::repo_timing_layer())
+        .try_init()
+        .map_err(|err| ServerSideError::ConfigError(err.to_string()))
+        .into_client_result()?;
 
-    let host = env::var("ADDRESS").unwrap_or("127.0.0.1".to_string());
+    let log_filters = guard.filters().clone();
 
-    let _guard = init_tracing();
+    match settings.storage.backend {
+        StorageBackend::Postgres => {
+            let db_repo = DbRepo::init(&settings.database)
+                .await
+                .into_client_result()?
+                .with_timeline(settings.timeline.clone())
+                .with_federation(settings.federation.clone());
+            metrics::metrics().watch_pool(WatchedPool::Postgres(db_repo.get_conn().clone()));
+            let client = outbound::http_client(&settings.outbound)?;
+            let workers = settings.jobs.in_process.then(|| {
+                worker::default_worker(db_repo.clone(), client.clone(), &settings).spawn()
+            });
+            let result = serve(&settings, client, db_repo, log_filters).await;
+            if let Some(workers) = workers {
+                workers.shutdown().await;
+            }
+            result
+        },
+        StorageBackend::Sqlite => {
+            let db_repo = SqliteRepo::init(&settings.storage.sqlite)
+                .await
+                .into_client_result()?;
+            metrics::metrics().watch_pool(WatchedPool::Sqlite(db_repo.get_conn().clone()));
+            serve(
+                &settings,
+                outbound::http_client(&settings.outbound)?,
+                db_repo,
+                log_filters,
+            )
+            .await
+        },
+        StorageBackend::Memory => {
+            info!("Using in-memory storage, data is lost on restart");
+            let client = outbound::http_client(&settings.outbound)?;
+            serve(&settings, client, InMemoryRepo::new(), log_filters).await
+        },
+    }
+}
 
-    let db_repo = DbRepo::init().await;
-    let app_data = web::Data::new(app_state::AppState { client: reqwest::Client::new(), db_repo });
+async fn serve<T: Repository>(
+    settings: &Settings,
+    client: Client,
+    db_repo: T,
+    log_filters: LogFilters,
+) -> Result<()> {
+    let link_previews = LinkPreviews::new(
+        client.clone(),
+        &settings.outbound,
+        settings.link_preview.clone(),
+    );
+    let federation = Federation::new(
+        client.clone(),
+        &settings.outbound,
+        settings.federation.clone(),
+    );
+    let app_data = web::Data::new(app_state::AppState {
+        client,
+        link_previews,
+        federation,
+        admin: AdminApi::new(settings.admin.clone(), log_filters),
+        db_repo,
+    });
+    let server = &settings.server;
 
     HttpServer::new(move || {
         App::new()
+            // Runs inside `TracingLogger`, which assigns the request id.
+            .wrap_fn(|req, srv| {
+                let request_id = req.extensions().get::<RequestId>().map(ToString::to_string);
+                let response = srv.call(req);
+                async move {
+                    match request_id {
+                        Some(request_id) => REQUEST_ID.scope(request_id, response).await,
+                        None => response.await,
+                    }
+                }
+            })
             .wrap(TracingLogger::default())
+            .wrap_fn(metrics::track_request)
             .app_data(app_data.clone())
-            .service(
-                web::scope("/api/v1")
-                    .route("/", web::get().to(get_root))
-                    .configure(routes::msg_routes::config)
-                    .configure(routes::profile_routes::config),
-            )
+            .configure(routes::config::<T>)
     })
-    .bind((host, port))
+    .bind((server.host.as_str(), server.port))
     .map_err(|err| ServerSideError::HostBindingError(err.to_string()))?
     .run()
     .await
     .map_err(|err| ServerSideError::ServerRunError(err.to_string()))
     .into_client_result()
 }
-
-async fn get_root() -> Result<ApiResponse<Value>> {
-    let value = json!({
-        "message": "Welcome to home page for twitter clone api"
-    });
-    Ok(ApiResponse::new(StatusCode::OK, value))
-}
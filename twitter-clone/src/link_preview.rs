@@ -0,0 +1,516 @@
+//! OpenGraph / Twitter card previews of the links found in messages and profiles.
+//!
+//! Pages are fetched in the background with the [`crate::outbound`] client and cached per url, so
+//! responses never wait on a remote host: a link shows its preview once the fetch is done.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use reqwest::{
+    header::{ACCEPT, CONTENT_TYPE},
+    Client, Url,
+};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{
+    outbound::{self, WithTraceContext},
+    settings::{LinkPreviewSettings, OutboundSettings},
+};
+
+const MAX_TITLE_CHARS: usize = 200;
+const MAX_DESCRIPTION_CHARS: usize = 500;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkPreview {
+    /// Where the page ended up after redirects.
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    stored_at: Instant,
+    /// `None` while the fetch is running, and for pages without a preview.
+    preview: Option<LinkPreview>,
+}
+
+/// Shared by every worker through [`crate::app_state::AppState`], clones share the cache.
+#[derive(Debug, Clone)]
+pub struct LinkPreviews {
+    client: Client,
+    allow_private_networks: bool,
+    settings: LinkPreviewSettings,
+    cache: Arc<DashMap<String, CacheEntry>>,
+}
+
+impl LinkPreviews {
+    pub fn new(client: Client, outbound: &OutboundSettings, settings: LinkPreviewSettings) -> Self {
+        Self {
+            client,
+            allow_private_networks: outbound.allow_private_networks,
+            settings,
+            cache: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// The cached preview of the first link in `text`. A missing or expired one is fetched in the
+    /// background and shows up on a later call.
+    pub fn preview_for(&self, text: &str) -> Option<LinkPreview> {
+        let url = self.first_url(text)?;
+        if let Some(entry) = self.cache.get(url.as_str()) {
+            if entry.stored_at.elapsed() < self.ttl() {
+                return entry.preview.clone();
+            }
+        }
+        self.prefetch(url);
+        None
+    }
+
+    /// Starts fetching the preview of the first link in `text` unless it is cached already.
+    pub fn prefetch_for(&self, text: &str) {
+        if let Some(url) = self.first_url(text) {
+            self.prefetch(url);
+        }
+    }
+
+    fn first_url(&self, text: &str) -> Option<Url> {
+        if !self.settings.enabled {
+            return None;
+        }
+        find_url(text)
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.settings.cache_ttl_secs)
+    }
+
+    fn prefetch(&self, url: Url) {
+        // The pending entry keeps concurrent reads of the same url from fetching it again.
+        match self.cache.entry(url.to_string()) {
+            dashmap::Entry::Occupied(entry) if entry.get().stored_at.elapsed() < self.ttl() => {
+                return;
+            },
+            entry => {
+                entry.insert(CacheEntry { stored_at: Instant::now(), preview: None });
+            },
+        }
+        self.evict();
+
+        let previews = self.clone();
+        tokio::spawn(async move {
+            let preview = match previews.fetch(&url).await {
+                Ok(preview) => preview,
+                Err(err) => {
+                    debug!("No link preview for {}: {}", url, err);
+                    None
+                },
+            };
+            previews.cache.insert(
+                url.to_string(),
+                CacheEntry { stored_at: Instant::now(), preview },
+            );
+        });
+    }
+
+    /// Drops expired entries once the cache is full, then the oldest ones.
+    fn evict(&self) {
+        if self.cache.len() <= self.settings.cache_capacity {
+            return;
+        }
+        let ttl = self.ttl();
+        self.cache
+            .retain(|_, entry| entry.stored_at.elapsed() < ttl);
+        while self.cache.len() > self.settings.cache_capacity {
+            let oldest = self
+                .cache
+                .iter()
+                .min_by_key(|entry| entry.stored_at)
+                .map(|entry| entry.key().clone());
+            match oldest {
+                Some(url) => self.cache.remove(&url),
+                None => break,
+            };
+        }
+    }
+
+    /// Reads at most `max_bytes` of an html page, the metadata lives in its head anyway.
+    async fn fetch(&self, url: &Url) -> Result<Option<LinkPreview>, String> {
+        outbound::check_url(url, self.allow_private_networks)?;
+        let mut response = self
+            .client
+            .get(url.clone())
+            .header(ACCEPT, "text/html,application/xhtml+xml")
+            .with_trace_context()
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("status {}", response.status()));
+        }
+        let is_html = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| {
+                value.starts_with("text/html") || value.starts_with("application/xhtml+xml")
+            });
+        if !is_html {
+            return Ok(None);
+        }
+
+        let base = response.url().clone();
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|err| err.to_string())? {
+            let room = self.settings.max_bytes - body.len();
+            body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+            if body.len() == self.settings.max_bytes {
+                break;
+            }
+        }
+        Ok(parse_preview(&base, &String::from_utf8_lossy(&body)))
+    }
+}
+
+/// The first http(s) link in `text`, without the punctuation that usually follows it in a
+/// sentence.
+pub fn find_url(text: &str) -> Option<Url> {
+    text.split_whitespace()
+        .filter_map(|word| {
+            let start = word.find("https://").or_else(|| word.find("http://"))?;
+            let candidate = word[start..].trim_end_matches(|c: char| {
+                matches!(
+                    c,
+                    '.' | ',' | ';' | ':' | '!' | '?' | ')' | ']' | '"' | '\''
+                )
+            });
+            let mut url = Url::parse(candidate).ok()?;
+            url.set_fragment(None);
+            Some(url)
+        })
+        .find(|url| url.host_str().is_some())
+}
+
+/// Picks the `og:`, `twitter:` and plain `description` meta tags of `html`, falling back to its
+/// `<title>`. `None` when the page has none of them.
+pub fn parse_preview(base: &Url, html: &str) -> Option<LinkPreview> {
+    // Same byte offsets as `html`, lowercasing only touches ascii.
+    let lower = html.to_ascii_lowercase();
+    let mut title = None;
+    let mut description = None;
+    let mut image = None;
+    let mut fallback_description = None;
+
+    let mut offset = 0;
+    while let Some(start) = lower[offset..].find("<meta") {
+        let start = offset + start + "<meta".len();
+        let end = lower[start..]
+            .find('>')
+            .map_or(lower.len(), |end| start + end);
+        offset = end;
+
+        let attributes = attributes(&html[start..end]);
+        let attribute = |name: &str| {
+            attributes
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| *value)
+        };
+        let (Some(key), Some(content)) = (
+            attribute("property").or_else(|| attribute("name")),
+            attribute("content"),
+        ) else {
+            continue;
+        };
+        // `og:` wins over `twitter:`, whichever comes first in the page.
+        match key.to_ascii_lowercase().as_str() {
+            "og:title" => title = Some(content),
+            "twitter:title" => title = title.or(Some(content)),
+            "og:description" => description = Some(content),
+            "twitter:description" => description = description.or(Some(content)),
+            "description" => fallback_description = Some(content),
+            "og:image" | "og:image:url" => image = Some(content),
+            "twitter:image" | "twitter:image:src" => image = image.or(Some(content)),
+            _ => {},
+        }
+    }
+
+    let title = title
+        .map(str::to_string)
+        .or_else(|| title_element(html, &lower))
+        .and_then(|title| clean_text(&title, MAX_TITLE_CHARS));
+    let description = description
+        .or(fallback_description)
+        .and_then(|description| clean_text(description, MAX_DESCRIPTION_CHARS));
+    let image = image
+        .and_then(|image| base.join(decode_entities(image).trim()).ok())
+        .filter(|image| matches!(image.scheme(), "http" | "https"))
+        .map(String::from);
+
+    if title.is_none() && description.is_none() && image.is_none() {
+        return None;
+    }
+    Some(LinkPreview {
+        url: base.to_string(),
+        title,
+        description,
+        image,
+    })
+}
+
+fn title_element(html: &str, lower: &str) -> Option<String> {
+    let start = lower.find("<title")?;
+    let start = start + lower[start..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    Some(html[start..end].to_string())
+}
+
+/// The `name=value` pairs of a tag, names lowercased. Values may be quoted with `"` or `'`.
+fn attributes(tag: &str) -> Vec<(String, &str)> {
+    let mut attributes = vec![];
+    let mut rest = tag;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            return attributes;
+        }
+        let name_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+
+        let mut value = "";
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let end = after[1..].find(quote).map_or(after.len(), |end| end + 1);
+                    value = &after[1..end];
+                    rest = after.get(end + 1..).unwrap_or_default();
+                },
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    value = &after[..end];
+                    rest = &after[end..];
+                },
+            }
+        }
+        attributes.push((name, value));
+    }
+}
+
+/// Decodes entities, collapses whitespace and cuts the text to `max_chars`.
+fn clean_text(text: &str, max_chars: usize) -> Option<String> {
+    let text = decode_entities(text)
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ");
+    if text.is_empty() {
+        return None;
+    }
+    Some(text.chars().take(max_chars).collect())
+}
+
+/// The named entities common in titles, and numeric ones. Anything else is kept as is.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..end])?, end)));
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = match entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+            {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => entity.strip_prefix('#')?.parse().ok(),
+            };
+            char::from_u32(code?)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    const PAGE: &str = r#"<!doctype html>
+        <html><head>
+            <title>Fallback title</title>
+            <meta name="twitter:title" content="Card title">
+            <meta property="og:title" content="Rust &amp; Actix">
+            <meta name=description content='A page about &quot;previews&quot;'>
+            <META PROPERTY="og:image" CONTENT="/img/cover.png" />
+        </head><body>Hello</body></html>"#;
+
+    /// Serves `PAGE` on `/page`, a page past the size limit on `/big` and a redirect to `/page`
+    /// on `/moved`.
+    async fn stub_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let read = socket.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..read]).to_string();
+                let (status, headers, body) = if request.starts_with("GET /page ") {
+                    (
+                        "200 OK",
+                        "content-type: text/html; charset=utf-8\r\n",
+                        PAGE.to_string(),
+                    )
+                } else if request.starts_with("GET /big ") {
+                    let body = format!("{}{PAGE}", " ".repeat(4096));
+                    ("200 OK", "content-type: text/html\r\n", body)
+                } else if request.starts_with("GET /moved ") {
+                    ("302 Found", "location: /page\r\n", String::new())
+                } else {
+                    ("404 Not Found", "", String::new())
+                };
+                let response = format!(
+                    "HTTP/1.1 {status}\r\n{headers}content-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        addr
+    }
+
+    fn previews(allow_private_networks: bool, max_bytes: usize) -> LinkPreviews {
+        let outbound = OutboundSettings {
+            allow_private_networks,
+            ..OutboundSettings::default()
+        };
+        let settings = LinkPreviewSettings {
+            max_bytes,
+            ..LinkPreviewSettings::default()
+        };
+        LinkPreviews::new(
+            outbound::http_client(&outbound).unwrap(),
+            &outbound,
+            settings,
+        )
+    }
+
+    #[test]
+    fn test_parse_preview() {
+        let base = Url::parse("https://example.com/posts/1").unwrap();
+        let preview = parse_preview(&base, PAGE).unwrap();
+        assert_eq!(preview.title.as_deref(), Some("Rust & Actix"));
+        assert_eq!(
+            preview.description.as_deref(),
+            Some("A page about \"previews\"")
+        );
+        assert_eq!(
+            preview.image.as_deref(),
+            Some("https://example.com/img/cover.png")
+        );
+
+        let plain = parse_preview(&base, "<title>\n  Just   a title &#x2014; </title>").unwrap();
+        assert_eq!(plain.title.as_deref(), Some("Just a title \u{2014}"));
+        assert_eq!(plain.image, None);
+        assert!(parse_preview(&base, "<p>nothing here</p>").is_none());
+    }
+
+    #[test]
+    fn test_find_url() {
+        let url = find_url("Read this (https://example.com/a?b=1#top), then reply").unwrap();
+        assert_eq!(url.as_str(), "https://example.com/a?b=1");
+        assert!(find_url("no links, ftp://example.com or example.com").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_from_stub_server() {
+        let addr = stub_server().await;
+        let previews = previews(true, 1024);
+
+        let page = Url::parse(&format!("http://{addr}/page")).unwrap();
+        let preview = previews.fetch(&page).await.unwrap().unwrap();
+        assert_eq!(preview.title.as_deref(), Some("Rust & Actix"));
+        assert_eq!(preview.image, Some(format!("http://{addr}/img/cover.png")));
+
+        let moved = Url::parse(&format!("http://{addr}/moved")).unwrap();
+        assert_eq!(
+            previews.fetch(&moved).await.unwrap().unwrap().url,
+            page.as_str()
+        );
+
+        // The metadata is past `max_bytes`.
+        let big = Url::parse(&format!("http://{addr}/big")).unwrap();
+        assert_eq!(previews.fetch(&big).await.unwrap(), None);
+        let missing = Url::parse(&format!("http://{addr}/missing")).unwrap();
+        assert!(previews.fetch(&missing).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_previews_are_cached_in_the_background() {
+        let addr = stub_server().await;
+        let previews = previews(true, 1024);
+        let body = format!("look at http://{addr}/page!");
+
+        assert_eq!(previews.preview_for(&body), None);
+        let mut preview = None;
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            preview = previews.preview_for(&body);
+            if preview.is_some() {
+                break;
+            }
+        }
+        assert_eq!(preview.unwrap().title.as_deref(), Some("Rust & Actix"));
+    }
+
+    #[tokio::test]
+    async fn test_private_addresses_are_not_fetched() {
+        let addr = stub_server().await;
+        let previews = previews(false, 1024);
+
+        let page = Url::parse(&format!("http://{addr}/page")).unwrap();
+        assert!(previews.fetch(&page).await.is_err());
+        let localhost = Url::parse(&format!("http://localhost:{}/page", addr.port())).unwrap();
+        assert!(previews.fetch(&localhost).await.is_err());
+    }
+}
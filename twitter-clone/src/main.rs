@@ -1,6 +1,14 @@
-use twitter_clone::{error::Result, run};
+use twitter_clone::{
+    error::{IntoClientResult, Result},
+    run,
+    settings::Settings,
+};
 
 #[actix_web::main]
 async fn main() -> Result<()> {
-    run().await
+    let settings = Settings::load()
+        .inspect_err(|err| eprintln!("{err}"))
+        .into_client_result()?;
+
+    run(settings).await
 }
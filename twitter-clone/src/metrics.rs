@@ -0,0 +1,347 @@
+//! Prometheus metrics, served in the text format from `/metrics`.
+//!
+//! Requests are counted and timed by [`track_request`] under their route pattern, so reading any
+//! message adds to the one `/api/v1/messages/{id}` series. Repo functions are timed from the spans
+//! of their `#[instrument]`ed `*_inner` functions by [`repo_timing_layer`], which covers both sql
+//! backends without touching their code. Everything lives in one process wide registry, since
+//! errors are counted in `ClientSideError::error_response` where no app state is at hand.
+
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        LazyLock, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse},
+    Error,
+};
+use prometheus::{
+    core::Collector, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use sqlx::{Pool, Postgres, Sqlite};
+use tracing::{span, Metadata, Subscriber};
+use tracing_subscriber::{
+    filter::filter_fn, layer::Context, registry::LookupSpan, Layer, Registry as Spans,
+};
+
+pub const PROMETHEUS_TEXT: &str = "text/plain; version=0.0.4; charset=utf-8";
+/// Module of the repo functions, the Postgres ones and those of [`crate::common::entities::sqlite`].
+const REPO_TARGET: &str = "twitter_clone::common::entities";
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_duration: HistogramVec,
+    client_errors: IntCounterVec,
+    repo_duration: HistogramVec,
+    messages_created: IntCounter,
+    profiles_created: IntCounter,
+    follows: IntCounter,
+    pool_size: IntGauge,
+    pool_idle: IntGauge,
+    pool_waiting: IntGauge,
+    /// Repo calls that haven't returned yet, not counting those made by another repo call.
+    repo_calls: AtomicI64,
+    pool: OnceLock<WatchedPool>,
+}
+
+/// The connection pool whose usage is reported by the `db_pool_*` gauges.
+#[derive(Debug, Clone)]
+pub enum WatchedPool {
+    Postgres(Pool<Postgres>),
+    Sqlite(Pool<Sqlite>),
+}
+
+impl WatchedPool {
+    /// Open and idle connections.
+    fn usage(&self) -> (u32, usize) {
+        match self {
+            WatchedPool::Postgres(pool) => (pool.size(), pool.num_idle()),
+            WatchedPool::Sqlite(pool) => (pool.size(), pool.num_idle()),
+        }
+    }
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let route_labels = ["method", "route", "status"];
+
+        Self {
+            http_requests: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new(
+                        "http_requests_total",
+                        "Requests answered, by method, route pattern and status",
+                    ),
+                    &route_labels,
+                ),
+            ),
+            http_duration: register(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::new(
+                        "http_request_duration_seconds",
+                        "Time to answer a request, by method, route pattern and status",
+                    ),
+                    &route_labels,
+                ),
+            ),
+            client_errors: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new(
+                        "client_errors_total",
+                        "Errors answered to clients, by `ClientSideError` variant",
+                    ),
+                    &["variant"],
+                ),
+            ),
+            repo_duration: register(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::new(
+                        "repo_query_duration_seconds",
+                        "Time spent in a repo function, including waiting for a connection",
+                    ),
+                    &["function"],
+                ),
+            ),
+            messages_created: register(
+                &registry,
+                IntCounter::new(
+                    "messages_created_total",
+                    "Messages created through the api, including scheduled messages, \
+                     responses and published drafts",
+                ),
+            ),
+            profiles_created: register(
+                &registry,
+                IntCounter::new("profiles_created_total", "Profiles created through the api"),
+            ),
+            follows: register(
+                &registry,
+                IntCounter::new(
+                    "follows_total",
+                    "Follows of remote accounts and follows received from them",
+                ),
+            ),
+            pool_size: register(
+                &registry,
+                IntGauge::new("db_pool_size", "Open database connections"),
+            ),
+            pool_idle: register(
+                &registry,
+                IntGauge::new("db_pool_idle", "Open database connections not in use"),
+            ),
+            pool_waiting: register(
+                &registry,
+                IntGauge::new(
+                    "db_pool_waiting",
+                    "Repo calls in flight beyond the connections in use, so waiting for one",
+                ),
+            ),
+            repo_calls: AtomicI64::new(0),
+            pool: OnceLock::new(),
+            registry,
+        }
+    }
+
+    /// Reports the usage of `pool` from the `db_pool_*` gauges. Only the first pool is watched,
+    /// the in-memory backend has none and leaves them at zero.
+    pub fn watch_pool(&self, pool: WatchedPool) {
+        if self.pool.set(pool).is_err() {
+            tracing::warn!("A database pool is already watched for metrics");
+        }
+    }
+
+    pub fn message_created(&self) {
+        self.messages_created.inc();
+    }
+
+    pub fn profile_created(&self) {
+        self.profiles_created.inc();
+    }
+
+    pub fn followed(&self) {
+        self.follows.inc();
+    }
+
+    pub(crate) fn client_error(&self, variant: &str) {
+        self.client_errors.with_label_values(&[variant]).inc();
+    }
+
+    fn observe_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let status = status.to_string();
+        let labels = [method, route, status.as_str()];
+        self.http_requests.with_label_values(&labels).inc();
+        self.http_duration
+            .with_label_values(&labels)
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Everything in the text exposition format, with the pool gauges read just now.
+    pub fn render(&self) -> Result<String, String> {
+        if let Some(pool) = self.pool.get() {
+            let (size, idle) = pool.usage();
+            let in_use = i64::from(size) - i64::try_from(idle).unwrap_or(i64::MAX);
+            self.pool_size.set(i64::from(size));
+            self.pool_idle.set(i64::try_from(idle).unwrap_or(i64::MAX));
+            // sqlx doesn't tell how many callers wait for a connection, every repo call holds one
+            // at a time though, so those in flight without one are waiting.
+            self.pool_waiting
+                .set((self.repo_calls.load(Ordering::Relaxed) - in_use).max(0));
+        }
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .map_err(|err| err.to_string())
+    }
+}
+
+fn register<M: Collector + Clone + 'static>(
+    registry: &Registry,
+    metric: prometheus::Result<M>,
+) -> M {
+    let metric = metric.expect("metric options are valid");
+    registry
+        .register(Box::new(metric.clone()))
+        .expect("metric names are unique");
+    metric
+}
+
+/// Counts and times a request under the pattern of the route that answered it, or `unmatched`.
+/// Used with `App::wrap_fn`.
+pub fn track_request<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, Error>> + use<S, B>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+{
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let response = srv.call(req);
+    async move {
+        let response = response.await?;
+        let route = response
+            .request()
+            .match_pattern()
+            .unwrap_or_else(|| "unmatched".to_string());
+        metrics().observe_request(
+            &method,
+            &route,
+            response.status().as_u16(),
+            started.elapsed(),
+        );
+        Ok(response)
+    }
+}
+
+/// A tracing layer timing the spans of the `*_inner` repo functions into
+/// `repo_query_duration_seconds`, labelled by the function name without `_inner`. Its filter
+/// only lets those spans through, so it doesn't enable any other span or event.
+pub fn repo_timing_layer() -> impl Layer<Spans> + Send + Sync {
+    RepoTimingLayer.with_filter(filter_fn(is_repo_span))
+}
+
+fn is_repo_span(metadata: &Metadata<'_>) -> bool {
+    metadata.is_span()
+        && metadata.target().starts_with(REPO_TARGET)
+        && metadata.name().ends_with("_inner")
+}
+
+struct RepoTimingLayer;
+
+struct RepoCall {
+    function: &'static str,
+    started: Instant,
+    /// Not made by another repo call, which already holds the connection.
+    outermost: bool,
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for RepoTimingLayer {
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let outermost = !span
+            .scope()
+            .skip(1)
+            .any(|parent| parent.extensions().get::<RepoCall>().is_some());
+        if outermost {
+            metrics().repo_calls.fetch_add(1, Ordering::Relaxed);
+        }
+        span.extensions_mut().insert(RepoCall {
+            function: attrs.metadata().name().trim_end_matches("_inner"),
+            started: Instant::now(),
+            outermost,
+        });
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(call) = ctx
+            .span(&id)
+            .and_then(|span| span.extensions_mut().remove::<RepoCall>())
+        else {
+            return;
+        };
+        let metrics = metrics();
+        if call.outermost {
+            metrics.repo_calls.fetch_sub(1, Ordering::Relaxed);
+        }
+        metrics
+            .repo_duration
+            .with_label_values(&[call.function])
+            .observe(call.started.elapsed().as_secs_f64());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    #[test]
+    fn test_repo_spans_are_timed_by_function() {
+        let subscriber = Spans::default().with(repo_timing_layer());
+        tracing::subscriber::with_default(subscriber, || {
+            let outer = tracing::info_span!(
+                target: "twitter_clone::common::entities::messages::repo",
+                "insert_message_inner"
+            );
+            let _outer = outer.enter();
+            assert_eq!(metrics().repo_calls.load(Ordering::Relaxed), 1);
+            // Made by the outer call, so not one more call in flight.
+            let inner = tracing::info_span!(
+                target: "twitter_clone::common::entities::sqlite::messages",
+                "fan_out_timeline_test_inner"
+            );
+            drop(inner.enter());
+            assert_eq!(metrics().repo_calls.load(Ordering::Relaxed), 1);
+            drop(inner);
+            tracing::info_span!(target: "twitter_clone::routes", "query_messages_inner")
+                .in_scope(|| {});
+        });
+
+        assert_eq!(metrics().repo_calls.load(Ordering::Relaxed), 0);
+        let text = metrics().render().unwrap();
+        assert!(text.contains("repo_query_duration_seconds_count{function=\"insert_message\"} "));
+        assert!(text
+            .contains("repo_query_duration_seconds_count{function=\"fan_out_timeline_test\"} 1"));
+        assert!(!text.contains("function=\"query_messages\""));
+    }
+}
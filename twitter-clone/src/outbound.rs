@@ -0,0 +1,274 @@
+//! The http client for requests to hosts taken from user content, like the pages behind links.
+//!
+//! Anyone posting a link picks the host, so requests must not reach the server's own network: host
+//! names only resolve to public addresses, literal addresses are checked before each request and
+//! redirect, and proxies from the environment are ignored since they would do the resolving.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect, Client, RequestBuilder, Url,
+};
+
+use crate::{error::ServerSideError, settings::OutboundSettings};
+
+const USER_AGENT: &str = concat!("twitter-clone/", env!("CARGO_PKG_VERSION"));
+
+/// Builds the client kept in [`crate::app_state::AppState`].
+pub fn http_client(settings: &OutboundSettings) -> Result<Client, ServerSideError> {
+    let max_redirects = settings.max_redirects;
+    let allow_private_networks = settings.allow_private_networks;
+    let policy = redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() > max_redirects {
+            attempt.error("too many redirects")
+        } else if let Err(err) = check_url(attempt.url(), allow_private_networks) {
+            attempt.error(err)
+        } else {
+            attempt.follow()
+        }
+    });
+
+    let mut builder = Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(Duration::from_millis(settings.timeout_ms))
+        .redirect(policy)
+        .no_proxy();
+    if !allow_private_networks {
+        builder = builder.dns_resolver(Arc::new(PublicResolver));
+    }
+    builder.build().map_err(|err| {
+        ServerSideError::InternalServerError(format!("Failed to build the http client: {err}"))
+    })
+}
+
+/// Adds the W3C `traceparent` of the current span, so the receiver continues its trace. Nothing is
+/// added while spans aren't exported.
+pub trait WithTraceContext {
+    fn with_trace_context(self) -> Self;
+}
+
+impl WithTraceContext for RequestBuilder {
+    fn with_trace_context(self) -> Self {
+        tracing_config::otlp::trace_context_headers()
+            .into_iter()
+            .fold(self, |request, (name, value)| request.header(name, value))
+    }
+}
+
+/// Refuses anything but http(s) urls, and hosts given as a non-public address. Host names are
+/// checked once resolved, by the client.
+pub fn check_url(url: &Url, allow_private_networks: bool) -> Result<(), String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("unsupported scheme `{}`", url.scheme()));
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| "url has no host".to_string())?;
+    // `Url` already normalized the address forms, e.g. `http://2130706433/` to `127.0.0.1`.
+    let Ok(ip) = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    else {
+        return Ok(());
+    };
+    if allow_private_networks || is_public_ip(ip) {
+        Ok(())
+    } else {
+        Err(format!("{ip} is not a public address"))
+    }
+}
+
+/// Whether `ip` is a globally routable unicast address.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        // Covers the v4-mapped and v4-compatible forms, which reach the embedded address.
+        IpAddr::V6(ip) => match ip.to_ipv4() {
+            Some(ipv4) => is_public_ipv4(ipv4),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "this network", shared address space (carrier-grade NAT), IETF protocol assignments,
+        // benchmarking and the reserved 240/4.
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (b == 18 || b == 19))
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let [first, second, ..] = ip.segments();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local, link-local, the deprecated site-local and documentation.
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+        || (first & 0xffc0) == 0xfec0
+        || (first == 0x2001 && second == 0x0db8)
+        // NAT64 (64:ff9b::/96 and the local-use 64:ff9b:1::/48), 6to4 and Teredo all tunnel to
+        // an embedded v4 address that may well be private, so none of them are let through.
+        || (first == 0x0064 && second == 0xff9b)
+        || first == 0x2002
+        || (first == 0x2001 && second == 0x0000))
+}
+
+/// Resolves with the system resolver and drops every non-public address.
+#[derive(Debug)]
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect::<Vec<SocketAddr>>();
+            if addrs.is_empty() {
+                return Err(format!("{host} has no public address").into());
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        test::{call_service, init_service, TestRequest},
+        web, App, HttpResponse,
+    };
+    use reqwest::StatusCode;
+    use tracing_actix_web::TracingLogger;
+    use tracing_config::{otlp::OtlpSettings, TracingConfig};
+
+    use super::*;
+    use crate::common_tests::stub_server::{StubResponse, StubServer};
+
+    #[test]
+    fn test_non_public_addresses_are_refused() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a00:1",
+            "64:ff9b:1::a00:1",
+            "2002:7f00:1::1",
+            "2002:a00:1::1",
+            "2001:0:4136:e378:8000:63bf:f5ff:fffe",
+            "fec0::1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in [
+            "93.184.216.34",
+            "1.1.1.1",
+            "2606:4700:4700::1111",
+            "2001:4860:4860::8888",
+        ] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn test_check_url() {
+        let check = |url: &str, allow| check_url(&Url::parse(url).unwrap(), allow);
+        assert!(check("https://example.com/page", false).is_ok());
+        assert!(check("http://93.184.216.34/", false).is_ok());
+        assert!(check("http://127.0.0.1:8080/", false).is_err());
+        assert!(check("http://[::1]/", false).is_err());
+        assert!(check("http://2130706433/", false).is_err());
+        assert!(check("http://127.0.0.1:8080/", true).is_ok());
+        assert!(check("file:///etc/passwd", true).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_incoming_trace_context_is_continued() {
+        let collector = StubServer::start(|_| StubResponse::status(StatusCode::OK)).await;
+        let remote = StubServer::start(|_| StubResponse::status(StatusCode::OK)).await;
+        let (subscriber, guard) = TracingConfig::new()
+            .otlp(
+                OtlpSettings {
+                    endpoint: collector.url(""),
+                    service_name: "twitter-clone".to_string(),
+                    sampling_ratio: 1.0,
+                },
+                "info",
+            )
+            .build()
+            .unwrap();
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let remote_url = remote.url("/hook");
+        let app = init_service(App::new().wrap(TracingLogger::default()).route(
+            "/",
+            web::get().to(move || {
+                let remote_url = remote_url.clone();
+                async move {
+                    Client::new()
+                        .get(remote_url)
+                        .with_trace_context()
+                        .send()
+                        .await
+                        .unwrap();
+                    HttpResponse::Ok().finish()
+                }
+            }),
+        ))
+        .await;
+        // Printable bytes, so they can be found in the protobuf export.
+        let trace_id = hex::encode("a-caller's-trace");
+        let request = TestRequest::get()
+            .uri("/")
+            .insert_header(("traceparent", format!("00-{trace_id}-00f067aa0ba902b7-01")))
+            .to_request();
+        assert!(call_service(&app, request).await.status().is_success());
+
+        // The outgoing request is part of the caller's trace, under the request's own span.
+        let requests = remote.requests();
+        let traceparent = requests[0].header("traceparent").unwrap();
+        assert!(
+            traceparent.starts_with(&format!("00-{trace_id}-")),
+            "{traceparent}"
+        );
+        assert!(!traceparent.contains("00f067aa0ba902b7"), "{traceparent}");
+
+        // Dropping the guard exports the spans, which blocks until the collector answered.
+        tokio::task::spawn_blocking(move || drop(guard))
+            .await
+            .unwrap();
+        let exports = collector.requests();
+        assert_eq!(exports[0].path(), "/v1/traces");
+        assert!(exports[0].body.contains("a-caller's-trace"));
+    }
+}
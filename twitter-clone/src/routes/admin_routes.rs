@@ -0,0 +1,17 @@
+use actix_web::web;
+
+use crate::{common::entities::repository::Repository, routes::handler::admin_handlers};
+
+pub fn config<T: Repository>(config: &mut web::ServiceConfig) {
+    config.service(
+        web::scope("/admin")
+            .route(
+                "/log-levels",
+                web::get().to(admin_handlers::get_log_levels::<T>),
+            )
+            .route(
+                "/log-levels/{sink}",
+                web::put().to(admin_handlers::set_log_level::<T>),
+            ),
+    );
+}
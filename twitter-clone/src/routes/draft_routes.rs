@@ -0,0 +1,18 @@
+use actix_web::web;
+
+use crate::{common::entities::repository::Repository, routes::handler::draft_handlers};
+
+pub fn config<T: Repository>(config: &mut web::ServiceConfig) {
+    config.service(
+        web::scope("/profile/{user_id}/drafts")
+            .route("", web::post().to(draft_handlers::create_draft::<T>))
+            .route("", web::get().to(draft_handlers::get_drafts::<T>))
+            .route("/{id}", web::get().to(draft_handlers::get_draft::<T>))
+            .route("/{id}", web::put().to(draft_handlers::update_draft::<T>))
+            .route("/{id}", web::delete().to(draft_handlers::delete_draft::<T>))
+            .route(
+                "/{id}/publish",
+                web::post().to(draft_handlers::publish_draft::<T>),
+            ),
+    );
+}
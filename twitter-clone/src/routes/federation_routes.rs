@@ -0,0 +1,44 @@
+use actix_web::web;
+
+use crate::{common::entities::repository::Repository, routes::handler::federation_handlers};
+
+/// The documents other instances fetch, at the root since their urls are the actors' ids.
+pub fn config<T: Repository>(config: &mut web::ServiceConfig) {
+    config
+        .route(
+            "/.well-known/webfinger",
+            web::get().to(federation_handlers::webfinger::<T>),
+        )
+        .service(
+            web::scope("/ap")
+                .route(
+                    "/users/{user_name}",
+                    web::get().to(federation_handlers::get_actor::<T>),
+                )
+                .route(
+                    "/users/{user_name}/outbox",
+                    web::get().to(federation_handlers::get_outbox::<T>),
+                )
+                .route(
+                    "/users/{user_name}/inbox",
+                    web::post().to(federation_handlers::post_inbox::<T>),
+                )
+                .route(
+                    "/messages/{id}",
+                    web::get().to(federation_handlers::get_note::<T>),
+                ),
+        );
+}
+
+/// Following remote actors and reading their notes, under `/api/v1`.
+pub fn api_config<T: Repository>(config: &mut web::ServiceConfig) {
+    config
+        .route(
+            "/profile/{user_id}/remote-follows",
+            web::post().to(federation_handlers::follow_remote::<T>),
+        )
+        .route(
+            "/profile/{user_id}/remote-notes",
+            web::get().to(federation_handlers::get_remote_notes::<T>),
+        );
+}
@@ -0,0 +1,72 @@
+use crate::error::{ClientSideError, Result};
+use crate::schemas::admin::{LogLevelJson, LogLevelResponder, LogLevelResponders};
+use crate::{api_response::ApiResponse, app_state::AppState};
+use actix_web::{web, HttpRequest};
+use chrono::{TimeDelta, Utc};
+use std::{fmt::Debug, time::Duration};
+use tracing::{info, instrument};
+
+/// A temporary change is meant for a debugging session, not to outlive the day.
+const MAX_REVERT_AFTER_SECS: u64 = 24 * 3600;
+
+#[instrument(skip(app_data, req))]
+pub(crate) async fn get_log_levels<T: Debug>(
+    app_data: web::Data<AppState<T>>,
+    req: HttpRequest,
+) -> Result<ApiResponse<LogLevelResponders>> {
+    app_data.admin.authorize(&req)?;
+    info!("Get log levels handler called");
+
+    Ok(ApiResponse::ok(LogLevelResponders(
+        app_data
+            .admin
+            .log_levels()
+            .into_iter()
+            .map(|filter| LogLevelResponder {
+                sink: filter.sink,
+                directives: filter.directives,
+                previous: None,
+                revert_at: None,
+            })
+            .collect(),
+    )))
+}
+
+#[instrument(skip(app_data, req, json))]
+pub(crate) async fn set_log_level<T: Debug>(
+    app_data: web::Data<AppState<T>>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    json: web::Json<LogLevelJson>,
+) -> Result<ApiResponse<LogLevelResponder>> {
+    app_data.admin.authorize(&req)?;
+    let sink = path.into_inner();
+    let json = json.into_inner();
+    info!("Set log level handler called for sink: {}", sink);
+
+    let revert_after = match json.revert_after_secs {
+        Some(secs) if secs == 0 || secs > MAX_REVERT_AFTER_SECS => {
+            return Err(ClientSideError::BadRequest(format!(
+                "revertAfterSecs must be between 1 and {MAX_REVERT_AFTER_SECS}"
+            )));
+        },
+        secs => secs.map(Duration::from_secs),
+    };
+    let previous = app_data
+        .admin
+        .set_log_level(&sink, &json.directives, revert_after)?;
+    let directives = app_data
+        .admin
+        .log_levels()
+        .into_iter()
+        .find(|filter| filter.sink == sink)
+        .map_or(json.directives, |filter| filter.directives);
+
+    Ok(ApiResponse::ok(LogLevelResponder {
+        sink,
+        directives,
+        previous: Some(previous.directives),
+        revert_at: revert_after
+            .and_then(|after| Some(Utc::now() + TimeDelta::from_std(after).ok()?)),
+    }))
+}
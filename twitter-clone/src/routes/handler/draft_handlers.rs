@@ -0,0 +1,191 @@
+use crate::common::entities::drafts::model::{DraftCreate, DraftQueryResult};
+use crate::error::{Result, ServerSideError};
+use crate::metrics::metrics;
+use crate::schemas::draft::{DraftJson, DraftResponder, DraftResponders};
+use crate::{
+    api_response::ApiResponse,
+    app_state::AppState,
+    common::entities::drafts::repo::{
+        DeleteDraftFn, InsertDraftFn, PublishDraftFn, QueryDraftFn, QueryDraftsFn, UpdateDraftFn,
+    },
+};
+use actix_web::web;
+use serde_json::{json, Value};
+use std::fmt::Debug;
+use tracing::{info, instrument};
+
+/// Same limit as the `message.body` column the draft gets published into.
+const MAX_BODY_CHARS: usize = 140;
+
+#[instrument(skip(app_data, json))]
+pub(crate) async fn create_draft<T: Debug + InsertDraftFn>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<i64>,
+    json: web::Json<DraftJson>,
+) -> Result<ApiResponse<Value>> {
+    let user_id = path.into_inner();
+    info!("Create draft handler called for user_id: {}", user_id);
+    let draft = DraftCreate::try_from(json.into_inner())?;
+
+    let result = app_data.db_repo.insert_draft(user_id, draft).await?;
+    info!("Draft created with id: {}", result);
+    Ok(ApiResponse::created(json!({
+        "message": "Draft created successfully",
+        "draft_id": result
+    })))
+}
+
+#[instrument(skip(app_data))]
+pub(crate) async fn get_drafts<T: Debug + QueryDraftsFn>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<i64>,
+) -> Result<ApiResponse<DraftResponders>> {
+    let user_id = path.into_inner();
+    info!("Get drafts handler called for user_id: {}", user_id);
+    let drafts = app_data.db_repo.query_drafts(user_id).await?;
+
+    Ok(ApiResponse::ok(DraftResponders(
+        drafts.into_iter().map(DraftResponder::from).collect(),
+    )))
+}
+
+#[instrument(skip(app_data))]
+pub(crate) async fn get_draft<T: Debug + QueryDraftFn>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<(i64, i64)>,
+) -> Result<ApiResponse<DraftResponder>> {
+    let (user_id, draft_id) = path.into_inner();
+    info!("Get draft handler called for id: {}", draft_id);
+    match app_data.db_repo.query_draft(draft_id, user_id).await? {
+        Some(draft) => Ok(ApiResponse::ok(draft.into())),
+        None => Err(
+            ServerSideError::DraftNotFound(format!("No draft found with id: {draft_id}")).into(),
+        ),
+    }
+}
+
+#[instrument(skip(app_data, json))]
+pub(crate) async fn update_draft<T: Debug + UpdateDraftFn>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<(i64, i64)>,
+    json: web::Json<DraftJson>,
+) -> Result<ApiResponse<Value>> {
+    let (user_id, draft_id) = path.into_inner();
+    info!("Update draft handler called for id: {}", draft_id);
+    let draft = DraftCreate::try_from(json.into_inner())?;
+
+    app_data
+        .db_repo
+        .update_draft(draft_id, user_id, draft)
+        .await?;
+    Ok(ApiResponse::ok(json!({
+        "message": "Draft updated successfully",
+        "draft_id": draft_id
+    })))
+}
+
+#[instrument(skip(app_data))]
+pub(crate) async fn delete_draft<T: Debug + DeleteDraftFn>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<(i64, i64)>,
+) -> Result<ApiResponse<Value>> {
+    let (user_id, draft_id) = path.into_inner();
+    info!("Delete draft handler called for id: {}", draft_id);
+
+    app_data.db_repo.delete_draft(draft_id, user_id).await?;
+    Ok(ApiResponse::ok(json!({
+        "message": "Draft deleted",
+        "draft_id": draft_id
+    })))
+}
+
+#[instrument(skip(app_data))]
+pub(crate) async fn publish_draft<T: Debug + PublishDraftFn>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<(i64, i64)>,
+) -> Result<ApiResponse<Value>> {
+    let (user_id, draft_id) = path.into_inner();
+    info!("Publish draft handler called for id: {}", draft_id);
+
+    let result = app_data.db_repo.publish_draft(draft_id, user_id).await?;
+    info!("Draft {} published as message {}", draft_id, result);
+    metrics().message_created();
+    Ok(ApiResponse::created(json!({
+        "message": "Draft published successfully",
+        "message_id": result
+    })))
+}
+
+impl TryFrom<DraftJson> for DraftCreate {
+    type Error = ServerSideError;
+
+    fn try_from(json: DraftJson) -> std::result::Result<Self, Self::Error> {
+        if json.body.chars().count() > MAX_BODY_CHARS {
+            return Err(ServerSideError::InvalidInput(format!(
+                "body must be at most {MAX_BODY_CHARS} characters"
+            )));
+        }
+        if json.broadcasting_msg_id.is_some() && json.original_msg_id.is_some() {
+            return Err(ServerSideError::InvalidInput(
+                "broadcastingMsgId and originalMsgId can't both be set".to_string(),
+            ));
+        }
+
+        Ok(DraftCreate {
+            body: json.body,
+            msg_group_type: json.group_type as i32,
+            broadcasting_msg_id: json.broadcasting_msg_id,
+            original_msg_id: json.original_msg_id,
+        })
+    }
+}
+
+impl From<DraftQueryResult> for DraftResponder {
+    fn from(draft: DraftQueryResult) -> Self {
+        DraftResponder {
+            id: draft.id,
+            created_at: draft.created_at,
+            updated_at: draft.updated_at,
+            user_id: draft.user_id,
+            body: draft.body,
+            group_type: draft.msg_group_type,
+            broadcasting_msg_id: draft.broadcasting_msg_id,
+            original_msg_id: draft.original_msg_id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schemas::message::MessageGroupTypes;
+
+    fn json(body: &str) -> DraftJson {
+        DraftJson {
+            body: body.to_string(),
+            group_type: MessageGroupTypes::Circle,
+            broadcasting_msg_id: None,
+            original_msg_id: Some(3),
+        }
+    }
+
+    #[test]
+    fn test_draft_json_is_validated() {
+        let draft = DraftCreate::try_from(json(&"é".repeat(MAX_BODY_CHARS))).unwrap();
+        assert_eq!(draft.msg_group_type, 2);
+        assert_eq!(draft.original_msg_id, Some(3));
+
+        assert!(matches!(
+            DraftCreate::try_from(json(&"a".repeat(MAX_BODY_CHARS + 1))),
+            Err(ServerSideError::InvalidInput(_))
+        ));
+        let both = DraftJson {
+            broadcasting_msg_id: Some(4),
+            ..json("both")
+        };
+        assert!(matches!(
+            DraftCreate::try_from(both),
+            Err(ServerSideError::InvalidInput(_))
+        ));
+    }
+}
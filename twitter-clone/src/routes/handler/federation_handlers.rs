@@ -0,0 +1,273 @@
+use crate::common::entities::{
+    federation::{
+        model::{InboundActivity, RemoteNoteQueryResult},
+        repo::{
+            FollowRemoteFn, QueryActorKeyFn, QueryNoteFn, QueryRemoteNotesFn, ReceiveActivityFn,
+        },
+    },
+    messages::repo::QueryAuthorMessagesFn,
+    profile::{
+        model::ProfileQueryResult,
+        repo::{QueryProfileByUserFn, QueryProfileFn},
+    },
+};
+use crate::error::{Result, ServerSideError};
+use crate::federation::{activities, ACTIVITY_JSON, JRD_JSON};
+use crate::metrics::metrics;
+use crate::schemas::{
+    federation::{
+        OutboxQuery, RemoteFollowJson, RemoteNoteResponder, RemoteNoteResponders, RemoteNotesQuery,
+        WebfingerQuery,
+    },
+    message::MessageGroupTypes,
+};
+use crate::{api_response::ApiResponse, app_state::AppState};
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse};
+use serde_json::{json, Value};
+use std::fmt::Debug;
+use tracing::{info, instrument, warn};
+
+const OUTBOX_PAGE_SIZE: i16 = 20;
+const DEFAULT_REMOTE_NOTES_LIMIT: i64 = 20;
+const MAX_REMOTE_NOTES_LIMIT: i64 = 100;
+
+#[instrument(skip(app_data))]
+pub(crate) async fn webfinger<T: Debug + QueryProfileByUserFn>(
+    app_data: web::Data<AppState<T>>,
+    query: web::Query<WebfingerQuery>,
+) -> Result<HttpResponse> {
+    let federation = &app_data.federation;
+    federation.check_enabled()?;
+    info!("WebFinger handler called for {}", query.resource);
+
+    let domain = federation.domain();
+    let user_name = query
+        .resource
+        .strip_prefix("acct:")
+        .and_then(|account| account.rsplit_once('@'))
+        .filter(|(_, host)| host.eq_ignore_ascii_case(&domain))
+        .map(|(user_name, _)| user_name.to_string())
+        .ok_or_else(|| {
+            ServerSideError::ProfileNotFound(format!("No account found for {}", query.resource))
+        })?;
+    let profile = profile_by_user_name(&app_data.db_repo, user_name).await?;
+
+    Ok(document(
+        JRD_JSON,
+        activities::webfinger(federation.urls(), &profile.user_name, &domain),
+    ))
+}
+
+#[instrument(skip(app_data))]
+pub(crate) async fn get_actor<T: Debug + QueryProfileByUserFn + QueryActorKeyFn>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    app_data.federation.check_enabled()?;
+    let profile = profile_by_user_name(&app_data.db_repo, path.into_inner()).await?;
+    info!("Get actor handler called for id: {}", profile.id);
+    let key = app_data.db_repo.query_actor_key(profile.id).await?;
+
+    Ok(document(
+        ACTIVITY_JSON,
+        activities::actor(app_data.federation.urls(), &profile, &key.public_key_pem),
+    ))
+}
+
+#[instrument(skip(app_data))]
+pub(crate) async fn get_outbox<T: Debug + QueryProfileByUserFn + QueryAuthorMessagesFn>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<String>,
+    query: web::Query<OutboxQuery>,
+) -> Result<HttpResponse> {
+    let urls = app_data.federation.urls();
+    app_data.federation.check_enabled()?;
+    let profile = profile_by_user_name(&app_data.db_repo, path.into_inner()).await?;
+    info!("Get outbox handler called for id: {}", profile.id);
+    if query.page != Some(true) {
+        return Ok(document(
+            ACTIVITY_JSON,
+            activities::outbox(urls, &profile.user_name),
+        ));
+    }
+
+    let messages = app_data
+        .db_repo
+        .query_author_messages(profile.id, query.before, OUTBOX_PAGE_SIZE)
+        .await?;
+    // Paged by every message, circle messages are only left out of the items.
+    let next_before = messages
+        .last()
+        .filter(|_| messages.len() == usize::from(OUTBOX_PAGE_SIZE.unsigned_abs()))
+        .map(|last| last.id);
+    let public = messages
+        .into_iter()
+        .filter(|message| message.msg_group_type == MessageGroupTypes::Public as i32)
+        .collect::<Vec<_>>();
+
+    Ok(document(
+        ACTIVITY_JSON,
+        activities::outbox_page(urls, &profile.user_name, query.before, &public, next_before),
+    ))
+}
+
+#[instrument(skip(app_data, req, body))]
+pub(crate) async fn post_inbox<T: Debug + QueryProfileByUserFn + ReceiveActivityFn>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<String>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse> {
+    let federation = &app_data.federation;
+    federation.check_enabled()?;
+    let profile = profile_by_user_name(&app_data.db_repo, path.into_inner()).await?;
+    info!("Post inbox handler called for id: {}", profile.id);
+
+    let actor = federation
+        .verify_request(&req, &body)
+        .await
+        .map_err(|err| {
+            warn!("Inbox request refused: {}", err);
+            ServerSideError::Unauthorized(err)
+        })?;
+    let activity = serde_json::from_slice::<Value>(&body)
+        .map_err(|err| ServerSideError::InvalidInput(format!("activity is not json: {err}")))?;
+    if activity.get("actor").and_then(Value::as_str) != Some(actor.id.as_str()) {
+        return Err(ServerSideError::Unauthorized(
+            "activity is not signed by its actor".to_string(),
+        )
+        .into());
+    }
+
+    match activities::parse_inbound(federation.urls(), &profile.user_name, &activity)
+        .map_err(ServerSideError::InvalidInput)?
+    {
+        Some(inbound) => {
+            let follow = matches!(inbound, InboundActivity::Follow { .. });
+            app_data
+                .db_repo
+                .receive_activity(profile.id, &profile.user_name, &actor, inbound)
+                .await?;
+            if follow {
+                metrics().followed();
+            }
+        },
+        None => {
+            let kind = activity.get("type").and_then(Value::as_str);
+            info!(
+                "Ignored {} activity from {}",
+                kind.unwrap_or("untyped"),
+                actor.id
+            );
+        },
+    }
+    Ok(HttpResponse::Accepted().finish())
+}
+
+#[instrument(skip(app_data))]
+pub(crate) async fn get_note<T: Debug + QueryNoteFn>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<i64>,
+) -> Result<HttpResponse> {
+    app_data.federation.check_enabled()?;
+    let id = path.into_inner();
+    info!("Get note handler called for id: {}", id);
+    let note = app_data.db_repo.query_note(id).await?.ok_or_else(|| {
+        ServerSideError::MessageNotFound(format!("No message found with id: {id}"))
+    })?;
+
+    let mut document = activities::note(app_data.federation.urls(), &note.message);
+    document["likes"] = json!({"type": "Collection", "totalItems": note.remote_likes});
+    document["shares"] = json!({"type": "Collection", "totalItems": note.remote_announces});
+    Ok(self::document(ACTIVITY_JSON, document))
+}
+
+#[instrument(skip(app_data))]
+pub(crate) async fn follow_remote<T: Debug + QueryProfileFn + FollowRemoteFn>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<i64>,
+    json: web::Json<RemoteFollowJson>,
+) -> Result<ApiResponse<Value>> {
+    app_data.federation.check_enabled()?;
+    let user_id = path.into_inner();
+    info!("Follow remote handler called for user_id: {}", user_id);
+    let profile = app_data
+        .db_repo
+        .query_profile(user_id)
+        .await?
+        .ok_or_else(|| {
+            ServerSideError::ProfileNotFound(format!("No profile found with id: {user_id}"))
+        })?;
+
+    let actor = app_data
+        .federation
+        .resolve_account(&json.account)
+        .await
+        .map_err(|err| ServerSideError::InvalidInput(format!("account not found: {err}")))?;
+    let following_id = app_data
+        .db_repo
+        .follow_remote(profile.id, &profile.user_name, &actor)
+        .await?;
+    metrics().followed();
+    Ok(ApiResponse::new(
+        StatusCode::ACCEPTED,
+        json!({
+            "message": "Follow request sent",
+            "following_id": following_id,
+            "actor_id": actor.id
+        }),
+    ))
+}
+
+#[instrument(skip(app_data))]
+pub(crate) async fn get_remote_notes<T: Debug + QueryRemoteNotesFn>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<i64>,
+    query: web::Query<RemoteNotesQuery>,
+) -> Result<ApiResponse<RemoteNoteResponders>> {
+    app_data.federation.check_enabled()?;
+    let user_id = path.into_inner();
+    info!("Get remote notes handler called for user_id: {}", user_id);
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_REMOTE_NOTES_LIMIT)
+        .clamp(1, MAX_REMOTE_NOTES_LIMIT);
+
+    let notes = app_data.db_repo.query_remote_notes(user_id, limit).await?;
+    Ok(ApiResponse::ok(RemoteNoteResponders(
+        notes.into_iter().map(RemoteNoteResponder::from).collect(),
+    )))
+}
+
+async fn profile_by_user_name<T: QueryProfileByUserFn>(
+    db_repo: &T,
+    user_name: String,
+) -> Result<ProfileQueryResult> {
+    match db_repo.query_profile_by_user(user_name.clone()).await? {
+        Some(profile) => Ok(profile),
+        None => Err(ServerSideError::ProfileNotFound(format!(
+            "No profile found with user_name: {user_name}"
+        ))
+        .into()),
+    }
+}
+
+fn document(content_type: &str, document: Value) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .body(document.to_string())
+}
+
+impl From<RemoteNoteQueryResult> for RemoteNoteResponder {
+    fn from(note: RemoteNoteQueryResult) -> Self {
+        RemoteNoteResponder {
+            id: note.id,
+            created_at: note.created_at,
+            ap_id: note.ap_id,
+            actor_id: note.actor_id,
+            content: note.content,
+            in_reply_to: note.in_reply_to,
+            published: note.published,
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::common::entities::{
+    messages::repo::QueryFeedMessagesFn, profile::repo::QueryProfileByUserFn,
+};
+use crate::error::{Result, ServerSideError};
+use crate::{
+    app_state::AppState,
+    feeds::{self, FeedLinks, ATOM_XML, RSS_XML},
+};
+use actix_web::{
+    http::header::{self, ETag, EntityTag, HttpDate, IfModifiedSince, IfNoneMatch, LastModified},
+    web, HttpMessage, HttpRequest, HttpResponse,
+};
+use chrono::SubsecRound;
+use sha2::{Digest, Sha256};
+use std::fmt::Debug;
+use std::time::SystemTime;
+use tracing::{info, instrument};
+
+const FEED_SIZE: i16 = 20;
+
+#[derive(Debug, Clone, Copy)]
+enum FeedFormat {
+    Rss,
+    Atom,
+}
+
+#[instrument(skip(app_data, req))]
+pub(crate) async fn get_rss_feed<T: Debug + QueryProfileByUserFn + QueryFeedMessagesFn>(
+    app_data: web::Data<AppState<T>>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    info!("Get rss feed handler called for user_name: {}", path);
+    feed(&app_data, &req, path.into_inner(), FeedFormat::Rss).await
+}
+
+#[instrument(skip(app_data, req))]
+pub(crate) async fn get_atom_feed<T: Debug + QueryProfileByUserFn + QueryFeedMessagesFn>(
+    app_data: web::Data<AppState<T>>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    info!("Get atom feed handler called for user_name: {}", path);
+    feed(&app_data, &req, path.into_inner(), FeedFormat::Atom).await
+}
+
+/// Renders the feed and answers 304 when the reader already has it. The feed is rendered either
+/// way since the `ETag` is a hash of it, a 304 only saves the transfer.
+async fn feed<T: Debug + QueryProfileByUserFn + QueryFeedMessagesFn>(
+    app_data: &AppState<T>,
+    req: &HttpRequest,
+    user_name: String,
+    format: FeedFormat,
+) -> Result<HttpResponse> {
+    let profile = app_data
+        .db_repo
+        .query_profile_by_user(user_name.clone())
+        .await?
+        .ok_or_else(|| {
+            ServerSideError::ProfileNotFound(format!(
+                "No profile found with user_name: {user_name}"
+            ))
+        })?;
+    let messages = app_data
+        .db_repo
+        .query_feed_messages(profile.id, FEED_SIZE)
+        .await?;
+
+    let links = {
+        let info = req.connection_info();
+        let mut feed = req.full_url();
+        feed.set_query(None);
+        FeedLinks {
+            base: format!("{}://{}", info.scheme(), info.host()),
+            feed: feed.to_string(),
+        }
+    };
+    let (content_type, body) = match format {
+        FeedFormat::Rss => (RSS_XML, feeds::rss(&links, &profile, &messages)),
+        FeedFormat::Atom => (ATOM_XML, feeds::atom(&links, &profile, &messages)),
+    };
+    let etag = EntityTag::new_strong(hex::encode(&Sha256::digest(body.as_bytes())[..16]));
+    // Http dates have whole seconds, so the date read back from a reader must compare equal.
+    let last_modified = HttpDate::from(SystemTime::from(
+        feeds::last_modified(&profile, &messages).trunc_subsecs(0),
+    ));
+
+    if not_modified(req, &etag, last_modified) {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(ETag(etag))
+            .insert_header(LastModified(last_modified))
+            .finish());
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ETag(etag))
+        .insert_header(LastModified(last_modified))
+        .body(body))
+}
+
+/// `If-None-Match` wins over `If-Modified-Since` when a reader sends both.
+fn not_modified(req: &HttpRequest, etag: &EntityTag, last_modified: HttpDate) -> bool {
+    if req.headers().contains_key(header::IF_NONE_MATCH) {
+        return match req.get_header::<IfNoneMatch>() {
+            Some(IfNoneMatch::Any) => true,
+            Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+            None => false,
+        };
+    }
+    req.get_header::<IfModifiedSince>()
+        .is_some_and(|IfModifiedSince(since)| last_modified <= since)
+}
@@ -0,0 +1,425 @@
+use crate::common::entities::{
+    messages::{
+        model::{MessageWithFollowingAndBroadcastQueryResult, ResponseTargetQueryResult},
+        repo::{
+            InsertMessageFn, InsertResponseMessageFn, QueryMessageFn, QueryMessagesFn,
+            QueryResponseTargetsFn,
+        },
+    },
+    profile::{
+        model::ProfileCountsQueryResult,
+        repo::{QueryProfileByUserFn, QueryProfileCountsFn, QueryProfileFn, QueryProfilesFn},
+    },
+};
+use crate::error::{Result, ServerSideError};
+use crate::federation::activities::{html, Urls};
+use crate::metrics::metrics;
+use crate::schemas::{
+    mastodon::{
+        AccountLookupQuery, AccountResponder, HomeTimelineQuery, StatusPostJson, StatusResponder,
+    },
+    message::{MessageGroupTypes, MessageResponder},
+    profile::ProfileResponder,
+};
+use crate::{api_response::ApiResponse, app_state::AppState};
+use actix_web::{http::header, web, Either, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::iter;
+use tracing::{info, instrument};
+
+const DEFAULT_TIMELINE_LIMIT: i16 = 20;
+const MAX_TIMELINE_LIMIT: i16 = 40;
+/// Same limit as the body of a native message.
+const MAX_STATUS_CHARS: usize = 281;
+
+#[instrument(skip(app_data))]
+pub(crate) async fn lookup_account<T: Debug + QueryProfileByUserFn + QueryProfileCountsFn>(
+    app_data: web::Data<AppState<T>>,
+    query: web::Query<AccountLookupQuery>,
+) -> Result<ApiResponse<AccountResponder>> {
+    info!("Lookup account handler called for acct: {}", query.acct);
+    let acct = query.acct.trim_start_matches('@');
+    let user_name = match acct.split_once('@') {
+        None => Some(acct),
+        Some((user_name, host)) => host
+            .eq_ignore_ascii_case(&app_data.federation.domain())
+            .then_some(user_name),
+    };
+    let profile = match user_name {
+        Some(user_name) => {
+            app_data
+                .db_repo
+                .query_profile_by_user(user_name.to_string())
+                .await?
+        },
+        None => None,
+    }
+    .ok_or_else(|| ServerSideError::ProfileNotFound(format!("No account found for {acct}")))?;
+
+    let id = profile.id;
+    let mut accounts = accounts(&app_data, vec![profile.into()]).await?;
+    Ok(ApiResponse::ok(accounts.remove(&id).unwrap()))
+}
+
+#[instrument(skip(app_data))]
+pub(crate) async fn get_account<T: Debug + QueryProfileFn + QueryProfileCountsFn>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<i64>,
+) -> Result<ApiResponse<AccountResponder>> {
+    let id = path.into_inner();
+    info!("Get account handler called for id: {}", id);
+    let profile = app_data.db_repo.query_profile(id).await?.ok_or_else(|| {
+        ServerSideError::ProfileNotFound(format!("No profile found with id: {id}"))
+    })?;
+
+    let mut accounts = accounts(&app_data, vec![profile.into()]).await?;
+    Ok(ApiResponse::ok(accounts.remove(&id).unwrap()))
+}
+
+#[instrument(skip(app_data))]
+pub(crate) async fn get_status<
+    T: Debug + QueryMessageFn + QueryProfilesFn + QueryProfileCountsFn + QueryResponseTargetsFn,
+>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<i64>,
+) -> Result<ApiResponse<StatusResponder>> {
+    let id = path.into_inner();
+    info!("Get status handler called for id: {}", id);
+    let message = app_data.db_repo.query_message(id).await?;
+
+    match statuses(&app_data, message.into_iter().collect())
+        .await?
+        .pop()
+    {
+        Some(status) => Ok(ApiResponse::ok(status)),
+        None => {
+            Err(ServerSideError::MessageNotFound(format!("No message found with id: {id}")).into())
+        },
+    }
+}
+
+#[instrument(skip(app_data, post))]
+pub(crate) async fn create_status<
+    T: Debug
+        + InsertMessageFn
+        + InsertResponseMessageFn
+        + QueryMessageFn
+        + QueryProfilesFn
+        + QueryProfileCountsFn
+        + QueryResponseTargetsFn,
+>(
+    app_data: web::Data<AppState<T>>,
+    post: Either<web::Json<StatusPostJson>, web::Form<StatusPostJson>>,
+) -> Result<ApiResponse<StatusResponder>> {
+    let post = match post {
+        Either::Left(json) => json.into_inner(),
+        Either::Right(form) => form.into_inner(),
+    };
+    let account_id = acting_account(post.account_id)?;
+    info!(
+        "Create status handler called for account_id: {}",
+        account_id
+    );
+    let body = post.status.trim();
+    if body.is_empty() || body.chars().count() > MAX_STATUS_CHARS {
+        return Err(ServerSideError::InvalidInput(format!(
+            "status must be 1 to {MAX_STATUS_CHARS} characters"
+        ))
+        .into());
+    }
+    let group_type = match post.visibility.as_deref().unwrap_or("public") {
+        "public" | "unlisted" => MessageGroupTypes::Public,
+        "private" => MessageGroupTypes::Circle,
+        other => {
+            return Err(ServerSideError::InvalidInput(format!(
+                "visibility {other} is not supported"
+            ))
+            .into());
+        },
+    } as i32;
+
+    let id = match post.in_reply_to_id.as_deref() {
+        Some(original) => {
+            let original_msg_id = original.parse::<i64>().map_err(|_| {
+                ServerSideError::InvalidInput(format!("in_reply_to_id {original} is not a status"))
+            })?;
+            app_data
+                .db_repo
+                .insert_response_message(account_id, body, group_type, original_msg_id)
+                .await?
+        },
+        None => {
+            app_data
+                .db_repo
+                .insert_message(account_id, body, group_type, None)
+                .await?
+        },
+    };
+    info!("Status created with id: {}", id);
+    metrics().message_created();
+    app_data.link_previews.prefetch_for(body);
+
+    let message = app_data.db_repo.query_message(id).await?;
+    match statuses(&app_data, message.into_iter().collect())
+        .await?
+        .pop()
+    {
+        Some(status) => Ok(ApiResponse::ok(status)),
+        None => {
+            Err(ServerSideError::MessageNotFound(format!("No message found with id: {id}")).into())
+        },
+    }
+}
+
+/// A page of `account_id`'s home timeline, newest first. The next page is linked from the
+/// `Link` header like Mastodon does, a `max_id` that is gone ends the timeline.
+#[instrument(skip(app_data, req))]
+pub(crate) async fn home_timeline<
+    T: Debug
+        + QueryMessageFn
+        + QueryMessagesFn
+        + QueryProfilesFn
+        + QueryProfileCountsFn
+        + QueryResponseTargetsFn,
+>(
+    app_data: web::Data<AppState<T>>,
+    req: HttpRequest,
+    query: web::Query<HomeTimelineQuery>,
+) -> Result<HttpResponse> {
+    let account_id = acting_account(query.account_id)?;
+    info!(
+        "Home timeline handler called for account_id: {}",
+        account_id
+    );
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_TIMELINE_LIMIT)
+        .clamp(1, MAX_TIMELINE_LIMIT);
+    let last_updated_at = match query.max_id {
+        Some(max_id) => match app_data.db_repo.query_message(max_id).await? {
+            Some(message) => message.updated_at,
+            None => return Ok(HttpResponse::Ok().json(Vec::<StatusResponder>::new())),
+        },
+        None => Utc::now(),
+    };
+
+    let messages = app_data
+        .db_repo
+        .query_messages(account_id, last_updated_at, limit)
+        .await?;
+    let next_max_id = messages
+        .last()
+        .filter(|_| messages.len() == usize::from(limit.unsigned_abs()))
+        .map(|last| last.id);
+    let statuses = statuses(&app_data, messages).await?;
+
+    let mut response = HttpResponse::Ok();
+    if let Some(max_id) = next_max_id {
+        let mut next = req.full_url();
+        next.query_pairs_mut()
+            .clear()
+            .append_pair("account_id", &account_id.to_string())
+            .append_pair("limit", &limit.to_string())
+            .append_pair("max_id", &max_id.to_string());
+        response.insert_header((header::LINK, format!("<{next}>; rel=\"next\"")));
+    }
+    Ok(response.json(statuses))
+}
+
+/// The account a request acts as. Mastodon clients identify it with an OAuth token, which this
+/// server doesn't issue, so a request without `account_id` is refused like one without a valid
+/// token.
+fn acting_account(account_id: Option<i64>) -> Result<i64> {
+    account_id.ok_or_else(|| {
+        ServerSideError::Unauthorized(
+            "account_id is required, access tokens are not supported".to_string(),
+        )
+        .into()
+    })
+}
+
+/// The accounts of `profiles` by profile id, with their counts.
+async fn accounts<T: Debug + QueryProfileCountsFn>(
+    app_data: &AppState<T>,
+    profiles: Vec<ProfileResponder>,
+) -> Result<HashMap<i64, AccountResponder>> {
+    let ids = profiles
+        .iter()
+        .map(|profile| profile.id)
+        .collect::<Vec<i64>>();
+    let counts = app_data
+        .db_repo
+        .query_profile_counts(&ids)
+        .await?
+        .into_iter()
+        .map(|counts| (counts.profile_id, counts))
+        .collect::<HashMap<i64, ProfileCountsQueryResult>>();
+
+    Ok(profiles
+        .into_iter()
+        .map(|profile| {
+            let counts = counts.get(&profile.id);
+            (
+                profile.id,
+                account(app_data.federation.urls(), profile, counts),
+            )
+        })
+        .collect())
+}
+
+/// Translates `messages` into statuses, with the messages they broadcast as reblogs and the
+/// messages they answer as `in_reply_to_id`. Messages whose author is gone are left out.
+async fn statuses<T: Debug + QueryProfilesFn + QueryProfileCountsFn + QueryResponseTargetsFn>(
+    app_data: &AppState<T>,
+    messages: Vec<MessageWithFollowingAndBroadcastQueryResult>,
+) -> Result<Vec<StatusResponder>> {
+    let group_types = messages
+        .iter()
+        .map(|message| message.msg_group_type)
+        .collect::<Vec<i32>>();
+    let published = messages
+        .iter()
+        .flat_map(|message| {
+            iter::once((message.id, message.published_at)).chain(
+                message
+                    .broadcast_msg_id
+                    .zip(message.broadcast_msg_published_at),
+            )
+        })
+        .collect::<HashMap<i64, DateTime<Utc>>>();
+    let messages = messages
+        .into_iter()
+        .map(MessageResponder::from)
+        .collect::<Vec<MessageResponder>>();
+    let shown = || {
+        messages
+            .iter()
+            .flat_map(|message| iter::once(message).chain(message.broadcasting_msg.as_deref()))
+    };
+
+    let message_ids = shown().map(|message| message.id).collect::<Vec<i64>>();
+    let replies = match message_ids.is_empty() {
+        true => HashMap::new(),
+        false => app_data
+            .db_repo
+            .query_response_targets(&message_ids)
+            .await?
+            .into_iter()
+            .map(|target| (target.responding_msg_id, target))
+            .collect(),
+    };
+
+    let mut author_ids = shown()
+        .map(|message| message.profile.id)
+        .collect::<Vec<i64>>();
+    author_ids.sort_unstable();
+    author_ids.dedup();
+    let profiles = match author_ids.is_empty() {
+        true => Vec::new(),
+        false => app_data.db_repo.query_profiles(&author_ids).await?,
+    };
+    let accounts = accounts(
+        app_data,
+        profiles.into_iter().map(ProfileResponder::from).collect(),
+    )
+    .await?;
+
+    let urls = app_data.federation.urls();
+    Ok(messages
+        .into_iter()
+        .zip(group_types)
+        .filter_map(|(message, group_type)| {
+            status(urls, &accounts, &replies, &published, message, group_type)
+        })
+        .collect())
+}
+
+fn status(
+    urls: &Urls,
+    accounts: &HashMap<i64, AccountResponder>,
+    replies: &HashMap<i64, ResponseTargetQueryResult>,
+    published: &HashMap<i64, DateTime<Utc>>,
+    message: MessageResponder,
+    group_type: i32,
+) -> Option<StatusResponder> {
+    let account = accounts.get(&message.profile.id)?.clone();
+    let reply = replies.get(&message.id);
+    // The group of a broadcast message isn't loaded, only public ones can be broadcast.
+    let reblog = match message.broadcasting_msg {
+        Some(broadcast) => Some(Box::new(status(
+            urls,
+            accounts,
+            replies,
+            published,
+            *broadcast,
+            MessageGroupTypes::Public as i32,
+        )?)),
+        None => None,
+    };
+    let visibility = match group_type == MessageGroupTypes::Circle as i32 {
+        true => "private",
+        false => "public",
+    };
+
+    Some(StatusResponder {
+        id: message.id.to_string(),
+        // `updated_at` orders the timelines, a status is created when its message is published.
+        created_at: published
+            .get(&message.id)
+            .copied()
+            .unwrap_or(message.updated_at),
+        in_reply_to_id: reply.map(|reply| reply.original_msg_id.to_string()),
+        in_reply_to_account_id: reply.map(|reply| reply.original_user_id.to_string()),
+        sensitive: false,
+        spoiler_text: String::new(),
+        visibility: visibility.to_string(),
+        language: None,
+        uri: urls.message(message.id),
+        url: Some(urls.message(message.id)),
+        replies_count: 0,
+        reblogs_count: 0,
+        favourites_count: i64::from(message.likes),
+        content: html(message.body.as_deref().unwrap_or_default()),
+        reblog,
+        account,
+        media_attachments: vec![],
+        mentions: vec![],
+        tags: vec![],
+        emojis: vec![],
+        card: None,
+        poll: None,
+    })
+}
+
+fn account(
+    urls: &Urls,
+    profile: ProfileResponder,
+    counts: Option<&ProfileCountsQueryResult>,
+) -> AccountResponder {
+    let avatar = match profile.avatar {
+        Some(_) => urls.avatar(profile.id),
+        None => String::new(),
+    };
+    AccountResponder {
+        id: profile.id.to_string(),
+        url: urls.actor(&profile.user_name),
+        acct: profile.user_name.clone(),
+        username: profile.user_name,
+        display_name: profile.full_name,
+        locked: false,
+        bot: false,
+        created_at: profile.created_at,
+        note: html(&profile.description),
+        avatar_static: avatar.clone(),
+        avatar,
+        header: String::new(),
+        header_static: String::new(),
+        followers_count: counts.map_or(0, |counts| counts.followers),
+        following_count: counts.map_or(0, |counts| counts.following),
+        statuses_count: counts.map_or(0, |counts| counts.messages),
+        emojis: vec![],
+        fields: vec![],
+    }
+}
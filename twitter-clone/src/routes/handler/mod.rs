@@ -1,2 +1,8 @@
+pub mod admin_handlers;
+pub mod draft_handlers;
+pub mod federation_handlers;
+pub mod feed_handlers;
+pub mod mastodon_handlers;
 pub mod msg_handlers;
-pub mod profile_handlers;
\ No newline at end of file
+pub mod profile_handlers;
+pub mod webhook_handlers;
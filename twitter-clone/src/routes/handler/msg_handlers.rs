@@ -1,21 +1,43 @@
-use crate::common::entities::base::DbRepo;
-use crate::common::entities::messages::model::MessageWithFollowingAndBroadcastQueryResult;
+use crate::common::entities::messages::model::{
+    MessageWithFollowingAndBroadcastQueryResult, ScheduledMessageQueryResult,
+};
+use crate::common::entities::polls::{
+    model::{PollCreate, PollOptionQueryResult},
+    repo::{QueryPollsFn, VotePollFn},
+};
 use crate::error::{Result, ServerSideError};
-use crate::schemas::message::{MessageByFollowingQuery, MessageResponder, MessageResponders};
+use crate::link_preview::LinkPreviews;
+use crate::metrics::metrics;
+use crate::schemas::message::{
+    MessageByFollowingQuery, MessageRescheduleJson, MessageResponder, MessageResponders,
+    MessageViewerQuery, PollJson, PollOptionResponder, PollResponder, PollVoteJson,
+    ScheduledMessageResponder, ScheduledMessageResponders, ScheduledMessagesQuery,
+};
 use crate::schemas::profile::ProfileShort;
 use crate::{
     api_response::ApiResponse,
     app_state::AppState,
-    common::entities::messages::repo::{InsertMessageFn, QueryMessageFn, QueryMessagesFn},
+    common::entities::messages::repo::{
+        CancelScheduledMessageFn, InsertMessageFn, InsertPollMessageFn, QueryMessageFn,
+        QueryMessagesFn, QueryScheduledMessagesFn, RescheduleMessageFn, ScheduleMessageFn,
+    },
     schemas::message::MessagePostJson,
 };
 use actix_web::web;
+use chrono::{DateTime, Utc};
 use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::iter;
 use tracing::{info, instrument};
 
-#[instrument(skip(app_data))]
-pub(crate) async fn create_message<T: Debug + InsertMessageFn>(
+/// Same limit as the `poll_option.label` column.
+const MAX_POLL_OPTION_CHARS: usize = 25;
+
+#[instrument(skip(app_data, msg), fields(user_id = msg.user_id))]
+pub(crate) async fn create_message<
+    T: Debug + InsertMessageFn + ScheduleMessageFn + InsertPollMessageFn,
+>(
     app_data: web::Data<AppState<T>>,
     msg: web::Json<MessagePostJson>,
 ) -> Result<ApiResponse<Value>> {
@@ -25,11 +47,61 @@ pub(crate) async fn create_message<T: Debug + InsertMessageFn>(
 
     let group_type = msg.group_type.clone() as i32;
 
+    if let Some(poll) = msg.poll.clone() {
+        if msg.publish_at.is_some() {
+            return Err(ServerSideError::InvalidInput(
+                "A scheduled message can't carry a poll".to_string(),
+            )
+            .into());
+        }
+        let result = app_data
+            .db_repo
+            .insert_poll_message(
+                msg.user_id,
+                body,
+                group_type,
+                msg.broadcasting_msg_id,
+                PollCreate::try_from(poll)?,
+            )
+            .await?;
+        info!("Message created with id: {} and a poll", result);
+        metrics().message_created();
+        app_data.link_previews.prefetch_for(body);
+        return Ok(ApiResponse::created(json!({
+            "message": "Message created successfully",
+            "message_id": result
+        })));
+    }
+
+    if let Some(publish_at) = msg.publish_at {
+        ensure_future(publish_at)?;
+        let result = app_data
+            .db_repo
+            .schedule_message(
+                msg.user_id,
+                body,
+                group_type,
+                msg.broadcasting_msg_id,
+                publish_at,
+            )
+            .await?;
+        info!("Message scheduled with id: {} for {}", result, publish_at);
+        metrics().message_created();
+        app_data.link_previews.prefetch_for(body);
+        return Ok(ApiResponse::created(json!({
+            "message": "Message scheduled successfully",
+            "message_id": result,
+            "publish_at": publish_at
+        })));
+    }
+
     let result = app_data
         .db_repo
         .insert_message(msg.user_id, body, group_type, msg.broadcasting_msg_id)
         .await?;
     info!("Message created with id: {}", result);
+    metrics().message_created();
+    app_data.link_previews.prefetch_for(body);
     Ok(ApiResponse::created(json!({
         "message": "Message created successfully",
         "message_id": result
@@ -37,9 +109,83 @@ pub(crate) async fn create_message<T: Debug + InsertMessageFn>(
 }
 
 #[instrument(skip(app_data))]
-pub(crate) async fn get_message<T: Debug + QueryMessageFn>(
-    app_data: web::Data<AppState<DbRepo>>,
+pub(crate) async fn get_scheduled_messages<T: Debug + QueryScheduledMessagesFn>(
+    app_data: web::Data<AppState<T>>,
+    query: web::Query<ScheduledMessagesQuery>,
+) -> Result<ApiResponse<ScheduledMessageResponders>> {
+    info!(
+        "Get scheduled messages handler called for user_id: {}",
+        query.user_id
+    );
+    let messages = app_data
+        .db_repo
+        .query_scheduled_messages(query.user_id)
+        .await?;
+
+    Ok(ApiResponse::ok(ScheduledMessageResponders(
+        messages
+            .into_iter()
+            .map(ScheduledMessageResponder::from)
+            .collect(),
+    )))
+}
+
+#[instrument(skip(app_data))]
+pub(crate) async fn reschedule_message<T: Debug + RescheduleMessageFn>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<i64>,
+    json: web::Json<MessageRescheduleJson>,
+) -> Result<ApiResponse<Value>> {
+    let message_id = path.into_inner();
+    info!("Reschedule message handler called for id: {}", message_id);
+    ensure_future(json.publish_at)?;
+
+    app_data
+        .db_repo
+        .reschedule_message(message_id, json.publish_at)
+        .await?;
+    Ok(ApiResponse::ok(json!({
+        "message": "Message rescheduled successfully",
+        "message_id": message_id,
+        "publish_at": json.publish_at
+    })))
+}
+
+#[instrument(skip(app_data))]
+pub(crate) async fn cancel_scheduled_message<T: Debug + CancelScheduledMessageFn>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<i64>,
+) -> Result<ApiResponse<Value>> {
+    let message_id = path.into_inner();
+    info!(
+        "Cancel scheduled message handler called for id: {}",
+        message_id
+    );
+
+    app_data
+        .db_repo
+        .cancel_scheduled_message(message_id)
+        .await?;
+    Ok(ApiResponse::ok(json!({
+        "message": "Scheduled message cancelled",
+        "message_id": message_id
+    })))
+}
+
+fn ensure_future(publish_at: DateTime<Utc>) -> Result<()> {
+    if publish_at <= Utc::now() {
+        return Err(
+            ServerSideError::InvalidInput("publishAt must be in the future".to_string()).into(),
+        );
+    }
+    Ok(())
+}
+
+#[instrument(skip(app_data))]
+pub(crate) async fn get_message<T: Debug + QueryMessageFn + QueryPollsFn>(
+    app_data: web::Data<AppState<T>>,
     path: web::Path<i64>,
+    query: web::Query<MessageViewerQuery>,
 ) -> Result<ApiResponse<MessageResponder>> {
     info!("Get message handler called for id: {}", path);
     let message_id = path.into_inner();
@@ -50,11 +196,16 @@ pub(crate) async fn get_message<T: Debug + QueryMessageFn>(
         ))
         .into());
     }
-    Ok(ApiResponse::ok(message.unwrap().into()))
+
+    let mut messages = [MessageResponder::from(message.unwrap())];
+    attach_polls(&app_data.db_repo, &mut messages, query.viewer_id).await?;
+    attach_link_previews(&app_data.link_previews, &mut messages);
+    let [message] = messages;
+    Ok(ApiResponse::ok(message))
 }
 
 #[instrument(skip(app_data))]
-pub(crate) async fn get_messages<T: Debug + QueryMessagesFn>(
+pub(crate) async fn get_messages<T: Debug + QueryMessagesFn + QueryPollsFn>(
     app_data: web::Data<AppState<T>>,
     path: web::Json<MessageByFollowingQuery>,
 ) -> Result<ApiResponse<MessageResponders>> {
@@ -71,12 +222,160 @@ pub(crate) async fn get_messages<T: Debug + QueryMessagesFn>(
         .await?;
     info!("Fetched {} messages", messages.len());
 
-    let msg_collection: Vec<MessageResponder> =
+    let mut msg_collection: Vec<MessageResponder> =
         messages.into_iter().map(MessageResponder::from).collect();
+    attach_polls(
+        &app_data.db_repo,
+        &mut msg_collection,
+        Some(path.follower_id),
+    )
+    .await?;
+    attach_link_previews(&app_data.link_previews, &mut msg_collection);
 
     Ok(ApiResponse::ok(MessageResponders(msg_collection)))
 }
 
+#[instrument(skip(app_data))]
+pub(crate) async fn vote_poll<T: Debug + VotePollFn + QueryPollsFn>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<i64>,
+    json: web::Json<PollVoteJson>,
+) -> Result<ApiResponse<PollResponder>> {
+    let message_id = path.into_inner();
+    info!("Vote poll handler called for message id: {}", message_id);
+
+    app_data
+        .db_repo
+        .vote_poll(message_id, json.user_id, json.option_id)
+        .await?;
+    // The voter gets to see the results right away.
+    let mut polls = poll_responders(
+        app_data
+            .db_repo
+            .query_polls(&[message_id], Some(json.user_id))
+            .await?,
+    );
+    match polls.remove(&message_id) {
+        Some(poll) => Ok(ApiResponse::created(poll)),
+        None => Err(ServerSideError::PollNotFound(format!(
+            "No poll found on message {message_id}"
+        ))
+        .into()),
+    }
+}
+
+/// Fills in the polls of `messages`, and of the messages they broadcast, as `viewer_id` sees them.
+async fn attach_polls<T: QueryPollsFn>(
+    repo: &T,
+    messages: &mut [MessageResponder],
+    viewer_id: Option<i64>,
+) -> Result<()> {
+    let message_ids = messages
+        .iter()
+        .flat_map(|message| {
+            iter::once(message.id).chain(message.broadcasting_msg.as_ref().map(|bm| bm.id))
+        })
+        .collect::<Vec<i64>>();
+    if message_ids.is_empty() {
+        return Ok(());
+    }
+
+    let polls = poll_responders(repo.query_polls(&message_ids, viewer_id).await?);
+    for message in messages {
+        message.poll = polls.get(&message.id).cloned();
+        if let Some(bm) = message.broadcasting_msg.as_mut() {
+            bm.poll = polls.get(&bm.id).cloned();
+        }
+    }
+    Ok(())
+}
+
+/// Fills in the cached previews of the first link of `messages` and of the messages they
+/// broadcast. Links not cached yet are fetched for later reads.
+fn attach_link_previews(previews: &LinkPreviews, messages: &mut [MessageResponder]) {
+    for message in messages {
+        message.link_preview = message
+            .body
+            .as_deref()
+            .and_then(|body| previews.preview_for(body));
+        if let Some(bm) = message.broadcasting_msg.as_mut() {
+            bm.link_preview = bm
+                .body
+                .as_deref()
+                .and_then(|body| previews.preview_for(body));
+        }
+    }
+}
+
+/// Groups the option rows by message. Counts stay hidden from viewers who haven't voted until
+/// the poll closes, so the results can't sway their vote.
+fn poll_responders(options: Vec<PollOptionQueryResult>) -> HashMap<i64, PollResponder> {
+    let now = Utc::now();
+    let mut polls = HashMap::<i64, PollResponder>::new();
+    for option in options {
+        let poll = polls
+            .entry(option.message_id)
+            .or_insert_with(|| PollResponder {
+                id: option.poll_id,
+                closes_at: option.closes_at,
+                closed: option.closes_at <= now,
+                voted_option_id: None,
+                total_votes: None,
+                options: vec![],
+            });
+        if option.viewer_voted {
+            poll.voted_option_id = Some(option.option_id);
+        }
+        poll.options.push(PollOptionResponder {
+            id: option.option_id,
+            label: option.label,
+            votes: Some(option.votes),
+        });
+    }
+
+    for poll in polls.values_mut() {
+        if poll.closed || poll.voted_option_id.is_some() {
+            poll.total_votes = Some(poll.options.iter().filter_map(|option| option.votes).sum());
+        } else {
+            poll.options
+                .iter_mut()
+                .for_each(|option| option.votes = None);
+        }
+    }
+    polls
+}
+
+impl TryFrom<PollJson> for PollCreate {
+    type Error = ServerSideError;
+
+    fn try_from(json: PollJson) -> std::result::Result<Self, Self::Error> {
+        if !(2..=4).contains(&json.options.len()) {
+            return Err(ServerSideError::InvalidInput(
+                "A poll needs 2 to 4 options".to_string(),
+            ));
+        }
+        if json
+            .options
+            .iter()
+            .any(|label| label.trim().is_empty() || label.chars().count() > MAX_POLL_OPTION_CHARS)
+        {
+            return Err(ServerSideError::InvalidInput(format!(
+                "Poll options must be 1 to {MAX_POLL_OPTION_CHARS} characters"
+            )));
+        }
+        if json.closes_at <= Utc::now() {
+            return Err(ServerSideError::InvalidInput(
+                "closesAt must be in the future".to_string(),
+            ));
+        }
+
+        Ok(PollCreate {
+            options: json.options,
+            closes_at: json.closes_at,
+        })
+    }
+}
+
 impl From<MessageWithFollowingAndBroadcastQueryResult> for MessageResponder {
     fn from(message: MessageWithFollowingAndBroadcastQueryResult) -> Self {
         MessageResponder {
@@ -91,6 +390,8 @@ impl From<MessageWithFollowingAndBroadcastQueryResult> for MessageResponder {
                     body: message.broadcast_msg_body.clone(),
                     likes: message.broadcast_msg_likes.unwrap(),
                     broadcasting_msg: None,
+                    poll: None,
+                    link_preview: None,
                     profile: ProfileShort {
                         id: message.broadcast_msg_user_id.unwrap(),
                         user_name: message.broadcast_msg_user_name.clone().unwrap(),
@@ -100,10 +401,26 @@ impl From<MessageWithFollowingAndBroadcastQueryResult> for MessageResponder {
                 None => None,
             },
             profile: ProfileShort {
-                id: message.id,
+                id: message.user_id,
                 user_name: message.user_name.clone(),
                 full_name: message.full_name.clone(),
             },
+            poll: None,
+            link_preview: None,
+        }
+    }
+}
+
+impl From<ScheduledMessageQueryResult> for ScheduledMessageResponder {
+    fn from(message: ScheduledMessageQueryResult) -> Self {
+        ScheduledMessageResponder {
+            id: message.id,
+            created_at: message.created_at,
+            user_id: message.user_id,
+            body: message.body,
+            group_type: message.msg_group_type,
+            broadcasting_msg_id: message.broadcast_msg_id,
+            publish_at: message.publish_at,
         }
     }
 }
@@ -114,6 +431,7 @@ mod tests {
     use super::*;
     use crate::common::entities::messages::repo::InsertMessageFn;
     use crate::common_tests::get_app_data;
+    use crate::error::ClientSideError;
     use crate::schemas::message::MessageGroupTypes;
     use std::fmt::Debug;
 
@@ -135,6 +453,34 @@ mod tests {
             }
         }
 
+        #[async_trait::async_trait]
+        impl ScheduleMessageFn for MockRepo {
+            async fn schedule_message(
+                &self,
+                user_id: i64,
+                body: &str,
+                group_type: i32,
+                broadcasting_msg_id: Option<i64>,
+                publish_at: DateTime<Utc>,
+            ) -> Result<i64> {
+                Ok(43)
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl InsertPollMessageFn for MockRepo {
+            async fn insert_poll_message(
+                &self,
+                user_id: i64,
+                body: &str,
+                group_type: i32,
+                broadcasting_msg_id: Option<i64>,
+                poll: PollCreate,
+            ) -> Result<i64> {
+                Ok(44)
+            }
+        }
+
         #[tokio::test]
         async fn test_create_message_normal_body() {
             let repo = MockRepo;
@@ -144,10 +490,91 @@ mod tests {
                 body: "Hello, world!".to_string(),
                 group_type: MessageGroupTypes::Public,
                 broadcasting_msg_id: None,
+                publish_at: None,
+                poll: None,
             };
             let result = create_message(app_data, web::Json(msg)).await.unwrap();
-            assert_eq!(result.data, 42);
+            assert_eq!(result.data["message_id"], 42);
         }
+
+        #[tokio::test]
+        async fn test_create_message_with_publish_at() {
+            let app_data = get_app_data(MockRepo).await;
+            let msg = MessagePostJson {
+                user_id: 1,
+                body: "Later".to_string(),
+                group_type: MessageGroupTypes::Public,
+                broadcasting_msg_id: None,
+                publish_at: Some(Utc::now() + chrono::TimeDelta::hours(1)),
+                poll: None,
+            };
+            let result = create_message(app_data.clone(), web::Json(msg.clone()))
+                .await
+                .unwrap();
+            assert_eq!(result.data["message_id"], 43);
+
+            let past = MessagePostJson {
+                publish_at: Some(Utc::now() - chrono::TimeDelta::hours(1)),
+                ..msg
+            };
+            assert!(matches!(
+                create_message(app_data, web::Json(past)).await,
+                Err(ClientSideError::BadRequest(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_poll_results_hidden_until_voted_or_closed() {
+        let closes_at = Utc::now() + chrono::TimeDelta::hours(1);
+        let option = |message_id, option_id, votes, viewer_voted| PollOptionQueryResult {
+            poll_id: message_id * 10,
+            message_id,
+            closes_at,
+            option_id,
+            position: option_id as i32,
+            label: format!("option {option_id}"),
+            votes,
+            viewer_voted,
+        };
+
+        let polls = poll_responders(vec![
+            option(1, 1, 3, false),
+            option(1, 2, 1, false),
+            option(2, 3, 2, true),
+            option(2, 4, 5, false),
+        ]);
+        let hidden = &polls[&1];
+        assert_eq!(hidden.total_votes, None);
+        assert!(hidden.options.iter().all(|option| option.votes.is_none()));
+        let voted = &polls[&2];
+        assert_eq!(voted.voted_option_id, Some(3));
+        assert_eq!(voted.total_votes, Some(7));
+
+        let closed = poll_responders(vec![PollOptionQueryResult {
+            closes_at: Utc::now() - chrono::TimeDelta::seconds(1),
+            ..option(1, 1, 3, false)
+        }]);
+        assert!(closed[&1].closed);
+        assert_eq!(closed[&1].options[0].votes, Some(3));
+    }
+
+    #[test]
+    fn test_poll_json_is_validated() {
+        let json = |options: &[&str]| PollJson {
+            options: options.iter().map(|label| label.to_string()).collect(),
+            closes_at: Utc::now() + chrono::TimeDelta::hours(1),
+        };
+        assert!(PollCreate::try_from(json(&["yes", "no"])).is_ok());
+        assert!(PollCreate::try_from(json(&["alone"])).is_err());
+        assert!(PollCreate::try_from(json(&["a", "b", "c", "d", "e"])).is_err());
+        assert!(PollCreate::try_from(json(&["yes", " "])).is_err());
+        assert!(PollCreate::try_from(json(&["yes", &"n".repeat(26)])).is_err());
+        let closed = PollJson {
+            closes_at: Utc::now(),
+            ..json(&["yes", "no"])
+        };
+        assert!(PollCreate::try_from(closed).is_err());
     }
 
     // /// Create failure returns correct error
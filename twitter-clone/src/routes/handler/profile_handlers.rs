@@ -1,13 +1,15 @@
 use crate::common::entities::profile::model::{ProfileCreate, ProfileQueryResult};
 use crate::common::entities::profile::repo::{InsertProfileFn, QueryProfileByUserFn};
 use crate::error::{Result, ServerSideError};
+use crate::link_preview::LinkPreviews;
+use crate::metrics::metrics;
 use crate::schemas::profile::ProfileCreateMultipart;
 use crate::{
     api_response::ApiResponse, app_state::AppState,
     common::entities::profile::repo::QueryProfileFn, schemas::profile::ProfileResponder,
 };
 use actix_multipart::form::MultipartForm;
-use actix_web::web;
+use actix_web::{web, HttpResponse};
 use serde_json::{json, Value};
 use std::fmt::Debug;
 use std::io::Read;
@@ -20,10 +22,13 @@ pub(crate) async fn create_profile<T: Debug + InsertProfileFn>(
 ) -> Result<ApiResponse<Value>> {
     info!("Create profile handler called");
 
-    let result = app_data
-        .db_repo
-        .insert_profile(profile.into_inner().try_into()?)
-        .await?;
+    let profile = ProfileCreate::try_from(profile.into_inner())?;
+    let main_url = profile.main_url.clone();
+    let result = app_data.db_repo.insert_profile(profile).await?;
+    metrics().profile_created();
+    if let Some(main_url) = main_url {
+        app_data.link_previews.prefetch_for(&main_url);
+    }
 
     Ok(ApiResponse::created(json!({
         "message": "Profile created successfully",
@@ -41,7 +46,10 @@ pub(crate) async fn get_profile<T: Debug + QueryProfileFn>(
 
     let profile = app_data.db_repo.query_profile(profile_id).await?;
     match profile {
-        Some(profile) => Ok(ApiResponse::ok(profile.into())),
+        Some(profile) => Ok(ApiResponse::ok(with_main_url_preview(
+            &app_data.link_previews,
+            profile.into(),
+        ))),
         None => Err(ServerSideError::ProfileNotFound(format!(
             "No profile found with id: {profile_id}"
         ))
@@ -49,6 +57,40 @@ pub(crate) async fn get_profile<T: Debug + QueryProfileFn>(
     }
 }
 
+#[instrument(skip(app_data))]
+pub(crate) async fn get_profile_avatar<T: Debug + QueryProfileFn>(
+    app_data: web::Data<AppState<T>>,
+    path: web::Path<i64>,
+) -> Result<HttpResponse> {
+    info!("Get profile avatar handler called for id: {}", path);
+    let profile_id = path.into_inner();
+
+    let avatar = app_data
+        .db_repo
+        .query_profile(profile_id)
+        .await?
+        .and_then(|profile| profile.avatar)
+        .ok_or_else(|| {
+            ServerSideError::ProfileNotFound(format!(
+                "No avatar found for profile id: {profile_id}"
+            ))
+        })?;
+    Ok(HttpResponse::Ok()
+        .content_type(image_type(&avatar))
+        .body(avatar))
+}
+
+/// Avatars are stored without their type, it is told from their first bytes.
+fn image_type(image: &[u8]) -> &'static str {
+    match image {
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [0xff, 0xd8, 0xff, ..] => "image/jpeg",
+        [b'G', b'I', b'F', b'8', ..] => "image/gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
 pub(crate) async fn get_profile_by_user_name<T: Debug + QueryProfileByUserFn>(
     app_data: web::Data<AppState<T>>,
     path: web::Path<String>,
@@ -69,7 +111,21 @@ pub(crate) async fn get_profile_by_user_name<T: Debug + QueryProfileByUserFn>(
             "No profile found with user_name: {username}"
         )))?;
 
-    Ok(ApiResponse::ok(profile))
+    Ok(ApiResponse::ok(with_main_url_preview(
+        &app_data.link_previews,
+        profile,
+    )))
+}
+
+fn with_main_url_preview(
+    previews: &LinkPreviews,
+    mut profile: ProfileResponder,
+) -> ProfileResponder {
+    profile.main_url_preview = profile
+        .main_url
+        .as_deref()
+        .and_then(|main_url| previews.preview_for(main_url));
+    profile
 }
 
 impl From<ProfileQueryResult> for ProfileResponder {
@@ -83,6 +139,7 @@ impl From<ProfileQueryResult> for ProfileResponder {
             region: item.region,
             main_url: item.main_url,
             avatar: item.avatar,
+            main_url_preview: None,
         }
     }
 }
@@ -94,14 +151,8 @@ impl TryFrom<ProfileCreateMultipart> for ProfileCreate {
             user_name: value.user_name.to_string(),
             full_name: value.full_name.to_string(),
             description: value.description.to_string(),
-            region: match value.region {
-                Some(region) => Some(region.to_string()),
-                None => None,
-            },
-            main_url: match value.main_url {
-                Some(url) => Some(url.to_string()),
-                None => None,
-            },
+            region: value.region.map(|region| region.to_string()),
+            main_url: value.main_url.map(|url| url.to_string()),
             avatar: match value.avatar {
                 Some(avatar) => {
                     let mut buffer: Vec<u8> = Vec::new();
@@ -0,0 +1,276 @@
+use crate::common::entities::webhooks::model::{
+    WebhookAttemptQueryResult, WebhookCreate, WebhookDeliveryQueryResult, WebhookQueryResult,
+};
+use crate::error::{Result, ServerSideError};
+use crate::schemas::webhook::{
+    WebhookAttemptResponder, WebhookDeliveriesQuery, WebhookDeliveryResponder,
+    WebhookDeliveryResponders, WebhookJson, WebhookResponder, WebhookResponders,
+};
+use crate::{
+    api_response::ApiResponse,
+    app_state::AppState,
+    common::entities::webhooks::repo::{
+        DeleteWebhookFn, InsertWebhookFn, QueryWebhookDeliveriesFn, QueryWebhooksFn,
+        RedeliverWebhookFn,
+    },
+};
+use actix_web::{http::StatusCode, web, HttpRequest};
+use rand::Rng;
+use reqwest::Url;
+use serde_json::{json, Value};
+use std::fmt::Debug;
+use tracing::{info, instrument};
+
+/// Same limits as the `webhook.url` and `webhook.secret` columns.
+const MAX_URL_CHARS: usize = 2048;
+const MAX_SECRET_CHARS: usize = 200;
+/// Shorter secrets are too easy to guess for the signatures to mean anything.
+const MIN_SECRET_CHARS: usize = 16;
+const DEFAULT_DELIVERIES_LIMIT: i64 = 20;
+const MAX_DELIVERIES_LIMIT: i64 = 100;
+
+#[instrument(skip(app_data, req, json))]
+pub(crate) async fn create_webhook<T: Debug + InsertWebhookFn>(
+    app_data: web::Data<AppState<T>>,
+    req: HttpRequest,
+    json: web::Json<WebhookJson>,
+) -> Result<ApiResponse<Value>> {
+    app_data.admin.authorize(&req)?;
+    info!("Create webhook handler called");
+    let webhook = WebhookCreate::try_from(json.into_inner())?;
+    let secret = webhook.secret.clone();
+
+    let result = app_data.db_repo.insert_webhook(webhook).await?;
+    info!("Webhook created with id: {}", result);
+    Ok(ApiResponse::created(json!({
+        "message": "Webhook created successfully",
+        "webhook_id": result,
+        "secret": secret
+    })))
+}
+
+#[instrument(skip(app_data, req))]
+pub(crate) async fn get_webhooks<T: Debug + QueryWebhooksFn>(
+    app_data: web::Data<AppState<T>>,
+    req: HttpRequest,
+) -> Result<ApiResponse<WebhookResponders>> {
+    app_data.admin.authorize(&req)?;
+    info!("Get webhooks handler called");
+    let webhooks = app_data.db_repo.query_webhooks().await?;
+
+    Ok(ApiResponse::ok(WebhookResponders(
+        webhooks.into_iter().map(WebhookResponder::from).collect(),
+    )))
+}
+
+#[instrument(skip(app_data, req))]
+pub(crate) async fn delete_webhook<T: Debug + DeleteWebhookFn>(
+    app_data: web::Data<AppState<T>>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> Result<ApiResponse<Value>> {
+    app_data.admin.authorize(&req)?;
+    let id = path.into_inner();
+    info!("Delete webhook handler called for id: {}", id);
+
+    app_data.db_repo.delete_webhook(id).await?;
+    Ok(ApiResponse::ok(json!({
+        "message": "Webhook deleted",
+        "webhook_id": id
+    })))
+}
+
+#[instrument(skip(app_data, req))]
+pub(crate) async fn get_webhook_deliveries<T: Debug + QueryWebhookDeliveriesFn>(
+    app_data: web::Data<AppState<T>>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    query: web::Query<WebhookDeliveriesQuery>,
+) -> Result<ApiResponse<WebhookDeliveryResponders>> {
+    app_data.admin.authorize(&req)?;
+    let id = path.into_inner();
+    info!("Get webhook deliveries handler called for id: {}", id);
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_DELIVERIES_LIMIT)
+        .clamp(1, MAX_DELIVERIES_LIMIT);
+
+    let deliveries = app_data.db_repo.query_webhook_deliveries(id, limit).await?;
+    Ok(ApiResponse::ok(WebhookDeliveryResponders(
+        deliveries
+            .into_iter()
+            .map(WebhookDeliveryResponder::from)
+            .collect(),
+    )))
+}
+
+#[instrument(skip(app_data, req))]
+pub(crate) async fn redeliver_webhook<T: Debug + RedeliverWebhookFn>(
+    app_data: web::Data<AppState<T>>,
+    req: HttpRequest,
+    path: web::Path<(i64, i64)>,
+) -> Result<ApiResponse<Value>> {
+    app_data.admin.authorize(&req)?;
+    let (id, delivery_id) = path.into_inner();
+    info!(
+        "Redeliver webhook handler called for delivery: {}",
+        delivery_id
+    );
+
+    let job_id = app_data.db_repo.redeliver_webhook(id, delivery_id).await?;
+    Ok(ApiResponse::new(
+        StatusCode::ACCEPTED,
+        json!({
+            "message": "Webhook delivery queued",
+            "delivery_id": delivery_id,
+            "job_id": job_id
+        }),
+    ))
+}
+
+impl TryFrom<WebhookJson> for WebhookCreate {
+    type Error = ServerSideError;
+
+    fn try_from(json: WebhookJson) -> std::result::Result<Self, Self::Error> {
+        if json.url.chars().count() > MAX_URL_CHARS {
+            return Err(ServerSideError::InvalidInput(format!(
+                "url must be at most {MAX_URL_CHARS} characters"
+            )));
+        }
+        let url = Url::parse(&json.url)
+            .map_err(|err| ServerSideError::InvalidInput(format!("url is invalid: {err}")))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(ServerSideError::InvalidInput(
+                "url must be http or https".to_string(),
+            ));
+        }
+
+        let mut unique = Vec::with_capacity(json.events.len());
+        for event in json.events {
+            if !unique.contains(&event) {
+                unique.push(event);
+            }
+        }
+        if unique.is_empty() {
+            return Err(ServerSideError::InvalidInput(
+                "events must name at least one event".to_string(),
+            ));
+        }
+
+        let secret = match json.secret {
+            Some(secret) => {
+                let chars = secret.chars().count();
+                if !(MIN_SECRET_CHARS..=MAX_SECRET_CHARS).contains(&chars) {
+                    return Err(ServerSideError::InvalidInput(format!(
+                        "secret must be {MIN_SECRET_CHARS} to {MAX_SECRET_CHARS} characters"
+                    )));
+                }
+                secret
+            },
+            None => hex::encode(rand::rng().random::<[u8; 32]>()),
+        };
+
+        Ok(WebhookCreate {
+            url: url.to_string(),
+            secret,
+            events: unique,
+        })
+    }
+}
+
+impl From<WebhookQueryResult> for WebhookResponder {
+    fn from(webhook: WebhookQueryResult) -> Self {
+        WebhookResponder {
+            id: webhook.id,
+            created_at: webhook.created_at,
+            updated_at: webhook.updated_at,
+            url: webhook.url,
+            events: webhook.events,
+        }
+    }
+}
+
+impl From<WebhookDeliveryQueryResult> for WebhookDeliveryResponder {
+    fn from(delivery: WebhookDeliveryQueryResult) -> Self {
+        WebhookDeliveryResponder {
+            id: delivery.id,
+            created_at: delivery.created_at,
+            updated_at: delivery.updated_at,
+            event: delivery.event,
+            payload: delivery.payload,
+            status: delivery.status,
+            attempts: delivery
+                .log
+                .0
+                .into_iter()
+                .map(WebhookAttemptResponder::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<WebhookAttemptQueryResult> for WebhookAttemptResponder {
+    fn from(attempt: WebhookAttemptQueryResult) -> Self {
+        WebhookAttemptResponder {
+            created_at: attempt.created_at,
+            status_code: attempt.status_code,
+            error: attempt.error,
+            response_body: attempt.response_body,
+            duration_ms: attempt.duration_ms,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::entities::webhooks::model::WebhookEvent;
+
+    fn json(url: &str, secret: Option<&str>) -> WebhookJson {
+        WebhookJson {
+            url: url.to_string(),
+            secret: secret.map(str::to_string),
+            events: vec![
+                WebhookEvent::MessageCreated,
+                WebhookEvent::ProfileFollowed,
+                WebhookEvent::MessageCreated,
+            ],
+        }
+    }
+
+    #[test]
+    fn test_webhook_json_is_validated() {
+        let webhook =
+            WebhookCreate::try_from(json("https://example.com/hook", Some("0123456789abcdef")))
+                .unwrap();
+        assert_eq!(
+            webhook.events,
+            vec![WebhookEvent::MessageCreated, WebhookEvent::ProfileFollowed]
+        );
+        assert_eq!(webhook.secret, "0123456789abcdef");
+
+        let generated = WebhookCreate::try_from(json("http://example.com/", None)).unwrap();
+        assert_eq!(generated.secret.len(), 64);
+
+        for invalid in [
+            json("ftp://example.com/", None),
+            json("not a url", None),
+            json("https://example.com/", Some("short")),
+            WebhookJson {
+                events: Vec::new(),
+                ..json("https://example.com/", None)
+            },
+        ] {
+            assert!(matches!(
+                WebhookCreate::try_from(invalid),
+                Err(ServerSideError::InvalidInput(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_unknown_event_is_rejected() {
+        let body = r#"{"url": "https://example.com/", "events": ["message.deleted"]}"#;
+        assert!(serde_json::from_str::<WebhookJson>(body).is_err());
+    }
+}
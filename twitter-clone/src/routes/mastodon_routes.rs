@@ -0,0 +1,27 @@
+use actix_web::web;
+
+use crate::{common::entities::repository::Repository, routes::handler::mastodon_handlers};
+
+/// The subset of Mastodon's client api, under `/api/v1` next to the native routes. None of its
+/// scopes is used by a native route.
+pub fn config<T: Repository>(config: &mut web::ServiceConfig) {
+    config
+        .service(
+            web::scope("/accounts")
+                // Ahead of `/{id}`, which would otherwise take its path.
+                .route(
+                    "/lookup",
+                    web::get().to(mastodon_handlers::lookup_account::<T>),
+                )
+                .route("/{id}", web::get().to(mastodon_handlers::get_account::<T>)),
+        )
+        .service(
+            web::scope("/statuses")
+                .route("", web::post().to(mastodon_handlers::create_status::<T>))
+                .route("/{id}", web::get().to(mastodon_handlers::get_status::<T>)),
+        )
+        .route(
+            "/timelines/home",
+            web::get().to(mastodon_handlers::home_timeline::<T>),
+        );
+}
@@ -1,3 +1,1044 @@
+pub mod admin_routes;
+pub mod draft_routes;
+pub mod federation_routes;
 pub mod handler;
+pub mod mastodon_routes;
 pub mod msg_routes;
-pub mod profile_routes;
\ No newline at end of file
+pub mod profile_routes;
+pub mod webhook_routes;
+
+use actix_web::{http::StatusCode, web, HttpResponse};
+use serde_json::{json, Value};
+
+use crate::{
+    api_response::ApiResponse,
+    common::entities::repository::Repository,
+    error::{Result, ServerSideError},
+    metrics::{metrics, PROMETHEUS_TEXT},
+};
+
+/// Mounts the whole `/api/v1` route tree, with the Mastodon compatible routes in it and the
+/// federation documents and `/metrics` next to it, on the given backend. `run()` and the route
+/// tests both go through here, so they always serve the same endpoints.
+pub fn config<T: Repository>(config: &mut web::ServiceConfig) {
+    config
+        .service(
+            web::scope("/api/v1")
+                .route("/", web::get().to(get_root))
+                .configure(msg_routes::config::<T>)
+                // Ahead of the `/profile` scope, which would otherwise take their paths.
+                .configure(draft_routes::config::<T>)
+                .configure(federation_routes::api_config::<T>)
+                .configure(profile_routes::config::<T>)
+                .configure(webhook_routes::config::<T>)
+                .configure(mastodon_routes::config::<T>)
+                .configure(admin_routes::config::<T>),
+        )
+        .configure(federation_routes::config::<T>)
+        .route("/metrics", web::get().to(get_metrics));
+}
+
+async fn get_root() -> Result<ApiResponse<Value>> {
+    let value = json!({
+        "message": "Welcome to home page for twitter clone api"
+    });
+    Ok(ApiResponse::new(StatusCode::OK, value))
+}
+
+async fn get_metrics() -> Result<HttpResponse> {
+    let text = metrics()
+        .render()
+        .map_err(ServerSideError::InternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(PROMETHEUS_TEXT).body(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::header, test, App, HttpServer};
+    use chrono::{TimeDelta, Utc};
+    use sqlx::PgPool;
+    use tracing::Level;
+    use tracing_config::{
+        capture::{ExpectedEvent, LogCapture},
+        LogFilters,
+    };
+
+    use super::*;
+    use crate::{
+        admin_api::AdminApi,
+        app_state::AppState,
+        common::entities::{
+            base::{DbConnGetter, DbRepo},
+            in_memory::InMemoryRepo,
+            messages::repo::InsertMessageFn,
+            profile::repo::{FollowUserFn, InsertProfileFn},
+        },
+        common_tests::{get_app_data, get_app_state, profile, signed_inbox_headers, stub_actor},
+        federation::{
+            delivery::ActivityDeliveryHandler, signatures::generate_key_pair, Federation,
+        },
+        outbound,
+        schemas::{
+            admin::{LogLevelResponder, LogLevelResponders},
+            draft::{DraftResponder, DraftResponders},
+            federation::RemoteNoteResponders,
+            mastodon::{AccountResponder, StatusResponder},
+            message::{
+                MessageGroupTypes, MessageResponder, MessageResponders, PollResponder,
+                ScheduledMessageResponders,
+            },
+            profile::ProfileResponder,
+        },
+        settings::{AdminSettings, FederationSettings, JobSettings, OutboundSettings},
+        worker::{Worker, DELIVER_ACTIVITY},
+    };
+
+    #[actix_web::test]
+    async fn test_root() {
+        let app = test::init_service(
+            App::new()
+                .app_data(get_app_data(InMemoryRepo::new()).await)
+                .configure(config::<InMemoryRepo>),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/api/v1/").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_create_and_get_profile() {
+        let app = test::init_service(
+            App::new()
+                .app_data(get_app_data(InMemoryRepo::new()).await)
+                .configure(config::<InMemoryRepo>),
+        )
+        .await;
+
+        let boundary = "profile-boundary";
+        let mut payload = String::new();
+        for (name, value) in [
+            ("user_name", "dave"),
+            ("full_name", "Dave Choi"),
+            ("description", "hi"),
+        ] {
+            payload.push_str(&format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n"
+            ));
+        }
+        payload.push_str(&format!("--{boundary}--\r\n"));
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/profile/")
+            .insert_header((
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={boundary}"),
+            ))
+            .set_payload(payload)
+            .to_request();
+        let created: Value = test::call_and_read_body_json(&app, req).await;
+        let id = created["profile_id"].as_i64().unwrap();
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/profile/username/dave")
+            .to_request();
+        let found: ProfileResponder = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(found.id, id);
+        assert_eq!(found.full_name, "Dave Choi");
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/profile/999")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_post_message_and_read_timeline() {
+        let repo = InMemoryRepo::new();
+        // Keeps the author's id apart from the message's, the response used to carry the latter.
+        repo.insert_profile(profile("other")).await.unwrap();
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let reader = repo.insert_profile(profile("reader")).await.unwrap();
+        repo.follow_user(reader, author).await.unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(get_app_data(repo).await)
+                .configure(config::<InMemoryRepo>),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/messages")
+            .set_json(json!({"userId": author, "body": "hello", "groupType": 1}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let created: Value = test::read_body_json(resp).await;
+        let message_id = created["message_id"].as_i64().unwrap();
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/messages/{message_id}"))
+            .to_request();
+        let message: MessageResponder = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(message.body.as_deref(), Some("hello"));
+        assert_ne!(message_id, author);
+        assert_eq!(message.profile.id, author);
+        assert_eq!(message.profile.user_name, "author");
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/messages/")
+            .set_json(
+                json!({"followerId": reader, "lastUpdatedAt": Utc::now() + TimeDelta::seconds(1)}),
+            )
+            .to_request();
+        let MessageResponders(timeline) = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(timeline.len(), 1);
+        assert_eq!(timeline[0].id, message_id);
+        assert_eq!(timeline[0].profile.id, author);
+    }
+
+    #[actix_web::test]
+    async fn test_handlers_log_calls_and_errors() {
+        let capture = LogCapture::new();
+        let _guard = capture.set_default();
+        let repo = InMemoryRepo::new();
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(get_app_data(repo).await)
+                .configure(config::<InMemoryRepo>),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/messages")
+            .set_json(json!({"userId": author, "body": "hello", "groupType": 1}))
+            .to_request();
+        let created: Value = test::call_and_read_body_json(&app, req).await;
+        let message_id = created["message_id"].as_i64().unwrap();
+        let called = capture.assert_event(
+            &ExpectedEvent::new(Level::INFO)
+                .message("Create message handler called")
+                .span_field_eq("create_message", "user_id", author.to_string()),
+        );
+        // The body stays out of the handler span.
+        assert_eq!(
+            called
+                .span("create_message")
+                .unwrap()
+                .fields
+                .keys()
+                .collect::<Vec<_>>(),
+            [&"user_id"]
+        );
+        capture.assert_event(
+            &ExpectedEvent::new(Level::INFO)
+                .message(format!("Message created with id: {message_id}"))
+                .in_span("create_message"),
+        );
+        capture.assert_no_event(&ExpectedEvent::new(Level::ERROR));
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/messages/999")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        capture.assert_event(
+            &ExpectedEvent::new(Level::INFO)
+                .message("Get message handler called for id: 999")
+                .in_span("get_message"),
+        );
+        capture.assert_event(
+            &ExpectedEvent::new(Level::ERROR)
+                .field("error_id")
+                .field_eq("error", "Message Not Found: No message found with id: 999")
+                .in_span("get_message"),
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_schedule_reschedule_and_cancel_message() {
+        let repo = InMemoryRepo::new();
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(get_app_data(repo).await)
+                .configure(config::<InMemoryRepo>),
+        )
+        .await;
+
+        let publish_at = Utc::now() + TimeDelta::hours(1);
+        let req = test::TestRequest::post()
+            .uri("/api/v1/messages")
+            .set_json(json!({
+                "userId": author, "body": "soon", "groupType": 1, "publishAt": publish_at
+            }))
+            .to_request();
+        let created: Value = test::call_and_read_body_json(&app, req).await;
+        let message_id = created["message_id"].as_i64().unwrap();
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/messages/{message_id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/api/v1/messages/scheduled/{message_id}"))
+            .set_json(json!({"publishAt": publish_at + TimeDelta::hours(1)}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/messages/scheduled?userId={author}"))
+            .to_request();
+        let ScheduledMessageResponders(scheduled) = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(scheduled.len(), 1);
+        assert_eq!(scheduled[0].id, message_id);
+        assert!(scheduled[0].publish_at > publish_at);
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/api/v1/messages/scheduled/{message_id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/api/v1/messages/scheduled/{message_id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_draft_lifecycle_and_publish() {
+        let repo = InMemoryRepo::new();
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let other = repo.insert_profile(profile("other")).await.unwrap();
+        let original = repo
+            .insert_message(other, "original", 1, None)
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(get_app_data(repo).await)
+                .configure(config::<InMemoryRepo>),
+        )
+        .await;
+        let drafts_uri = format!("/api/v1/profile/{author}/drafts");
+
+        let req = test::TestRequest::post()
+            .uri(&drafts_uri)
+            .set_json(json!({"body": "first", "groupType": 1}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let created: Value = test::read_body_json(resp).await;
+        let draft_id = created["draft_id"].as_i64().unwrap();
+
+        let req = test::TestRequest::put()
+            .uri(&format!("{drafts_uri}/{draft_id}"))
+            .set_json(json!({"body": "thanks", "groupType": 1, "originalMsgId": original}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("{drafts_uri}/{draft_id}"))
+            .to_request();
+        let draft: DraftResponder = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(draft.body, "thanks");
+        assert_eq!(draft.original_msg_id, Some(original));
+
+        // Drafts are only reachable through their owner.
+        let req = test::TestRequest::post()
+            .uri(&format!(
+                "/api/v1/profile/{other}/drafts/{draft_id}/publish"
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("{drafts_uri}/{draft_id}/publish"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let published: Value = test::read_body_json(resp).await;
+        let message_id = published["message_id"].as_i64().unwrap();
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/messages/{message_id}"))
+            .to_request();
+        let message: MessageResponder = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(message.body.as_deref(), Some("thanks"));
+
+        let req = test::TestRequest::get().uri(&drafts_uri).to_request();
+        let DraftResponders(drafts) = test::call_and_read_body_json(&app, req).await;
+        assert!(drafts.is_empty());
+
+        let req = test::TestRequest::post()
+            .uri(&drafts_uri)
+            .set_json(json!({
+                "body": "both", "groupType": 1, "broadcastingMsgId": original,
+                "originalMsgId": original
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_poll_votes_and_results() {
+        let repo = InMemoryRepo::new();
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let voter = repo.insert_profile(profile("voter")).await.unwrap();
+        repo.follow_user(voter, author).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(get_app_data(repo).await)
+                .configure(config::<InMemoryRepo>),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/messages")
+            .set_json(json!({
+                "userId": author, "body": "tea or coffee?", "groupType": 1,
+                "poll": {"options": ["tea", "coffee"], "closesAt": Utc::now() + TimeDelta::hours(1)}
+            }))
+            .to_request();
+        let created: Value = test::call_and_read_body_json(&app, req).await;
+        let message_id = created["message_id"].as_i64().unwrap();
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/messages/{message_id}?viewerId={voter}"))
+            .to_request();
+        let message: MessageResponder = test::call_and_read_body_json(&app, req).await;
+        let poll = message.poll.unwrap();
+        assert_eq!(poll.total_votes, None);
+        assert!(poll.options.iter().all(|option| option.votes.is_none()));
+
+        let vote = json!({"userId": voter, "optionId": poll.options[1].id});
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/v1/messages/{message_id}/votes"))
+            .set_json(&vote)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let voted: PollResponder = test::read_body_json(resp).await;
+        assert_eq!(voted.voted_option_id, Some(poll.options[1].id));
+        assert_eq!(voted.total_votes, Some(1));
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/v1/messages/{message_id}/votes"))
+            .set_json(&vote)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/messages/")
+            .set_json(
+                json!({"followerId": voter, "lastUpdatedAt": Utc::now() + TimeDelta::seconds(1)}),
+            )
+            .to_request();
+        let MessageResponders(timeline) = test::call_and_read_body_json(&app, req).await;
+        let poll = timeline[0].poll.as_ref().unwrap();
+        assert_eq!(
+            poll.options
+                .iter()
+                .map(|option| option.votes)
+                .collect::<Vec<Option<i64>>>(),
+            vec![Some(0), Some(1)]
+        );
+    }
+
+    const ADMIN_TOKEN: &str = "0123456789abcdef0123456789abcdef";
+
+    /// App data with `ADMIN_TOKEN` as the admin token.
+    async fn get_admin_app_data() -> web::Data<AppState<InMemoryRepo>> {
+        let mut app_state = get_app_state(InMemoryRepo::new()).await;
+        app_state.admin = AdminApi::new(
+            AdminSettings { token: Some(ADMIN_TOKEN.to_string()) },
+            LogFilters::default(),
+        );
+        web::Data::new(app_state)
+    }
+
+    #[actix_web::test]
+    async fn test_webhooks_need_postgres() {
+        let app = test::init_service(
+            App::new()
+                .app_data(get_admin_app_data().await)
+                .configure(config::<InMemoryRepo>),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/webhooks")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {ADMIN_TOKEN}")))
+            .set_json(json!({"url": "https://example.com/hook", "events": ["message.created"]}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_webhooks_need_the_admin_token() {
+        let app = test::init_service(
+            App::new()
+                .app_data(get_admin_app_data().await)
+                .configure(config::<InMemoryRepo>),
+        )
+        .await;
+
+        for req in [
+            test::TestRequest::post().uri("/api/v1/webhooks").set_json(
+                json!({"url": "https://example.com/hook", "events": ["message.created"]}),
+            ),
+            test::TestRequest::get().uri("/api/v1/webhooks"),
+            test::TestRequest::delete().uri("/api/v1/webhooks/1"),
+            test::TestRequest::get().uri("/api/v1/webhooks/1/deliveries"),
+            test::TestRequest::post().uri("/api/v1/webhooks/1/deliveries/2/redeliver"),
+            test::TestRequest::get()
+                .uri("/api/v1/webhooks")
+                .insert_header((header::AUTHORIZATION, "Bearer not-the-token")),
+        ] {
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_federation_is_off_by_default() {
+        let app = test::init_service(
+            App::new()
+                .app_data(get_app_data(InMemoryRepo::new()).await)
+                .configure(config::<InMemoryRepo>),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/.well-known/webfinger?resource=acct:dave@localhost:8080")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_webfinger_and_unsigned_inbox() {
+        let repo = InMemoryRepo::new();
+        repo.insert_profile(profile("dave")).await.unwrap();
+        let mut app_state = get_app_state(repo).await;
+        app_state.federation = Federation::new(
+            app_state.client.clone(),
+            &OutboundSettings::default(),
+            FederationSettings {
+                enabled: true,
+                ..FederationSettings::default()
+            },
+        );
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(app_state))
+                .configure(config::<InMemoryRepo>),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/.well-known/webfinger?resource=acct:dave@localhost:8080")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/jrd+json"
+        );
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(
+            body["links"][0]["href"],
+            "http://localhost:8080/ap/users/dave"
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/.well-known/webfinger?resource=acct:dave@elsewhere.example")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::post()
+            .uri("/ap/users/dave/inbox")
+            .set_json(json!({
+                "id": "https://b.example/follows/1",
+                "type": "Follow",
+                "actor": "https://b.example/users/erin",
+                "object": "http://localhost:8080/ap/users/dave"
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_signed_inbox_request_is_accepted() {
+        let (public_key_pem, private_key_pem) = generate_key_pair().unwrap();
+        let actor = stub_actor(public_key_pem).await;
+        let actor_id = actor.url("/users/erin");
+        let repo = InMemoryRepo::new();
+        repo.insert_profile(profile("dave")).await.unwrap();
+        let outbound = OutboundSettings {
+            allow_private_networks: true,
+            ..OutboundSettings::default()
+        };
+        let mut app_state = get_app_state(repo).await;
+        app_state.federation = Federation::new(
+            outbound::http_client(&outbound).unwrap(),
+            &outbound,
+            FederationSettings {
+                enabled: true,
+                ..FederationSettings::default()
+            },
+        );
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(app_state))
+                .configure(config::<InMemoryRepo>),
+        )
+        .await;
+        let inbox = "/ap/users/dave/inbox";
+        let post = |activity: Value| {
+            let body = activity.to_string();
+            signed_inbox_headers(
+                &private_key_pem,
+                &format!("{actor_id}#main-key"),
+                inbox,
+                Utc::now(),
+                &body,
+            )
+            .into_iter()
+            .fold(test::TestRequest::post().uri(inbox), |req, header| {
+                req.insert_header(header)
+            })
+            .set_payload(body)
+            .to_request()
+        };
+
+        // A like of a remote note, which is accepted and dropped.
+        let like = json!({
+            "id": format!("{actor_id}/likes/1"),
+            "type": "Like",
+            "actor": actor_id,
+            "object": "https://c.example/notes/1"
+        });
+        let resp = test::call_service(&app, post(like)).await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+        // Signed by erin, but the activity is eve's.
+        let forwarded = json!({
+            "id": "https://c.example/likes/1",
+            "type": "Like",
+            "actor": "https://c.example/users/eve",
+            "object": "https://c.example/notes/1"
+        });
+        let resp = test::call_service(&app, post(forwarded)).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    /// Serves the whole app on a free port of 127.0.0.1 with federation on, as its own instance
+    /// with the base url returned. Only the database is shared with the other instances.
+    async fn start_instance(pool: PgPool) -> (String, DbRepo) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let federation = FederationSettings {
+            enabled: true,
+            base_url: base_url.clone(),
+            ..FederationSettings::default()
+        };
+        let outbound = OutboundSettings {
+            allow_private_networks: true,
+            ..OutboundSettings::default()
+        };
+        let repo = DbRepo::from_pool(pool).with_federation(federation.clone());
+        let mut app_state = get_app_state(repo.clone()).await;
+        app_state.federation = Federation::new(
+            outbound::http_client(&outbound).unwrap(),
+            &outbound,
+            federation,
+        );
+        let app_data = web::Data::new(app_state);
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(app_data.clone())
+                .configure(config::<DbRepo>)
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        tokio::spawn(server);
+        (base_url, repo)
+    }
+
+    /// Sends the queued activities, the way the workers of either instance would.
+    async fn deliver_activities(repo: &DbRepo) {
+        let worker = Worker::new(
+            repo.clone(),
+            JobSettings {
+                in_process: true,
+                concurrency: 1,
+                poll_interval_ms: 10,
+                lease_secs: 60,
+                max_attempts: 1,
+                initial_backoff_secs: 1,
+                max_backoff_secs: 1,
+            },
+        )
+        .register(
+            DELIVER_ACTIVITY,
+            ActivityDeliveryHandler::new(repo.clone(), reqwest::Client::new(), true),
+        );
+        while worker.run_once().await.unwrap() {}
+        let failed = sqlx::query_as::<_, (Option<String>,)>("select last_error from job")
+            .fetch_all(repo.get_conn())
+            .await
+            .unwrap();
+        assert!(failed.is_empty(), "{failed:?}");
+    }
+
+    #[sqlx::test(migrator = "crate::common::entities::base::MIGRATOR")]
+    #[ignore = "needs postgres at DATABASE_URL"]
+    async fn test_two_instances_federate(pool: PgPool) {
+        let (a, repo_a) = start_instance(pool.clone()).await;
+        let (b, repo_b) = start_instance(pool).await;
+        let alice = repo_a.insert_profile(profile("alice")).await.unwrap();
+        let bob = repo_b.insert_profile(profile("bob")).await.unwrap();
+        let client = reqwest::Client::new();
+
+        // alice on a follows bob on b, found through b's WebFinger.
+        let bob_account = format!("bob@{}", b.trim_start_matches("http://"));
+        let resp = client
+            .post(format!("{a}/api/v1/profile/{alice}/remote-follows"))
+            .header("content-type", "application/json")
+            .body(json!({ "account": bob_account }).to_string())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status().as_u16(), StatusCode::ACCEPTED.as_u16());
+        // The signed `Follow` goes to b, which answers with a signed `Accept`.
+        deliver_activities(&repo_a).await;
+        deliver_activities(&repo_b).await;
+
+        // bob's public message reaches alice's remote notes on a.
+        repo_b
+            .insert_message(bob, "hello from b", MessageGroupTypes::Public as i32, None)
+            .await
+            .unwrap();
+        deliver_activities(&repo_b).await;
+        let body = client
+            .get(format!("{a}/api/v1/profile/{alice}/remote-notes"))
+            .send()
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        let RemoteNoteResponders(notes) = serde_json::from_slice(&body).unwrap();
+        let [note] = notes.as_slice() else {
+            panic!("expected bob's note: {notes:?}");
+        };
+        assert_eq!(note.actor_id, format!("{b}/ap/users/bob"));
+        assert_eq!(note.content, "<p>hello from b</p>");
+    }
+
+    #[actix_web::test]
+    async fn test_mastodon_statuses_and_home_timeline() {
+        let repo = InMemoryRepo::new();
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let booster = repo.insert_profile(profile("booster")).await.unwrap();
+        let reader = repo.insert_profile(profile("reader")).await.unwrap();
+        repo.follow_user(reader, author).await.unwrap();
+        repo.follow_user(reader, booster).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(get_app_data(repo.clone()).await)
+                .configure(config::<InMemoryRepo>),
+        )
+        .await;
+        // Timelines page by timestamp, keep the statuses apart.
+        let tick = || tokio::time::sleep(std::time::Duration::from_millis(5));
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/statuses")
+            .set_json(json!({"account_id": author, "status": "hello"}))
+            .to_request();
+        let hello: StatusResponder = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(hello.content, "<p>hello</p>");
+        assert_eq!(hello.account.username, "author");
+        tick().await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/statuses")
+            .set_form([
+                ("account_id", booster.to_string()),
+                ("status", "welcome".to_string()),
+                ("in_reply_to_id", hello.id.clone()),
+            ])
+            .to_request();
+        let reply: StatusResponder = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(reply.in_reply_to_id, Some(hello.id.clone()));
+        assert_eq!(reply.in_reply_to_account_id, Some(author.to_string()));
+        tick().await;
+        let hello_id = hello.id.parse::<i64>().unwrap();
+        repo.insert_message(booster, "", 1, Some(hello_id))
+            .await
+            .unwrap();
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/api/v1/timelines/home?account_id={reader}&limit=2"
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let link = resp.headers().get(header::LINK).unwrap().to_str().unwrap();
+        let next = link
+            .strip_prefix("<http://localhost:8080")
+            .and_then(|link| link.strip_suffix(">; rel=\"next\""))
+            .unwrap()
+            .to_string();
+        let page: Vec<StatusResponder> = test::read_body_json(resp).await;
+        assert_eq!(page.len(), 2);
+        assert_eq!(page[0].account.username, "booster");
+        assert_eq!(page[0].reblog.as_ref().unwrap().id, hello.id);
+        assert_eq!(page[1].id, reply.id);
+
+        let req = test::TestRequest::get().uri(&next).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.headers().get(header::LINK).is_none());
+        let page: Vec<StatusResponder> = test::read_body_json(resp).await;
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].id, hello.id);
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/accounts/lookup?acct=author")
+            .to_request();
+        let account: AccountResponder = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(account.id, author.to_string());
+        assert_eq!(account.followers_count, 1);
+        assert_eq!(account.statuses_count, 1);
+
+        // Tokens can't be resolved to an account, the client is told it isn't authorized.
+        let req = test::TestRequest::get()
+            .uri("/api/v1/timelines/home")
+            .insert_header((header::AUTHORIZATION, "Bearer some-token"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let req = test::TestRequest::post()
+            .uri("/api/v1/statuses")
+            .insert_header((header::AUTHORIZATION, "Bearer some-token"))
+            .set_json(json!({"status": "hello"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        // The native routes are still reachable next to the Mastodon ones.
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/profile/{author}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_feeds_answer_conditional_requests() {
+        let repo = InMemoryRepo::new();
+        let dave = repo.insert_profile(profile("dave")).await.unwrap();
+        let erin = repo.insert_profile(profile("erin")).await.unwrap();
+        let quoted = repo
+            .insert_message(erin, "quote me", 1, None)
+            .await
+            .unwrap();
+        repo.insert_message(dave, "", 1, Some(quoted))
+            .await
+            .unwrap();
+        repo.insert_message(dave, "circle only", 2, None)
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(get_app_data(repo).await)
+                .configure(config::<InMemoryRepo>),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/profile/dave/feed.atom")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let etag = resp.headers().get(header::ETAG).unwrap().clone();
+        let last_modified = resp.headers().get(header::LAST_MODIFIED).unwrap().clone();
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains("&lt;blockquote&gt;&lt;p&gt;quote me&lt;/p&gt;"));
+        assert!(!body.contains("circle only"));
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/profile/dave/feed.atom")
+            .insert_header((header::IF_NONE_MATCH, etag))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/profile/dave/feed.rss")
+            .insert_header((header::IF_MODIFIED_SINCE, last_modified))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/profile/dave/feed.rss")
+            .insert_header((header::IF_MODIFIED_SINCE, "Thu, 01 Jan 2015 00:00:00 GMT"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/rss+xml; charset=utf-8"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_metrics_count_requests_by_route_pattern() {
+        let repo = InMemoryRepo::new();
+        let author = repo.insert_profile(profile("author")).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(crate::metrics::track_request)
+                .app_data(get_app_data(repo).await)
+                .configure(config::<InMemoryRepo>),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/messages")
+            .set_json(json!({"userId": author, "body": "counted", "groupType": 1}))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::CREATED
+        );
+        let req = test::TestRequest::get()
+            .uri("/api/v1/messages/987654")
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::NOT_FOUND
+        );
+
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains(
+            "http_requests_total{method=\"GET\",route=\"/api/v1/messages/{id}\",status=\"404\"}"
+        ));
+        assert!(body.contains(
+            "http_request_duration_seconds_count{method=\"POST\",route=\"/api/v1/messages\",status=\"201\"}"
+        ));
+        assert!(body.contains("client_errors_total{variant=\"NotFound\"}"));
+        assert!(!body.contains("messages_created_total 0"));
+    }
+
+    #[actix_web::test]
+    async fn test_admin_log_levels_need_the_token() {
+        const TOKEN: &str = "0123456789abcdef0123456789abcdef";
+        // Not made the default subscriber, its filters change all the same while it is alive.
+        let (_subscriber, guard) = tracing_config::TracingConfig::new()
+            .sink(tracing_config::Sink::stdout().filter("warn"))
+            .build()
+            .unwrap();
+        let mut app_state = get_app_state(InMemoryRepo::new()).await;
+        app_state.admin = AdminApi::new(
+            AdminSettings { token: Some(TOKEN.to_string()) },
+            guard.filters().clone(),
+        );
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(app_state))
+                .configure(config::<InMemoryRepo>),
+        )
+        .await;
+        let bearer = |token: &str| (header::AUTHORIZATION, format!("Bearer {token}"));
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/admin/log-levels")
+            .insert_header(bearer("0123456789abcdef0123456789abcdeX"))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        let req = test::TestRequest::put()
+            .uri("/api/v1/admin/log-levels/stdout")
+            .insert_header(bearer(TOKEN))
+            .set_json(json!({
+                "directives": "warn,twitter_clone::common::entities::messages=trace",
+                "revertAfterSecs": 600
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let changed: LogLevelResponder = test::read_body_json(resp).await;
+        assert_eq!(changed.previous.as_deref(), Some("warn"));
+        assert!(changed.revert_at.is_some());
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/admin/log-levels")
+            .insert_header(bearer(TOKEN))
+            .to_request();
+        let LogLevelResponders(levels) = test::call_and_read_body_json(&app, req).await;
+        let [level] = levels.as_slice() else {
+            panic!("expected only the stdout sink: {levels:?}");
+        };
+        assert_eq!(level.sink, "stdout");
+        assert_eq!(level.directives, changed.directives);
+        assert!(level
+            .directives
+            .contains("twitter_clone::common::entities::messages=trace"));
+
+        for (sink, directives, status) in [
+            ("file", "info", StatusCode::NOT_FOUND),
+            ("stdout", "info,twitter_clone=loud", StatusCode::BAD_REQUEST),
+        ] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/api/v1/admin/log-levels/{sink}"))
+                .insert_header(bearer(TOKEN))
+                .set_json(json!({ "directives": directives }))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), status);
+        }
+
+        // Without a configured token the endpoints don't exist.
+        let app = test::init_service(
+            App::new()
+                .app_data(get_app_data(InMemoryRepo::new()).await)
+                .configure(config::<InMemoryRepo>),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/api/v1/admin/log-levels")
+            .insert_header(bearer(TOKEN))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+}
@@ -1,12 +1,25 @@
 use actix_web::web;
 
-use crate::{common::entities::base::DbRepo, routes::handler::msg_handlers};
+use crate::{common::entities::repository::Repository, routes::handler::msg_handlers};
 
-pub fn config(config: &mut web::ServiceConfig) {
+pub fn config<T: Repository>(config: &mut web::ServiceConfig) {
     config.service(
         web::scope("/messages")
-            .route("", web::post().to(msg_handlers::create_message::<DbRepo>))
-            .route("/{id}", web::get().to(msg_handlers::get_message::<DbRepo>))
-            .route("/", web::get().to(msg_handlers::get_messages::<DbRepo>)),
+            .route("", web::post().to(msg_handlers::create_message::<T>))
+            .route(
+                "/scheduled",
+                web::get().to(msg_handlers::get_scheduled_messages::<T>),
+            )
+            .route(
+                "/scheduled/{id}",
+                web::put().to(msg_handlers::reschedule_message::<T>),
+            )
+            .route(
+                "/scheduled/{id}",
+                web::delete().to(msg_handlers::cancel_scheduled_message::<T>),
+            )
+            .route("/{id}", web::get().to(msg_handlers::get_message::<T>))
+            .route("/{id}/votes", web::post().to(msg_handlers::vote_poll::<T>))
+            .route("/", web::get().to(msg_handlers::get_messages::<T>)),
     );
 }
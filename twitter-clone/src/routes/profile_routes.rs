@@ -1,21 +1,30 @@
 use actix_web::web;
 
-use crate::{common::entities::base::DbRepo, routes::handler::profile_handlers};
+use crate::{
+    common::entities::repository::Repository,
+    routes::handler::{feed_handlers, profile_handlers},
+};
 
-pub fn config(config: &mut web::ServiceConfig) {
+pub fn config<T: Repository>(config: &mut web::ServiceConfig) {
     config.service(
         web::scope("/profile")
+            .route("/{id}", web::get().to(profile_handlers::get_profile::<T>))
             .route(
-                "/{id}",
-                web::get().to(profile_handlers::get_profile::<DbRepo>),
+                "/{id}/avatar",
+                web::get().to(profile_handlers::get_profile_avatar::<T>),
             )
+            .route("/", web::post().to(profile_handlers::create_profile::<T>))
             .route(
-                "/",
-                web::post().to(profile_handlers::create_profile::<DbRepo>),
+                "/{user_name}/feed.rss",
+                web::get().to(feed_handlers::get_rss_feed::<T>),
+            )
+            .route(
+                "/{user_name}/feed.atom",
+                web::get().to(feed_handlers::get_atom_feed::<T>),
             )
             .route(
                 "/username/{user_name}",
-                web::get().to(profile_handlers::get_profile_by_user_name::<DbRepo>),
+                web::get().to(profile_handlers::get_profile_by_user_name::<T>),
             ),
     );
 }
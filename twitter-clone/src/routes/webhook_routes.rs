@@ -0,0 +1,23 @@
+use actix_web::web;
+
+use crate::{common::entities::repository::Repository, routes::handler::webhook_handlers};
+
+pub fn config<T: Repository>(config: &mut web::ServiceConfig) {
+    config.service(
+        web::scope("/webhooks")
+            .route("", web::post().to(webhook_handlers::create_webhook::<T>))
+            .route("", web::get().to(webhook_handlers::get_webhooks::<T>))
+            .route(
+                "/{id}",
+                web::delete().to(webhook_handlers::delete_webhook::<T>),
+            )
+            .route(
+                "/{id}/deliveries",
+                web::get().to(webhook_handlers::get_webhook_deliveries::<T>),
+            )
+            .route(
+                "/{id}/deliveries/{delivery_id}/redeliver",
+                web::post().to(webhook_handlers::redeliver_webhook::<T>),
+            ),
+    );
+}
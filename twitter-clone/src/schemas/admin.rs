@@ -0,0 +1,28 @@
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::vec::Vec;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLevelJson {
+    /// `EnvFilter` directives, like `info,twitter_clone::common::entities::messages=trace`.
+    pub directives: String,
+    /// Sets the previous directives back after this long, unless changed again before.
+    pub revert_after_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLevelResponder {
+    pub sink: String,
+    pub directives: String,
+    /// Only in the response to a change.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revert_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLevelResponders(pub Vec<LogLevelResponder>);
@@ -0,0 +1,34 @@
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::vec::Vec;
+
+use super::message::MessageGroupTypes;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DraftJson {
+    pub body: String,
+    pub group_type: MessageGroupTypes,
+    /// Publishes the draft as a broadcast of this message.
+    pub broadcasting_msg_id: Option<i64>,
+    /// Publishes the draft as an answer to this message, can't be combined with
+    /// `broadcastingMsgId`.
+    pub original_msg_id: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DraftResponder {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub user_id: i64,
+    pub body: String,
+    pub group_type: i32,
+    pub broadcasting_msg_id: Option<i64>,
+    pub original_msg_id: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DraftResponders(pub Vec<DraftResponder>);
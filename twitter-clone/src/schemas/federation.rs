@@ -0,0 +1,49 @@
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::vec::Vec;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WebfingerQuery {
+    /// `acct:user_name@domain`.
+    pub resource: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OutboxQuery {
+    /// Asks for a page of activities instead of the collection.
+    pub page: Option<bool>,
+    /// Only messages older than this message id.
+    pub before: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteFollowJson {
+    /// `user@host` looked up with WebFinger, or the url of an actor.
+    pub account: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteNotesQuery {
+    /// Defaults to 20, at most 100.
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteNoteResponder {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    /// The note's ActivityPub id.
+    pub ap_id: String,
+    pub actor_id: String,
+    /// Html, as the remote server sent it.
+    pub content: String,
+    pub in_reply_to: Option<String>,
+    pub published: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteNoteResponders(pub Vec<RemoteNoteResponder>);
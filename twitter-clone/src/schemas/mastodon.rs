@@ -0,0 +1,86 @@
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::vec::Vec;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AccountLookupQuery {
+    /// A local `user_name`, with or without the `@domain` of this instance.
+    pub acct: String,
+}
+
+/// There is no authentication, so the account whose timeline is read is named like the native
+/// `followerId` instead of coming from a token.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct HomeTimelineQuery {
+    /// Required, it is only optional so that clients sending a token get a 401 instead of a 400.
+    pub account_id: Option<i64>,
+    /// Only statuses older than this status.
+    pub max_id: Option<i64>,
+    /// Defaults to 20, at most 40.
+    pub limit: Option<i16>,
+}
+
+/// Sent as JSON or as a form, clients do both.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct StatusPostJson {
+    /// The author, in place of the account of a token. Required like the timeline's.
+    pub account_id: Option<i64>,
+    pub status: String,
+    pub in_reply_to_id: Option<String>,
+    /// `public` or `unlisted` post a public message, `private` a circle message.
+    pub visibility: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct AccountResponder {
+    pub id: String,
+    pub username: String,
+    pub acct: String,
+    pub display_name: String,
+    pub locked: bool,
+    pub bot: bool,
+    pub created_at: DateTime<Utc>,
+    /// Html of the profile description.
+    pub note: String,
+    pub url: String,
+    pub avatar: String,
+    pub avatar_static: String,
+    pub header: String,
+    pub header_static: String,
+    pub followers_count: i64,
+    pub following_count: i64,
+    pub statuses_count: i64,
+    pub emojis: Vec<Value>,
+    pub fields: Vec<Value>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct StatusResponder {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub in_reply_to_id: Option<String>,
+    pub in_reply_to_account_id: Option<String>,
+    pub sensitive: bool,
+    pub spoiler_text: String,
+    /// `public` or `private` for circle messages.
+    pub visibility: String,
+    pub language: Option<String>,
+    pub uri: String,
+    pub url: Option<String>,
+    /// Replies and reblogs aren't counted by this api and stay 0.
+    pub replies_count: i64,
+    pub reblogs_count: i64,
+    pub favourites_count: i64,
+    /// Html of the message body.
+    pub content: String,
+    /// The broadcast message.
+    pub reblog: Option<Box<StatusResponder>>,
+    pub account: AccountResponder,
+    pub media_attachments: Vec<Value>,
+    pub mentions: Vec<Value>,
+    pub tags: Vec<Value>,
+    pub emojis: Vec<Value>,
+    pub card: Option<Value>,
+    pub poll: Option<Value>,
+}
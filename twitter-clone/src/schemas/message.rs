@@ -1,12 +1,13 @@
+use super::profile::ProfileShort;
+use crate::link_preview::LinkPreview;
+use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_repr::*;
-use chrono::prelude::*;
-use super::profile::ProfileShort;
 use std::vec::Vec;
 
 #[derive(Debug, Deserialize)]
 pub struct MessageQuery {
-    pub id: i64
+    pub id: i64,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -14,7 +15,7 @@ pub struct MessageQuery {
 pub struct MessageByFollowingQuery {
     pub follower_id: i64,
     pub last_updated_at: DateTime<Utc>,
-    pub page_size: Option<i16>
+    pub page_size: Option<i16>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -23,7 +24,45 @@ pub struct MessagePostJson {
     pub user_id: i64,
     pub body: String,
     pub group_type: MessageGroupTypes,
-    pub broadcasting_msg_id: Option<i64>
+    pub broadcasting_msg_id: Option<i64>,
+    /// Keeps the message hidden until then, must be in the future.
+    pub publish_at: Option<DateTime<Utc>>,
+    /// Attaches a poll, not available for scheduled messages.
+    pub poll: Option<PollJson>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PollJson {
+    /// 2 to 4 labels, in display order.
+    pub options: Vec<String>,
+    pub closes_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageViewerQuery {
+    /// Profile the message is shown to, for the poll results it may see.
+    pub viewer_id: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PollVoteJson {
+    pub user_id: i64,
+    pub option_id: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledMessagesQuery {
+    pub user_id: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageRescheduleJson {
+    pub publish_at: DateTime<Utc>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -34,16 +73,55 @@ pub struct MessageResponder {
     pub body: Option<String>,
     pub likes: i32,
     pub broadcasting_msg: Option<Box<MessageResponder>>,
-    pub profile: ProfileShort
+    pub profile: ProfileShort,
+    pub poll: Option<PollResponder>,
+    /// Preview of the first link in `body`, once it has been fetched.
+    pub link_preview: Option<LinkPreview>,
+}
+
+/// Vote counts are only filled in once the viewer voted or the poll closed.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PollResponder {
+    pub id: i64,
+    pub closes_at: DateTime<Utc>,
+    pub closed: bool,
+    pub voted_option_id: Option<i64>,
+    pub total_votes: Option<i64>,
+    pub options: Vec<PollOptionResponder>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PollOptionResponder {
+    pub id: i64,
+    pub label: String,
+    pub votes: Option<i64>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct MessageResponders(pub Vec<MessageResponder>);
 
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledMessageResponder {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub user_id: i64,
+    pub body: Option<String>,
+    pub group_type: i32,
+    pub broadcasting_msg_id: Option<i64>,
+    pub publish_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledMessageResponders(pub Vec<ScheduledMessageResponder>);
+
 #[derive(Debug, Deserialize_repr, Serialize_repr, Clone)]
 #[repr(i32)]
 pub enum MessageGroupTypes {
     Public = 1,
-    Circle = 2
-}
\ No newline at end of file
+    Circle = 2,
+}
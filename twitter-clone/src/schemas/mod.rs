@@ -1,2 +1,7 @@
+pub mod admin;
+pub mod draft;
+pub mod federation;
+pub mod mastodon;
 pub mod message;
-pub mod profile;
\ No newline at end of file
+pub mod profile;
+pub mod webhook;
@@ -2,6 +2,8 @@ use actix_multipart::form::{tempfile::TempFile, text::Text, MultipartForm};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::link_preview::LinkPreview;
+
 #[derive(Debug, Deserialize)]
 pub struct ProfileQuery {
     pub id: i64,
@@ -41,4 +43,6 @@ pub struct ProfileResponder {
     pub region: Option<String>,
     pub main_url: Option<String>,
     pub avatar: Option<Vec<u8>>,
+    /// Preview of `main_url`, once it has been fetched.
+    pub main_url_preview: Option<LinkPreview>,
 }
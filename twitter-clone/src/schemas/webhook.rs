@@ -0,0 +1,67 @@
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::vec::Vec;
+
+use crate::common::entities::webhooks::model::WebhookEvent;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookJson {
+    /// Where deliveries are POSTed, http(s) only.
+    pub url: String,
+    /// Key of the `X-Webhook-Signature` HMAC. Generated when left out, and only shown in the
+    /// response of this request.
+    pub secret: Option<String>,
+    pub events: Vec<WebhookEvent>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookResponder {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub url: String,
+    pub events: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookResponders(pub Vec<WebhookResponder>);
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookDeliveriesQuery {
+    /// Defaults to 20, at most 100.
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookAttemptResponder {
+    pub created_at: DateTime<Utc>,
+    /// Missing when no response came back, see `error`.
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    /// The first KiB of the response.
+    pub response_body: Option<String>,
+    pub duration_ms: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookDeliveryResponder {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub event: String,
+    pub payload: Value,
+    /// `pending`, `delivered` or `failed`, by the last attempt.
+    pub status: String,
+    pub attempts: Vec<WebhookAttemptResponder>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookDeliveryResponders(pub Vec<WebhookDeliveryResponder>);
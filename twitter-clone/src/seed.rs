@@ -0,0 +1,471 @@
+use std::{collections::HashSet, ops::Range};
+
+use fake::{
+    faker::{
+        address::en::CountryName,
+        company::en::CompanyName,
+        internet::en::{DomainSuffix, Username},
+        lorem::en::Sentence,
+        name::en::{FirstName, LastName},
+    },
+    Fake,
+};
+use rand::{
+    distr::{weighted::WeightedIndex, Distribution},
+    rngs::StdRng,
+    seq::{IndexedRandom, SliceRandom},
+    Rng, SeedableRng,
+};
+use tracing::info;
+
+use crate::{
+    common::entities::{
+        circles::repo::{AddCircleMemberFn, InsertCircleFn},
+        messages::repo::{InsertMessageFn, InsertResponseMessageFn},
+        profile::{
+            model::ProfileCreate,
+            repo::{FollowUserFn, InsertProfileFn},
+        },
+    },
+    error::Result,
+    schemas::message::MessageGroupTypes,
+};
+
+/// Longest body accepted by the `message.body` column.
+const MAX_BODY_CHARS: usize = 140;
+/// Exponent of the Zipf distribution used to pick who gets followed. Values just above 1 give the
+/// few very popular accounts and long tail of barely followed ones seen on real networks.
+const FOLLOW_POPULARITY_EXPONENT: f64 = 1.1;
+/// Avatars are `AVATAR_CELLS` x `AVATAR_CELLS` identicons, each cell `AVATAR_CELL_PX` wide.
+const AVATAR_CELLS: usize = 8;
+const AVATAR_CELL_PX: usize = 4;
+
+/// Shape of the generated dataset. The same options and `seed` always produce the same plan.
+#[derive(Debug, Clone)]
+pub struct SeedOptions {
+    pub seed: u64,
+    pub profiles: usize,
+    /// Average number of accounts each profile follows.
+    pub follows_per_profile: usize,
+    /// Average number of messages written by each profile, replies and broadcasts included.
+    pub messages_per_profile: usize,
+    /// Share of messages that reply to an earlier message.
+    pub reply_ratio: f64,
+    /// Share of messages that broadcast an earlier message.
+    pub broadcast_ratio: f64,
+    /// Share of messages posted to a circle instead of publicly.
+    pub circle_message_ratio: f64,
+    /// Share of profiles owning a circle.
+    pub circle_owner_ratio: f64,
+    /// Average number of members per circle.
+    pub circle_size: usize,
+}
+
+impl Default for SeedOptions {
+    fn default() -> Self {
+        Self {
+            seed: 42,
+            profiles: 100,
+            follows_per_profile: 20,
+            messages_per_profile: 10,
+            reply_ratio: 0.2,
+            broadcast_ratio: 0.1,
+            circle_message_ratio: 0.1,
+            circle_owner_ratio: 0.3,
+            circle_size: 6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlannedMessageKind {
+    Original,
+    /// Reply to the message at this index of [`SeedPlan::messages`].
+    Reply(usize),
+    /// Broadcast of the message at this index of [`SeedPlan::messages`].
+    Broadcast(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedMessage {
+    /// Index into [`SeedPlan::profiles`].
+    pub author: usize,
+    pub body: String,
+    pub group_type: i32,
+    pub kind: PlannedMessageKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedCircle {
+    pub owner: usize,
+    pub members: Vec<usize>,
+}
+
+/// Dataset generated from [`SeedOptions`]. Rows reference each other by index so the plan can be
+/// built without a database and replayed on any repository.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeedPlan {
+    pub profiles: Vec<ProfileCreate>,
+    /// `(follower, following)` pairs of profile indices.
+    pub follows: Vec<(usize, usize)>,
+    /// In insertion order, replies and broadcasts only point at earlier messages.
+    pub messages: Vec<PlannedMessage>,
+    pub circles: Vec<PlannedCircle>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SeedSummary {
+    pub profiles: usize,
+    pub follows: usize,
+    pub messages: usize,
+    pub replies: usize,
+    pub broadcasts: usize,
+    pub circles: usize,
+    pub circle_members: usize,
+}
+
+impl SeedPlan {
+    pub fn generate(options: &SeedOptions) -> Self {
+        let mut rng = StdRng::seed_from_u64(options.seed);
+
+        let profiles = (0..options.profiles)
+            .map(|index| fake_profile(&mut rng, index))
+            .collect::<Vec<ProfileCreate>>();
+        let follows = plan_follows(&mut rng, options);
+        let messages = plan_messages(&mut rng, options);
+        let circles = plan_circles(&mut rng, options);
+
+        Self { profiles, follows, messages, circles }
+    }
+}
+
+/// Inserts the plan through the repository traits, so it works with every backend.
+pub async fn apply<T>(repo: &T, plan: &SeedPlan) -> Result<SeedSummary>
+where
+    T: InsertProfileFn
+        + FollowUserFn
+        + InsertMessageFn
+        + InsertResponseMessageFn
+        + InsertCircleFn
+        + AddCircleMemberFn,
+{
+    let mut summary = SeedSummary::default();
+
+    let mut profile_ids = Vec::with_capacity(plan.profiles.len());
+    for profile in &plan.profiles {
+        profile_ids.push(repo.insert_profile(profile.clone()).await?);
+    }
+    summary.profiles = profile_ids.len();
+    info!(profiles = summary.profiles, "Seeded profiles");
+
+    for (follower, following) in &plan.follows {
+        repo.follow_user(profile_ids[*follower], profile_ids[*following])
+            .await?;
+    }
+    summary.follows = plan.follows.len();
+    info!(follows = summary.follows, "Seeded follows");
+
+    let mut message_ids: Vec<i64> = Vec::with_capacity(plan.messages.len());
+    for message in &plan.messages {
+        let user_id = profile_ids[message.author];
+        let id = match message.kind {
+            PlannedMessageKind::Original => {
+                repo.insert_message(user_id, &message.body, message.group_type, None)
+                    .await?
+            },
+            PlannedMessageKind::Reply(original) => {
+                summary.replies += 1;
+                repo.insert_response_message(
+                    user_id,
+                    &message.body,
+                    message.group_type,
+                    message_ids[original],
+                )
+                .await?
+            },
+            PlannedMessageKind::Broadcast(source) => {
+                summary.broadcasts += 1;
+                repo.insert_message(
+                    user_id,
+                    &message.body,
+                    message.group_type,
+                    Some(message_ids[source]),
+                )
+                .await?
+            },
+        };
+        message_ids.push(id);
+    }
+    summary.messages = message_ids.len();
+    info!(messages = summary.messages, "Seeded messages");
+
+    for circle in &plan.circles {
+        let circle_id = repo.insert_circle(profile_ids[circle.owner]).await?;
+        for member in &circle.members {
+            repo.add_circle_member(circle_id, profile_ids[*member])
+                .await?;
+        }
+        summary.circles += 1;
+        summary.circle_members += circle.members.len();
+    }
+    info!(circles = summary.circles, "Seeded circles");
+
+    Ok(summary)
+}
+
+fn fake_profile(rng: &mut StdRng, index: usize) -> ProfileCreate {
+    let first_name: String = FirstName().fake_with_rng(rng);
+    let last_name: String = LastName().fake_with_rng(rng);
+    let user_name: String = Username().fake_with_rng(rng);
+
+    ProfileCreate {
+        // The suffix keeps user names unique however small the faker's word list is.
+        user_name: format!("{}_{index}", truncate(&user_name, 40)),
+        full_name: truncate(&format!("{first_name} {last_name}"), 100),
+        description: truncate(&fake_sentence(rng, 8..12), 250),
+        region: Some(truncate(&CountryName().fake_with_rng::<String, _>(rng), 50)),
+        main_url: Some(fake_main_url(rng)),
+        avatar: Some(fake_avatar(rng)),
+    }
+}
+
+/// Every profile follows a varying number of accounts, picked with Zipf weights over a shuffled
+/// popularity ranking, so follower counts follow a power law.
+fn plan_follows(rng: &mut StdRng, options: &SeedOptions) -> Vec<(usize, usize)> {
+    if options.profiles < 2 {
+        return vec![];
+    }
+
+    let mut ranking = (0..options.profiles).collect::<Vec<usize>>();
+    ranking.shuffle(rng);
+    let weights = (0..options.profiles)
+        .map(|rank| 1.0 / ((rank + 1) as f64).powf(FOLLOW_POPULARITY_EXPONENT))
+        .collect::<Vec<f64>>();
+    let popularity = WeightedIndex::new(&weights).expect("weights are positive and finite");
+
+    let max_follows = options.profiles - 1;
+    let mut follows = vec![];
+    for follower in 0..options.profiles {
+        let wanted = rng
+            .random_range(0..=options.follows_per_profile * 2)
+            .min(max_follows);
+        let mut following = HashSet::with_capacity(wanted);
+        // Popular accounts get drawn over and over, cap the attempts so tiny networks finish.
+        let mut attempts = 0;
+        while following.len() < wanted && attempts < wanted * 20 {
+            attempts += 1;
+            let candidate = ranking[popularity.sample(rng)];
+            if candidate != follower && following.insert(candidate) {
+                follows.push((follower, candidate));
+            }
+        }
+    }
+    follows
+}
+
+fn plan_messages(rng: &mut StdRng, options: &SeedOptions) -> Vec<PlannedMessage> {
+    let mut authors = vec![];
+    for author in 0..options.profiles {
+        let count = rng.random_range(0..=options.messages_per_profile * 2);
+        authors.extend(std::iter::repeat_n(author, count));
+    }
+    // Interleave authors so timelines don't show one profile's messages in a block.
+    authors.shuffle(rng);
+
+    let mut messages: Vec<PlannedMessage> = Vec::with_capacity(authors.len());
+    for author in authors {
+        let roll: f64 = rng.random();
+        let kind = if messages.is_empty() {
+            PlannedMessageKind::Original
+        } else if roll < options.reply_ratio {
+            PlannedMessageKind::Reply(rng.random_range(0..messages.len()))
+        } else if roll < options.reply_ratio + options.broadcast_ratio {
+            PlannedMessageKind::Broadcast(rng.random_range(0..messages.len()))
+        } else {
+            PlannedMessageKind::Original
+        };
+        let group_type = if rng.random_bool(options.circle_message_ratio.clamp(0.0, 1.0)) {
+            MessageGroupTypes::Circle as i32
+        } else {
+            MessageGroupTypes::Public as i32
+        };
+
+        messages.push(PlannedMessage {
+            author,
+            body: truncate(&fake_sentence(rng, 3..12), MAX_BODY_CHARS),
+            group_type,
+            kind,
+        });
+    }
+    messages
+}
+
+fn plan_circles(rng: &mut StdRng, options: &SeedOptions) -> Vec<PlannedCircle> {
+    let mut circles = vec![];
+    let profiles = (0..options.profiles).collect::<Vec<usize>>();
+
+    for owner in 0..options.profiles {
+        if !rng.random_bool(options.circle_owner_ratio.clamp(0.0, 1.0)) {
+            continue;
+        }
+        let size = rng.random_range(1..=options.circle_size.max(1) * 2);
+        let mut members = profiles
+            .choose_multiple(rng, size + 1)
+            .copied()
+            .filter(|member| *member != owner)
+            .collect::<Vec<usize>>();
+        members.truncate(size);
+        circles.push(PlannedCircle { owner, members });
+    }
+    circles
+}
+
+fn fake_sentence(rng: &mut StdRng, words: Range<usize>) -> String {
+    Sentence(words).fake_with_rng(rng)
+}
+
+fn fake_main_url(rng: &mut StdRng) -> String {
+    let mut domain: String = CompanyName().fake_with_rng(rng);
+    domain.retain(|c| c.is_ascii_alphanumeric());
+    let suffix: String = DomainSuffix().fake_with_rng(rng);
+    truncate(&format!("https://{}.{suffix}", domain.to_lowercase()), 250)
+}
+
+/// A mirrored identicon encoded as a 24-bit BMP, small enough to insert thousands of them.
+fn fake_avatar(rng: &mut StdRng) -> Vec<u8> {
+    let color: [u8; 3] = [rng.random(), rng.random(), rng.random()];
+    let background = [0xF0, 0xF0, 0xF0];
+    let mut cells = [[false; AVATAR_CELLS]; AVATAR_CELLS];
+    for row in cells.iter_mut() {
+        for col in 0..AVATAR_CELLS / 2 {
+            let filled = rng.random_bool(0.5);
+            row[col] = filled;
+            row[AVATAR_CELLS - 1 - col] = filled;
+        }
+    }
+
+    let side = AVATAR_CELLS * AVATAR_CELL_PX;
+    // Rows are padded to a multiple of four bytes, 24-bit rows of `side` pixels already are when
+    // `side` is a multiple of four.
+    let pixel_bytes = side * side * 3;
+    let file_size = 54 + pixel_bytes;
+
+    let mut bmp = Vec::with_capacity(file_size);
+    bmp.extend_from_slice(b"BM");
+    bmp.extend_from_slice(&(file_size as u32).to_le_bytes());
+    bmp.extend_from_slice(&[0; 4]);
+    bmp.extend_from_slice(&54u32.to_le_bytes());
+    bmp.extend_from_slice(&40u32.to_le_bytes());
+    bmp.extend_from_slice(&(side as i32).to_le_bytes());
+    bmp.extend_from_slice(&(side as i32).to_le_bytes());
+    bmp.extend_from_slice(&1u16.to_le_bytes());
+    bmp.extend_from_slice(&24u16.to_le_bytes());
+    bmp.extend_from_slice(&[0; 4]);
+    bmp.extend_from_slice(&(pixel_bytes as u32).to_le_bytes());
+    bmp.extend_from_slice(&[0; 16]);
+
+    // BMP stores rows bottom up and pixels as BGR.
+    for y in (0..side).rev() {
+        for x in 0..side {
+            let [r, g, b] = if cells[y / AVATAR_CELL_PX][x / AVATAR_CELL_PX] {
+                color
+            } else {
+                background
+            };
+            bmp.extend_from_slice(&[b, g, r]);
+        }
+    }
+    bmp
+}
+
+fn truncate(value: &str, max_chars: usize) -> String {
+    value.chars().take(max_chars).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn options() -> SeedOptions {
+        SeedOptions { profiles: 200, ..SeedOptions::default() }
+    }
+
+    #[test]
+    fn test_same_seed_same_plan() {
+        assert_eq!(
+            SeedPlan::generate(&options()),
+            SeedPlan::generate(&options())
+        );
+
+        let other = SeedOptions { seed: 7, ..options() };
+        assert_ne!(
+            SeedPlan::generate(&options()).profiles,
+            SeedPlan::generate(&other).profiles
+        );
+    }
+
+    #[test]
+    fn test_follow_graph_is_valid_and_skewed() {
+        let plan = SeedPlan::generate(&options());
+
+        let unique = plan.follows.iter().collect::<HashSet<_>>();
+        assert_eq!(unique.len(), plan.follows.len());
+        assert!(plan.follows.iter().all(|(a, b)| a != b));
+
+        let mut followers: HashMap<usize, usize> = HashMap::new();
+        for (_, following) in &plan.follows {
+            *followers.entry(*following).or_default() += 1;
+        }
+        let mut counts = followers.into_values().collect::<Vec<usize>>();
+        counts.sort_unstable_by(|a, b| b.cmp(a));
+        let median = counts[counts.len() / 2];
+        assert!(counts[0] > median * 5, "{counts:?}");
+    }
+
+    #[test]
+    fn test_messages_reference_earlier_messages() {
+        let plan = SeedPlan::generate(&options());
+
+        assert!(!plan.messages.is_empty());
+        for (index, message) in plan.messages.iter().enumerate() {
+            assert!(message.body.chars().count() <= MAX_BODY_CHARS);
+            match message.kind {
+                PlannedMessageKind::Reply(target) | PlannedMessageKind::Broadcast(target) => {
+                    assert!(target < index)
+                },
+                PlannedMessageKind::Original => {},
+            }
+        }
+        assert!(plan
+            .messages
+            .iter()
+            .any(|m| matches!(m.kind, PlannedMessageKind::Reply(_))));
+        assert!(plan
+            .messages
+            .iter()
+            .any(|m| matches!(m.kind, PlannedMessageKind::Broadcast(_))));
+    }
+
+    #[test]
+    fn test_circles_exclude_owner() {
+        let plan = SeedPlan::generate(&options());
+
+        assert!(!plan.circles.is_empty());
+        for circle in &plan.circles {
+            assert!(!circle.members.contains(&circle.owner));
+        }
+    }
+
+    #[test]
+    fn test_avatar_is_bmp() {
+        let plan = SeedPlan::generate(&SeedOptions { profiles: 1, ..SeedOptions::default() });
+        let avatar = plan.profiles[0].avatar.as_ref().unwrap();
+        assert_eq!(&avatar[..2], b"BM");
+        assert_eq!(
+            u32::from_le_bytes(avatar[2..6].try_into().unwrap()) as usize,
+            avatar.len()
+        );
+    }
+}
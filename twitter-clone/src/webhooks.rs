@@ -0,0 +1,318 @@
+//! Sends webhook deliveries, one `deliver_webhook` job each.
+//!
+//! A delivery is POSTed as JSON `{"id", "event", "createdAt", "data"}` with the headers
+//! `X-Webhook-Event`, `X-Webhook-Delivery`, `X-Webhook-Timestamp` and `X-Webhook-Signature`. The
+//! signature is `sha256=` followed by the hex HMAC-SHA256 of `"{timestamp}.{body}"` keyed with the
+//! webhook's secret, so receivers can check both the sender and that the request isn't replayed.
+
+use std::{fmt, time::Instant};
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use reqwest::{header::CONTENT_TYPE, Client, Url};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tracing::info;
+
+use crate::{
+    common::entities::webhooks::{
+        model::{WebhookAttemptCreate, WebhookDeliveryTarget},
+        repo::{QueryWebhookDeliveryFn, RecordWebhookAttemptFn},
+    },
+    error::{Result, ServerSideError},
+    outbound::{self, WithTraceContext},
+    worker::JobHandler,
+};
+
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+/// How much of a receiver's response is kept in the delivery log.
+const MAX_RESPONSE_BYTES: usize = 1024;
+
+/// The `X-Webhook-Signature` value of `body` sent at `timestamp`, in unix seconds.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac takes keys of any size");
+    mac.update(format!("{timestamp}.{body}").as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[derive(Debug, Deserialize)]
+struct DeliveryPayload {
+    delivery_id: i64,
+}
+
+/// Runs the `deliver_webhook` jobs. Every attempt goes to the delivery log, and a failed one
+/// fails the job, so the queue retries it with backoff.
+#[derive(Debug)]
+pub struct WebhookHandler<T> {
+    db_repo: T,
+    client: Client,
+    allow_private_networks: bool,
+}
+
+impl<T> WebhookHandler<T> {
+    /// `client` is the outbound client of [`crate::outbound::http_client`], which keeps the
+    /// requests away from private networks.
+    pub fn new(db_repo: T, client: Client, allow_private_networks: bool) -> Self {
+        Self { db_repo, client, allow_private_networks }
+    }
+
+    async fn send(&self, delivery: &WebhookDeliveryTarget) -> WebhookAttemptCreate {
+        let started = Instant::now();
+        let (status_code, response_body, error) = match self.post(delivery).await {
+            Ok((status_code, response_body)) => (Some(status_code), Some(response_body), None),
+            Err(err) => (None, None, Some(err)),
+        };
+        WebhookAttemptCreate {
+            status_code,
+            error,
+            response_body,
+            duration_ms: i64::try_from(started.elapsed().as_millis()).unwrap_or(i64::MAX),
+        }
+    }
+
+    async fn post(
+        &self,
+        delivery: &WebhookDeliveryTarget,
+    ) -> std::result::Result<(i32, String), String> {
+        let url = Url::parse(&delivery.url).map_err(|err| err.to_string())?;
+        outbound::check_url(&url, self.allow_private_networks)?;
+
+        let body = json!({
+            "id": delivery.id,
+            "event": delivery.event,
+            "createdAt": delivery.created_at,
+            "data": delivery.payload,
+        })
+        .to_string();
+        let timestamp = chrono::Utc::now().timestamp();
+
+        let mut response = self
+            .client
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, &delivery.event)
+            .header(DELIVERY_HEADER, delivery.id)
+            .header(TIMESTAMP_HEADER, timestamp)
+            .header(SIGNATURE_HEADER, sign(&delivery.secret, timestamp, &body))
+            .body(body)
+            .with_trace_context()
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+
+        let status_code = i32::from(response.status().as_u16());
+        let mut response_body = Vec::new();
+        while let Ok(Some(chunk)) = response.chunk().await {
+            let room = MAX_RESPONSE_BYTES - response_body.len();
+            response_body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+            if response_body.len() == MAX_RESPONSE_BYTES {
+                break;
+            }
+        }
+        Ok((
+            status_code,
+            String::from_utf8_lossy(&response_body).to_string(),
+        ))
+    }
+}
+
+#[async_trait]
+impl<T> JobHandler for WebhookHandler<T>
+where
+    T: QueryWebhookDeliveryFn + RecordWebhookAttemptFn + fmt::Debug + Send + Sync,
+{
+    async fn run(&self, payload: Value) -> Result<()> {
+        let payload =
+            serde_json::from_value::<DeliveryPayload>(payload).map_err(ServerSideError::from)?;
+        // Gone with its webhook.
+        let Some(delivery) = self
+            .db_repo
+            .query_webhook_delivery(payload.delivery_id)
+            .await?
+        else {
+            return Ok(());
+        };
+
+        let attempt = self.send(&delivery).await;
+        self.db_repo
+            .record_webhook_attempt(delivery.id, &attempt)
+            .await?;
+        if attempt.succeeded() {
+            info!(delivery_id = delivery.id, "Webhook delivered");
+            return Ok(());
+        }
+        let reason = match (&attempt.error, attempt.status_code) {
+            (Some(err), _) => err.clone(),
+            (None, Some(status_code)) => format!("status {status_code}"),
+            (None, None) => "no response".to_string(),
+        };
+        Err(ServerSideError::InternalServerError(format!(
+            "Webhook delivery {} failed: {reason}",
+            delivery.id
+        ))
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use mockall::{mock, predicate::eq};
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::{
+        common_tests::stub_server::{StubResponse, StubServer},
+        settings::OutboundSettings,
+    };
+
+    mock! {
+        #[derive(Debug)]
+        Repo {}
+
+        #[async_trait]
+        impl QueryWebhookDeliveryFn for Repo {
+            async fn query_webhook_delivery(&self, id: i64) -> Result<Option<WebhookDeliveryTarget>>;
+        }
+
+        #[async_trait]
+        impl RecordWebhookAttemptFn for Repo {
+            async fn record_webhook_attempt(
+                &self,
+                delivery_id: i64,
+                attempt: &WebhookAttemptCreate,
+            ) -> Result<()>;
+        }
+    }
+
+    /// Answers `/ok` with 204 and anything else with 500.
+    async fn stub_receiver() -> StubServer {
+        StubServer::start(|request| match request.path() {
+            "/ok" => StubResponse::status(StatusCode::NO_CONTENT),
+            _ => StubResponse::status(StatusCode::INTERNAL_SERVER_ERROR),
+        })
+        .await
+    }
+
+    fn delivery(url: String) -> WebhookDeliveryTarget {
+        WebhookDeliveryTarget {
+            id: 9,
+            created_at: Utc::now(),
+            event: "message.created".to_string(),
+            payload: json!({"id": 1, "body": "Hello"}),
+            url,
+            secret: "a-secret-of-enough-length".to_string(),
+        }
+    }
+
+    fn handler(repo: MockRepo, allow_private_networks: bool) -> WebhookHandler<MockRepo> {
+        let outbound = OutboundSettings {
+            allow_private_networks,
+            ..OutboundSettings::default()
+        };
+        WebhookHandler::new(
+            repo,
+            outbound::http_client(&outbound).unwrap(),
+            allow_private_networks,
+        )
+    }
+
+    #[test]
+    fn test_sign() {
+        // Same as `printf '1700000000.{}' | openssl dgst -sha256 -hmac secret`.
+        assert_eq!(
+            sign("secret", 1_700_000_000, "{}"),
+            "sha256=b8569b78799ff9e3cbff0fc2d63a33a2b57f3282abd07c37ae5e8e7d79a5f163"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delivery_is_signed_and_recorded() {
+        let receiver = stub_receiver().await;
+        let url = receiver.url("/ok");
+        let mut repo = MockRepo::new();
+        repo.expect_query_webhook_delivery()
+            .with(eq(9))
+            .returning(move |_| Ok(Some(delivery(url.clone()))));
+        repo.expect_record_webhook_attempt()
+            .withf(|id, attempt| *id == 9 && attempt.succeeded())
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        handler(repo, true)
+            .run(json!({"delivery_id": 9}))
+            .await
+            .unwrap();
+
+        let request = receiver.requests().pop().unwrap();
+        assert_eq!(request.method(), "POST");
+        let header = |name: &str| request.header(name).unwrap();
+        let body = request.body.as_str();
+        let timestamp = header(TIMESTAMP_HEADER).parse::<i64>().unwrap();
+        assert_eq!(header(EVENT_HEADER), "message.created");
+        assert_eq!(header(DELIVERY_HEADER), "9");
+        assert_eq!(
+            header(SIGNATURE_HEADER),
+            sign("a-secret-of-enough-length", timestamp, body)
+        );
+        let body = serde_json::from_str::<Value>(body).unwrap();
+        assert_eq!(body["data"], json!({"id": 1, "body": "Hello"}));
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_is_recorded_and_retried() {
+        let url = stub_receiver().await.url("/down");
+        let mut repo = MockRepo::new();
+        repo.expect_query_webhook_delivery()
+            .returning(move |_| Ok(Some(delivery(url.clone()))));
+        repo.expect_record_webhook_attempt()
+            .withf(|_, attempt| attempt.status_code == Some(500) && !attempt.succeeded())
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        assert!(handler(repo, true)
+            .run(json!({"delivery_id": 9}))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_private_address_is_refused() {
+        let mut repo = MockRepo::new();
+        repo.expect_query_webhook_delivery()
+            .returning(|_| Ok(Some(delivery("http://127.0.0.1:9/ok".to_string()))));
+        repo.expect_record_webhook_attempt()
+            .withf(|_, attempt| {
+                attempt.status_code.is_none()
+                    && attempt
+                        .error
+                        .as_deref()
+                        .is_some_and(|err| err.contains("not a public address"))
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        assert!(handler(repo, false)
+            .run(json!({"delivery_id": 9}))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_deleted_delivery_is_skipped() {
+        let mut repo = MockRepo::new();
+        repo.expect_query_webhook_delivery().returning(|_| Ok(None));
+        repo.expect_record_webhook_attempt().never();
+
+        handler(repo, false)
+            .run(json!({"delivery_id": 9}))
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,489 @@
+use std::{collections::HashMap, fmt, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::{sync::watch, task::JoinHandle};
+use tracing::{error, field, info, info_span, warn, Instrument};
+
+use crate::{
+    common::entities::{
+        base::DbRepo,
+        jobs::{model::JobQueryResult, repo::JobQueue},
+        messages::{model::NewMessage, repo::QueryMessageFn},
+        timeline::repo::FanOutMessageFn,
+    },
+    error::{Result, ServerSideError},
+    federation::delivery::ActivityDeliveryHandler,
+    settings::{JobSettings, Settings},
+    webhooks::WebhookHandler,
+};
+
+/// Copies a new message into its followers' home timelines, payload `{"message_id": <id>}`.
+pub const FAN_OUT_MESSAGE: &str = "fan_out_message";
+
+/// Announces a scheduled message to webhooks and remote followers once it is published, payload
+/// `{"message_id": <id>}`.
+pub const ANNOUNCE_MESSAGE: &str = "announce_message";
+
+/// Sends one webhook delivery, payload `{"delivery_id": <id>}`, see [`crate::webhooks`].
+pub const DELIVER_WEBHOOK: &str = "deliver_webhook";
+
+/// Sends one activity to a remote inbox, see [`crate::federation::delivery`].
+pub const DELIVER_ACTIVITY: &str = "deliver_activity";
+
+/// Runs the jobs of one kind. Jobs are delivered at least once, so running the same payload twice
+/// must be harmless.
+#[async_trait]
+pub trait JobHandler: fmt::Debug + Send + Sync {
+    async fn run(&self, payload: Value) -> Result<()>;
+}
+
+/// Polls the job queue and dispatches due jobs to the handler registered for their kind.
+///
+/// Any number of workers, in the server or in `twitter-worker` processes, can share one queue:
+/// claims use `for update skip locked`, so a job is only handed to one of them at a time.
+#[derive(Debug)]
+pub struct Worker<T: JobQueue> {
+    queue: T,
+    settings: JobSettings,
+    handlers: HashMap<String, Arc<dyn JobHandler>>,
+}
+
+/// Running worker tasks, stopped with [`WorkerHandle::shutdown`].
+#[derive(Debug)]
+pub struct WorkerHandle {
+    shutdown: watch::Sender<bool>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl<T: JobQueue> Worker<T> {
+    pub fn new(queue: T, settings: JobSettings) -> Self {
+        Self {
+            queue,
+            settings,
+            handlers: HashMap::new(),
+        }
+    }
+
+    pub fn register(mut self, kind: &str, handler: impl JobHandler + 'static) -> Self {
+        self.handlers.insert(kind.to_string(), Arc::new(handler));
+        self
+    }
+
+    /// Starts `jobs.concurrency` tasks polling the queue.
+    pub fn spawn(self) -> WorkerHandle {
+        let (shutdown, receiver) = watch::channel(false);
+        let worker = Arc::new(self);
+        let tasks = (0..worker.settings.concurrency)
+            .map(|_| tokio::spawn(Arc::clone(&worker).poll(receiver.clone())))
+            .collect();
+        info!(
+            concurrency = worker.settings.concurrency,
+            kinds = ?worker.handlers.keys().collect::<Vec<&String>>(),
+            "Job workers started"
+        );
+
+        WorkerHandle { shutdown, tasks }
+    }
+
+    async fn poll(self: Arc<Self>, mut shutdown: watch::Receiver<bool>) {
+        let poll_interval = Duration::from_millis(self.settings.poll_interval_ms);
+
+        while !*shutdown.borrow() {
+            // Keep draining while jobs are due, errors are already logged by the repo.
+            if let Ok(true) = self.run_once().await {
+                continue;
+            }
+            tokio::select! {
+                _ = shutdown.changed() => {},
+                () = tokio::time::sleep(poll_interval) => {},
+            }
+        }
+    }
+
+    /// Claims and runs the next due job. Returns false when none was due.
+    pub async fn run_once(&self) -> Result<bool> {
+        let Some(job) = self
+            .queue
+            .claim_job(self.settings.lease_secs, self.settings.max_attempts)
+            .await?
+        else {
+            return Ok(false);
+        };
+
+        let span = info_span!(
+            "job",
+            job.id = job.id,
+            job.kind = %job.kind,
+            job.attempt = job.attempts,
+            request_id = field::Empty,
+        );
+        if let Some(request_id) = &job.request_id {
+            span.record("request_id", field::display(request_id));
+        }
+
+        self.process(job).instrument(span).await?;
+        Ok(true)
+    }
+
+    async fn process(&self, job: JobQueryResult) -> Result<()> {
+        let result = match self.handlers.get(&job.kind) {
+            // A panicking handler fails its job instead of taking the worker task down with it.
+            Some(handler) => {
+                let handler = Arc::clone(handler);
+                match tokio::spawn(async move { handler.run(job.payload).await }.in_current_span())
+                    .await
+                {
+                    Ok(result) => result.map_err(|err| err.to_string()),
+                    Err(err) => Err(format!("Job handler panicked: {err}")),
+                }
+            },
+            None => Err(format!("No handler registered for job kind {}", job.kind)),
+        };
+
+        match result {
+            Ok(()) => {
+                self.queue.complete_job(job.id).await?;
+                info!("Job completed");
+            },
+            Err(err) => {
+                let backoff_secs = backoff_secs(job.attempts, &self.settings);
+                let dead = self
+                    .queue
+                    .fail_job(job.id, &err, self.settings.max_attempts, backoff_secs)
+                    .await?;
+                if dead {
+                    error!(error = %err, "Job dead-lettered");
+                } else {
+                    warn!(error = %err, backoff_secs, "Job failed, retrying");
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+impl WorkerHandle {
+    /// Lets the running jobs finish and stops polling.
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(true);
+        for task in self.tasks {
+            if let Err(err) = task.await {
+                error!("Job worker task failed: {}", err);
+            }
+        }
+        info!("Job workers stopped");
+    }
+}
+
+/// A worker with the handlers of every job kind the application enqueues. Webhooks are sent with
+/// `client`, the server's outbound client when the worker runs in process.
+pub fn default_worker(db_repo: DbRepo, client: Client, settings: &Settings) -> Worker<DbRepo> {
+    let webhooks = WebhookHandler::new(
+        db_repo.clone(),
+        client.clone(),
+        settings.outbound.allow_private_networks,
+    );
+    let activities = ActivityDeliveryHandler::new(
+        db_repo.clone(),
+        client,
+        settings.outbound.allow_private_networks,
+    );
+    Worker::new(db_repo.clone(), settings.jobs.clone())
+        .register(FAN_OUT_MESSAGE, FanOutHandler { db_repo: db_repo.clone() })
+        .register(ANNOUNCE_MESSAGE, AnnounceHandler { db_repo })
+        .register(DELIVER_WEBHOOK, webhooks)
+        .register(DELIVER_ACTIVITY, activities)
+}
+
+/// Delay before the next attempt of a job that failed its `attempts`th run, doubling from
+/// `jobs.initial_backoff_secs` up to `jobs.max_backoff_secs`.
+fn backoff_secs(attempts: i32, settings: &JobSettings) -> i64 {
+    let doublings = attempts.saturating_sub(1).clamp(0, 32) as u32;
+    settings
+        .initial_backoff_secs
+        .saturating_mul(2_i64.saturating_pow(doublings))
+        .min(settings.max_backoff_secs)
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagePayload {
+    message_id: i64,
+}
+
+#[derive(Debug)]
+struct FanOutHandler {
+    db_repo: DbRepo,
+}
+
+#[async_trait]
+impl JobHandler for FanOutHandler {
+    async fn run(&self, payload: Value) -> Result<()> {
+        let payload =
+            serde_json::from_value::<MessagePayload>(payload).map_err(ServerSideError::from)?;
+        let rows = self.db_repo.fan_out_message(payload.message_id).await?;
+        info!(message_id = payload.message_id, rows, "Message fanned out");
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct AnnounceHandler {
+    db_repo: DbRepo,
+}
+
+#[async_trait]
+impl JobHandler for AnnounceHandler {
+    async fn run(&self, payload: Value) -> Result<()> {
+        let payload =
+            serde_json::from_value::<MessagePayload>(payload).map_err(ServerSideError::from)?;
+        // Hidden or cancelled since it was scheduled.
+        let Some(message) = self.db_repo.query_message(payload.message_id).await? else {
+            info!(
+                message_id = payload.message_id,
+                "Message gone, not announced"
+            );
+            return Ok(());
+        };
+        self.db_repo
+            .announce_message(&NewMessage {
+                id: message.id,
+                user_id: message.user_id,
+                body: message.body.as_deref().unwrap_or_default(),
+                msg_group_type: message.msg_group_type,
+                broadcasting_msg_id: message.broadcast_msg_id,
+                // Replies can't be scheduled.
+                original_msg_id: None,
+                publish_at: Some(message.published_at),
+            })
+            .await;
+        info!(message_id = payload.message_id, "Message announced");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use chrono::{TimeDelta, Utc};
+    use mockall::{mock, predicate::eq};
+    use serde_json::json;
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::{
+        common::entities::{
+            base::DbConnGetter,
+            jobs::repo::{ClaimJobFn, CompleteJobFn, FailJobFn},
+            messages::repo::ScheduleMessageFn,
+            profile::repo::InsertProfileFn,
+            webhooks::{
+                model::{WebhookCreate, WebhookEvent},
+                repo::{InsertWebhookFn, QueryWebhookDeliveriesFn},
+            },
+        },
+        common_tests::profile,
+        schemas::message::MessageGroupTypes,
+    };
+
+    mock! {
+        #[derive(Debug)]
+        Queue {}
+
+        #[async_trait]
+        impl ClaimJobFn for Queue {
+            async fn claim_job(
+                &self,
+                lease_secs: i64,
+                max_attempts: i32,
+            ) -> Result<Option<JobQueryResult>>;
+        }
+
+        #[async_trait]
+        impl CompleteJobFn for Queue {
+            async fn complete_job(&self, id: i64) -> Result<()>;
+        }
+
+        #[async_trait]
+        impl FailJobFn for Queue {
+            async fn fail_job(
+                &self,
+                id: i64,
+                error: &str,
+                max_attempts: i32,
+                backoff_secs: i64,
+            ) -> Result<bool>;
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct RecordingHandler {
+        payloads: Arc<Mutex<Vec<Value>>>,
+    }
+
+    #[async_trait]
+    impl JobHandler for RecordingHandler {
+        async fn run(&self, payload: Value) -> Result<()> {
+            self.payloads.lock().unwrap().push(payload);
+            Ok(())
+        }
+    }
+
+    #[derive(Debug)]
+    struct PanickingHandler;
+
+    #[async_trait]
+    impl JobHandler for PanickingHandler {
+        async fn run(&self, _payload: Value) -> Result<()> {
+            panic!("boom");
+        }
+    }
+
+    fn settings() -> JobSettings {
+        JobSettings {
+            in_process: true,
+            concurrency: 1,
+            poll_interval_ms: 10,
+            lease_secs: 60,
+            max_attempts: 3,
+            initial_backoff_secs: 10,
+            max_backoff_secs: 60,
+        }
+    }
+
+    fn job(kind: &str, attempts: i32) -> JobQueryResult {
+        JobQueryResult {
+            id: 7,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            kind: kind.to_string(),
+            payload: json!({"message_id": 1}),
+            status: "running".to_string(),
+            attempts,
+            run_at: Utc::now(),
+            last_error: None,
+            request_id: Some("2f1c1a6e-0b7a-4c43-9a63-8f3c6f9d2b10".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_backoff_doubles_until_max() {
+        let schedule = (1..=5)
+            .map(|attempts| backoff_secs(attempts, &settings()))
+            .collect::<Vec<i64>>();
+        assert_eq!(schedule, vec![10, 20, 40, 60, 60]);
+        assert_eq!(backoff_secs(i32::MAX, &settings()), 60);
+    }
+
+    #[tokio::test]
+    async fn test_job_is_dispatched_and_completed() {
+        let mut queue = MockQueue::new();
+        queue
+            .expect_claim_job()
+            .with(eq(60), eq(3))
+            .returning(|_, _| Ok(Some(job("record", 1))));
+        queue
+            .expect_complete_job()
+            .with(eq(7))
+            .times(1)
+            .returning(|_| Ok(()));
+        queue.expect_fail_job().never();
+
+        let handler = RecordingHandler::default();
+        let payloads = Arc::clone(&handler.payloads);
+        let worker = Worker::new(queue, settings()).register("record", handler);
+
+        assert!(worker.run_once().await.unwrap());
+        assert_eq!(*payloads.lock().unwrap(), vec![json!({"message_id": 1})]);
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_retried_with_backoff() {
+        let mut queue = MockQueue::new();
+        queue
+            .expect_claim_job()
+            .returning(|_, _| Ok(Some(job("unknown", 2))));
+        queue
+            .expect_fail_job()
+            .withf(|id, error, max_attempts, backoff_secs| {
+                *id == 7 && error.contains("unknown") && *max_attempts == 3 && *backoff_secs == 20
+            })
+            .times(1)
+            .returning(|_, _, _, _| Ok(false));
+
+        let worker = Worker::new(queue, settings());
+        assert!(worker.run_once().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_panicking_handler_fails_its_job() {
+        let mut queue = MockQueue::new();
+        queue
+            .expect_claim_job()
+            .returning(|_, _| Ok(Some(job("panic", 3))));
+        queue
+            .expect_fail_job()
+            .withf(|_, error, _, _| error.contains("panicked"))
+            .times(1)
+            .returning(|_, _, _, _| Ok(true));
+
+        let worker = Worker::new(queue, settings()).register("panic", PanickingHandler);
+        assert!(worker.run_once().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_empty_queue() {
+        let mut queue = MockQueue::new();
+        queue.expect_claim_job().returning(|_, _| Ok(None));
+
+        let worker = Worker::new(queue, settings());
+        assert!(!worker.run_once().await.unwrap());
+    }
+
+    #[sqlx::test(migrator = "crate::common::entities::base::MIGRATOR")]
+    #[ignore = "needs postgres at DATABASE_URL"]
+    async fn test_scheduled_message_is_only_announced_once_published(pool: PgPool) {
+        let db_repo = DbRepo::from_pool(pool);
+        let author = db_repo.insert_profile(profile("author")).await.unwrap();
+        let webhook = db_repo
+            .insert_webhook(WebhookCreate {
+                url: "https://hooks.example/messages".to_string(),
+                secret: "secret".to_string(),
+                events: vec![WebhookEvent::MessageCreated],
+            })
+            .await
+            .unwrap();
+        let id = db_repo
+            .schedule_message(
+                author,
+                "soon",
+                MessageGroupTypes::Public as i32,
+                None,
+                Utc::now() + TimeDelta::hours(1),
+            )
+            .await
+            .unwrap();
+        let handler = AnnounceHandler { db_repo: db_repo.clone() };
+
+        // Run early, like a job claimed again after its message was rescheduled.
+        handler.run(json!({ "message_id": id })).await.unwrap();
+        assert!(db_repo
+            .query_webhook_deliveries(webhook, 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        sqlx::query("update message set publish_at = now() - interval '1 second' where id = $1")
+            .bind(id)
+            .execute(db_repo.get_conn())
+            .await
+            .unwrap();
+        handler.run(json!({ "message_id": id })).await.unwrap();
+        let deliveries = db_repo.query_webhook_deliveries(webhook, 10).await.unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].payload["body"], "soon");
+    }
+}